@@ -8,7 +8,7 @@ use log::{info, warn};
 use rand::{SeedableRng, rngs::StdRng};
 use umaai::protocol::{GameStatus, GameStatusOnsen, onsen::serialize_game};
 use umasim::{
-    game::{Game, InheritInfo, onsen::game::OnsenGame},
+    game::{DEFAULT_FORECAST_SAMPLES, Game, InheritInfo, onsen::game::OnsenGame, unique_effect_report},
     gamedata::{GAMECONSTANTS, GameConfig, init_global},
     global,
     search::SearchConfig,
@@ -31,6 +31,12 @@ fn run_onsen_once(
     if let Some(g) = &load_game {
         info!("---- 载入游戏状态 ----");
         println!("{}", g.explain_distribution()?);
+        // 按当前羁绊和得意率预测下回合的人头分布
+        let mut next = g.clone();
+        next.turn += 1;
+        let forecast = next.forecast_distribution(DEFAULT_FORECAST_SAMPLES, &mut rng)?;
+        let names: Vec<_> = next.persons.iter().map(|p| p.short_name()).collect();
+        println!("{}", forecast.explain(&names));
         info!("---------------------");
     }
     let mut game = load_game.unwrap_or(OnsenGame::newgame(uma, cards, inherit)?);
@@ -38,6 +44,9 @@ fn run_onsen_once(
     loop {
         let game_for_save = game.clone();
         let mut selected = false; // 表示这阶段是否有选择
+        if game.pending_selection {
+            println!("{}", game.explain_dig_plan());
+        }
         if game.pending_selection || !game.list_actions().unwrap_or_default().is_empty() {
             let mut game2 = game.clone();
             game2.run_stage(&trainer_hand, &mut rng)?;
@@ -95,6 +104,7 @@ async fn main() -> Result<()> {
 
     // 3. 再初始化全局数据
     init_global()?;
+    info!("{}", unique_effect_report());
 
     // 如果指定了文件名，则载入游戏
     let mut load_game = None;
@@ -109,10 +119,7 @@ async fn main() -> Result<()> {
     // 开始计时
     //let start = Instant::now();
 
-    let inherit = InheritInfo {
-        blue_count: game_config.blue_count.clone(),
-        extra_count: game_config.extra_count.clone()
-    };
+    let inherit = game_config.inherit_info();
 
     let search_config = SearchConfig::default()
         .with_search_n(game_config.mcts.search_n)
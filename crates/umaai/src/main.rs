@@ -14,10 +14,10 @@ use umasim::{
         Game,
         InheritInfo,
         Trainer,
-        onsen::{OnsenTurnStage, action::OnsenAction}
+        onsen::{OnsenTurnStage, action::OnsenAction, ticket}
     },
     gamedata::{GameConfig, init_global},
-    neural::{Evaluator, NeuralNetEvaluator},
+    neural::{Evaluator, InferenceBackendKind, NeuralNetEvaluator},
     search::SearchConfig,
     trainer::MctsTrainer,
     utils::{check_windows_terminal, check_working_dir, init_logger, pause}
@@ -71,6 +71,9 @@ async fn main_guard() -> Result<()> {
 
     // 3. 再初始化全局数据
     init_global()?;
+    if !game_config.inherit_parents.is_empty() {
+        println!("{}", "提示: 已配置 inherit_parents，仅在小黑板未提供种马蓝因子时生效".yellow());
+    }
 
     let mut rng = StdRng::from_os_rng();
 
@@ -106,8 +109,9 @@ async fn main_guard() -> Result<()> {
                 return Err(anyhow!("mcts.rollout_evaluator=\"nn\" 但模型文件不存在: {model_path}"));
             }
             // 先验证模型可加载（避免“以为开了 NN 实际没开”的伪对照）
-            let _ = NeuralNetEvaluator::load(model_path)?;
-            trainer.search = trainer.search.with_leaf_evaluator_nn(model_path.to_string());
+            let backend = InferenceBackendKind::from_name(&game_config.neuralnet_backend)?;
+            let _ = NeuralNetEvaluator::load_with_backend(model_path, backend)?;
+            trainer.search = trainer.search.with_leaf_evaluator_nn(model_path.to_string(), backend);
         }
         other => {
             return Err(anyhow!(
@@ -137,7 +141,8 @@ async fn main_guard() -> Result<()> {
                         .collect::<Vec<_>>();
                     let inherit = InheritInfo {
                         blue_count: game.inherit.blue_count.clone(),
-                        extra_count: game.inherit.extra_count.clone()
+                        extra_count: game.inherit.extra_count.clone(),
+                        ..Default::default()
                     };
                     info!("- sim 模拟参数: {} {deck:?}, {inherit:?}", game.uma.uma_id);
                 }
@@ -145,21 +150,13 @@ async fn main_guard() -> Result<()> {
                 println!("{}", game.explain_distribution()?);
                 println!("正在计算...");
                 if game.pending_selection {
-                    // 是温泉选择状态
-                    let actions = game.list_actions_onsen_select();
-                    let onsen = trainer.select_action(&game, &actions, &mut rng)?;
-                    println!("{}", format!("蒙特卡洛：{}", actions[onsen]).magenta());
-                    // 前进一步选择升级
-                    game.apply_action(&actions[onsen], &mut rng)?;
-                    let upgradeable = game.get_upgradeable_equipment();
-                    if !upgradeable.is_empty() {
-                        let actions = upgradeable
-                            .iter()
-                            .map(|x| OnsenAction::Upgrade(*x as i32))
-                            .collect::<Vec<_>>();
-                        let upgrade = trainer.select_action(&game, &actions, &mut rng)?;
-                        println!("{}", format!("蒙特卡洛：{}", actions[upgrade]).magenta());
+                    // 是温泉选择状态，温泉和装备升级一起搜索
+                    println!("{}", game.explain_dig_plan().cyan());
+                    let output = trainer.search.search_selection(&game, &mut rng)?;
+                    for (action, result) in output.actions.iter().zip(&output.action_results) {
+                        println!("{action}: {:.0}", result.1.weighted_mean(output.radical_factor));
                     }
+                    println!("{}", format!("蒙特卡洛：{}", output.best_action_2()).magenta());
                 } else {
                     // 如果被解析成 Bathing 但没有温泉券合buff，就直接跳过到 Train
                     if game.stage == OnsenTurnStage::Bathing
@@ -177,6 +174,17 @@ async fn main_guard() -> Result<()> {
                     let action_idx = trainer.select_action(&game, &actions, &mut rng)?;
                     let action = actions[action_idx].clone();
                     println!("{}", format!("蒙特卡洛: {action}").bright_green());
+                    // 温泉券时机分析
+                    if game.stage == OnsenTurnStage::Bathing
+                        && let Some(advice) = game.analyze_ticket_timing(ticket::DEFAULT_HORIZON)
+                    {
+                        println!("{}", advice.explain().cyan());
+                    }
+                    // 自选比赛规划
+                    let race_plan = game.current_race_plan();
+                    if race_plan.has_pending() {
+                        println!("{}", race_plan.explain().cyan());
+                    }
 
                     // 当 mcts 建议 UseTicket(false) 时，直接跳过 Bathing 阶段，继续给出训练推荐。
                     if action == OnsenAction::UseTicket(false) && game.stage == OnsenTurnStage::Bathing {
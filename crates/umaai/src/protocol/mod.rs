@@ -7,7 +7,7 @@ use umasim::{
     game::{BaseGame, BasePerson, FriendOutState, FriendState, InheritInfo, SupportCard, TurnStage, Uma, UmaFlags},
     gamedata::{GAMEDATA, GameConfig},
     global,
-    utils::Array5
+    utils::{Array5, AttributeArray}
 };
 
 pub mod onsen;
@@ -187,9 +187,14 @@ impl GameStatusBase {
         // 1. 先读取配置文件
         let config_file = fs_err::read_to_string("game_config.toml")?;
         let game_config: GameConfig = toml::from_str(&config_file)?;
+        if self.zhongma_blue_count.is_default() && !game_config.inherit_parents.is_empty() {
+            // 小黑板没有提供蓝因子时才使用配置的详细种马
+            return Ok(game_config.inherit_info());
+        }
         Ok(InheritInfo {
             blue_count: self.zhongma_blue_count.clone(),
-            extra_count: game_config.extra_count.clone()
+            extra_count: game_config.extra_count.clone(),
+            ..Default::default()
         })
     }
 
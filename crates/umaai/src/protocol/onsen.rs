@@ -29,7 +29,9 @@ impl From<&BathingStatus> for BathingInfo {
             ticket_num: status.ticket_num,
             buff_remain_turn: status.buff_remain_turn,
             is_super: false,
-            is_super_ready: status.is_super_ready
+            is_super_ready: status.is_super_ready,
+            // 小黑板没有使用次数，Buff生效时至少用过一张
+            used_count: status.buff_remain_turn.signum()
         }
     }
 }
@@ -125,6 +127,8 @@ impl GameStatus for GameStatusOnsen {
             person.is_hint = self.persons[i].is_hint;
             persons.push(person);
         }
+        // 小黑板不提供非剧本友人卡的出行进度，按未点击处理
+        base.init_card_friends(&persons);
         // 添加理事长,记者(记者在12回合才会出现)
         let mut yayoi = BasePerson::yayoi();
         let mut reporter = BasePerson::reporter();
@@ -167,7 +171,8 @@ impl GameStatus for GameStatusOnsen {
             dig_progress,
             scenario_buff: OnsenBuff::default(),
             pending_selection: pending,
-            deck_can_split
+            deck_can_split,
+            race_plan: None
         };
         // 刷新温泉Buff
         ret.update_scenario_buff(true);
@@ -0,0 +1,570 @@
+//! 训练数据集工具
+//!
+//! `stats`：读取 part_*.bin 和 score_mean_values.bin，输出每回合样本数、value 目标直方图、
+//! policy 熵分布、每回合常见动作、choice 样本覆盖和特征块值域（含 NaN/Inf 检查），
+//! 在训练前发现特征提取的问题。
+//!
+//! `merge`：合并多个数据目录，去掉 nn_input 完全相同的样本，按 game_id 把整局划入
+//! train/ 或 val/（避免同一局同时出现在训练集和验证集），边读边写成新的分片；
+//! 样本轮流放入同时填充的多个分片，同一局的样本分散到不同分片，分片写出前再打乱。
+//!
+//! `export`：把分片导出为 .npy 或 safetensors，附带 manifest 和特征布局，Python 端可以直接 mmap 读取。
+//!
+//! `verify`：完整读取每个分片，对照 manifest 检查大小、内容 hash 和样本数，发现截断或损坏的分片；
+//! 加 `--quarantine` 时把坏分片移到 quarantine/，并重建 manifest 和 score_mean_values.bin。
+//!
+//! `replay`：按样本记录的种子和决策序列重新模拟，重建产生样本时的 `OnsenGame`，
+//! 输出局面和候选动作，并检查重新提取的特征是否与样本一致。
+//!
+//! # 用法
+//! ```bash
+//! cargo run --release --bin dataset -- stats --data training_data/mean_filtered_60k
+//! cargo run --release --bin dataset -- merge --data training_data/a --data training_data/b \
+//!     --output training_data/merged --val-ratio 0.05
+//! cargo run --release --bin dataset -- export --data training_data/merged/train \
+//!     --output training_data/merged_npy/train --format npy
+//! cargo run --release --bin dataset -- verify --data training_data/mean_filtered_60k --quarantine
+//! cargo run --release --bin dataset -- replay --data training_data/mean_filtered_60k --index 1234
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    time::Instant
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use clap::{Args, Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressStyle};
+use umasim::{
+    collector::{
+        CollectorManifest,
+        QUARANTINE_DIR,
+        calc_score_mean_summary,
+        compute_text_hash_fnv1a64,
+        load_score_mean_values,
+        quarantine_parts,
+        verify_output_dir
+    },
+    dataset::{
+        DatasetMerger,
+        DatasetReader,
+        DatasetStats,
+        MANIFEST_NAME,
+        MergeOptions,
+        MergedSplit,
+        ReadOrder,
+        SCORE_MEAN_VALUES_NAME
+    },
+    game::onsen::game::OnsenGame,
+    gamedata::{GameConfig, init_global},
+    sample_collector::action_to_global_index,
+    sample_export::{ExportFile, ExportFormat, NpyExporter, write_export_index, write_safetensors},
+    trainer::replay_sample,
+    training_sample::TrainingSampleReader
+};
+
+#[derive(Parser, Debug)]
+#[command(name = "dataset")]
+#[command(about = "训练数据集工具")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 统计样本分布并检查特征
+    Stats(StatsArgs),
+    /// 合并多个数据集，去重并按对局划分训练集和验证集
+    Merge(MergeArgs),
+    /// 导出为 .npy 或 safetensors
+    Export(ExportArgs),
+    /// 校验分片完整性，可隔离损坏的分片
+    Verify(VerifyArgs),
+    /// 从样本的来源记录重建对局状态
+    Replay(ReplayArgs)
+}
+
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// 训练数据，可以是包含 part_*.bin 的目录或单个 .bin 文件，可重复
+    #[arg(long, required = true)]
+    data: Vec<PathBuf>,
+
+    /// 直方图分箱数
+    #[arg(long, default_value = "20")]
+    bins: usize,
+
+    /// 每回合显示的常见动作数
+    #[arg(long, default_value = "3")]
+    top: usize,
+
+    /// 最多统计的样本数（可选）
+    #[arg(long)]
+    max_samples: Option<u64>,
+
+    /// 发现问题时以错误退出
+    #[arg(long)]
+    strict: bool
+}
+
+#[derive(Args, Debug)]
+struct MergeArgs {
+    /// 输入数据，可以是包含 part_*.bin 的目录或单个 .bin 文件，可重复
+    #[arg(long, required = true)]
+    data: Vec<PathBuf>,
+
+    /// 输出目录，结果写入其中的 train/ 和 val/
+    #[arg(long)]
+    output: PathBuf,
+
+    /// 划入验证集的对局比例
+    #[arg(long, default_value = "0.05")]
+    val_ratio: f64,
+
+    /// 每个分片的最大样本数
+    #[arg(long, default_value = "4096")]
+    shard_size: usize,
+
+    /// 每份数据同时填充的分片数，越大同一局的样本分得越散，内存占用为 open_shards * shard_size 个样本
+    #[arg(long, default_value = "16")]
+    open_shards: usize,
+
+    /// 打乱顺序用的随机种子
+    #[arg(long, default_value = "0")]
+    seed: u64,
+
+    /// 保留 nn_input 完全相同的样本
+    #[arg(long)]
+    no_dedup: bool,
+
+    /// 输出分片的 zstd 压缩等级，0 表示不压缩
+    #[arg(long, default_value = "0")]
+    zstd_level: i32,
+
+    /// 清空已存在的输出目录
+    #[arg(long)]
+    overwrite: bool
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// 输入数据，可以是包含 part_*.bin 的目录或单个 .bin 文件
+    #[arg(long)]
+    data: PathBuf,
+
+    /// 输出目录
+    #[arg(long)]
+    output: PathBuf,
+
+    /// 导出格式 npy | safetensors
+    #[arg(long, default_value = "npy")]
+    format: String,
+
+    /// 清空已存在的输出目录
+    #[arg(long)]
+    overwrite: bool
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// 包含 manifest.json 和 part_*.bin 的目录
+    #[arg(long)]
+    data: PathBuf,
+
+    /// 把损坏的分片移到 quarantine/，并重建 manifest 和 score_mean_values.bin
+    #[arg(long)]
+    quarantine: bool
+}
+
+#[derive(Args, Debug)]
+struct ReplayArgs {
+    /// 训练数据，可以是包含 part_*.bin 的目录或单个 .bin 文件
+    #[arg(long)]
+    data: PathBuf,
+
+    /// 样本序号（按分片顺序，从 0 开始）
+    #[arg(long)]
+    index: u64,
+
+    /// 生成数据时使用的配置文件（马娘、支援卡和继承需要一致）
+    #[arg(long, default_value = "game_config.toml")]
+    config: String
+}
+
+/// 检查目录中的 score_mean_values.bin 是否与分片一致
+fn check_score_mean_values(dir: &std::path::Path, samples: u64, warnings: &mut Vec<String>) -> Result<()> {
+    let manifest_path = dir.join(MANIFEST_NAME);
+    let name = if manifest_path.exists() {
+        let manifest = CollectorManifest::load(&manifest_path)?;
+        println!(
+            "{}: manifest 记录 {} 局, {} 个样本, {} 个分片",
+            dir.display(),
+            manifest.progress.games_run,
+            manifest.progress.accepted,
+            manifest.parts.len()
+        );
+        if manifest.collector_config.score_mean_values_name.is_empty() {
+            SCORE_MEAN_VALUES_NAME.to_string()
+        } else {
+            manifest.collector_config.score_mean_values_name
+        }
+    } else {
+        SCORE_MEAN_VALUES_NAME.to_string()
+    };
+    let path = dir.join(name);
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut values = load_score_mean_values(&path)?;
+    if values.len() as u64 != samples {
+        warnings.push(format!(
+            "{} 有 {} 个值，分片中有 {} 个样本",
+            path.display(),
+            values.len(),
+            samples
+        ));
+    }
+    let summary = calc_score_mean_summary(&mut values);
+    println!(
+        "{}: scoreMean min={:?} mean={:?} p50={:?} p90={:?} p99={:?}",
+        path.display(),
+        summary.min.map(|v| v.round() as i64),
+        summary.mean.map(|v| v.round() as i64),
+        summary.p50.map(|v| v.round() as i64),
+        summary.p90.map(|v| v.round() as i64),
+        summary.p99.map(|v| v.round() as i64)
+    );
+    Ok(())
+}
+
+fn sample_progress_bar(len: u64) -> Result<ProgressBar> {
+    let pb = ProgressBar::new(len);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} 个样本 ({eta})")?
+            .progress_chars("#>-")
+    );
+    Ok(pb)
+}
+
+fn run_stats(args: StatsArgs) -> Result<()> {
+    let start = Instant::now();
+    let max_samples = args.max_samples.unwrap_or(u64::MAX);
+    let mut stats = DatasetStats::new();
+    let mut warnings = Vec::new();
+
+    for path in &args.data {
+        let reader = DatasetReader::open(path)?;
+        let pb = sample_progress_bar(reader.len())?;
+        let before = stats.samples;
+        for sample in reader.iter(ReadOrder::Sequential) {
+            if stats.samples >= max_samples {
+                break;
+            }
+            stats.add(&sample?);
+            pb.inc(1);
+        }
+        pb.finish_and_clear();
+        if path.is_dir() && stats.samples - before == reader.len() {
+            check_score_mean_values(path, reader.len(), &mut warnings)?;
+        }
+    }
+    println!();
+    print!("{}", stats.explain(args.bins, args.top));
+
+    warnings.extend(stats.warnings());
+    println!();
+    if warnings.is_empty() {
+        println!("没有发现问题");
+    } else {
+        println!("发现 {} 个问题:", warnings.len());
+        for w in &warnings {
+            println!("  - {w}");
+        }
+    }
+    println!("耗时: {:?}", start.elapsed());
+    if args.strict && !warnings.is_empty() {
+        bail!("数据集检查未通过");
+    }
+    Ok(())
+}
+
+fn run_merge(args: MergeArgs) -> Result<()> {
+    let start = Instant::now();
+    ensure!((0.0..1.0).contains(&args.val_ratio), "--val-ratio 需要在 [0, 1) 内");
+    ensure!(args.shard_size > 0, "--shard-size 需要大于 0");
+    ensure!(args.open_shards > 0, "--open-shards 需要大于 0");
+    if args.output.exists() && !args.overwrite {
+        bail!("输出目录已存在，请换一个目录或使用 --overwrite: {}", args.output.display());
+    }
+
+    let mut merger = DatasetMerger::new(
+        MergeOptions {
+            val_ratio: args.val_ratio,
+            shard_size: args.shard_size,
+            open_shards: args.open_shards,
+            seed: args.seed,
+            dedup: !args.no_dedup,
+            zstd_level: args.zstd_level
+        },
+        &args.output,
+        args.overwrite
+    );
+    println!("写出到 {}", args.output.display());
+    for path in &args.data {
+        let reader = DatasetReader::open(path)?;
+        let manifest_path = path.join(MANIFEST_NAME);
+        let manifest = if path.is_dir() && manifest_path.exists() {
+            Some(CollectorManifest::load(&manifest_path)?)
+        } else {
+            None
+        };
+        merger.begin_source(path, manifest.as_ref());
+        let pb = sample_progress_bar(reader.len())?;
+        let before = merger.samples();
+        for sample in reader.iter(ReadOrder::Sequential) {
+            merger.add_sample(sample?)?;
+            pb.inc(1);
+        }
+        pb.finish_and_clear();
+        println!("{}: 读入 {} 个样本", path.display(), merger.samples() - before);
+    }
+
+    let report = merger.finish()?;
+    println!();
+    println!("去重样本: {}", report.duplicates);
+    if report.inferred_games > 0 {
+        println!("旧数据没有 game_id，按回合号推断了 {} 局", report.inferred_games);
+    }
+    let show = |name: &str, split: &MergedSplit| {
+        println!(
+            "{name}: {} 局, {} 个样本, {} 个分片",
+            split.games, split.samples, split.parts
+        );
+    };
+    show("train", &report.train);
+    show("val", &report.val);
+    println!("耗时: {:?}", start.elapsed());
+    Ok(())
+}
+
+fn run_export(args: ExportArgs) -> Result<()> {
+    let start = Instant::now();
+    let format = ExportFormat::from_name(&args.format)?;
+    let reader = DatasetReader::open(&args.data)?;
+    if args.output.exists() {
+        if !args.overwrite {
+            bail!("输出目录已存在，请换一个目录或使用 --overwrite: {}", args.output.display());
+        }
+        for entry in fs_err::read_dir(&args.output)? {
+            let p = entry?.path();
+            if p.is_file() {
+                fs_err::remove_file(&p)?;
+            }
+        }
+    }
+    fs_err::create_dir_all(&args.output)?;
+
+    let pb = sample_progress_bar(reader.len())?;
+    let exported = match format {
+        ExportFormat::Npy => {
+            let mut exporter = NpyExporter::create(&args.output)?;
+            for sample in reader.iter(ReadOrder::Sequential) {
+                exporter.add(&sample?)?;
+                pb.inc(1);
+            }
+            exporter.finish()?
+        }
+        ExportFormat::Safetensors => {
+            // 每次只读入一个分片
+            let mut exported = Vec::with_capacity(reader.parts().len());
+            for part in reader.parts() {
+                let samples = TrainingSampleReader::open(&part.path)?.collect::<Result<Vec<_>>>()?;
+                let source = part.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                let name = Path::new(&source).with_extension("safetensors");
+                write_safetensors(&args.output.join(&name), &samples, &source)?;
+                exported.push(ExportFile {
+                    name: name.to_string_lossy().to_string(),
+                    source: Some(source),
+                    samples: samples.len() as u64
+                });
+                pb.inc(part.samples);
+            }
+            exported
+        }
+    };
+    pb.finish_and_clear();
+
+    let manifest_path = args.data.join(MANIFEST_NAME);
+    if args.data.is_dir() && manifest_path.exists() {
+        fs_err::copy(&manifest_path, args.output.join(MANIFEST_NAME))?;
+    }
+    let index = write_export_index(&args.output, format, exported)?;
+    println!(
+        "已导出 {} 个样本到 {} ({}，{} 个文件)",
+        index.samples,
+        args.output.display(),
+        format.name(),
+        index.files.len()
+    );
+    println!("耗时: {:?}", start.elapsed());
+    Ok(())
+}
+
+fn run_verify(args: VerifyArgs) -> Result<()> {
+    let start = Instant::now();
+    let manifest_path = args.data.join(MANIFEST_NAME);
+    ensure!(manifest_path.exists(), "找不到 {}", manifest_path.display());
+    let manifest = CollectorManifest::load(&manifest_path)?;
+    let values_name = if manifest.collector_config.score_mean_values_name.is_empty() {
+        SCORE_MEAN_VALUES_NAME.to_string()
+    } else {
+        manifest.collector_config.score_mean_values_name.clone()
+    };
+
+    let pb = ProgressBar::new(manifest.parts.len() as u64);
+    let report = verify_output_dir(&args.data, &manifest, &values_name, |check| {
+        pb.inc(1);
+        if !check.in_manifest {
+            pb.println(format!("{}: 不在 manifest 中，resume 时会被加入", check.name));
+        }
+    })?;
+    pb.finish_and_clear();
+
+    let samples: u64 = report.parts.iter().map(|p| p.samples).sum();
+    println!("{}: {} 个分片, {} 个样本", args.data.display(), report.parts.len(), samples);
+    let bad: Vec<String> = report.bad_parts().map(|p| p.name.clone()).collect();
+    for part in report.bad_parts() {
+        for problem in &part.problems {
+            println!("  {}: {}", part.name, problem);
+        }
+    }
+    if let Some(problem) = &report.score_mean_values {
+        println!("  {problem}");
+    }
+    if report.is_ok() {
+        println!("校验通过，耗时: {:?}", start.elapsed());
+        return Ok(());
+    }
+    if !args.quarantine {
+        bail!("发现 {} 个损坏的分片，使用 --quarantine 隔离并重建 manifest", bad.len());
+    }
+
+    let manifest = quarantine_parts(&args.data, MANIFEST_NAME, &values_name, &bad)?;
+    println!(
+        "已隔离 {} 个分片到 {}，剩余 {} 个分片, {} 个样本",
+        bad.len(),
+        args.data.join(QUARANTINE_DIR).display(),
+        manifest.parts.len(),
+        manifest.progress.accepted
+    );
+    println!("耗时: {:?}", start.elapsed());
+    Ok(())
+}
+
+/// 整局的决策记录只保存在该局最后一个样本中
+fn find_decision_log(reader: &DatasetReader, game_id: u64) -> Result<Vec<u16>> {
+    if game_id == 0 {
+        bail!("样本没有 game_id，找不到所在对局的决策记录");
+    }
+    for sample in reader.iter(ReadOrder::Sequential) {
+        let sample = sample?;
+        if sample.game_id == game_id && !sample.provenance.decisions.is_empty() {
+            return Ok(sample.provenance.decisions);
+        }
+    }
+    bail!("数据中没有 game_id={game_id} 的决策记录（该局最后一个样本可能被过滤或未合并进来）")
+}
+
+fn run_replay(args: ReplayArgs) -> Result<()> {
+    let reader = DatasetReader::open(&args.data)?;
+    let Some((part, offset)) = reader.locate(args.index) else {
+        bail!("样本序号 {} 超出范围，共 {} 个样本", args.index, reader.len());
+    };
+    let sample = reader.iter_from(part, offset)?.next().context("读取样本失败")??;
+    let p = &sample.provenance;
+    println!("样本 #{}: {} 第 {} 个", args.index, reader.parts()[part].path.display(), offset);
+    println!(
+        "game_id={} seed={} game_index={} 回合={} 阶段={:?}",
+        sample.game_id,
+        p.seed,
+        p.game_index,
+        p.turn + 1,
+        p.stage
+    );
+    println!(
+        "可选数={} 模拟次数={} 最优动作标准差={:.0} 决策数={}",
+        p.legal_actions,
+        p.search_count,
+        p.best_stdev,
+        p.decision_index
+    );
+    if p.seed == 0 && p.decision_index == 0 && p.decisions.is_empty() {
+        bail!("样本没有来源记录（样本格式版本 3 之前生成），无法重放");
+    }
+    let log = if p.decisions.len() >= p.decision_index as usize {
+        p.decisions.clone()
+    } else {
+        find_decision_log(&reader, sample.game_id)?
+    };
+
+    let config_text =
+        fs_err::read_to_string(&args.config).with_context(|| format!("读取配置文件失败: {}", args.config))?;
+    let manifest_path = args.data.join(MANIFEST_NAME);
+    if args.data.is_dir() && manifest_path.exists() {
+        let manifest = CollectorManifest::load(&manifest_path)?;
+        if manifest.config_hash_fnv1a64 != compute_text_hash_fnv1a64(&config_text) {
+            println!(
+                "warn: {} 与生成数据时的配置（{}）不一致，马娘、支援卡或继承不同时无法重建",
+                args.config, manifest.config_path
+            );
+        }
+    }
+    let config: GameConfig =
+        toml::from_str(&config_text).with_context(|| format!("解析配置文件失败: {}", args.config))?;
+    init_global()?;
+    let game = OnsenGame::newgame(config.uma, &config.cards, config.inherit_info())?;
+    let point = replay_sample(game, p, &log)?;
+
+    println!();
+    println!("{}", point.game.explain()?);
+    match &point.choices {
+        Some(choices) => {
+            for (i, choice) in choices.iter().enumerate() {
+                let target = sample.choice_target.get(i).copied().unwrap_or_default();
+                println!("选项 {}: {} | choice_target={target:.3}", i + 1, choice.explain());
+            }
+        }
+        None => {
+            for action in &point.actions {
+                let target = action_to_global_index(action).and_then(|g| sample.policy_target.get(g).copied());
+                println!("{action} | policy_target={}", target.map(|t| format!("{t:.3}")).unwrap_or("-".into()));
+            }
+        }
+    }
+    println!(
+        "value_target: scoreMean={:.0} stdev={:.0} value={:.0}",
+        sample.value_target[0], sample.value_target[1], sample.value_target[2]
+    );
+
+    let mismatches = point.mismatches(&sample);
+    if !mismatches.is_empty() {
+        for m in &mismatches {
+            println!("  {m}");
+        }
+        bail!("重建的状态与样本不一致");
+    }
+    println!("重建成功，特征与样本一致");
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Stats(args) => run_stats(args),
+        Command::Merge(args) => run_merge(args),
+        Command::Export(args) => run_export(args),
+        Command::Verify(args) => run_verify(args),
+        Command::Replay(args) => run_replay(args)
+    }
+}
@@ -24,7 +24,7 @@ use std::{
 use anyhow::{Context, Result, anyhow};
 use chrono::Local;
 use clap::Parser;
-use rand::{SeedableRng, rngs::StdRng};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use umasim::{
     collector::{
         CollectorManifest,
@@ -36,10 +36,11 @@ use umasim::{
         load_score_mean_values,
         try_get_git_commit
     },
-    game::{Game, InheritInfo, onsen::game::OnsenGame},
+    game::{Game, onsen::game::OnsenGame},
     gamedata::{GameConfig, init_global},
+    neural::feature_schema::{FEATURE_SCHEMA_FILE, FEATURE_SCHEMA_VERSION, FeatureSchema},
     search::{FlatSearch, SearchConfig},
-    trainer::MeanFilterCollectorTrainer,
+    trainer::{MeanFilterCollectorTrainer, RecordingTrainer},
     utils::init_logger
 };
 
@@ -107,6 +108,10 @@ struct Args {
     #[arg(long)]
     shard_size: Option<usize>,
 
+    /// 分片 zstd 压缩等级，0 表示不压缩（覆盖 [collector].shard_zstd_level）
+    #[arg(long)]
+    zstd_level: Option<i32>,
+
     /// 采样回合范围（覆盖 [collector].turn_min/turn_max/turn_stride；人类回合 1..=78）
     #[arg(long)]
     turn_min: Option<i32>,
@@ -287,6 +292,9 @@ fn main() -> Result<()> {
     if let Some(v) = args.shard_size {
         game_config.collector.shard_size = v;
     }
+    if let Some(v) = args.zstd_level {
+        game_config.collector.shard_zstd_level = v;
+    }
     if let Some(v) = args.turn_min {
         game_config.collector.turn_min = v;
     }
@@ -466,6 +474,7 @@ fn main() -> Result<()> {
     );
     println!("threads               : {}", game_config.collector.threads);
     println!("shard_size            : {}", game_config.collector.shard_size);
+    println!("shard_zstd_level      : {}", game_config.collector.shard_zstd_level);
     println!(
         "resume/overwrite      : {}/{}",
         game_config.collector.resume, game_config.collector.overwrite
@@ -501,9 +510,18 @@ fn main() -> Result<()> {
             ))
         }
     )?;
+    writer.set_zstd_level(game_config.collector.shard_zstd_level);
 
     // 若目录里已有 part 且本次有效配置（尤其 threshold/search_n/turn_range）不一致，直接拒绝继续
     if scan.accepted_written > 0 && !writer.manifest.parts.is_empty() && !game_config.collector.overwrite {
+        if writer.manifest.feature_schema_version != FEATURE_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "输出目录已包含特征布局版本 {} 的数据，当前为 {}。请使用新的 output_dir 或显式 --overwrite。\n- output_dir: {}",
+                writer.manifest.feature_schema_version,
+                FEATURE_SCHEMA_VERSION,
+                output_dir.display()
+            ));
+        }
         // 只做“会改变数据分布/质量”的关键字段比对；允许 threads/progress_interval 等运行参数变化
         let old_c = &writer.manifest.collector_config;
         if (old_c.score_mean_threshold - game_config.collector.score_mean_threshold).abs() > 1e-9
@@ -542,7 +560,10 @@ fn main() -> Result<()> {
         }
     }
 
-    // RNG（仅尽量控制游戏本体随机性）
+    // 特征布局，供 Python 端读取
+    FeatureSchema::current().save_json(&output_dir.join(FEATURE_SCHEMA_FILE))?;
+
+    // RNG：为每局生成种子（仅控制游戏本体随机性）
     let mut rng = match args.seed {
         Some(seed) => StdRng::seed_from_u64(seed),
         None => StdRng::from_os_rng()
@@ -581,10 +602,7 @@ fn main() -> Result<()> {
         && (base_progress.games_run + games_run_delta) < game_config.collector.max_games as u64
     {
         // 创建新局
-        let inherit = InheritInfo {
-            blue_count: game_config.blue_count.clone(),
-            extra_count: game_config.extra_count.clone()
-        };
+        let inherit = game_config.inherit_info();
         let mut game = match OnsenGame::newgame(game_config.uma, &game_config.cards, inherit) {
             Ok(g) => g,
             Err(e) => {
@@ -595,13 +613,15 @@ fn main() -> Result<()> {
         };
 
         // 运行一整局（内部会在达到 target 后不再接收样本，且可切换为快速决策）
-        match game.run_full_game(&trainer, &mut rng) {
+        // 每局单独的种子，样本记录种子和决策序列，可用 replay_sample 重建对局
+        let recorder = RecordingTrainer::new(&trainer, rng.random(), base_progress.games_run + games_run_delta);
+        match game.run_full_game(&recorder, &mut recorder.game_rng()) {
             Ok(()) => {
                 games_run_delta += 1;
 
                 // 每局结束后 drain accepted 样本，统一写盘（避免 IO 混入 select_action 热路径）
                 let drained = trainer.drain_samples();
-                writer.push_samples(drained)?;
+                writer.push_game(drained)?;
 
                 // 更新 manifest（base + delta）
                 let stats = trainer.stats_snapshot();
@@ -15,12 +15,14 @@ use std::time::Instant;
 use anyhow::Result;
 use clap::Parser;
 use indicatif::{ProgressBar, ProgressStyle};
-use rand::{SeedableRng, rngs::StdRng};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use umasim::{
-    game::{Game, InheritInfo, onsen::game::OnsenGame},
+    collector::make_game_id,
+    game::{Game, onsen::game::OnsenGame},
     gamedata::{GameConfig, init_global},
+    neural::feature_schema::{FEATURE_SCHEMA_FILE, FeatureSchema},
     sample_collector::GameSample,
-    trainer::CollectorTrainer,
+    trainer::{CollectorTrainer, RecordingTrainer},
     training_sample::TrainingSampleBatch,
     utils::init_logger
 };
@@ -52,19 +54,19 @@ struct Args {
 }
 
 /// 运行单局游戏并收集样本
-fn run_single_game(trainer: &CollectorTrainer, config: &GameConfig, rng: &mut StdRng) -> Result<GameSample> {
+///
+/// 游戏 RNG 由 `seed` 初始化，样本记录种子和决策序列，可用 replay_sample 重建对局
+fn run_single_game(trainer: &CollectorTrainer, config: &GameConfig, seed: u64, game_index: u64) -> Result<GameSample> {
     // 重置收集器
     trainer.reset();
 
     // 创建游戏
-    let inherit = InheritInfo {
-        blue_count: config.blue_count.clone(),
-        extra_count: config.extra_count.clone()
-    };
+    let inherit = config.inherit_info();
     let mut game = OnsenGame::newgame(config.uma, &config.cards, inherit)?;
 
     // 运行完整游戏
-    game.run_full_game(trainer, rng)?;
+    let recorder = RecordingTrainer::new(trainer, seed, game_index);
+    game.run_full_game(&recorder, &mut recorder.game_rng())?;
 
     // 获取最终分数并完成收集
     let score = game.uma.calc_score();
@@ -109,11 +111,15 @@ fn main() -> Result<()> {
     );
 
     let start = Instant::now();
+    let run_key = chrono::Utc::now().to_rfc3339();
 
     // 主循环：运行模拟
     for i in 0..args.num_games {
-        match run_single_game(&trainer, &config, &mut rng) {
-            Ok(sample) => game_samples.push(sample),
+        match run_single_game(&trainer, &config, rng.random(), i as u64) {
+            Ok(mut sample) => {
+                sample.set_game_id(make_game_id(&run_key, i as u64));
+                game_samples.push(sample);
+            }
             Err(e) => {
                 eprintln!("警告: 第 {} 局模拟失败: {}", i + 1, e);
             }
@@ -171,6 +177,9 @@ fn main() -> Result<()> {
     println!("\n保存数据...");
     let batch = TrainingSampleBatch { samples: all_samples };
     batch.save_binary(&args.output)?;
+    // 特征布局保存在数据文件旁边，供 Python 端读取
+    let schema_path = std::path::Path::new(&args.output).with_file_name(FEATURE_SCHEMA_FILE);
+    FeatureSchema::current().save_json(&schema_path)?;
 
     let file_size = std::fs::metadata(&args.output)?.len();
     println!(
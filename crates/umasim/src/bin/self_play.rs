@@ -0,0 +1,494 @@
+//! Self-play 训练数据生成器
+//!
+//! 用当前模型引导搜索进行对局：FlatSearch（UCB 分配）的 rollout 动作按模型 policy 采样，
+//! max_depth>0 时截断处用模型估值。每个决策的访问次数分布作为 policy_target，
+//! 整局最终分数作为 value_target，分片写盘并记录 manifest。
+//!
+//! 每跑完 `--games-per-generation` 局检查一次模型文件：
+//! - 文件有更新：拷贝快照到输出目录的 models/，模型代数 +1，之后的分片记为新的一代
+//! - 没有更新：开启 `--wait-for-model` 时轮询等待，否则继续用当前模型
+//!
+//! 可以和 train_model 交替运行：
+//! ```bash
+//! cargo run --release --bin self_play -- \
+//!   --model saved_models/native/model.onnx \
+//!   --output-dir training_data/self_play \
+//!   --games-per-generation 200 \
+//!   --wait-for-model
+//! cargo run --release --bin train_model -- \
+//!   --data training_data/self_play \
+//!   --output saved_models/native/model.onnx
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant}
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::Utc;
+use clap::Parser;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use umasim::{
+    collector::{
+        CollectorManifest,
+        FileSignature,
+        ManifestGeneration,
+        ShardWriter,
+        calc_score_mean_summary,
+        compute_file_signature,
+        compute_text_hash_fnv1a64,
+        load_score_mean_values,
+        try_get_git_commit
+    },
+    game::{Game, onsen::game::OnsenGame},
+    gamedata::{GameConfig, init_global},
+    neural::{
+        InferenceBackendKind,
+        feature_schema::{FEATURE_SCHEMA_FILE, FEATURE_SCHEMA_VERSION, FeatureSchema}
+    },
+    search::{FlatSearch, SearchConfig},
+    trainer::{RecordingTrainer, SelfPlayTrainer},
+    utils::init_logger
+};
+
+const MANIFEST_NAME: &str = "manifest.json";
+const SCORE_MEAN_VALUES_NAME: &str = "score_mean_values.bin";
+/// 模型快照所在的子目录
+const MODELS_DIR: &str = "models";
+
+#[derive(Parser, Debug)]
+#[command(name = "self_play")]
+#[command(about = "用当前模型引导搜索自我对局生成训练数据，模型更新后继续下一代")]
+struct Args {
+    /// 配置文件路径
+    #[arg(long, default_value = "game_config.toml")]
+    config: String,
+
+    /// 输出目录
+    #[arg(long, default_value = "training_data/self_play")]
+    output_dir: PathBuf,
+
+    /// 监视的模型文件（默认为配置中的 neuralnet_model_path）
+    #[arg(long)]
+    model: Option<PathBuf>,
+
+    /// 推理后端 tract | dense（默认为配置中的 neuralnet_backend）
+    #[arg(long)]
+    backend: Option<String>,
+
+    /// 每个动作的搜索次数（默认 128）
+    #[arg(long)]
+    search_n: Option<usize>,
+
+    /// rollout 截断深度，>0 时截断处用模型估值（默认为 [mcts].max_depth）
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// 每代模型至少跑多少局后检查模型更新
+    #[arg(long, default_value = "100")]
+    games_per_generation: u64,
+
+    /// 最多使用多少代模型（含第 0 代），达到后跑完该代就结束
+    #[arg(long)]
+    max_generations: Option<u32>,
+
+    /// 最大总局数（含 resume 前已跑的局数）
+    #[arg(long)]
+    max_games: Option<u64>,
+
+    /// 模型没有更新时等待，而不是继续用当前模型
+    #[arg(long)]
+    wait_for_model: bool,
+
+    /// 等待模型时的检查间隔（秒）
+    #[arg(long, default_value = "30")]
+    poll_secs: u64,
+
+    /// 并行线程数（覆盖 [collector].threads）
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// 每片样本数（覆盖 [collector].shard_size）
+    #[arg(long)]
+    shard_size: Option<usize>,
+
+    /// 分片 zstd 压缩等级，0 表示不压缩（覆盖 [collector].shard_zstd_level）
+    #[arg(long)]
+    zstd_level: Option<i32>,
+
+    /// 继续已有输出目录
+    #[arg(long)]
+    resume: bool,
+
+    /// 清空已有输出目录（危险操作）
+    #[arg(long)]
+    overwrite: bool,
+
+    /// 随机种子（仅控制游戏过程，搜索线程 RNG 不可复现）
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// 是否输出更详细的调试信息
+    #[arg(long)]
+    verbose: bool
+}
+
+/// 读取模型文件签名（含 hash），文件不存在时返回 None
+fn model_signature(path: &Path) -> Result<Option<FileSignature>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(Some(compute_file_signature(path, true)?))
+}
+
+/// 模型文件是否与该代不同
+fn is_new_model(sig: &FileSignature, current: Option<&ManifestGeneration>) -> bool {
+    match current {
+        Some(g) => g.model_sig.hash_fnv1a64 != sig.hash_fnv1a64,
+        None => true
+    }
+}
+
+/// 把模型拷贝为输出目录中的新一代快照，能加载才记入 manifest
+///
+/// 快照按代数命名，线程内缓存的模型不会和旧一代混用
+fn accept_model(
+    writer: &mut ShardWriter, output_dir: &Path, model_path: &Path, sig: FileSignature, backend: InferenceBackendKind
+) -> Result<String> {
+    let generation = writer.manifest.generations.last().map(|g| g.generation + 1).unwrap_or(0);
+    let ext = model_path.extension().and_then(|e| e.to_str()).unwrap_or("onnx");
+    let models_dir = output_dir.join(MODELS_DIR);
+    fs_err::create_dir_all(&models_dir)?;
+    let snapshot = models_dir.join(format!("gen_{generation:03}.{ext}"));
+    fs_err::copy(model_path, &snapshot)?;
+    let snapshot = snapshot.to_string_lossy().to_string();
+    if let Err(e) = backend.load(&snapshot) {
+        let _ = fs_err::remove_file(&snapshot);
+        return Err(e.context(format!("模型加载失败: {}", model_path.display())));
+    }
+
+    // 上一代的分片先写完，新分片才记为新的一代
+    writer.finish()?;
+    writer.manifest.generations.push(ManifestGeneration {
+        generation,
+        model_path: snapshot.clone(),
+        model_sig: sig.clone(),
+        started_at: Utc::now().to_rfc3339(),
+        games_run: 0,
+        samples: 0
+    });
+    writer.manifest.model_generation = Some(generation);
+    writer.manifest.model_sig = Some(sig);
+    writer.save_manifest()?;
+    println!("第 {generation} 代模型: {snapshot}");
+    Ok(snapshot)
+}
+
+fn build_trainer(
+    search_config: &SearchConfig, snapshot: &str, backend: InferenceBackendKind, rollout_batch_size: usize,
+    verbose: bool
+) -> SelfPlayTrainer {
+    let search = FlatSearch::new(search_config.clone())
+        .with_leaf_evaluator_nn(snapshot, backend)
+        .with_rollout_policy_nn(snapshot, backend)
+        .with_rollout_batch_size(rollout_batch_size);
+    SelfPlayTrainer::new(search).verbose(verbose)
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let config_file_text =
+        fs_err::read_to_string(&args.config).with_context(|| format!("读取配置文件失败: {}", args.config))?;
+    let mut game_config: GameConfig =
+        toml::from_str(&config_file_text).with_context(|| format!("解析配置文件失败: {}", args.config))?;
+    if let Some(v) = args.threads {
+        game_config.collector.threads = v;
+    }
+    if let Some(v) = args.shard_size {
+        game_config.collector.shard_size = v;
+    }
+    if let Some(v) = args.zstd_level {
+        game_config.collector.shard_zstd_level = v;
+    }
+    game_config.collector.threads = game_config.collector.threads.max(1);
+    game_config.collector.shard_size = game_config.collector.shard_size.max(1);
+    game_config.collector.progress_interval = game_config.collector.progress_interval.max(1);
+    let games_per_generation = args.games_per_generation.max(1);
+
+    init_logger("self_play", &game_config.log_level)?;
+    init_global()?;
+
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(game_config.collector.threads)
+        .build_global()
+    {
+        eprintln!("warn: rayon 全局线程池初始化失败（可能已初始化）：{e}");
+    }
+
+    // 搜索配置：policy 取访问次数，必须用 UCB 分配
+    let mut search_config = SearchConfig::new_game_config(&game_config);
+    search_config.search_n = args.search_n.unwrap_or(128).max(1);
+    if let Some(v) = args.max_depth {
+        search_config.max_depth = v;
+    }
+    search_config.use_ucb = true;
+    search_config.search_group_size = search_config.search_group_size.clamp(1, search_config.search_n);
+
+    let backend_name = args.backend.as_deref().unwrap_or(&game_config.neuralnet_backend);
+    let backend = InferenceBackendKind::from_name(backend_name)?;
+    let model_path = args
+        .model
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(&game_config.neuralnet_model_path));
+    let poll = Duration::from_secs(args.poll_secs.max(1));
+    if !args.wait_for_model && !model_path.is_file() {
+        bail!("模型文件不存在: {}", model_path.display());
+    }
+
+    let output_dir = args.output_dir.clone();
+    let manifest_path = output_dir.join(MANIFEST_NAME);
+    let config_hash = compute_text_hash_fnv1a64(&config_file_text);
+    if output_dir.exists() && args.resume && !args.overwrite && manifest_path.exists() {
+        let old = CollectorManifest::load(&manifest_path)?;
+        if old.config_hash_fnv1a64 != config_hash {
+            bail!(
+                "manifest 的 config_hash 与当前配置不一致（会导致数据混杂）。请使用新的 output_dir，或显式 --overwrite。\n- output_dir: {}",
+                output_dir.display()
+            );
+        }
+    }
+    let repo_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let git_commit = try_get_git_commit(&repo_dir);
+    let mut gamedata_sig = Vec::new();
+    for p in [
+        "gamedata/constants.json",
+        "gamedata/events.json",
+        "gamedata/umaDB.json",
+        "gamedata/cardDB.json"
+    ] {
+        let path = Path::new(p);
+        if path.exists() {
+            let hash = fs_err::metadata(path)
+                .map(|m| m.len() <= 32 * 1024 * 1024)
+                .unwrap_or(false);
+            gamedata_sig.push(compute_file_signature(path, hash)?);
+        }
+    }
+
+    println!("=== Self-play 数据生成器 ===");
+    println!("config                : {}", args.config);
+    println!("output_dir            : {}", output_dir.display());
+    println!("model                 : {}", model_path.display());
+    println!("backend               : {}", backend.name());
+    println!("games_per_generation  : {games_per_generation}");
+    println!("max_generations       : {:?}", args.max_generations);
+    println!("max_games             : {:?}", args.max_games);
+    println!("wait_for_model        : {}", args.wait_for_model);
+    println!("search_n              : {}", search_config.search_n);
+    println!("search_group_size     : {}", search_config.search_group_size);
+    println!("max_depth             : {}", search_config.max_depth);
+    println!("threads               : {}", game_config.collector.threads);
+    println!("shard_size            : {}", game_config.collector.shard_size);
+    println!("shard_zstd_level      : {}", game_config.collector.shard_zstd_level);
+    if search_config.max_depth == 0 {
+        println!("note                  : max_depth=0，rollout 跑到终局，只用到模型 policy");
+    }
+    println!();
+
+    let (mut writer, _scan) = ShardWriter::open_or_create(
+        &output_dir,
+        MANIFEST_NAME,
+        SCORE_MEAN_VALUES_NAME,
+        game_config.collector.shard_size,
+        args.resume,
+        args.overwrite,
+        || {
+            Ok(CollectorManifest::new(
+                &output_dir,
+                git_commit,
+                &args.config,
+                config_hash,
+                gamedata_sig,
+                None,
+                game_config.collector.clone(),
+                &search_config
+            ))
+        }
+    )?;
+    writer.set_zstd_level(game_config.collector.shard_zstd_level);
+    if !writer.manifest.parts.is_empty() && writer.manifest.feature_schema_version != FEATURE_SCHEMA_VERSION {
+        bail!(
+            "输出目录已包含特征布局版本 {} 的数据，当前为 {}。请使用新的 output_dir 或显式 --overwrite。",
+            writer.manifest.feature_schema_version,
+            FEATURE_SCHEMA_VERSION
+        );
+    }
+    FeatureSchema::current().save_json(&output_dir.join(FEATURE_SCHEMA_FILE))?;
+
+    // 第一代：resume 时模型没变就沿用上次的一代
+    let mut snapshot = loop {
+        match model_signature(&model_path)? {
+            Some(sig) if !is_new_model(&sig, writer.manifest.generations.last()) => {
+                let last = writer.manifest.generations.last().expect("generation");
+                println!("沿用第 {} 代模型: {}", last.generation, last.model_path);
+                break last.model_path.clone();
+            }
+            Some(sig) => match accept_model(&mut writer, &output_dir, &model_path, sig, backend) {
+                Ok(v) => break v,
+                Err(e) if args.wait_for_model => eprintln!("warn: {e:#}"),
+                Err(e) => return Err(e)
+            },
+            None if args.wait_for_model => {}
+            None => bail!("模型文件不存在: {}", model_path.display())
+        }
+        println!("等待模型: {}（每 {} 秒检查一次）", model_path.display(), poll.as_secs());
+        thread::sleep(poll);
+    };
+    let mut trainer = build_trainer(
+        &search_config,
+        &snapshot,
+        backend,
+        game_config.mcts.rollout_batch_size,
+        args.verbose
+    );
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng()
+    };
+    let max_games = args.max_games.unwrap_or(u64::MAX);
+    let start = Instant::now();
+    let mut games_this_run: u64 = 0;
+    let mut samples_this_run: u64 = 0;
+
+    while writer.manifest.progress.games_run < max_games {
+        // 这一代跑够了：检查模型更新
+        let generation = writer.manifest.generations.last().expect("generation");
+        if generation.games_run >= games_per_generation
+            && generation.games_run.is_multiple_of(games_per_generation)
+        {
+            if let Some(max) = args.max_generations
+                && generation.generation + 1 >= max
+            {
+                println!("已用满 {max} 代模型");
+                break;
+            }
+            let current = generation.generation;
+            let mut waited = false;
+            let accepted = loop {
+                if let Some(sig) = model_signature(&model_path)?
+                    && is_new_model(&sig, writer.manifest.generations.last())
+                {
+                    match accept_model(&mut writer, &output_dir, &model_path, sig, backend) {
+                        Ok(v) => break Some(v),
+                        Err(e) => eprintln!("warn: {e:#}")
+                    }
+                }
+                if !args.wait_for_model {
+                    break None;
+                }
+                if !waited {
+                    println!(
+                        "第 {current} 代已跑 {} 局，等待新模型: {}（每 {} 秒检查一次）",
+                        writer.manifest.generations.last().map(|g| g.games_run).unwrap_or(0),
+                        model_path.display(),
+                        poll.as_secs()
+                    );
+                    waited = true;
+                }
+                thread::sleep(poll);
+            };
+            if let Some(v) = accepted {
+                snapshot = v;
+                trainer = build_trainer(
+                    &search_config,
+                    &snapshot,
+                    backend,
+                    game_config.mcts.rollout_batch_size,
+                    args.verbose
+                );
+            }
+        }
+
+        let inherit = game_config.inherit_info();
+        let mut game = match OnsenGame::newgame(game_config.uma, &game_config.cards, inherit) {
+            Ok(g) => g,
+            Err(e) => {
+                writer.manifest.progress.games_failed += 1;
+                eprintln!("warn: newgame 失败：{e}");
+                continue;
+            }
+        };
+        // 每局单独的种子，样本记录种子和决策序列，可用 replay_sample 重建对局
+        let recorder = RecordingTrainer::new(&trainer, rng.random(), writer.manifest.progress.games_run);
+        let result = game.run_full_game(&recorder, &mut recorder.game_rng());
+        let stats = trainer.take_stats();
+        writer.manifest.progress.candidates += stats.decisions;
+        writer.manifest.progress.search_errors += stats.search_errors;
+        writer.manifest.progress.dropped += stats.skipped_no_policy;
+        if let Err(e) = result {
+            trainer.discard_game();
+            writer.manifest.progress.games_failed += 1;
+            eprintln!("warn: 模拟失败：{e}");
+            continue;
+        }
+
+        let samples = trainer.finish_game(game.uma().calc_score() as f64);
+        let n = samples.len() as u64;
+        writer.push_game(samples)?;
+        writer.manifest.progress.games_run += 1;
+        writer.manifest.progress.accepted += n;
+        if let Some(g) = writer.manifest.generations.last_mut() {
+            g.games_run += 1;
+            g.samples += n;
+        }
+        games_this_run += 1;
+        samples_this_run += n;
+
+        if games_this_run.is_multiple_of(game_config.collector.progress_interval as u64) {
+            let elapsed = start.elapsed().as_secs_f64();
+            println!(
+                "[gen={} games={}] samples={} (+{}) samples/s={:.2} search_errors={} elapsed={:.1}s",
+                writer.manifest.model_generation.unwrap_or(0),
+                writer.manifest.progress.games_run,
+                writer.manifest.progress.accepted,
+                samples_this_run,
+                if elapsed > 0.0 { samples_this_run as f64 / elapsed } else { 0.0 },
+                writer.manifest.progress.search_errors,
+                elapsed
+            );
+        }
+        writer.save_manifest()?;
+    }
+
+    writer.finish()?;
+    let mut score_means = load_score_mean_values(writer.score_mean_values_path())?;
+    writer.manifest.score_mean = calc_score_mean_summary(&mut score_means);
+    writer.save_manifest()?;
+
+    println!("\n=== 完成 ===");
+    println!("output_dir            : {}", output_dir.display());
+    println!("games_run             : {}", writer.manifest.progress.games_run);
+    println!("games_failed          : {}", writer.manifest.progress.games_failed);
+    println!("samples_total         : {}", writer.accepted_written());
+    for g in &writer.manifest.generations {
+        println!(
+            "generation {:>3}        : games={} samples={} model={}",
+            g.generation, g.games_run, g.samples, g.model_path
+        );
+    }
+    if let Some(stats) = trainer.search().leaf_nn_stats() {
+        println!("nn_model_loads        : {}", stats.model_loads);
+    }
+    println!(
+        "final_score           : mean={:?} p50={:?} p90={:?}",
+        writer.manifest.score_mean.mean.map(|v| v.round() as i64),
+        writer.manifest.score_mean.p50.map(|v| v.round() as i64),
+        writer.manifest.score_mean.p90.map(|v| v.round() as i64)
+    );
+    println!("elapsed               : {:?}", start.elapsed());
+    Ok(())
+}
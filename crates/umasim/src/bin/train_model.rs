@@ -0,0 +1,258 @@
+//! 神经网络训练器
+//!
+//! 流式读取 part_*.bin 分片（经过固定大小的缓冲区打乱，内存占用与数据集大小无关），
+//! 在 CPU 上训练 MLP（policy + choice + value 三个输出头），
+//! 按验证集损失早停，并把最好的模型导出为 ONNX，可由 NeuralNetEvaluator 直接加载。
+//! 验证集按 game_id 划分整局（与 `dataset merge` 的划分一致），同一局的样本不会同时出现在训练集和验证集。
+//! 同时在 ONNX 旁边导出 `.dense` 文件，供 `neuralnet_backend = "dense"` 使用。
+//!
+//! # 用法
+//! ```bash
+//! cargo run --release --bin train_model -- \
+//!     --data training_data \
+//!     --hidden 256,128 \
+//!     --epochs 20 \
+//!     --output saved_models/native/model.onnx
+//! ```
+
+use std::{path::PathBuf, time::Instant};
+
+use anyhow::{Result, bail, ensure};
+use clap::Parser;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use umasim::{
+    dataset::{DatasetReader, ReadOrder, split_unit},
+    neural::{
+        Adam,
+        DENSE_MODEL_EXT,
+        DenseBackend,
+        LossWeights,
+        Mlp,
+        MlpLoss,
+        feature_schema::{ModelMeta, ValueNormalization}
+    },
+    training_sample::TrainingSample
+};
+
+/// 计算验证损失时每次送入模型的样本数
+const EVAL_CHUNK: usize = 4096;
+
+/// 训练器命令行参数
+#[derive(Parser, Debug)]
+#[command(name = "train_model")]
+#[command(about = "用训练数据分片训练神经网络并导出 ONNX")]
+struct Args {
+    /// 训练数据，可以是包含 part_*.bin 的目录或单个 .bin 文件，可重复
+    #[arg(long, required = true)]
+    data: Vec<PathBuf>,
+
+    /// 输出的 ONNX 模型路径
+    #[arg(long, default_value = "saved_models/native/model.onnx")]
+    output: PathBuf,
+
+    /// 隐藏层大小，逗号分隔
+    #[arg(long, value_delimiter = ',', default_value = "256,128")]
+    hidden: Vec<usize>,
+
+    /// 最大训练轮数
+    #[arg(long, default_value = "20")]
+    epochs: usize,
+
+    /// 批大小
+    #[arg(long, default_value = "256")]
+    batch_size: usize,
+
+    /// 学习率
+    #[arg(long, default_value = "0.001")]
+    lr: f32,
+
+    /// 划入验证集的对局比例
+    #[arg(long, default_value = "0.1")]
+    val_ratio: f64,
+
+    /// 验证损失连续多少轮没有改善时停止
+    #[arg(long, default_value = "3")]
+    patience: usize,
+
+    /// 每轮最多使用的训练样本数（可选）
+    #[arg(long)]
+    max_samples: Option<usize>,
+
+    /// 打乱缓冲区的样本数
+    #[arg(long, default_value = "65536")]
+    shuffle_buffer: usize,
+
+    /// policy 损失权重
+    #[arg(long, default_value = "1.0")]
+    policy_weight: f32,
+
+    /// choice 损失权重
+    #[arg(long, default_value = "1.0")]
+    choice_weight: f32,
+
+    /// value 损失权重
+    #[arg(long, default_value = "1.0")]
+    value_weight: f32,
+
+    /// 随机种子（可选）
+    #[arg(long)]
+    seed: Option<u64>
+}
+
+/// 按 game_id 划入验证集的样本，没有 game_id 的旧样本无法按局划分，全部用于训练
+fn is_val(sample: &TrainingSample, val_ratio: f64) -> bool {
+    sample.game_id != 0 && split_unit(sample.game_id) < val_ratio
+}
+
+/// 用一批样本更新一次参数，返回这批样本的损失
+fn train_batch(
+    model: &mut Mlp, adam: &mut Adam, batch: &[TrainingSample], weights: &LossWeights, norm: &ValueNormalization
+) -> MlpLoss {
+    let refs: Vec<_> = batch.iter().collect();
+    let (grad, loss) = model.batch_grad(&refs, weights, norm);
+    adam.update(model, &grad, refs.len());
+    loss
+}
+
+/// 顺序读一遍数据集，计算验证集损失，返回损失和验证样本数
+fn evaluate(
+    model: &Mlp, reader: &DatasetReader, val_ratio: f64, weights: &LossWeights, norm: &ValueNormalization
+) -> Result<(MlpLoss, usize)> {
+    let mut loss = MlpLoss::default();
+    let mut count = 0;
+    let mut chunk = Vec::with_capacity(EVAL_CHUNK);
+    let flush = |chunk: &mut Vec<TrainingSample>, loss: &mut MlpLoss| {
+        let refs: Vec<_> = chunk.iter().collect();
+        *loss = std::mem::take(loss).merge(model.evaluate(&refs, weights, norm));
+        chunk.clear();
+    };
+    for sample in reader.iter(ReadOrder::Sequential) {
+        let sample = sample?;
+        if !is_val(&sample, val_ratio) {
+            continue;
+        }
+        count += 1;
+        chunk.push(sample);
+        if chunk.len() >= EVAL_CHUNK {
+            flush(&mut chunk, &mut loss);
+        }
+    }
+    if !chunk.is_empty() {
+        flush(&mut chunk, &mut loss);
+    }
+    Ok((loss, count))
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    ensure!(args.batch_size > 0, "batch_size 必须大于 0");
+    ensure!((0.0..1.0).contains(&args.val_ratio), "val_ratio 必须在 [0, 1) 之间");
+
+    println!("=== 神经网络训练器 ===");
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng()
+    };
+
+    // 只读取分片的文件头，样本在每轮训练时流式读取
+    let start = Instant::now();
+    let reader = DatasetReader::open_all(&args.data)?;
+    for part in reader.parts() {
+        println!("{}: {} 个样本", part.path.display(), part.samples);
+    }
+    ensure!(reader.len() >= 2, "样本太少: {}", reader.len());
+    println!("样本总数: {}", reader.len());
+    println!("隐藏层: {:?}", args.hidden);
+    println!();
+
+    let weights = LossWeights {
+        policy: args.policy_weight,
+        choice: args.choice_weight,
+        value: args.value_weight
+    };
+    let norm = ValueNormalization::default();
+    let mut model = Mlp::new(&args.hidden, &mut rng);
+    let mut adam = Adam::new(&model, args.lr);
+    let mut best: Option<(f64, usize, Mlp)> = None;
+
+    for epoch in 1..=args.epochs {
+        let pb = ProgressBar::new(reader.len());
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")?
+                .progress_chars("#>-")
+        );
+        let order = ReadOrder::Shuffled {
+            seed: rng.random(),
+            buffer: args.shuffle_buffer
+        };
+        let mut train_loss = MlpLoss::default();
+        let mut trained = 0;
+        let mut legacy = 0;
+        let mut batch = Vec::with_capacity(args.batch_size);
+        for sample in reader.iter(order) {
+            let sample = sample?;
+            pb.inc(1);
+            if is_val(&sample, args.val_ratio) {
+                continue;
+            }
+            if args.max_samples.is_some_and(|max| trained >= max) {
+                break;
+            }
+            trained += 1;
+            legacy += (sample.game_id == 0) as usize;
+            batch.push(sample);
+            if batch.len() >= args.batch_size {
+                train_loss = train_loss.merge(train_batch(&mut model, &mut adam, &batch, &weights, &norm));
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            train_loss = train_loss.merge(train_batch(&mut model, &mut adam, &batch, &weights, &norm));
+        }
+        pb.finish_and_clear();
+        ensure!(trained > 0, "没有训练样本，val_ratio 太大或对局太少");
+
+        let (val_loss, val_count) = evaluate(&model, &reader, args.val_ratio, &weights, &norm)?;
+        if epoch == 1 {
+            println!("训练样本: {trained}");
+            println!("验证样本: {val_count}");
+            if legacy > 0 {
+                println!("warn: {legacy} 个样本没有 game_id，全部划入训练集");
+            }
+        }
+        // 没有验证集时用训练损失早停
+        let val_loss = if val_count == 0 { train_loss } else { val_loss };
+        let total = val_loss.total(&weights);
+        println!(
+            "第 {epoch} 轮: 训练 {:.4} ({}) | 验证 {total:.4} ({})",
+            train_loss.total(&weights),
+            train_loss.explain(),
+            val_loss.explain()
+        );
+        match &best {
+            Some((best_loss, best_epoch, _)) if total >= *best_loss => {
+                if epoch - best_epoch >= args.patience {
+                    println!("验证损失 {} 轮没有改善，提前停止", args.patience);
+                    break;
+                }
+            }
+            _ => best = Some((total, epoch, model.clone()))
+        }
+    }
+
+    let Some((best_loss, best_epoch, best_model)) = best else {
+        bail!("没有完成任何一轮训练");
+    };
+    best_model.export_onnx(&args.output, &ModelMeta::current())?;
+    // 同时导出 dense 后端使用的权重文件
+    let dense_path = args.output.with_extension(DENSE_MODEL_EXT);
+    DenseBackend::new(best_model.layers, ModelMeta::current())?.save(&dense_path)?;
+    println!();
+    println!("最佳模型: 第 {best_epoch} 轮, 验证损失 {best_loss:.4}");
+    println!("已导出: {}", args.output.display());
+    println!("已导出: {}", dense_path.display());
+    println!("总耗时: {:?}", start.elapsed());
+    Ok(())
+}
@@ -7,7 +7,7 @@
 
 use std::{
     ffi::OsStr,
-    io::{BufWriter, Write},
+    io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
     time::UNIX_EPOCH
 };
@@ -18,8 +18,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     gamedata::CollectorConfig,
+    neural::feature_schema::FEATURE_SCHEMA_VERSION,
     search::SearchConfig,
-    training_sample::{TrainingSample, TrainingSampleBatch}
+    training_sample::{TrainingSample, TrainingSampleBatch, TrainingSampleReader}
 };
 
 // ============================================================================
@@ -34,7 +35,7 @@ pub struct FileSignature {
     pub hash_fnv1a64: Option<String>
 }
 
-fn fnv1a64(bytes: &[u8]) -> u64 {
+pub(crate) fn fnv1a64(bytes: &[u8]) -> u64 {
     // FNV-1a 64-bit
     let mut hash: u64 = 0xcbf29ce484222325;
     for &b in bytes {
@@ -72,6 +73,13 @@ pub fn compute_text_hash_fnv1a64(text: &str) -> String {
     hex_u64(fnv1a64(text.as_bytes()))
 }
 
+/// 由运行标识（如 manifest 的 created_at）和局序号计算对局编号
+///
+/// 不同运行的编号基本不会重复，合并数据集时可以直接按编号分组；结果不为 0（0 表示未知）
+pub fn make_game_id(run_key: &str, game_index: u64) -> u64 {
+    fnv1a64(format!("{run_key}#{game_index}").as_bytes()).max(1)
+}
+
 pub fn try_get_git_commit(repo_dir: &Path) -> Option<String> {
     let out = std::process::Command::new("git")
         .arg("rev-parse")
@@ -184,12 +192,48 @@ pub struct ManifestScoreMeanSummary {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ManifestPart {
     pub name: String,
-    pub samples: usize
+    pub samples: usize,
+    /// 生成该分片的模型代数（仅 self-play）
+    #[serde(default)]
+    pub model_generation: Option<u32>,
+    /// 文件大小和内容 hash（写入时计算，旧 manifest 没有记录）
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub hash_fnv1a64: Option<String>
+}
+
+/// self-play 使用过的一代模型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestGeneration {
+    pub generation: u32,
+    /// 输出目录中的模型快照
+    pub model_path: String,
+    /// 来源模型文件的签名（含 hash，用于判断模型是否更新）
+    pub model_sig: FileSignature,
+    pub started_at: String,
+    pub games_run: u64,
+    pub samples: u64
+}
+
+/// 合并数据集的一个来源
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestSource {
+    pub path: String,
+    /// 来源 manifest 的创建时间和配置 hash（单文件来源为 None）
+    pub created_at: Option<String>,
+    pub config_hash_fnv1a64: Option<String>,
+    pub samples: u64,
+    /// 与已读取样本 nn_input 完全相同而被去掉的样本数
+    pub duplicates: u64
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CollectorManifest {
     pub version: u32,
+    /// 样本的特征布局版本，旧manifest没有记录时为1
+    #[serde(default = "default_feature_schema_version")]
+    pub feature_schema_version: u32,
     pub created_at: String,
     pub updated_at: String,
 
@@ -210,7 +254,21 @@ pub struct CollectorManifest {
     pub progress: ManifestProgress,
     pub per_turn: ManifestPerTurn,
     pub score_mean: ManifestScoreMeanSummary,
-    pub parts: Vec<ManifestPart>
+    pub parts: Vec<ManifestPart>,
+
+    // self-play：当前模型代数与历史（其他生成器为空）
+    #[serde(default)]
+    pub model_generation: Option<u32>,
+    #[serde(default)]
+    pub generations: Vec<ManifestGeneration>,
+
+    // dataset merge：合并来源（其他生成器为空）
+    #[serde(default)]
+    pub sources: Vec<ManifestSource>
+}
+
+fn default_feature_schema_version() -> u32 {
+    1
 }
 
 impl CollectorManifest {
@@ -222,6 +280,7 @@ impl CollectorManifest {
         let now = Utc::now().to_rfc3339();
         Self {
             version: 2,
+            feature_schema_version: FEATURE_SCHEMA_VERSION,
             created_at: now.clone(),
             updated_at: now,
             output_dir: output_dir.to_string_lossy().to_string(),
@@ -264,7 +323,10 @@ impl CollectorManifest {
                 p90: None,
                 p99: None
             },
-            parts: Vec::new()
+            parts: Vec::new(),
+            model_generation: None,
+            generations: Vec::new(),
+            sources: Vec::new()
         }
     }
 
@@ -347,10 +409,45 @@ pub fn scan_part_files(output_dir: &Path) -> Result<Vec<(usize, PathBuf)>> {
     Ok(ret)
 }
 
+/// 检查分片文件大小是否与 manifest 一致（只读元信息），resume 时发现截断的分片
+fn check_part_size(path: &Path, part: &ManifestPart) -> Result<()> {
+    let Some(size) = part.size else {
+        return Ok(());
+    };
+    let actual = fs_err::metadata(path)?.len();
+    if actual != size {
+        return Err(anyhow!(
+            "分片 {} 大小为 {}，manifest 记录为 {}，可能已损坏。请先运行 `dataset verify --data {} --quarantine`",
+            path.display(),
+            actual,
+            size,
+            path.parent().unwrap_or(Path::new(".")).display()
+        ));
+    }
+    Ok(())
+}
+
 fn load_part_sample_count(path: &Path) -> Result<usize> {
-    let batch = TrainingSampleBatch::load_binary(path.to_string_lossy().as_ref())
-        .with_context(|| format!("读取 part 失败: {}", path.display()))?;
-    Ok(batch.samples.len())
+    // 只读取文件头中的样本数
+    let reader = TrainingSampleReader::open(path).with_context(|| format!("读取 part 失败: {}", path.display()))?;
+    Ok(reader.len() as usize)
+}
+
+/// 按 manifest 的分片顺序逐个读取样本，收集 scoreMean
+fn rebuild_score_mean_values(output_dir: &Path, manifest: &CollectorManifest, capacity: u64) -> Result<Vec<f32>> {
+    let mut rebuilt: Vec<f32> = Vec::with_capacity(capacity as usize);
+    for part in &manifest.parts {
+        let path = output_dir.join(&part.name);
+        let reader = TrainingSampleReader::open(&path)
+            .with_context(|| format!("重建 score_mean_values 读取 part 失败: {}", path.display()))?;
+        for s in reader {
+            let s = s.with_context(|| format!("重建 score_mean_values 读取 part 失败: {}", path.display()))?;
+            if let Some(v) = s.value_target.first() {
+                rebuilt.push(*v);
+            }
+        }
+    }
+    Ok(rebuilt)
 }
 
 pub fn scan_existing_parts(output_dir: &Path, manifest: Option<&CollectorManifest>) -> Result<ExistingPartsScan> {
@@ -368,6 +465,9 @@ pub fn scan_existing_parts(output_dir: &Path, manifest: Option<&CollectorManifes
     if let Some(m) = manifest {
         let names_in_manifest: Vec<String> = m.parts.iter().map(|p| p.name.clone()).collect();
         if names_in_manifest == names_on_disk {
+            for (part, (_, path)) in m.parts.iter().zip(&files) {
+                check_part_size(path, part)?;
+            }
             let accepted_written: u64 = m.parts.iter().map(|p| p.samples as u64).sum();
             let next_part_index = files.last().map(|(i, _)| i + 1).unwrap_or(0);
             return Ok(ExistingPartsScan {
@@ -383,9 +483,26 @@ pub fn scan_existing_parts(output_dir: &Path, manifest: Option<&CollectorManifes
     let mut accepted_written: u64 = 0;
     for (idx, path) in files {
         let samples = load_part_sample_count(&path)?;
+        let name = format!("part_{:06}.bin", idx);
+        let old = manifest.and_then(|m| m.parts.iter().find(|p| p.name == name));
+        // 模型代数只能从旧 manifest 里找回；大小和 hash 沿用旧记录，新发现的分片重新计算
+        let model_generation = old.and_then(|p| p.model_generation);
+        let (size, hash_fnv1a64) = match old {
+            Some(p) if p.hash_fnv1a64.is_some() => {
+                check_part_size(&path, p)?;
+                (p.size, p.hash_fnv1a64.clone())
+            }
+            _ => {
+                let sig = compute_file_signature(&path, true)?;
+                (Some(sig.size), sig.hash_fnv1a64)
+            }
+        };
         parts.push(ManifestPart {
-            name: format!("part_{:06}.bin", idx),
-            samples
+            name,
+            samples,
+            model_generation,
+            size,
+            hash_fnv1a64
         });
         accepted_written += samples as u64;
     }
@@ -412,6 +529,8 @@ pub struct ShardWriter {
 
     current_shard: Vec<TrainingSample>,
     current_score_means: Vec<f32>,
+    /// 分片的 zstd 压缩级别，0 表示不压缩
+    zstd_level: i32,
 
     pub manifest: CollectorManifest
 }
@@ -469,6 +588,7 @@ impl ShardWriter {
             next_part_index: scan.next_part_index,
             current_shard: Vec::with_capacity(shard_size.max(1)),
             current_score_means: Vec::with_capacity(shard_size.max(1)),
+            zstd_level: 0,
             manifest
         };
 
@@ -483,6 +603,11 @@ impl ShardWriter {
         &self.score_mean_values_path
     }
 
+    /// 设置之后写入分片的 zstd 压缩级别，0 表示不压缩（已有分片不受影响）
+    pub fn set_zstd_level(&mut self, level: i32) {
+        self.zstd_level = level;
+    }
+
     pub fn push_samples(&mut self, samples: Vec<TrainingSample>) -> Result<()> {
         for sample in samples {
             let score_mean = sample.value_target.first().copied().unwrap_or(0.0);
@@ -495,6 +620,17 @@ impl ShardWriter {
         Ok(())
     }
 
+    /// 写入一整局的样本，并按 manifest 中的局数给样本分配 game_id
+    ///
+    /// 需要在 `progress.games_run` 计入本局之前调用
+    pub fn push_game(&mut self, mut samples: Vec<TrainingSample>) -> Result<()> {
+        let game_id = make_game_id(&self.manifest.created_at, self.manifest.progress.games_run);
+        for sample in &mut samples {
+            sample.game_id = game_id;
+        }
+        self.push_samples(samples)
+    }
+
     pub fn finish(&mut self) -> Result<()> {
         if !self.current_shard.is_empty() {
             self.flush_shard()?;
@@ -520,26 +656,33 @@ impl ShardWriter {
         let batch = TrainingSampleBatch { samples: shard_samples };
 
         // 先写 part（失败则恢复内存缓冲）
-        if let Err(e) = (|| -> Result<()> {
-            write_batch_binary(&tmp_path, &batch)?;
+        let written = (|| -> Result<(u64, String)> {
+            let written = write_batch_binary(&tmp_path, &batch, self.zstd_level)?;
             fs_err::rename(&tmp_path, &final_path)
                 .with_context(|| format!("重命名 part 失败: {} -> {}", tmp_path.display(), final_path.display()))?;
-            Ok(())
-        })() {
-            // 尽量清理 tmp 文件（忽略错误）
-            let _ = fs_err::remove_file(&tmp_path);
-            // 恢复内存缓冲，便于上层决定是否重试/退出
-            self.current_shard = batch.samples;
-            self.current_score_means = shard_score_means;
-            return Err(e);
-        }
+            Ok(written)
+        })();
+        let (size, hash) = match written {
+            Ok(v) => v,
+            Err(e) => {
+                // 尽量清理 tmp 文件（忽略错误）
+                let _ = fs_err::remove_file(&tmp_path);
+                // 恢复内存缓冲，便于上层决定是否重试/退出
+                self.current_shard = batch.samples;
+                self.current_score_means = shard_score_means;
+                return Err(e);
+            }
+        };
 
         // part 写入成功后，再追加 score_mean_values（append-only）
         append_f32_values(&self.score_mean_values_path, &shard_score_means)?;
 
         self.manifest.parts.push(ManifestPart {
             name: part_name,
-            samples: batch.samples.len()
+            samples: batch.samples.len(),
+            model_generation: self.manifest.model_generation,
+            size: Some(size),
+            hash_fnv1a64: Some(hash)
         });
         self.next_part_index += 1;
         Ok(())
@@ -551,13 +694,18 @@ impl ShardWriter {
     }
 }
 
-fn write_batch_binary(path: &Path, batch: &TrainingSampleBatch) -> Result<()> {
-    let file = fs_err::File::create(path).with_context(|| format!("创建文件失败: {}", path.display()))?;
-    let mut writer = BufWriter::new(file);
-    bincode::serialize_into(&mut writer, batch).with_context(|| "bincode 写入失败")?;
-    writer.flush().with_context(|| "flush part 失败")?;
-    writer.get_ref().sync_all().ok();
-    Ok(())
+/// 写入分片（可选 zstd 压缩），返回文件大小和内容 hash
+fn write_batch_binary(path: &Path, batch: &TrainingSampleBatch, zstd_level: i32) -> Result<(u64, String)> {
+    let mut bytes = Vec::new();
+    batch.write_binary(&mut bytes).with_context(|| "bincode 写入失败")?;
+    if zstd_level != 0 {
+        bytes = zstd::bulk::compress(&bytes, zstd_level).with_context(|| "zstd 压缩失败")?;
+    }
+    let mut file = fs_err::File::create(path).with_context(|| format!("创建文件失败: {}", path.display()))?;
+    file.write_all(&bytes)?;
+    file.flush().with_context(|| "flush part 失败")?;
+    file.sync_all().ok();
+    Ok((bytes.len() as u64, hex_u64(fnv1a64(&bytes))))
 }
 
 fn append_f32_values(path: &Path, values: &[f32]) -> Result<()> {
@@ -608,17 +756,7 @@ fn align_score_mean_values(
         }
 
         // values 缺失但已有历史 part：直接重建
-        let mut rebuilt: Vec<f32> = Vec::with_capacity(accepted_written as usize);
-        for part in &manifest.parts {
-            let path = output_dir.join(&part.name);
-            let batch = TrainingSampleBatch::load_binary(path.to_string_lossy().as_ref())
-                .with_context(|| format!("重建 score_mean_values 读取 part 失败: {}", path.display()))?;
-            for s in batch.samples {
-                if let Some(v) = s.value_target.first() {
-                    rebuilt.push(*v);
-                }
-            }
-        }
+        let rebuilt = rebuild_score_mean_values(output_dir, manifest, accepted_written)?;
 
         let tmp_path = PathBuf::from(format!("{}.tmp", values_path.display()));
         let mut f = fs_err::File::create(&tmp_path)?;
@@ -651,17 +789,7 @@ fn align_score_mean_values(
     }
 
     // values 落后：从 part 文件重建（较慢，但保证正确）
-    let mut rebuilt: Vec<f32> = Vec::with_capacity(accepted_written as usize);
-    for part in &manifest.parts {
-        let path = output_dir.join(&part.name);
-        let batch = TrainingSampleBatch::load_binary(path.to_string_lossy().as_ref())
-            .with_context(|| format!("重建 score_mean_values 读取 part 失败: {}", path.display()))?;
-        for s in batch.samples {
-            if let Some(v) = s.value_target.first() {
-                rebuilt.push(*v);
-            }
-        }
-    }
+    let rebuilt = rebuild_score_mean_values(output_dir, manifest, accepted_written)?;
 
     // 重写 values 文件
     let tmp_path = PathBuf::from(format!("{}.tmp", values_path.display()));
@@ -680,6 +808,192 @@ fn align_score_mean_values(
     Ok(())
 }
 
+// ============================================================================
+// 分片校验与隔离
+// ============================================================================
+
+/// 隔离坏分片的子目录（输出目录内）
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// 一个分片的校验结果
+#[derive(Debug, Clone)]
+pub struct PartCheck {
+    pub name: String,
+    /// 是否记录在 manifest 中（不在 manifest 中的分片 resume 时会被加入）
+    pub in_manifest: bool,
+    /// 成功读取的样本数
+    pub samples: u64,
+    /// 发现的问题，为空表示正常
+    pub problems: Vec<String>
+}
+
+impl PartCheck {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// 检查分片的大小和内容 hash，一致时再完整读取，检查样本数和样本维度
+pub fn verify_part(path: &Path, part: Option<&ManifestPart>) -> PartCheck {
+    let mut check = PartCheck {
+        name: path.file_name().and_then(OsStr::to_str).unwrap_or_default().to_string(),
+        in_manifest: part.is_some(),
+        samples: 0,
+        problems: Vec::new()
+    };
+    if !path.exists() {
+        check.problems.push("文件不存在".to_string());
+        return check;
+    }
+    if let Some(p) = part {
+        match compute_file_signature(path, p.hash_fnv1a64.is_some()) {
+            Ok(sig) => {
+                if let Some(size) = p.size
+                    && size != sig.size
+                {
+                    check.problems.push(format!("大小 {} 与 manifest 记录的 {} 不一致", sig.size, size));
+                }
+                if p.hash_fnv1a64.is_some() && sig.hash_fnv1a64 != p.hash_fnv1a64 {
+                    check.problems.push("内容 hash 与 manifest 不一致".to_string());
+                }
+            }
+            Err(e) => check.problems.push(format!("读取失败: {e:#}"))
+        }
+        // 文件已经和 manifest 不一致，不用再解码
+        if !check.is_ok() {
+            return check;
+        }
+    }
+
+    let mut reader = match TrainingSampleReader::open(path) {
+        Ok(v) => v,
+        Err(e) => {
+            check.problems.push(format!("文件头无效: {e:#}"));
+            return check;
+        }
+    };
+    let expected = reader.len();
+    loop {
+        match reader.read_sample() {
+            Ok(Some(_)) => check.samples += 1,
+            Ok(None) => break,
+            Err(e) => {
+                // 解码失败（文件可能被截断）或维度错误
+                check
+                    .problems
+                    .push(format!("第 {}/{} 个样本读取失败: {e:#}", check.samples, expected));
+                return check;
+            }
+        }
+    }
+    match reader.into_inner().read(&mut [0u8; 1]) {
+        Ok(0) => {}
+        Ok(_) => check.problems.push("样本之后还有多余数据".to_string()),
+        Err(e) => check.problems.push(format!("读取样本之后的数据失败: {e:#}"))
+    }
+    if let Some(p) = part
+        && p.samples as u64 != expected
+    {
+        check
+            .problems
+            .push(format!("样本数 {} 与 manifest 记录的 {} 不一致", expected, p.samples));
+    }
+    check
+}
+
+/// 输出目录的校验结果
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    /// manifest 中的分片在前，之后是目录中不在 manifest 里的分片
+    pub parts: Vec<PartCheck>,
+    /// score_mean_values 与 manifest 不一致时的说明
+    pub score_mean_values: Option<String>
+}
+
+impl VerifyReport {
+    pub fn bad_parts(&self) -> impl Iterator<Item = &PartCheck> {
+        self.parts.iter().filter(|p| !p.is_ok())
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.bad_parts().next().is_none() && self.score_mean_values.is_none()
+    }
+}
+
+/// 校验 manifest 中的所有分片，以及目录中不在 manifest 里的分片；每校验完一个分片调用一次 on_part
+pub fn verify_output_dir(
+    output_dir: &Path, manifest: &CollectorManifest, score_mean_values_name: &str, mut on_part: impl FnMut(&PartCheck)
+) -> Result<VerifyReport> {
+    let mut parts = Vec::new();
+    for part in &manifest.parts {
+        let check = verify_part(&output_dir.join(&part.name), Some(part));
+        on_part(&check);
+        parts.push(check);
+    }
+    for (_, path) in scan_part_files(output_dir)? {
+        let name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        if manifest.parts.iter().any(|p| p.name == name) {
+            continue;
+        }
+        let check = verify_part(&path, None);
+        on_part(&check);
+        parts.push(check);
+    }
+
+    let values_path = output_dir.join(score_mean_values_name);
+    let expected: u64 = manifest.parts.iter().map(|p| p.samples as u64).sum();
+    let score_mean_values = if values_path.exists() {
+        let len = load_score_mean_values(&values_path)?.len() as u64;
+        (len != expected).then(|| format!("{} 有 {} 个值，manifest 记录 {} 个样本", values_path.display(), len, expected))
+    } else {
+        (expected > 0).then(|| format!("{} 不存在", values_path.display()))
+    };
+    Ok(VerifyReport {
+        parts,
+        score_mean_values
+    })
+}
+
+/// 把分片移到 output_dir/quarantine/，再按剩余文件重建 manifest 的分片列表和 score_mean_values
+///
+/// manifest 中其他统计（局数、候选数等）保持不变
+pub fn quarantine_parts(
+    output_dir: &Path, manifest_name: &str, score_mean_values_name: &str, names: &[String]
+) -> Result<CollectorManifest> {
+    let quarantine_dir = output_dir.join(QUARANTINE_DIR);
+    fs_err::create_dir_all(&quarantine_dir)?;
+    for name in names {
+        let src = output_dir.join(name);
+        if !src.exists() {
+            continue;
+        }
+        let mut dst = quarantine_dir.join(name);
+        if dst.exists() {
+            dst = quarantine_dir.join(format!("{name}.{}", Utc::now().format("%Y%m%d_%H%M%S")));
+        }
+        fs_err::rename(&src, &dst)?;
+    }
+
+    let manifest_path = output_dir.join(manifest_name);
+    let mut manifest = CollectorManifest::load(&manifest_path)?;
+    let scan = scan_existing_parts(output_dir, Some(&manifest))?;
+    manifest.parts = scan.parts;
+    manifest.progress.accepted = scan.accepted_written;
+
+    // score_mean_values 按分片顺序追加，中间的分片被移走后只能整体重建
+    let values_path = output_dir.join(score_mean_values_name);
+    if values_path.exists() {
+        fs_err::remove_file(&values_path)?;
+    }
+    align_score_mean_values(&values_path, output_dir, &manifest, scan.accepted_written)?;
+    let mut values = load_score_mean_values(&values_path)?;
+    manifest.score_mean = calc_score_mean_summary(&mut values);
+
+    manifest.touch_updated_at();
+    manifest.save_replace(&manifest_path)?;
+    Ok(manifest)
+}
+
 // ============================================================================
 // 分位数计算（基于 score_mean_values.bin 或内存 Vec）
 // ============================================================================
@@ -716,3 +1030,44 @@ pub fn calc_score_mean_summary(values: &mut [f32]) -> ManifestScoreMeanSummary {
         p99: Some(p(0.99))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dataset::{MANIFEST_NAME, SCORE_MEAN_VALUES_NAME},
+        test_utils::TempDir,
+        training_sample::ZSTD_MAGIC
+    };
+
+    #[test]
+    fn test_verify_and_quarantine() {
+        let temp = TempDir::new("verify");
+        let dir = temp.path();
+        let (manifest_name, values_name) = (MANIFEST_NAME, SCORE_MEAN_VALUES_NAME);
+        let manifest = temp.write_shards(10, 3, 3);
+        assert_eq!(manifest.parts.len(), 4);
+        let bad = dir.join(&manifest.parts[1].name);
+        assert_eq!(fs_err::read(&bad).unwrap()[..4], ZSTD_MAGIC);
+        assert!(verify_output_dir(dir, &manifest, values_name, |_| {}).unwrap().is_ok());
+
+        // 截断第二个分片：resume 时直接报错，verify 能定位到它
+        let bytes = fs_err::read(&bad).unwrap();
+        fs_err::write(&bad, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(
+            ShardWriter::open_or_create(dir, manifest_name, values_name, 3, true, false, || unreachable!()).is_err()
+        );
+        let report = verify_output_dir(dir, &manifest, values_name, |_| {}).unwrap();
+        let bad_names: Vec<String> = report.bad_parts().map(|p| p.name.clone()).collect();
+        assert_eq!(bad_names, vec![manifest.parts[1].name.clone()]);
+        // 大小已经不一致，不再解码
+        assert_eq!(report.bad_parts().next().unwrap().samples, 0);
+
+        let manifest = quarantine_parts(dir, manifest_name, values_name, &bad_names).unwrap();
+        assert_eq!(manifest.parts.len(), 3);
+        assert_eq!(manifest.progress.accepted, 7);
+        assert!(dir.join(QUARANTINE_DIR).join(&bad_names[0]).exists());
+        assert_eq!(load_score_mean_values(&dir.join(values_name)).unwrap().len(), 7);
+        assert!(verify_output_dir(dir, &manifest, values_name, |_| {}).unwrap().is_ok());
+    }
+}
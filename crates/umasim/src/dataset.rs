@@ -0,0 +1,1173 @@
+//! 训练数据集工具
+//!
+//! 读取 collector 输出目录（part_*.bin + score_mean_values.bin），统计样本分布，
+//! 并检查特征是否有 NaN/Inf、回合 One-Hot 是否合法等，避免用坏数据训练。
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    path::{Path, PathBuf}
+};
+
+use anyhow::{Result, bail, ensure};
+use comfy_table::Table;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+use crate::{
+    collector::{
+        CollectorManifest,
+        ManifestSource,
+        ShardWriter,
+        calc_score_mean_summary,
+        fnv1a64,
+        load_score_mean_values,
+        make_game_id,
+        scan_part_files,
+        try_get_git_commit
+    },
+    gamedata::CollectorConfig,
+    neural::feature_schema::{
+        CARD_BLOCKS,
+        CARD_NUM,
+        CardBlock,
+        FEATURE_SCHEMA_FILE,
+        FEATURE_SCHEMA_VERSION,
+        FeatureSchema,
+        GLOBAL_BLOCKS,
+        GlobalBlock,
+        block_offset
+    },
+    search::SearchConfig,
+    training_sample::{CHOICE_DIM, NN_INPUT_DIM, POLICY_DIM, TrainingSample, TrainingSampleReader}
+};
+
+/// 数据集目录中的 manifest 文件名
+pub const MANIFEST_NAME: &str = "manifest.json";
+/// 数据集目录中的 scoreMean 文件名
+pub const SCORE_MEAN_VALUES_NAME: &str = "score_mean_values.bin";
+
+/// 总回合数
+pub const TURN_NUM: usize = 78;
+
+/// 列出数据路径下的分片文件，目录中有特征布局时检查版本
+pub fn data_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    ensure!(path.is_dir(), "训练数据不存在: {}", path.display());
+    let schema_path = path.join(FEATURE_SCHEMA_FILE);
+    if schema_path.exists() {
+        let schema = FeatureSchema::load_json(&schema_path)?;
+        if schema.version != FEATURE_SCHEMA_VERSION {
+            bail!(
+                "训练数据 {} 的特征布局版本为 {}，当前为 {}",
+                path.display(),
+                schema.version,
+                FEATURE_SCHEMA_VERSION
+            );
+        }
+    }
+    let files: Vec<_> = scan_part_files(path)?.into_iter().map(|(_, p)| p).collect();
+    ensure!(!files.is_empty(), "目录中没有 part_*.bin: {}", path.display());
+    Ok(files)
+}
+
+/// 全局动作索引的显示名，与 [`crate::sample_collector::action_to_global_index`] 对应
+pub fn global_action_name(idx: usize) -> String {
+    const TRAIN_NAMES: [&str; 5] = ["速", "耐", "力", "根", "智"];
+    match idx {
+        0..=4 => format!("{}训练", TRAIN_NAMES[idx]),
+        5 => "休息".to_string(),
+        6 => "普通出行".to_string(),
+        7 => "友人出行".to_string(),
+        8 => "比赛".to_string(),
+        9 => "治病".to_string(),
+        10 => "PR".to_string(),
+        11..=20 => format!("挖掘#{}", idx - 11),
+        21..=23 => format!("升级#{}", idx - 21),
+        24 => "不使用温泉券".to_string(),
+        25 => "使用温泉券".to_string(),
+        26..=31 => format!("友人卡#{}出行", idx - 26),
+        _ => format!("保留#{idx}")
+    }
+}
+
+/// 样本所在回合（0-based），回合块不是合法的 One-Hot 时返回 None
+pub fn sample_turn(nn_input: &[f32]) -> Option<usize> {
+    let offset = GlobalBlock::Turn.offset();
+    let block = nn_input.get(offset..offset + GlobalBlock::Turn.dim())?;
+    let mut turn = None;
+    for (i, &v) in block.iter().enumerate() {
+        if v == 1.0 {
+            if turn.is_some() {
+                return None;
+            }
+            turn = Some(i);
+        } else if v != 0.0 {
+            return None;
+        }
+    }
+    turn
+}
+
+/// choice 样本的选项数，从事件选项特征块读取
+fn choice_option_count(nn_input: &[f32]) -> usize {
+    let v = nn_input[GlobalBlock::Choices.offset()];
+    ((v * CHOICE_DIM as f32).round().max(0.0) as usize).min(CHOICE_DIM)
+}
+
+fn argmax(values: &[f32]) -> usize {
+    let mut best = 0;
+    for (i, &v) in values.iter().enumerate() {
+        if v > values[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+/// 概率分布的熵（nats）
+pub fn entropy(probs: &[f32]) -> f32 {
+    -probs.iter().filter(|&&p| p > 0.0).map(|&p| p * p.ln()).sum::<f32>()
+}
+
+/// 一个特征块的值域统计
+#[derive(Debug, Clone)]
+pub struct ValueRange {
+    pub min: f32,
+    pub max: f32,
+    pub sum: f64,
+    pub count: u64,
+    pub nan: u64,
+    pub inf: u64
+}
+
+impl Default for ValueRange {
+    fn default() -> Self {
+        Self {
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            sum: 0.0,
+            count: 0,
+            nan: 0,
+            inf: 0
+        }
+    }
+}
+
+impl ValueRange {
+    pub fn add(&mut self, v: f32) {
+        if v.is_nan() {
+            self.nan += 1;
+            return;
+        }
+        if v.is_infinite() {
+            self.inf += 1;
+            return;
+        }
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        self.sum += v as f64;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { self.sum / self.count as f64 }
+    }
+}
+
+/// 单回合统计
+#[derive(Debug, Clone)]
+pub struct TurnStats {
+    pub action: u64,
+    pub choice: u64,
+    /// 按 policy argmax 统计的动作次数，下标为全局动作索引
+    pub argmax_actions: Vec<u64>
+}
+
+impl Default for TurnStats {
+    fn default() -> Self {
+        Self {
+            action: 0,
+            choice: 0,
+            argmax_actions: vec![0; POLICY_DIM]
+        }
+    }
+}
+
+/// 数据集统计
+#[derive(Debug, Clone)]
+pub struct DatasetStats {
+    pub samples: u64,
+    /// 特征或目标维度不对的样本，不计入其他统计
+    pub bad_dim: u64,
+    /// policy_target 非零的样本
+    pub action_samples: u64,
+    /// choice_target 非零的样本
+    pub choice_samples: u64,
+    /// policy_target 和 choice_target 都为零的样本
+    pub empty_target: u64,
+    /// 回合块不是合法 One-Hot 的样本
+    pub bad_turn: u64,
+    pub policy_sum_not_one: u64,
+    pub choice_sum_not_one: u64,
+    /// 目标中有 NaN/Inf 的样本
+    pub target_not_finite: u64,
+    pub per_turn: Vec<TurnStats>,
+    /// 下标为选项数
+    pub choice_options: Vec<u64>,
+    /// 下标为 choice argmax
+    pub choice_argmax: Vec<u64>,
+    pub score_mean: Vec<f32>,
+    pub score_stdev: Vec<f32>,
+    pub policy_entropy: Vec<f32>,
+    /// 全局特征块和支援卡特征块（6 张卡合并）的值域
+    pub blocks: Vec<(String, ValueRange)>
+}
+
+impl Default for DatasetStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DatasetStats {
+    pub fn new() -> Self {
+        let mut blocks: Vec<_> = GLOBAL_BLOCKS
+            .iter()
+            .map(|b| (b.name.to_string(), ValueRange::default()))
+            .collect();
+        blocks.extend(
+            CARD_BLOCKS
+                .iter()
+                .map(|b| (format!("card.{}", b.name), ValueRange::default()))
+        );
+        Self {
+            samples: 0,
+            bad_dim: 0,
+            action_samples: 0,
+            choice_samples: 0,
+            empty_target: 0,
+            bad_turn: 0,
+            policy_sum_not_one: 0,
+            choice_sum_not_one: 0,
+            target_not_finite: 0,
+            per_turn: vec![TurnStats::default(); TURN_NUM],
+            choice_options: vec![0; CHOICE_DIM + 1],
+            choice_argmax: vec![0; CHOICE_DIM],
+            score_mean: Vec::new(),
+            score_stdev: Vec::new(),
+            policy_entropy: Vec::new(),
+            blocks
+        }
+    }
+
+    pub fn add(&mut self, sample: &TrainingSample) {
+        self.samples += 1;
+        if sample.nn_input.len() != NN_INPUT_DIM
+            || sample.policy_target.len() != POLICY_DIM
+            || sample.choice_target.len() != CHOICE_DIM
+        {
+            self.bad_dim += 1;
+            return;
+        }
+
+        // 特征值域
+        for (i, b) in GLOBAL_BLOCKS.iter().enumerate() {
+            let offset = block_offset(&GLOBAL_BLOCKS, i);
+            for &v in &sample.nn_input[offset..offset + b.dim] {
+                self.blocks[i].1.add(v);
+            }
+        }
+        for card in 0..CARD_NUM {
+            for (i, block) in [CardBlock::Person, CardBlock::CardParams].into_iter().enumerate() {
+                let offset = block.offset(card);
+                for &v in &sample.nn_input[offset..offset + block.dim()] {
+                    self.blocks[GLOBAL_BLOCKS.len() + i].1.add(v);
+                }
+            }
+        }
+
+        let targets = [&sample.policy_target, &sample.choice_target, &sample.value_target];
+        if targets.iter().any(|t| t.iter().any(|v| !v.is_finite())) {
+            self.target_not_finite += 1;
+        }
+        if let [mean, stdev, ..] = sample.value_target[..] {
+            self.score_mean.push(mean);
+            self.score_stdev.push(stdev);
+        }
+
+        let turn = sample_turn(&sample.nn_input);
+        if turn.is_none() {
+            self.bad_turn += 1;
+        }
+        let policy_sum: f32 = sample.policy_target.iter().sum();
+        let choice_sum: f32 = sample.choice_target.iter().sum();
+        if policy_sum > 0.0 {
+            self.action_samples += 1;
+            if (policy_sum - 1.0).abs() > 1e-3 {
+                self.policy_sum_not_one += 1;
+            }
+            self.policy_entropy.push(entropy(&sample.policy_target));
+            if let Some(t) = turn {
+                self.per_turn[t].action += 1;
+                self.per_turn[t].argmax_actions[argmax(&sample.policy_target)] += 1;
+            }
+        }
+        if choice_sum > 0.0 {
+            self.choice_samples += 1;
+            if (choice_sum - 1.0).abs() > 1e-3 {
+                self.choice_sum_not_one += 1;
+            }
+            self.choice_options[choice_option_count(&sample.nn_input)] += 1;
+            self.choice_argmax[argmax(&sample.choice_target)] += 1;
+            if let Some(t) = turn {
+                self.per_turn[t].choice += 1;
+            }
+        }
+        if policy_sum <= 0.0 && choice_sum <= 0.0 {
+            self.empty_target += 1;
+        }
+    }
+
+    /// 可能导致训练出错的问题
+    pub fn warnings(&self) -> Vec<String> {
+        let mut ret = Vec::new();
+        if self.samples == 0 {
+            ret.push("没有样本".to_string());
+        }
+        for (name, range) in &self.blocks {
+            if range.nan > 0 || range.inf > 0 {
+                ret.push(format!("特征块 {name} 有 {} 个 NaN、{} 个 Inf", range.nan, range.inf));
+            }
+        }
+        let checks = [
+            (self.bad_dim, "维度不对，未计入统计"),
+            (self.bad_turn, "回合块不是合法 One-Hot"),
+            (self.target_not_finite, "目标中有 NaN/Inf"),
+            (self.policy_sum_not_one, "policy_target 之和不为 1"),
+            (self.choice_sum_not_one, "choice_target 之和不为 1"),
+            (self.empty_target, "policy_target 和 choice_target 都为零")
+        ];
+        for (count, desc) in checks {
+            if count > 0 {
+                ret.push(format!("{count} 个样本{desc}"));
+            }
+        }
+        ret
+    }
+
+    /// 显示统计报告，bins 为直方图分箱数，top 为每回合显示的常见动作数
+    pub fn explain(&self, bins: usize, top: usize) -> String {
+        let mut out = String::new();
+        let pct = |n: u64, total: u64| if total == 0 { 0.0 } else { n as f64 * 100.0 / total as f64 };
+        out += &format!(
+            "样本: {} (action {}, choice {}, 空目标 {})\n\n",
+            self.samples, self.action_samples, self.choice_samples, self.empty_target
+        );
+
+        // 每回合
+        let mut table = Table::new();
+        table.set_header(vec!["回合", "action", "choice", "常见动作(argmax)"]);
+        for (t, s) in self.per_turn.iter().enumerate() {
+            if s.action == 0 && s.choice == 0 {
+                continue;
+            }
+            let mut actions: Vec<_> = s.argmax_actions.iter().enumerate().filter(|(_, n)| **n > 0).collect();
+            actions.sort_by(|a, b| b.1.cmp(a.1));
+            let top_actions: Vec<_> = actions
+                .iter()
+                .take(top)
+                .map(|(i, n)| format!("{} {:.0}%", global_action_name(*i), pct(**n, s.action)))
+                .collect();
+            table.add_row(vec![
+                (t + 1).to_string(),
+                s.action.to_string(),
+                s.choice.to_string(),
+                top_actions.join(", "),
+            ]);
+        }
+        out += &format!("每回合样本:\n{table}\n\n");
+
+        out += &format!("scoreMean 分布:\n{}\n", explain_histogram(&self.score_mean, bins));
+        out += &format!("scoreStdev 分布:\n{}\n", explain_histogram(&self.score_stdev, bins));
+        out += &format!(
+            "policy 熵分布(nats, 均匀分布上限 ln50={:.2}):\n{}\n",
+            (POLICY_DIM as f32).ln(),
+            explain_histogram(&self.policy_entropy, bins)
+        );
+
+        // choice 覆盖
+        let mut table = Table::new();
+        table.set_header(vec!["选项数", "样本", "占比"]);
+        for (n, count) in self.choice_options.iter().enumerate() {
+            if *count > 0 {
+                table.add_row(vec![
+                    n.to_string(),
+                    count.to_string(),
+                    format!("{:.1}%", pct(*count, self.choice_samples)),
+                ]);
+            }
+        }
+        let argmax: Vec<_> = self
+            .choice_argmax
+            .iter()
+            .enumerate()
+            .map(|(i, n)| format!("#{} {:.0}%", i + 1, pct(*n, self.choice_samples)))
+            .collect();
+        out += &format!(
+            "choice 样本: {} 个, 覆盖 {} 个回合\n{table}\nchoice argmax: {}\n\n",
+            self.choice_samples,
+            self.per_turn.iter().filter(|s| s.choice > 0).count(),
+            argmax.join(", ")
+        );
+
+        // 特征值域
+        let mut table = Table::new();
+        table.set_header(vec!["特征块", "min", "max", "mean", "NaN", "Inf", "备注"]);
+        for (name, r) in &self.blocks {
+            let note = if r.count > 0 && r.min == r.max { "常量" } else { "" };
+            table.add_row(vec![
+                name.clone(),
+                format!("{:.3}", r.min),
+                format!("{:.3}", r.max),
+                format!("{:.3}", r.mean()),
+                r.nan.to_string(),
+                r.inf.to_string(),
+                note.to_string(),
+            ]);
+        }
+        out += &format!("特征值域:\n{table}\n");
+        out
+    }
+}
+
+/// 等宽直方图，返回每个分箱的 [下界, 上界) 和计数，忽略非有限值
+pub fn histogram(values: &[f32], bins: usize) -> Vec<(f32, f32, u64)> {
+    let finite: Vec<_> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if finite.is_empty() || bins == 0 {
+        return Vec::new();
+    }
+    let min = finite.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = finite.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    if min == max {
+        return vec![(min, max, finite.len() as u64)];
+    }
+    let width = (max - min) / bins as f32;
+    let mut counts = vec![0u64; bins];
+    for v in finite {
+        let i = (((v - min) / width) as usize).min(bins - 1);
+        counts[i] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, n)| (min + width * i as f32, min + width * (i + 1) as f32, n))
+        .collect()
+}
+
+fn explain_histogram(values: &[f32], bins: usize) -> String {
+    let hist = histogram(values, bins);
+    let peak = hist.iter().map(|h| h.2).max().unwrap_or(0).max(1);
+    let mut out = String::new();
+    for (lo, hi, n) in hist {
+        let bar = "#".repeat((n * 40).div_ceil(peak) as usize);
+        out += &format!("  [{lo:>10.2}, {hi:>10.2}) {n:>8} {bar}\n");
+    }
+    out
+}
+
+// ============================================================================
+// 流式读取
+// ============================================================================
+
+/// 数据集中的一个分片
+#[derive(Debug, Clone)]
+pub struct DatasetPart {
+    pub path: PathBuf,
+    pub samples: u64
+}
+
+/// 读取顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadOrder {
+    /// 按 manifest 中的分片顺序
+    Sequential,
+    /// 打乱分片顺序，样本再经过固定大小的缓冲区随机输出
+    Shuffled { seed: u64, buffer: usize }
+}
+
+/// 流式读取数据集，内存中只保留当前样本和打乱缓冲区
+///
+/// 打开时只读取各分片的文件头获取样本数，可以按全局下标或 (分片, 样本) 定位。
+#[derive(Debug, Clone)]
+pub struct DatasetReader {
+    parts: Vec<DatasetPart>
+}
+
+impl DatasetReader {
+    /// 打开数据路径（目录或单个 .bin 文件），目录中有 manifest 时按 manifest 的分片顺序
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_all(std::slice::from_ref(&path.to_path_buf()))
+    }
+
+    /// 依次打开多个数据路径，拼接成一个数据集
+    pub fn open_all(paths: &[PathBuf]) -> Result<Self> {
+        let mut parts = Vec::new();
+        for path in paths {
+            for file in dataset_part_files(path)? {
+                let samples = TrainingSampleReader::open(&file)?.len();
+                parts.push(DatasetPart { path: file, samples });
+            }
+        }
+        Ok(Self { parts })
+    }
+
+    pub fn parts(&self) -> &[DatasetPart] {
+        &self.parts
+    }
+
+    /// 总样本数
+    pub fn len(&self) -> u64 {
+        self.parts.iter().map(|p| p.samples).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 全局样本下标 -> (分片下标, 分片内下标)
+    pub fn locate(&self, mut index: u64) -> Option<(usize, u64)> {
+        for (i, part) in self.parts.iter().enumerate() {
+            if index < part.samples {
+                return Some((i, index));
+            }
+            index -= part.samples;
+        }
+        None
+    }
+
+    /// 按指定顺序读取全部样本
+    pub fn iter(&self, order: ReadOrder) -> DatasetIter {
+        let mut parts: Vec<PathBuf> = self.parts.iter().map(|p| p.path.clone()).collect();
+        let (seed, buffer_size) = match order {
+            ReadOrder::Sequential => (0, 0),
+            ReadOrder::Shuffled { seed, buffer } => (seed, buffer.max(1))
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        if buffer_size > 0 {
+            parts.shuffle(&mut rng);
+        }
+        DatasetIter::new(parts, 0, rng, buffer_size)
+    }
+
+    /// 从第 part 个分片的第 sample 个样本开始顺序读取
+    pub fn iter_from(&self, part: usize, sample: u64) -> Result<DatasetIter> {
+        ensure!(part < self.parts.len(), "分片下标越界: {part}/{}", self.parts.len());
+        ensure!(
+            sample < self.parts[part].samples,
+            "样本下标越界: {sample}/{} ({})",
+            self.parts[part].samples,
+            self.parts[part].path.display()
+        );
+        let parts = self.parts[part..].iter().map(|p| p.path.clone()).collect();
+        Ok(DatasetIter::new(parts, sample, StdRng::seed_from_u64(0), 0))
+    }
+}
+
+/// 数据集中的分片文件；目录中有 manifest 时按 manifest 的顺序，并要求分片都存在
+fn dataset_part_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let files = data_files(path)?;
+    let manifest_path = path.join(MANIFEST_NAME);
+    if !path.is_dir() || !manifest_path.exists() {
+        return Ok(files);
+    }
+    let manifest = CollectorManifest::load(&manifest_path)?;
+    let mut ordered = Vec::with_capacity(manifest.parts.len());
+    for part in &manifest.parts {
+        let file = path.join(&part.name);
+        ensure!(files.contains(&file), "manifest 中的分片不存在: {}", file.display());
+        ordered.push(file);
+    }
+    Ok(ordered)
+}
+
+/// [`DatasetReader`] 的样本迭代器
+pub struct DatasetIter {
+    parts: Vec<PathBuf>,
+    next_part: usize,
+    current: Option<TrainingSampleReader<Box<dyn Read + Send>>>,
+    /// 第一个分片开头要跳过的样本数
+    skip: u64,
+    exhausted: bool,
+
+    // 打乱缓冲区，buffer_size 为 0 时按顺序输出
+    rng: StdRng,
+    buffer: Vec<TrainingSample>,
+    buffer_size: usize
+}
+
+impl DatasetIter {
+    fn new(parts: Vec<PathBuf>, skip: u64, rng: StdRng, buffer_size: usize) -> Self {
+        Self {
+            parts,
+            next_part: 0,
+            current: None,
+            skip,
+            exhausted: false,
+            rng,
+            buffer: Vec::with_capacity(buffer_size),
+            buffer_size
+        }
+    }
+
+    fn read_next(&mut self) -> Result<Option<TrainingSample>> {
+        loop {
+            if let Some(reader) = &mut self.current
+                && let Some(sample) = reader.read_sample()?
+            {
+                return Ok(Some(sample));
+            }
+            let Some(path) = self.parts.get(self.next_part) else {
+                return Ok(None);
+            };
+            let mut reader = TrainingSampleReader::open(path)?;
+            reader.skip_samples(std::mem::take(&mut self.skip))?;
+            self.current = Some(reader);
+            self.next_part += 1;
+        }
+    }
+}
+
+impl Iterator for DatasetIter {
+    type Item = Result<TrainingSample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer_size == 0 {
+            return self.read_next().transpose();
+        }
+        while !self.exhausted && self.buffer.len() < self.buffer_size {
+            match self.read_next() {
+                Ok(Some(sample)) => self.buffer.push(sample),
+                Ok(None) => self.exhausted = true,
+                Err(e) => return Some(Err(e))
+            }
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let i = self.rng.random_range(0..self.buffer.len());
+        Some(Ok(self.buffer.swap_remove(i)))
+    }
+}
+
+// ============================================================================
+// 合并、去重、按对局划分
+// ============================================================================
+
+/// 合并参数
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    /// 划入验证集的对局比例
+    pub val_ratio: f64,
+    /// 每个分片的最大样本数
+    pub shard_size: usize,
+    /// 每份数据同时填充的分片数，样本轮流放入，同一局的样本分散到不同分片
+    pub open_shards: usize,
+    /// 打乱顺序用的随机种子（划分只由 game_id 决定，不受种子影响）
+    pub seed: u64,
+    /// 去掉 nn_input 完全相同的样本
+    pub dedup: bool,
+    /// 输出分片的 zstd 压缩等级，0 表示不压缩
+    pub zstd_level: i32
+}
+
+/// 合并结果中的一份数据（训练集或验证集）
+#[derive(Debug, Clone, Default)]
+pub struct MergedSplit {
+    pub games: u64,
+    pub samples: u64,
+    pub parts: usize
+}
+
+/// 合并结果
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    pub train: MergedSplit,
+    pub val: MergedSplit,
+    pub duplicates: u64,
+    /// 没有 game_id、按回合号推断出的对局数
+    pub inferred_games: u64
+}
+
+/// 合并输出的子目录，下标 0 为训练集，1 为验证集
+const SPLIT_NAMES: [&str; 2] = ["train", "val"];
+
+/// 合并输出中一份数据（训练集或验证集）的写入状态
+struct SplitWriter {
+    dir: PathBuf,
+    writer: ShardWriter,
+    /// 正在填充的分片，某个攒满后打乱写出
+    buffers: Vec<Vec<TrainingSample>>,
+    /// 下一个样本放入的分片
+    next_buffer: usize,
+    samples: u64
+}
+
+/// 数据集合并器
+///
+/// 依次读入多个来源，去掉重复样本后按 game_id 把整局划入训练集或验证集，边读边写成新的分片。
+/// 内存中只保留 nn_input 的 hash、game_id 的划分和每份数据 `open_shards` 个分片的样本。
+/// 样本轮流放入这些分片，同一局相邻的样本落在不同分片中，各回合的样本也均匀分布；分片写出前再打乱。
+pub struct DatasetMerger {
+    options: MergeOptions,
+    output_dir: PathBuf,
+    overwrite: bool,
+    /// 已写入样本的 nn_input hash（64 位 hash 冲突的概率可以忽略）
+    seen: HashSet<u64>,
+    /// game_id -> 是否划入验证集
+    games: HashMap<u64, bool>,
+    /// 训练集和验证集，有样本划入时才创建
+    splits: [Option<SplitWriter>; 2],
+    rng: StdRng,
+    accepted: usize,
+    sources: Vec<ManifestSource>,
+    /// 第一个带 manifest 的来源，新 manifest 沿用它的配置
+    base_manifest: Option<CollectorManifest>,
+
+    // 旧数据没有 game_id：回合号变小时认为开始了新的一局
+    last_turn: Option<usize>,
+    inferred_game: Option<u64>,
+    inferred_games: u64
+}
+
+impl DatasetMerger {
+    /// 合并到 output_dir/train 和 output_dir/val（没有验证集时不创建）
+    pub fn new(options: MergeOptions, output_dir: &Path, overwrite: bool) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(options.seed),
+            options,
+            output_dir: output_dir.to_path_buf(),
+            overwrite,
+            seen: HashSet::new(),
+            games: HashMap::new(),
+            splits: Default::default(),
+            accepted: 0,
+            sources: Vec::new(),
+            base_manifest: None,
+            last_turn: None,
+            inferred_game: None,
+            inferred_games: 0
+        }
+    }
+
+    /// 已接受（去重后）的样本数
+    pub fn samples(&self) -> usize {
+        self.accepted
+    }
+
+    /// 开始读入一个新来源，之后的 [`Self::add_samples`] 都计入这个来源
+    pub fn begin_source(&mut self, path: &Path, manifest: Option<&CollectorManifest>) {
+        if self.base_manifest.is_none()
+            && let Some(m) = manifest
+        {
+            self.base_manifest = Some(m.clone());
+        }
+        self.sources.push(ManifestSource {
+            path: path.to_string_lossy().to_string(),
+            created_at: manifest.map(|m| m.created_at.clone()),
+            config_hash_fnv1a64: manifest.map(|m| m.config_hash_fnv1a64.clone()),
+            samples: 0,
+            duplicates: 0
+        });
+        self.last_turn = None;
+        self.inferred_game = None;
+    }
+
+    /// 读入当前来源的一个样本（需要保持原来的顺序，用于推断旧数据的对局边界）
+    pub fn add_sample(&mut self, mut sample: TrainingSample) -> Result<()> {
+        ensure!(!self.sources.is_empty(), "添加样本前需要调用 begin_source");
+        let source_idx = self.sources.len() - 1;
+        self.sources[source_idx].samples += 1;
+        if sample.game_id == 0 {
+            sample.game_id = self.infer_game_id(source_idx, &sample);
+        }
+        // 带整局决策记录的样本不去重，否则该局的样本无法重放
+        if self.options.dedup
+            && !self.seen.insert(features_hash(&sample.nn_input))
+            && sample.provenance.decisions.is_empty()
+        {
+            self.sources[source_idx].duplicates += 1;
+            return Ok(());
+        }
+        let val = *self
+            .games
+            .entry(sample.game_id)
+            .or_insert_with(|| split_unit(sample.game_id) < self.options.val_ratio);
+        self.accepted += 1;
+        self.push(val as usize, sample)
+    }
+
+    /// 依次读入当前来源的一批样本
+    pub fn add_samples(&mut self, samples: impl IntoIterator<Item = TrainingSample>) -> Result<()> {
+        for sample in samples {
+            self.add_sample(sample)?;
+        }
+        Ok(())
+    }
+
+    fn infer_game_id(&mut self, source_idx: usize, sample: &TrainingSample) -> u64 {
+        let turn = sample_turn(&sample.nn_input);
+        let new_game = match (self.last_turn, turn) {
+            (Some(last), Some(t)) => t < last,
+            _ => self.inferred_game.is_none()
+        };
+        if turn.is_some() {
+            self.last_turn = turn;
+        }
+        if new_game {
+            let key = format!("{}#legacy", self.sources[source_idx].path);
+            self.inferred_game = Some(make_game_id(&key, self.inferred_games));
+            self.inferred_games += 1;
+        }
+        self.inferred_game.expect("inferred game")
+    }
+
+    /// 把样本轮流放进第 index 份数据的各个分片，攒满一个分片时打乱写出
+    fn push(&mut self, index: usize, sample: TrainingSample) -> Result<()> {
+        if self.splits[index].is_none() {
+            self.splits[index] = Some(self.open_split(&self.output_dir.join(SPLIT_NAMES[index]))?);
+        }
+        let split = self.splits[index].as_mut().expect("split");
+        record_sample(&mut split.writer.manifest, &sample);
+        split.samples += 1;
+        let current = split.next_buffer;
+        split.next_buffer = (current + 1) % split.buffers.len();
+        let buffer = &mut split.buffers[current];
+        buffer.push(sample);
+        if buffer.len() >= self.options.shard_size.max(1) {
+            buffer.shuffle(&mut self.rng);
+            split.writer.push_samples(std::mem::take(buffer))?;
+        }
+        Ok(())
+    }
+
+    fn open_split(&self, dir: &Path) -> Result<SplitWriter> {
+        let manifest = self.new_manifest(dir);
+        let (mut writer, _scan) = ShardWriter::open_or_create(
+            dir,
+            MANIFEST_NAME,
+            SCORE_MEAN_VALUES_NAME,
+            self.options.shard_size,
+            false,
+            self.overwrite,
+            || Ok(manifest)
+        )?;
+        writer.set_zstd_level(self.options.zstd_level);
+        FeatureSchema::current().save_json(&dir.join(FEATURE_SCHEMA_FILE))?;
+        Ok(SplitWriter {
+            dir: dir.to_path_buf(),
+            writer,
+            buffers: vec![Vec::new(); self.options.open_shards.max(1)],
+            next_buffer: 0,
+            samples: 0
+        })
+    }
+
+    /// 写出剩余样本并保存 manifest
+    pub fn finish(mut self) -> Result<MergeReport> {
+        let mut report = MergeReport {
+            duplicates: self.sources.iter().map(|s| s.duplicates).sum(),
+            inferred_games: self.inferred_games,
+            ..Default::default()
+        };
+        let splits = std::mem::take(&mut self.splits);
+        for (index, split) in splits.into_iter().enumerate() {
+            let Some(mut split) = split else {
+                continue;
+            };
+            // 剩下的样本都不满一个分片，合在一起打乱写出
+            let mut rest: Vec<TrainingSample> = split.buffers.drain(..).flatten().collect();
+            rest.shuffle(&mut self.rng);
+            split.writer.push_samples(rest)?;
+            split.writer.finish()?;
+            let accepted = split.writer.accepted_written();
+
+            // 来源和配置要读完所有来源才确定，统计沿用写入时的记录
+            let written = std::mem::replace(&mut split.writer.manifest, self.new_manifest(&split.dir));
+            let games = self.games.values().filter(|v| **v == (index == 1)).count() as u64;
+            let manifest = &mut split.writer.manifest;
+            manifest.created_at = written.created_at;
+            manifest.parts = written.parts;
+            manifest.per_turn = written.per_turn;
+            manifest.progress = written.progress;
+            manifest.progress.games_run = games;
+            manifest.progress.accepted = accepted;
+            let mut values = load_score_mean_values(split.writer.score_mean_values_path())?;
+            split.writer.manifest.score_mean = calc_score_mean_summary(&mut values);
+            split.writer.save_manifest()?;
+
+            let merged = MergedSplit {
+                games,
+                samples: split.samples,
+                parts: split.writer.manifest.parts.len()
+            };
+            if index == 0 {
+                report.train = merged;
+            } else {
+                report.val = merged;
+            }
+        }
+        Ok(report)
+    }
+
+    fn new_manifest(&self, output_dir: &Path) -> CollectorManifest {
+        let git_commit = try_get_git_commit(Path::new("."));
+        let mut manifest = match &self.base_manifest {
+            Some(base) => {
+                let mut m = CollectorManifest::new(
+                    output_dir,
+                    git_commit,
+                    &base.config_path,
+                    base.config_hash_fnv1a64.clone(),
+                    base.gamedata_sig.clone(),
+                    base.model_sig.clone(),
+                    base.collector_config.clone(),
+                    &SearchConfig::default()
+                );
+                m.search_config_effective = base.search_config_effective.clone();
+                m
+            }
+            None => CollectorManifest::new(
+                output_dir,
+                git_commit,
+                "",
+                String::new(),
+                Vec::new(),
+                None,
+                CollectorConfig::default(),
+                &SearchConfig::default()
+            )
+        };
+        manifest.collector_config.score_mean_values_name = SCORE_MEAN_VALUES_NAME.to_string();
+        manifest.sources = self.sources.clone();
+        manifest
+    }
+}
+
+fn features_hash(nn_input: &[f32]) -> u64 {
+    let bytes: Vec<u8> = nn_input.iter().flat_map(|v| v.to_le_bytes()).collect();
+    fnv1a64(&bytes)
+}
+
+/// 由 game_id 得到 [0, 1) 中的值，小于 val_ratio 的对局划入验证集
+///
+/// 只取决于 game_id，追加新数据后重新合并，旧对局仍在原来的一侧
+pub fn split_unit(game_id: u64) -> f64 {
+    let hash = fnv1a64(&game_id.to_le_bytes());
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// 把一个样本计入 manifest 的每回合统计
+fn record_sample(manifest: &mut CollectorManifest, sample: &TrainingSample) {
+    let policy_sum: f32 = sample.policy_target.iter().sum();
+    let choice_sum: f32 = sample.choice_target.iter().sum();
+    let turn = sample_turn(&sample.nn_input);
+    if policy_sum > 0.0
+        && let Some(t) = turn
+    {
+        manifest.per_turn.accepted[t] += 1;
+    }
+    if choice_sum > 0.0 {
+        manifest.progress.choice_accepted += 1;
+        if let Some(t) = turn {
+            manifest.per_turn.choice_accepted[t] += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::TempDir,
+        training_sample::{TrainingSampleBatch, VALUE_DIM}
+    };
+
+    #[test]
+    fn test_dataset_stats() {
+        let mut features = vec![0.0_f32; NN_INPUT_DIM];
+        features[GlobalBlock::Turn.offset() + 10] = 1.0;
+        let mut policy = vec![0.0_f32; POLICY_DIM];
+        policy[5] = 0.75;
+        policy[0] = 0.25;
+        let action = TrainingSample::new(
+            features.clone(),
+            policy,
+            vec![0.0; CHOICE_DIM],
+            vec![30000.0, 1000.0, 30500.0]
+        );
+        assert_eq!(sample_turn(&action.nn_input), Some(10));
+
+        features[GlobalBlock::Choices.offset()] = 3.0 / CHOICE_DIM as f32;
+        features[GlobalBlock::Misc.offset()] = f32::NAN;
+        let mut choice = vec![0.0_f32; CHOICE_DIM];
+        choice[..3].copy_from_slice(&[0.2, 0.5, 0.3]);
+        let choice = TrainingSample::new(features, vec![0.0; POLICY_DIM], choice, vec![0.0; VALUE_DIM]);
+
+        let mut stats = DatasetStats::new();
+        stats.add(&action);
+        stats.add(&choice);
+        assert_eq!(stats.samples, 2);
+        assert_eq!(stats.action_samples, 1);
+        assert_eq!(stats.choice_samples, 1);
+        assert_eq!(stats.per_turn[10].action, 1);
+        assert_eq!(stats.per_turn[10].choice, 1);
+        assert_eq!(stats.per_turn[10].argmax_actions[5], 1);
+        assert_eq!(stats.choice_options[3], 1);
+        assert_eq!(stats.choice_argmax[1], 1);
+        let expected = -(0.75_f32 * 0.75_f32.ln() + 0.25 * 0.25_f32.ln());
+        assert!((stats.policy_entropy[0] - expected).abs() < 1e-6);
+        let warnings = stats.warnings();
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(warnings[0].contains("misc"));
+
+        let hist = histogram(&stats.score_mean, 4);
+        assert_eq!(hist.len(), 4);
+        assert_eq!(hist[0].2 + hist[3].2, 2);
+        assert!(stats.explain(4, 3).contains("休息 100%"));
+
+        // 维度不对的样本只计数，不会越界
+        let mut bad = action.clone();
+        bad.nn_input.truncate(10);
+        stats.add(&bad);
+        assert_eq!(stats.samples, 3);
+        assert_eq!(stats.bad_dim, 1);
+        assert_eq!(stats.action_samples, 1);
+        assert!(stats.warnings().iter().any(|w| w.contains("维度")));
+    }
+
+    #[test]
+    fn test_dataset_reader() {
+        let dir = TempDir::new("reader");
+        dir.write_shards(10, 3, 0);
+
+        let reader = DatasetReader::open(dir.path()).unwrap();
+        assert_eq!(reader.len(), 10);
+        assert_eq!(reader.parts().len(), 4);
+        assert_eq!(reader.locate(7), Some((2, 1)));
+        assert_eq!(reader.locate(10), None);
+        let first = |s: Result<TrainingSample>| s.unwrap().nn_input[0] as usize;
+        let seq: Vec<usize> = reader.iter(ReadOrder::Sequential).map(first).collect();
+        assert_eq!(seq, (0..10).collect::<Vec<_>>());
+        let from: Vec<usize> = reader.iter_from(2, 1).unwrap().map(first).collect();
+        assert_eq!(from, vec![7, 8, 9]);
+        assert!(reader.iter_from(3, 1).is_err());
+        let mut shuffled: Vec<usize> = reader.iter(ReadOrder::Shuffled { seed: 3, buffer: 4 }).map(first).collect();
+        assert_ne!(shuffled, seq);
+        shuffled.sort();
+        assert_eq!(shuffled, seq);
+    }
+
+    #[test]
+    fn test_merge_split_by_game() {
+        let sample = |game_id: u64, turn: usize, x: f32| {
+            let mut features = vec![0.0_f32; NN_INPUT_DIM];
+            features[GlobalBlock::Turn.offset() + turn] = 1.0;
+            features[GlobalBlock::Misc.offset()] = x;
+            let mut policy = vec![0.0_f32; POLICY_DIM];
+            policy[0] = 1.0;
+            let mut s = TrainingSample::new(features, policy, vec![0.0; CHOICE_DIM], vec![x; VALUE_DIM]);
+            s.game_id = game_id;
+            s
+        };
+        let dir = TempDir::new("merge");
+        let options = MergeOptions {
+            val_ratio: 0.5,
+            shard_size: 16,
+            open_shards: 4,
+            seed: 1,
+            dedup: true,
+            zstd_level: 0
+        };
+        let mut merger = DatasetMerger::new(options, dir.path(), true);
+        // 40 局，每局 4 个回合；第二个来源与第一个完全相同，应全部去重
+        let games: Vec<TrainingSample> = (0..40_usize)
+            .flat_map(|g| (0..4).map(move |t| (g, t)))
+            .map(|(g, t)| sample(g as u64 + 1, t * 10, (g * 4 + t) as f32))
+            .collect();
+        merger.begin_source(Path::new("a"), None);
+        merger.add_samples(games.clone()).unwrap();
+        merger.begin_source(Path::new("b"), None);
+        merger.add_samples(games).unwrap();
+        // 旧数据：回合号变小即新的一局
+        merger.begin_source(Path::new("legacy"), None);
+        let legacy = [0, 5, 9, 0, 3, 0].iter().enumerate().map(|(i, &t)| sample(0, t, 1000.0 + i as f32));
+        merger.add_samples(legacy).unwrap();
+        assert_eq!(merger.samples(), 166);
+
+        let report = merger.finish().unwrap();
+        assert_eq!(report.duplicates, 160);
+        assert_eq!(report.inferred_games, 3);
+        assert_eq!(report.train.games + report.val.games, 43);
+        assert_eq!(report.train.samples + report.val.samples, 166);
+        assert!(report.train.games > 0 && report.val.games > 0);
+
+        let load = |name: &str| -> Vec<TrainingSample> {
+            data_files(&dir.join(name))
+                .unwrap()
+                .iter()
+                .flat_map(|f| TrainingSampleBatch::load_binary(f.to_string_lossy().as_ref()).unwrap().samples)
+                .collect()
+        };
+        let train = load("train");
+        let val = load("val");
+        let train_games: HashSet<u64> = train.iter().map(|s| s.game_id).collect();
+        assert!(val.iter().all(|s| !train_games.contains(&s.game_id)));
+        assert!(train.iter().chain(&val).all(|s| s.game_id != 0));
+        let manifest = CollectorManifest::load(&dir.join("train").join(MANIFEST_NAME)).unwrap();
+        assert_eq!(manifest.sources.len(), 3);
+        assert_eq!(manifest.progress.accepted, train.len() as u64);
+        assert_eq!(report.train.parts, train.len().div_ceil(16));
+        assert_eq!(manifest.progress.games_run, report.train.games);
+    }
+
+    #[test]
+    fn test_merge_spreads_games_across_shards() {
+        let dir = TempDir::new("merge_spread");
+        let options = MergeOptions {
+            val_ratio: 0.0,
+            shard_size: 16,
+            open_shards: 4,
+            seed: 1,
+            dedup: true,
+            zstd_level: 0
+        };
+        let mut merger = DatasetMerger::new(options, dir.path(), true);
+        // 8 局，每局 16 个样本，按读入顺序写出时每局正好占一个分片
+        merger.begin_source(Path::new("a"), None);
+        for i in 0..128 {
+            let mut s = TrainingSample::new(
+                vec![i as f32; NN_INPUT_DIM],
+                vec![0.0; POLICY_DIM],
+                vec![0.0; CHOICE_DIM],
+                vec![0.0; VALUE_DIM]
+            );
+            s.game_id = i / 16 + 1;
+            merger.add_sample(s).unwrap();
+        }
+        let report = merger.finish().unwrap();
+        assert_eq!(report.train.parts, 8);
+
+        let mut parts_of_game: HashMap<u64, HashSet<usize>> = HashMap::new();
+        for (part, file) in data_files(&dir.join("train")).unwrap().iter().enumerate() {
+            let batch = TrainingSampleBatch::load_binary(file.to_string_lossy().as_ref()).unwrap();
+            assert_eq!(batch.len(), 16);
+            for sample in batch.samples {
+                parts_of_game.entry(sample.game_id).or_default().insert(part);
+            }
+        }
+        assert_eq!(parts_of_game.len(), 8);
+        assert!(parts_of_game.values().all(|parts| parts.len() == 4));
+    }
+}
@@ -23,7 +23,9 @@ pub enum BaseAction {
     /// 普通出行
     NormalOuting,
     /// 治病
-    Clinic
+    Clinic,
+    /// 非剧本友人卡/团队卡出行(人头编号)
+    CardOuting(i32)
 }
 
 impl Display for BaseAction {
@@ -34,7 +36,8 @@ impl Display for BaseAction {
             BaseAction::Sleep => write!(f, "休息"),
             BaseAction::FriendOuting => write!(f, "友人出行"),
             BaseAction::NormalOuting => write!(f, "普通出行"),
-            BaseAction::Clinic => write!(f, "治病")
+            BaseAction::Clinic => write!(f, "治病"),
+            BaseAction::CardOuting(x) => write!(f, "友人卡#{x}出行")
         }
     }
 }
@@ -94,6 +97,10 @@ impl BaseAction {
                 }
             }
         }
+        // 休息时坏状态可能自然恢复
+        for name in game.uma.flags.cure(false, rng) {
+            info!(">> 【{name}】恢复了");
+        }
         Ok(())
     }
 
@@ -114,6 +121,21 @@ impl BaseAction {
         }
     }
 
+    pub fn do_card_outing(game: &mut BaseGame, person_index: i32, _rng: &mut StdRng) -> Result<()> {
+        let turn = game.turn;
+        let friend = game
+            .card_friends
+            .iter_mut()
+            .find(|f| f.person_index as i32 == person_index && f.can_outing(turn))
+            .ok_or_else(|| anyhow!("友人卡#{person_index}无法出行"))?;
+        let outing = friend.next_outing().ok_or_else(|| anyhow!("友人卡#{person_index}没有出行事件"))?;
+        info!(">> 友人卡#{person_index}出行 #{}", friend.out_count + 1);
+        let event = friend.bonus_event(outing);
+        friend.out_count += 1;
+        game.unresolved_events.push(event);
+        Ok(())
+    }
+
     pub fn do_normal_outing(game: &mut BaseGame, rng: &mut StdRng) -> Result<()> {
         let event_name = format!("normal_outing_{}", rng.random_range(1..=3));
         game.unresolved_events.push(system_event(&event_name)?.clone());
@@ -126,11 +148,15 @@ impl BaseAction {
         Ok(())
     }
 
-    pub fn do_clinic(game: &mut BaseGame, _rng: &mut StdRng) -> Result<()> {
+    pub fn do_clinic(game: &mut BaseGame, rng: &mut StdRng) -> Result<()> {
         info!(">> 治病");
         let value = ActionValue { vital: 20, ..Default::default() };
-        game.uma.flags.ill = false;
-        game.uma.flags.bad_trainer = false;
+        let cured = game.uma.flags.cure(true, rng);
+        if cured.is_empty() {
+            info!(">> 治疗失败");
+        } else {
+            info!(">> 治好了【{}】", cured.join("、"));
+        }
         game.uma.add_value(&value);
         Ok(())
     }
@@ -146,7 +172,8 @@ impl ActionEnum for BaseAction {
             BaseAction::Sleep => BaseAction::do_sleep(game, rng),
             BaseAction::FriendOuting => BaseAction::do_friend_outing(game, rng),
             BaseAction::NormalOuting => BaseAction::do_normal_outing(game, rng),
-            BaseAction::Clinic => BaseAction::do_clinic(game, rng)
+            BaseAction::Clinic => BaseAction::do_clinic(game, rng),
+            BaseAction::CardOuting(x) => BaseAction::do_card_outing(game, *x, rng)
         }
     }
 
@@ -23,8 +23,10 @@ use crate::{
         SupportCard,
         TurnStage,
         Uma,
+        UniqueTurnContext,
         traits::*
     },
+    explain::Explain,
     gamedata::*,
     global,
     utils::{AttributeArray, global_events, system_event, system_event_prob}
@@ -52,6 +54,7 @@ impl ActionEnum for BasicAction {
     fn apply(&self, game: &mut Self::Game, rng: &mut StdRng) -> Result<()> {
         match &self.0 {
             BaseAction::Train(train) => self.do_train(game, *train as usize, rng),
+            BaseAction::Race if game.ura_finals_stage().is_some() => self.do_ura_race(game),
             _ => self.0.apply(game, rng)
         }
     }
@@ -61,7 +64,32 @@ impl ActionEnum for BasicAction {
     }
 }
 
+/// URA决赛: (回合, 事件, 名称)
+const URA_FINALS: [(i32, &str, &str); 3] = [
+    (73, "ura_qualifier", "预赛"),
+    (75, "ura_semifinal", "半决赛"),
+    (77, "ura_final", "决赛")
+];
+
 impl BasicAction {
+    /// URA决赛比赛，决赛胜利后追加优胜奖励
+    pub fn do_ura_race(&self, game: &mut BasicGame) -> Result<()> {
+        let Some(stage) = game.ura_finals_stage() else {
+            return Err(anyhow!("不是URA决赛回合: {}", game.turn));
+        };
+        let (_, event_name, name) = URA_FINALS[stage];
+        let race_bonus = (100 + game.uma.race_bonus) as f32 / 100.0;
+        info!(">> URA{name} - 比赛加成: {}", game.uma.race_bonus);
+        let mut event = system_event(event_name)?.clone();
+        // 事件面板乘算比赛加成
+        event.choices[0].map_status(|x| (x as f32 * race_bonus).round() as i32);
+        game.unresolved_events.push(event);
+        if stage == URA_FINALS.len() - 1 {
+            game.unresolved_events.push(system_event("ura_ending")?.clone());
+        }
+        Ok(())
+    }
+
     pub fn do_train(&self, game: &mut BasicGame, train: usize, rng: &mut StdRng) -> Result<()> {
         // sanity check
         if train >= 5 {
@@ -76,40 +104,42 @@ impl BasicAction {
         let failure_rate = game.calc_training_failure_rate(&buffs, train) / 100.0;
 
         if rng.random_bool(failure_rate as f64) {
-            // 再判断一次，如果还失败就是大失败
-            if rng.random_bool(failure_rate as f64) {
-                warn!("训练大失败!");
-                game.apply_event(system_event("training_fail_low")?, 0, rng)?;
-                game.uma.flags.ill = true;
-                game.uma.flags.bad_trainer = true;
-            } else {
-                warn!("训练失败!");
-                game.apply_event(system_event("training_fail")?, 0, rng)?;
-            }
+            let (event, outcome) = game.roll_training_failure(train, failure_rate, rng)?;
+            warn!("{}!", outcome.name);
+            game.apply_event(&event, 0, rng)?;
+            game.apply_failure_outcome(outcome, rng);
         } else {
             let value = game.calc_training_value(&buffs, train)?;
             game.uma.add_value(&value);
             // 增加训练次数
             game.train_level_count[train] += 1;
+            if game.shining_count(train) > 0 {
+                game.on_shining_train(train);
+            }
             // 增加羁绊
             let f = if game.uma.flags.aijiao { 9 } else { 7 };
             let mut hint_persons = vec![];
             let mut friend_clicked = false;
-            let mut yayoi_clicked = false;
-            let mut reporter_clicked = false;
+            let mut noncard_clicked = vec![];
+            let mut card_friend_clicked = vec![];
             for person_index in game.distribution[train].clone() {
                 // 跳过空位（-1 表示空位）
                 if person_index < 0 {
                     continue;
                 }
-                game.add_friendship(person_index as usize, f);
+                // 记者/理事长训练时的羁绊按数据表，点击效果的羁绊另外结算
+                if let Some(data) = game.persons[person_index as usize].noncard_data() {
+                    game.add_friendship(person_index as usize, data.train_friendship);
+                } else {
+                    game.add_friendship(person_index as usize, f);
+                }
                 if game.persons[person_index as usize].is_hint {
                     hint_persons.push(person_index);
                 }
                 match game.persons[person_index as usize].person_type {
                     PersonType::ScenarioCard => friend_clicked = true,
-                    PersonType::Yayoi => yayoi_clicked = true,
-                    PersonType::Reporter => reporter_clicked = true,
+                    PersonType::Yayoi | PersonType::Reporter => noncard_clicked.push(person_index as usize),
+                    PersonType::OtherFriend | PersonType::TeamCard => card_friend_clicked.push(person_index as usize),
                     _ => {}
                 };
             }
@@ -151,14 +181,16 @@ impl BasicAction {
                     }
                 }
             }
-            if yayoi_clicked {
-                game.unresolved_events.push(system_event("yayoi_click")?.clone());
-            }
-            if reporter_clicked {
-                let mut event = system_event("reporter_click")?.clone();
-                event.choices[0].status_pt[train] = 2;
+            for person_index in noncard_clicked {
+                let event = game.persons[person_index].noncard_click_event(train)?;
                 game.unresolved_events.push(event);
             }
+            for person_index in card_friend_clicked {
+                let friendship = game.persons[person_index].friendship;
+                if let Some(event) = game.click_card_friend(person_index, friendship, rng) {
+                    game.unresolved_events.push(event);
+                }
+            }
         }
         Ok(())
     }
@@ -194,6 +226,41 @@ impl BasicGame {
         self.uma.is_race_turn(self.turn)
     }
 
+    /// 当前回合是URA决赛的第几场(0预赛 1半决赛 2决赛)
+    pub fn ura_finals_stage(&self) -> Option<usize> {
+        URA_FINALS.iter().position(|(turn, _, _)| *turn == self.turn)
+    }
+
+    pub fn explain(&self) -> Result<String> {
+        let mut lines = vec![];
+        lines.push(format!(
+            "回合: {} - {:?} 训练等级: {} 友人: {}",
+            self.turn + 1,
+            self.stage,
+            Explain::train_level_count(&self.train_level_count),
+            self.friend.explain()
+        ));
+        lines.push(self.uma.explain()?);
+        if let Some(line) = self.explain_failure() {
+            lines.push(line);
+        }
+        lines.push(self.explain_ura());
+        Ok(lines.join("\n"))
+    }
+
+    /// URA决赛进度
+    pub fn explain_ura(&self) -> String {
+        let stages: Vec<_> = URA_FINALS
+            .iter()
+            .map(|(turn, event_name, name)| {
+                let won = system_event(event_name).is_ok_and(|e| self.events.contains_key(&e.id));
+                let mark = if won { "胜" } else { "-" };
+                format!("{name}(回合{}){mark}", turn + 1)
+            })
+            .collect();
+        format!("URA决赛: {}", stages.join(" "))
+    }
+
     pub fn newgame(uma_id: u32, deck_ids: &[u32; 6], inherit: InheritInfo) -> Result<Self> {
         let mut ret = BasicGame {
             base: BaseGame::new(uma_id, deck_ids, inherit)?,
@@ -227,11 +294,18 @@ impl Game for BasicGame {
     type Person = BasePerson;
     type Action = BasicAction;
     fn init_persons(&mut self) -> Result<()> {
-        let persons = self
+        let mut persons = self
             .deck
             .iter()
             .map(|card| BasePerson::try_from(card))
             .collect::<Result<Vec<_>>>()?;
+        // 剧本友人以外的友人卡
+        for (i, person) in persons.iter_mut().enumerate() {
+            if person.person_type == PersonType::ScenarioCard && i != self.friend.person_index {
+                person.person_type = PersonType::OtherFriend;
+            }
+        }
+        self.init_card_friends(&persons);
         for p in persons {
             self.add_person(p);
         }
@@ -277,7 +351,7 @@ impl Game for BasicGame {
                 if self.turn > 13 && self.turn < 72 {
                     actions.push(BasicAction(Race));
                 }
-                if self.uma.flags.ill {
+                if self.uma.flags.has_bad_condition() {
                     actions.push(BasicAction(Clinic));
                 }
                 if self.friend.out_state == FriendOutState::AfterUnlock && self.turn < 72 {
@@ -285,6 +359,11 @@ impl Game for BasicGame {
                         actions.push(BasicAction(FriendOuting));
                     }
                 }
+                for friend in &self.card_friends {
+                    if friend.can_outing(self.turn) {
+                        actions.push(BasicAction(CardOuting(friend.person_index as i32)));
+                    }
+                }
             }
             Ok(actions)
         }
@@ -324,32 +403,31 @@ impl Game for BasicGame {
                     return events;
                 }
             }
+            // 非剧本友人卡/团队卡出行解锁
+            if let Some(event) = self.check_card_friend_unlock(&self.persons, rng) {
+                events.push(event);
+                return events;
+            }
+            // 理事长/记者连续事件
+            if let Some(event) = self.check_noncard_event(&self.persons, rng) {
+                events.push(event);
+                return events;
+            }
             // 之后处理一般随机事件
             let weights = WeightedIndex::new(global!(GAMECONSTANTS).get_event_distribution()).expect("event weights");
             match weights.sample(rng) {
                 0 => {
-                    // 支援卡事件. 再精细一点模拟 后一段事件发生次数不能多于前一段事件
-                    let card_event_times: Vec<_> = vec![8001, 8002, 8003]
-                        .iter()
-                        .map(|x| *self.events.get(x).unwrap_or(&0))
-                        .collect();
-                    let mut available_events = vec![];
-                    if card_event_times[0] < 5 {
-                        available_events.push(0);
-                    }
-                    if card_event_times[1] < card_event_times[0] {
-                        available_events.push(1);
-                    }
-                    if card_event_times[2] < card_event_times[1] {
-                        available_events.push(2);
-                    }
-                    if let Some(index) = available_events.choose(rng) {
-                        let mut event = global_events().card_events[*index].clone();
-                        let mut p = rng.random_range(0..6);
-                        while !self.person_is_available(p as usize) {
-                            p = rng.random_range(0..6);
-                        }
-                        event.person_index = Some(p);
+                    // 支援卡连续事件
+                    let available_indices = (0..self.deck.len())
+                        .filter(|x| {
+                            self.persons[*x].person_type == PersonType::Card
+                                && self.person_is_available(*x)
+                                && self.has_card_event(*x)
+                        })
+                        .collect::<Vec<_>>();
+                    if let Some(index) = available_indices.choose(rng)
+                        && let Some(event) = self.generate_card_event(*index as i32, rng)
+                    {
                         events.push(event);
                     }
                 }
@@ -382,6 +460,8 @@ impl Game for BasicGame {
             TurnStage::Begin => {
                 println!("-----------------------------------------");
                 info!("{}", self.explain()?);
+                self.update_conditions(rng);
+                self.update_group_buff(rng);
                 let mut events = self.generate_events(rng);
                 // 友人强制事件
                 if self.friend.out_state == FriendOutState::AfterUnlock {
@@ -406,6 +486,7 @@ impl Game for BasicGame {
                 }
             }
             TurnStage::Distribute => {
+                self.unique_ctx = Some(UniqueTurnContext::new(self));
                 if self.is_race_turn() {
                     self.reset_distribution();
                 } else {
@@ -421,6 +502,7 @@ impl Game for BasicGame {
                 self.apply_action(&actions[selection], rng)?;
             }
             TurnStage::AfterTrain => {
+                self.unique_ctx = None;
                 let after_events = std::mem::take(&mut self.unresolved_events);
                 for event in &after_events {
                     self.run_event(event, trainer, rng)?;
@@ -439,24 +521,22 @@ impl Game for BasicGame {
                 self.add_friendship(*person_index as usize, value.friendship);
             }
         }
+        // 事件获得的状态
+        for name in self.uma.flags.grant_by_event(event.id, rng) {
+            warn!(">> 获得【{name}】");
+        }
         // 判断特殊事件
         match event.id {
             4012 | 4013 => {
                 // 继承
+                let result = self.inherit.inherit_event(rng);
                 let inherit_value = ActionValue {
-                    status_pt: self.inherit.inherit(rng),
+                    status_pt: result.status,
+                    hint_level: result.hint_level,
                     ..Default::default()
                 };
-                let inherit_limit = self.inherit.inherit_limit(rng);
                 self.uma.add_value(&inherit_value);
-                self.uma.five_status_limit.add_eq(&inherit_limit);
-            }
-            5007 => {
-                // 大成功事件
-                if rng.random_bool(system_event_prob("qiezhe_normal")?) {
-                    warn!(">> 获得【切者】");
-                    self.uma.flags.qiezhe = true;
-                }
+                self.uma.five_status_limit.add_eq(&result.limit);
             }
             809050004 => {
                 // 友人出门事件
@@ -471,8 +551,9 @@ impl Game for BasicGame {
     fn deyilv(&mut self, person_index: i32) -> Result<f32> {
         if person_index < 6 {
             let (eff, lock) = self.deck[person_index as usize].calc_training_effect(self, 0)?;
-            self.deck[person_index as usize].effect = eff.clone();
+            // 只有锁定的固有可以写回面板，随局面变化的固有每次重新计算
             if lock {
+                self.deck[person_index as usize].effect = eff.clone();
                 self.deck[person_index as usize].is_locked = true;
             }
             Ok(eff.deyilv)
@@ -530,6 +611,9 @@ impl Game for BasicGame {
     fn turn(&self) -> i32 {
         self.turn
     }
+    fn stage_name(&self) -> String {
+        format!("{:?}", self.stage)
+    }
     fn max_turn(&self) -> i32 {
         77
     }
@@ -542,6 +626,9 @@ impl Game for BasicGame {
     fn deck(&self) -> &Vec<SupportCard> {
         &self.deck
     }
+    fn unique_turn_context(&self) -> Option<&UniqueTurnContext> {
+        self.unique_ctx.as_ref()
+    }
     fn distribution(&self) -> &Vec<Vec<i32>> {
         &self.distribution
     }
@@ -549,7 +636,10 @@ impl Game for BasicGame {
         &mut self.distribution
     }
     fn has_group_buff(&self) -> bool {
-        self.friend.group_buff_turn > 0
+        self.friend.group_buff_turn > 0 || self.has_card_group_buff()
+    }
+    fn has_group_buff_at(&self, person_index: usize) -> bool {
+        self.group_buff_at(person_index)
     }
     fn train_level(&self, train: usize) -> usize {
         if self.is_xiahesu() {
@@ -574,7 +664,8 @@ mod tests {
         init_global()?;
         let mut game = BasicGame::newgame(101901, &[302424, 302464, 302484, 302564, 302574, 302644], InheritInfo {
             blue_count: [15, 3, 0, 0, 0],
-            extra_count: [0, 30, 0, 0, 30, 30]
+            extra_count: [0, 30, 0, 0, 30, 30],
+            ..Default::default()
         })?;
         println!("{}", game.explain()?);
         let score = game.uma.calc_score();
@@ -592,4 +683,25 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_ura_finals() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let mut game = BasicGame::newgame(101901, &[302424, 302464, 302484, 302564, 302574, 302644], InheritInfo {
+            blue_count: [15, 3, 0, 0, 0],
+            ..Default::default()
+        })?;
+        // 从决赛期间开始
+        game.turn = 72;
+        let trainer = RandomTrainer {};
+        let mut rng = StdRng::seed_from_u64(1);
+        game.run_full_game(&trainer, &mut rng)?;
+        println!("{}", game.explain_ura());
+        // URA决赛三场和优胜奖励都已结算
+        for id in [4021, 4022, 4023, 5012] {
+            assert_eq!(game.events.get(&id), Some(&1));
+        }
+        Ok(())
+    }
 }
@@ -4,13 +4,20 @@ pub mod person;
 use std::{default::Default, sync::Arc};
 
 pub use action::*;
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use hashbrown::HashMap;
 use log::{info, warn};
 pub use person::*;
-use rand::{rngs::StdRng, seq::IndexedRandom};
+use rand::{Rng, rngs::StdRng, seq::IndexedRandom};
+use rand_distr::{Distribution, weighted::WeightedIndex};
 
-use crate::{explain::Explain, game::*, gamedata::EventData, utils::*};
+use crate::{
+    explain::Explain,
+    game::*,
+    gamedata::{ActionValue, EventData, FailureOutcome, GAMECONSTANTS},
+    global,
+    utils::*
+};
 
 /// 一局游戏的基本状态，剧本通用，用于计算，不用于通信(例如通信只传递卡组id)  
 /// 不包含人头信息(Person类型可能不同)，实际的剧本对象需要补上Vec<Person>才能实现Game Trait    
@@ -29,6 +36,10 @@ pub struct BaseGame {
     pub inherit: Arc<InheritInfo>,
     /// 友人数据
     pub friend: FriendState,
+    /// 非剧本友人卡和团队卡
+    pub card_friends: Vec<CardFriendState>,
+    /// 最近一次训练失败的回合和结果
+    pub last_failure: Option<(i32, String)>,
     /// 设施等级计数 (设施等级x4)
     pub train_level_count: Array5,
     /// 人头分布 [训练, persons_index]. -1为不在    
@@ -41,7 +52,9 @@ pub struct BaseGame {
     /// 本回合内还没触发的事件(Hint, 点击友人等)
     pub unresolved_events: Vec<EventData>,
     /// 每种训练卡数量，用于训练倾向和固有判断
-    pub card_type_count: Arc<[i32; 7]>
+    pub card_type_count: Arc<[i32; 7]>,
+    /// 本回合固有计算共用的局面信息，分配人头时更新，训练后清空
+    pub unique_ctx: Option<UniqueTurnContext>
 }
 
 impl BaseGame {
@@ -54,10 +67,25 @@ impl BaseGame {
             Explain::train_level_count(&self.train_level_count),
             self.friend.explain()
         ));
+        if !self.card_friends.is_empty() {
+            let text: Vec<_> = self.card_friends.iter().map(|f| f.explain()).collect();
+            lines.push(format!("友人卡: {}", text.join(" ")));
+        }
         lines.push(self.uma.explain()?);
+        if let Some(line) = self.explain_failure() {
+            lines.push(line);
+        }
         Ok(lines.join("\n"))
     }
 
+    /// 上回合或本回合的训练失败结果
+    pub fn explain_failure(&self) -> Option<String> {
+        match &self.last_failure {
+            Some((turn, name)) if turn + 1 >= self.turn => Some(format!("回合{}训练失败: {name}", turn + 1)),
+            _ => None
+        }
+    }
+
     /// 建立游戏对象
     pub fn new(uma_id: u32, deck_ids: &[u32; 6], inherit: InheritInfo) -> Result<Self> {
         let mut uma = Uma::new(uma_id)?;
@@ -81,8 +109,8 @@ impl BaseGame {
             uma.five_status.add_eq(initial);
             uma.skill_pt += pt;
             uma.race_bonus += race_bonus;
-            // 友人. 暂时不处理多个友人
-            if card.card_type >= 5 {
+            // 剧本友人取第一张友人卡，其他友人卡和团队卡在init_persons里加入card_friends
+            if card.card_type == 5 && friend_id.is_none() {
                 friend_id = Some(*id);
                 friend_index = index;
             }
@@ -92,6 +120,26 @@ impl BaseGame {
             }
             deck.push(card);
         }
+        // 开局生效的固有: 初始属性和卡组初始羁绊
+        let mut friendship_bonus = 0;
+        for card in &deck {
+            if let Some(rule) = &card.unique_rule {
+                let bonus = rule.initial_status(&card_type_count);
+                if !bonus.is_default() {
+                    info!("{} 固有+初始属性 {bonus:?}", card.short_name());
+                    let (bonus, pt) = split_status(&bonus)?;
+                    uma.five_status.add_eq(bonus);
+                    uma.skill_pt += pt;
+                }
+                friendship_bonus += rule.initial_friendship();
+            }
+        }
+        if friendship_bonus > 0 {
+            info!("固有+初始羁绊 {friendship_bonus}");
+            for card in &mut deck {
+                card.friendship = (card.friendship + friendship_bonus).min(100);
+            }
+        }
         // 继承
         let newgame_inherit = inherit.inherit_newgame();
         let newgame_limit_inherit = inherit.inherit_limit_newgame();
@@ -105,12 +153,15 @@ impl BaseGame {
             deck,
             inherit: Arc::new(inherit),
             friend: FriendState::new(friend_id, friend_index)?,
+            card_friends: vec![],
+            last_failure: None,
             train_level_count: [0; 5],
             distribution: vec![],
             events: HashMap::new(),
             absent_rate_drop: 0,
             unresolved_events: vec![],
-            card_type_count: Arc::new(card_type_count)
+            card_type_count: Arc::new(card_type_count),
+            unique_ctx: None
         })
     }
 
@@ -131,38 +182,202 @@ impl BaseGame {
         if !event.choices.is_empty() {
             self.uma.add_value(&event.choices[choice]);
         }
+        // 支援卡连续事件推进到下一段
+        if let Some(index) = event.person_index
+            && let Some(card) = self.deck.get_mut(index as usize)
+            && card.card_events().get(card.event_progress).is_some_and(|e| e.id == event.id)
+        {
+            card.event_progress += 1;
+        }
+        // 非剧本友人卡/团队卡出行解锁
+        if let Some(index) = event.person_index
+            && let Some(friend) = self.card_friends.iter_mut().find(|f| f.person_index as i32 == index)
+            && friend.events().is_some_and(|e| e.unlock.id == event.id)
+        {
+            info!(">> 友人卡#{index}出行已解锁");
+            friend.out_state = FriendOutState::AfterUnlock;
+        }
+    }
+
+    /// 训练失败时按失败结果分布抽取结果，返回要结算的事件和结果
+    pub fn roll_training_failure(
+        &self, train: usize, failure_rate: f32, rng: &mut StdRng
+    ) -> Result<(EventData, &'static FailureOutcome)> {
+        let data = &global!(GAMECONSTANTS).training_failure;
+        let rate = failure_rate as f64;
+        let big = rate >= data.big_min_rate && rng.random_bool((rate * data.big_rate_scale).clamp(0.0, 1.0));
+        let outcomes: Vec<_> = data.outcomes.iter().filter(|o| o.big == big).collect();
+        if outcomes.is_empty() {
+            return Err(anyhow!("缺少训练失败结果数据: big={big}"));
+        }
+        let weights = WeightedIndex::new(outcomes.iter().map(|o| o.weight))?;
+        let outcome = outcomes[weights.sample(rng)];
+        let mut event = system_event(if big { "training_fail_low" } else { "training_fail" })?.clone();
+        event.name = outcome.name.clone();
+        let mut value = ActionValue {
+            motivation: outcome.motivation,
+            vital: outcome.vital,
+            ..Default::default()
+        };
+        value.status_pt[train] -= outcome.train_loss;
+        let others: Vec<_> = (0..5).filter(|i| *i != train).collect();
+        for i in others.choose_multiple(rng, outcome.random_loss_count) {
+            value.status_pt[*i] -= outcome.random_loss;
+        }
+        event.choices = vec![value];
+        Ok((event, outcome))
+    }
+
+    /// 结算训练失败结果带来的坏状态，并记录失败结果
+    pub fn apply_failure_outcome(&mut self, outcome: &FailureOutcome, rng: &mut StdRng) {
+        for (key, prob) in &outcome.conditions {
+            if let Some(name) = self.uma.flags.grant(key, *prob, rng) {
+                warn!(">> 获得【{name}】");
+            }
+        }
+        self.last_failure = Some((self.turn, outcome.name.clone()));
+    }
+
+    /// 回合开始时结算状态发作
+    pub fn update_conditions(&mut self, rng: &mut StdRng) {
+        for (name, value) in self.uma.flags.roll_turn_effects(rng) {
+            warn!(">> 【{name}】发作");
+            self.uma.add_value(value);
+        }
+    }
+
+    /// 友情训练成功后，叠加参加训练的支援卡固有
+    pub fn on_shining_train(&mut self, train: usize) {
+        for person_index in self.distribution[train].clone() {
+            if (0..self.deck.len() as i32).contains(&person_index) {
+                self.deck[person_index as usize].on_shining_train();
+            }
+        }
     }
 
     pub fn is_xiahesu(&self) -> bool {
         (self.turn >= 36 && self.turn < 40) || (self.turn >= 60 && self.turn < 64)
     }
 
-    pub fn generate_card_event(&self, person_index: i32, rng: &mut StdRng) -> Option<EventData> {
-        // 支援卡事件. 再精细一点模拟 后一段事件发生次数不能多于前一段事件
-        let card_event_times: Vec<_> = vec![8001, 8002, 8003]
+    /// 记录卡组中有事件数据的非剧本友人卡和团队卡
+    pub fn init_card_friends(&mut self, persons: &[BasePerson]) {
+        self.card_friends = persons
             .iter()
-            .map(|x| *self.events.get(x).unwrap_or(&0))
+            .enumerate()
+            .filter(|(i, p)| {
+                *i < self.deck.len() && matches!(p.person_type, PersonType::OtherFriend | PersonType::TeamCard)
+            })
+            .filter_map(|(i, _)| CardFriendState::new(&self.deck[i], i))
             .collect();
-        let mut available_events = vec![];
-        if card_event_times[0] < 5 {
-            available_events.push(0);
+    }
+
+    /// 点击非剧本友人卡/团队卡，返回点击事件。团队卡羁绊足够时可能开启团队Buff
+    pub fn click_card_friend(&mut self, person_index: usize, friendship: i32, rng: &mut StdRng) -> Option<EventData> {
+        let friend = self.card_friends.iter_mut().find(|f| f.person_index == person_index)?;
+        let events = friend.events()?;
+        if friend.group_buff_turn == 0
+            && events.group_buff_friendship > 0
+            && friendship >= events.group_buff_friendship
+            && rng.random_bool(events.group_buff_prob as f64 / 100.0)
+        {
+            info!(">> 团队卡#{person_index}开启团队Buff");
+            friend.group_buff_turn = 1;
         }
-        if card_event_times[1] < card_event_times[0] {
-            available_events.push(1);
+        let event = if friend.out_state == FriendOutState::UnClicked {
+            friend.out_state = FriendOutState::BeforeUnlock;
+            &events.first
+        } else {
+            &events.click
+        };
+        Some(friend.bonus_event(event))
+    }
+
+    /// 判断非剧本友人卡/团队卡的出行解锁事件，概率与剧本友人相同
+    pub fn check_card_friend_unlock(&self, persons: &[BasePerson], rng: &mut StdRng) -> Option<EventData> {
+        for friend in &self.card_friends {
+            if friend.out_state == FriendOutState::BeforeUnlock
+                && let Some(events) = friend.events()
+            {
+                let key = if persons[friend.person_index].friendship < 60 {
+                    "friend_unlock_low"
+                } else {
+                    "friend_unlock_high"
+                };
+                let out_prob = system_event_prob(key).expect("friend_unlock_* prob key not found");
+                if rng.random_bool(out_prob) {
+                    return Some(friend.bonus_event(&events.unlock));
+                }
+            }
         }
-        if card_event_times[2] < card_event_times[1] {
-            available_events.push(2);
+        None
+    }
+
+    /// 回合开始时更新团队Buff，持续回合越多越容易结束
+    pub fn update_group_buff(&mut self, rng: &mut StdRng) {
+        let end_probs = &global!(GAMECONSTANTS).group_buff_end_prob;
+        for friend in &mut self.card_friends {
+            let t = friend.group_buff_turn as usize;
+            if t > 0 {
+                friend.group_buff_turn += 1;
+                if rng.random_bool(end_probs[t.min(end_probs.len() - 1)]) {
+                    info!(">> 团队卡#{}的团队Buff结束", friend.person_index);
+                    friend.group_buff_turn = 0;
+                }
+            }
         }
-        if let Some(index) = available_events.choose(rng) {
-            let mut event = global_events().card_events[*index].clone();
+    }
+
+    /// 是否有非剧本团队卡的团队Buff
+    pub fn has_card_group_buff(&self) -> bool {
+        self.card_friends.iter().any(|f| f.group_buff_turn > 0)
+    }
 
-            event.person_index = Some(person_index);
-            Some(event)
+    /// 指定团队卡自己的团队Buff是否开启，非剧本团队卡看自己的Buff，其他看剧本友人的Buff
+    pub fn group_buff_at(&self, person_index: usize) -> bool {
+        match self.card_friends.iter().find(|f| f.person_index == person_index) {
+            Some(friend) => friend.group_buff_turn > 0,
+            None => self.friend.group_buff_turn > 0
+        }
+    }
+
+    /// 理事长/记者的连续事件，按顺序判定下一段是否触发
+    pub fn check_noncard_event(&self, persons: &[BasePerson], rng: &mut StdRng) -> Option<EventData> {
+        for person in persons {
+            if let Some(data) = person.noncard_data()
+                && let Some(event) = data.next_event(&self.events, self.turn, person.friendship)
+                && rng.random_bool(event.prob as f64 / 100.0)
+            {
+                let mut event = event.clone();
+                event.person_index = Some(person.person_index);
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// 支援卡连续事件，按概率触发这张卡的下一段
+    pub fn generate_card_event(&self, person_index: i32, rng: &mut StdRng) -> Option<EventData> {
+        let event = self.next_card_event(person_index as usize)?;
+        if rng.random_bool(event.prob as f64 / 100.0) {
+            let value = self.deck[person_index as usize].card_value();
+            Some(event.with_bonus(person_index, value.event_effect_up, value.event_recovery_amount_up))
         } else {
             None
         }
     }
 
+    /// 支援卡下一段可以触发的连续事件，每段事件整局最多触发max_trigger_time次
+    fn next_card_event(&self, person_index: usize) -> Option<&'static EventData> {
+        let event = self.deck.get(person_index)?.next_card_event(self.turn)?;
+        let times = *self.events.get(&event.id).unwrap_or(&0);
+        (event.max_trigger_time == 0 || times < event.max_trigger_time).then_some(event)
+    }
+
+    /// 可以触发连续事件的支援卡
+    pub fn has_card_event(&self, person_index: usize) -> bool {
+        self.next_card_event(person_index).is_some()
+    }
+
     /// 检测自选比赛是否达标
     pub fn check_free_race(&self) -> bool {
         if let Ok(data) = self.uma.get_data() {
@@ -190,6 +405,7 @@ impl BaseGame {
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
+    use rand::SeedableRng;
 
     use super::*;
     use crate::{gamedata::*, utils::init_logger};
@@ -207,17 +423,78 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_training_failure() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let game = BaseGame::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        // 失败率100%时必定大失败
+        let (event, outcome) = game.roll_training_failure(2, 1.0, &mut rng)?;
+        assert!(outcome.big);
+        let value = &event.choices[0];
+        assert_eq!(value.status_pt[2], -outcome.train_loss);
+        let others = (0..5).filter(|i| *i != 2 && value.status_pt[*i] < 0).count();
+        assert_eq!(others, outcome.random_loss_count);
+        // 大失败事件按状态表获得生病和不擅长训练
+        let mut flags = game.uma.flags.clone();
+        flags.grant_by_event(event.id, &mut rng);
+        assert!(flags.ill && flags.bad_trainer);
+        Ok(())
+    }
+
     #[test]
     fn test_newgame() -> Result<()> {
         init_logger("test", "info")?;
         init_global()?;
         let game = BaseGame::new(101901, &[302424, 302464, 302484, 302564, 302574, 302644], InheritInfo {
             blue_count: [15, 3, 0, 0, 0],
-            extra_count: [0, 30, 0, 0, 30, 30]
+            extra_count: [0, 30, 0, 0, 30, 30],
+            ..Default::default()
         })?;
         println!("{}", game.explain()?);
         let score = game.uma.calc_score();
         println!("评分: {} {}", global!(GAMECONSTANTS).get_rank_name(score), score);
         Ok(())
     }
+
+    #[test]
+    fn test_card_event_chain() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let mut game = BaseGame::new(101901, &[302424, 302464, 302484, 302564, 302574, 302644], Default::default())?;
+        let mut rng = StdRng::seed_from_u64(4);
+        let chain = game.deck[0].card_events();
+        assert_eq!(chain.iter().map(|e| e.id).collect::<Vec<_>>(), [8001, 8002, 8003]);
+        game.turn = 5;
+        game.apply_event(&game.generate_card_event(0, &mut rng).expect("first"), 0);
+        assert_eq!(game.deck[0].event_progress, 1);
+        // 按顺序触发，不是这张卡下一段的事件不推进
+        game.apply_event(&chain[2].with_bonus(0, 0, 0), 0);
+        assert_eq!(game.deck[0].event_progress, 1);
+        for expected in &chain[1..] {
+            let event = (0..100)
+                .find_map(|_| game.generate_card_event(0, &mut rng))
+                .expect("chain event");
+            assert_eq!(event.id, expected.id);
+            // 事件效果加成
+            let bonus = game.deck[0].card_value().event_effect_up;
+            assert_eq!(
+                event.choices[0].status_pt[0],
+                expected.choices[0].status_pt[0] * (100 + bonus) / 100
+            );
+            game.apply_event(&event, 0);
+        }
+        assert_eq!(game.deck[0].event_progress, 3);
+        assert!(game.generate_card_event(0, &mut rng).is_none());
+        // 最后一段教技能
+        assert!(chain[2].choices.iter().all(|c| c.hint_level > 0));
+        // 其他卡的进度不受影响
+        assert_eq!(game.deck[1].event_progress, 0);
+        assert!(game.has_card_event(1));
+        // 每段事件整局最多触发max_trigger_time次
+        game.events.insert(chain[0].id, chain[0].max_trigger_time);
+        assert!(!game.has_card_event(1));
+        Ok(())
+    }
 }
@@ -1,8 +1,14 @@
 use std::default::Default;
 
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 
-use crate::{game::*, gamedata::GAMEDATA, global};
+use crate::{
+    game::*,
+    gamedata::{EventData, GAMEDATA, NonCardPersonData},
+    global,
+    utils::{global_events, system_event}
+};
 
 /// 训练人头信息（动态）
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
@@ -78,6 +84,31 @@ impl BasePerson {
             card_id: None
         }
     }
+
+    /// 理事长/记者的数据，其他人头为None
+    pub fn noncard_data(&self) -> Option<&'static NonCardPersonData> {
+        let noncard = &global_events().noncard;
+        match self.person_type {
+            PersonType::Yayoi => Some(&noncard.yayoi),
+            PersonType::Reporter => Some(&noncard.reporter),
+            _ => None
+        }
+    }
+
+    /// 在训练train点击理事长/记者的事件，效果按羁绊分档
+    pub fn noncard_click_event(&self, train: usize) -> Result<EventData> {
+        let key = match self.person_type {
+            PersonType::Yayoi => "yayoi_click",
+            PersonType::Reporter => "reporter_click",
+            _ => return Err(anyhow!("不是理事长或记者: {:?}", self.person_type))
+        };
+        let mut event = system_event(key)?.clone();
+        event.person_index = Some(self.person_index);
+        if let Some(value) = self.noncard_data().and_then(|d| d.click_value(self.friendship, train)) {
+            event.choices = vec![value];
+        }
+        Ok(event)
+    }
 }
 
 impl Person for BasePerson {
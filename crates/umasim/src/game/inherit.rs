@@ -1,18 +1,122 @@
 use std::default::Default;
 
+use log::debug;
 use rand::{Rng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
 
 //use colored::Colorize;
 use crate::{explain::Explain, utils::*};
 
+/// 只有因子数量的旧配置转换成种马时使用的相性，保证因子必定发动
+pub const DEFAULT_AFFINITY: i32 = 150;
+/// 蓝因子按星数(1-3)的基础发动率
+const BLUE_PROC_RATE: [f64; 3] = [0.7, 0.8, 0.9];
+/// 绿/白因子按星数(1-3)的基础发动率
+const GREEN_WHITE_PROC_RATE: [f64; 3] = [0.05, 0.1, 0.15];
+
+/// 因子类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FactorKind {
+    /// 蓝因子(属性)
+    #[default]
+    Blue,
+    /// 绿因子(固有技能)
+    Green,
+    /// 白因子(技能)
+    White,
+    /// 剧本因子(属性和pt)
+    Scenario
+}
+
+/// 单个因子
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InheritFactor {
+    /// 因子类型
+    pub kind: FactorKind,
+    /// 星数 1-3，合并后的因子可以超过3
+    pub stars: i32,
+    /// 蓝因子对应的属性 [0, 5)
+    #[serde(default)]
+    pub stat: usize,
+    /// 剧本因子提供的属性和pt
+    #[serde(default)]
+    pub status: Array6
+}
+
+impl InheritFactor {
+    /// 在相性affinity下的发动率
+    pub fn proc_prob(&self, affinity: i32) -> f64 {
+        let star = (self.stars.clamp(1, 3) - 1) as usize;
+        let base = match self.kind {
+            FactorKind::Blue => BLUE_PROC_RATE[star],
+            FactorKind::Green | FactorKind::White => GREEN_WHITE_PROC_RATE[star],
+            FactorKind::Scenario => 1.0
+        };
+        (base * (1.0 + affinity as f64 / 100.0)).clamp(0.0, 1.0)
+    }
+
+    pub fn explain(&self) -> String {
+        match self.kind {
+            FactorKind::Blue => format!("蓝{}{}星", ["速", "耐", "力", "根", "智"][self.stat.min(4)], self.stars),
+            FactorKind::Green => format!("绿{}星", self.stars),
+            FactorKind::White => format!("白{}星", self.stars),
+            FactorKind::Scenario => format!("剧本 {}", Explain::status_with_pt(&self.status))
+        }
+    }
+}
+
+/// 种马(父母)或祖代的因子
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InheritParent {
+    /// 马娘编号，0为未知
+    #[serde(default)]
+    pub uma_id: u32,
+    /// 与育成马娘的相性
+    #[serde(default)]
+    pub affinity: i32,
+    /// 因子
+    #[serde(default)]
+    pub factors: Vec<InheritFactor>,
+    /// 祖代，最多2个，祖代不再有祖代
+    #[serde(default)]
+    pub grandparents: Vec<InheritParent>
+}
+
+impl InheritParent {
+    /// 自身和祖代的因子，附带各自的相性
+    pub fn all_factors(&self) -> Vec<(&InheritFactor, i32)> {
+        let mut ret: Vec<_> = self.factors.iter().map(|f| (f, self.affinity)).collect();
+        for gp in &self.grandparents {
+            ret.extend(gp.factors.iter().map(|f| (f, gp.affinity)));
+        }
+        ret
+    }
+}
+
+/// 一次继承事件的结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InheritResult {
+    /// 属性和pt
+    pub status: Array6,
+    /// 属性上限
+    pub limit: Array5,
+    /// 绿白因子带来的Hint等级
+    pub hint_level: i32,
+    /// 发动的因子
+    pub procs: Vec<String>
+}
+
 /// 继承信息，剧本通用
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct InheritInfo {
     /// 蓝因子数量
     pub blue_count: Array5,
     /// 剧本因子属性
-    pub extra_count: Array6
+    pub extra_count: Array6,
+    /// 详细的种马和祖代因子，为空时按blue_count/extra_count转换
+    #[serde(default)]
+    pub parents: Vec<InheritParent>
 }
 
 impl InheritInfo {
@@ -24,6 +128,63 @@ impl InheritInfo {
         )
     }
 
+    /// 从详细的种马信息建立，蓝因子和剧本因子数量由因子汇总
+    pub fn from_parents(parents: Vec<InheritParent>) -> Self {
+        let mut blue_count = Array5::default();
+        let mut extra_count = Array6::default();
+        for parent in &parents {
+            for (factor, _) in parent.all_factors() {
+                match factor.kind {
+                    FactorKind::Blue if factor.stat < 5 => blue_count[factor.stat] += factor.stars,
+                    FactorKind::Scenario => {
+                        extra_count.add_eq(&factor.status);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Self {
+            blue_count,
+            extra_count,
+            parents
+        }
+    }
+
+    /// 旧配置(只有因子数量)转换成一个必定发动的种马
+    pub fn parents_from_counts(blue_count: &Array5, extra_count: &Array6) -> Vec<InheritParent> {
+        let mut factors: Vec<_> = (0..5)
+            .filter(|i| blue_count[*i] > 0)
+            .map(|i| InheritFactor {
+                kind: FactorKind::Blue,
+                stars: blue_count[i],
+                stat: i,
+                ..Default::default()
+            })
+            .collect();
+        if !extra_count.is_default() {
+            factors.push(InheritFactor {
+                kind: FactorKind::Scenario,
+                stars: 1,
+                status: *extra_count,
+                ..Default::default()
+            });
+        }
+        vec![InheritParent {
+            affinity: DEFAULT_AFFINITY,
+            factors,
+            ..Default::default()
+        }]
+    }
+
+    /// 实际参与继承的种马
+    pub fn effective_parents(&self) -> Vec<InheritParent> {
+        if self.parents.is_empty() {
+            Self::parents_from_counts(&self.blue_count, &self.extra_count)
+        } else {
+            self.parents.clone()
+        }
+    }
+
     /// 获取开局属性
     pub fn inherit_newgame(&self) -> Array5 {
         let mut ret = self.blue_count.clone();
@@ -33,16 +194,38 @@ impl InheritInfo {
         ret
     }
 
-    /// 获取局中继承属性
-    pub fn inherit(&self, rng: &mut StdRng) -> Array6 {
-        let mut ret = Array6::default();
+    /// 局中继承事件：逐个因子判定发动，返回属性、上限和Hint
+    pub fn inherit_event(&self, rng: &mut StdRng) -> InheritResult {
+        let mut ret = InheritResult::default();
+        let mut blue_stars = Array5::default();
+        let mut extra = Array6::default();
+        // 剧本因子的随机量在一次继承内共用
         let extra_scale: f64 = rng.random_range(0.0..2.0);
+        for parent in self.effective_parents() {
+            for (factor, affinity) in parent.all_factors() {
+                if !rng.random_bool(factor.proc_prob(affinity)) {
+                    continue;
+                }
+                match factor.kind {
+                    FactorKind::Blue if factor.stat < 5 => blue_stars[factor.stat] += factor.stars,
+                    FactorKind::Green | FactorKind::White => ret.hint_level += factor.stars,
+                    FactorKind::Scenario => {
+                        extra.add_eq(&factor.status);
+                    }
+                    _ => {}
+                }
+                ret.procs.push(factor.explain());
+            }
+        }
         for i in 0..5 {
             // 属性: 蓝因子 x 6+剧本因子 x extra_scale
-            ret[i] = self.blue_count[i] * 6 + (extra_scale * self.extra_count[i] as f64).round() as i32;
+            ret.status[i] = blue_stars[i] * 6 + (extra_scale * extra[i] as f64).round() as i32;
+            // 3星蓝因子提供16上限
+            ret.limit[i] = (blue_stars[i] as f32 * 5.33) as i32;
         }
         // pt
-        ret[5] = (self.extra_count[5] as f64 * (0.5 + 0.5 * extra_scale)).round() as i32;
+        ret.status[5] = (extra[5] as f64 * (0.5 + 0.5 * extra_scale)).round() as i32;
+        debug!("继承发动: {}", ret.procs.join(" "));
         ret
     }
 
@@ -54,15 +237,6 @@ impl InheritInfo {
         }
         ret
     }
-
-    /// 获取局中继承上限 0-8
-    pub fn inherit_limit(&self, _rng: &mut StdRng) -> Array5 {
-        let mut ret = self.blue_count.clone();
-        for i in 0..5 {
-            ret[i] = (ret[i] as f32 * 5.33) as i32;
-        }
-        ret
-    }
 }
 
 #[cfg(test)]
@@ -74,9 +248,12 @@ mod tests {
 
     #[test]
     fn test_inherit() -> Result<()> {
+        init_logger("test", "info")?;
+        crate::gamedata::init_global()?;
         let ii = InheritInfo {
             blue_count: [15, 3, 0, 0, 0],
-            extra_count: [0, 30, 0, 0, 30, 40]
+            extra_count: [0, 30, 0, 0, 30, 40],
+            ..Default::default()
         };
         let mut rng = StdRng::from_os_rng();
         println!(
@@ -84,16 +261,40 @@ mod tests {
             ii.inherit_newgame(),
             ii.inherit_limit_newgame()
         );
-        println!(
-            "first time: {:?} limit: {:?}",
-            ii.inherit(&mut rng),
-            ii.inherit_limit(&mut rng)
-        );
-        println!(
-            "second time: {:?} limit: {:?}",
-            ii.inherit(&mut rng),
-            ii.inherit_limit(&mut rng)
-        );
+        let first = ii.inherit_event(&mut rng);
+        println!("first time: {:?} limit: {:?}", first.status, first.limit);
+        let second = ii.inherit_event(&mut rng);
+        println!("second time: {:?} limit: {:?}", second.status, second.limit);
+        // 旧配置转换后蓝因子必定发动
+        assert_eq!(first.status[0], 90);
+        assert_eq!(first.limit, [79, 15, 0, 0, 0]);
         Ok(())
     }
+
+    #[test]
+    fn test_inherit_parents() {
+        let parent = InheritParent {
+            affinity: 0,
+            factors: vec![InheritFactor {
+                kind: FactorKind::Blue,
+                stars: 3,
+                stat: 0,
+                ..Default::default()
+            }],
+            grandparents: vec![InheritParent {
+                factors: vec![InheritFactor {
+                    kind: FactorKind::Blue,
+                    stars: 2,
+                    stat: 3,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let ii = InheritInfo::from_parents(vec![parent]);
+        assert_eq!(ii.blue_count, [3, 0, 0, 2, 0]);
+        assert!((ii.parents[0].factors[0].proc_prob(0) - 0.9).abs() < 1e-9);
+        assert_eq!(ii.parents[0].factors[0].proc_prob(50), 1.0);
+    }
 }
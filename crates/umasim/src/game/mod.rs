@@ -1,10 +1,12 @@
 pub mod base;
 pub mod inherit;
 pub mod onsen;
+pub mod race_planner;
 pub mod simulator;
 pub mod support_card;
 pub mod traits;
 pub mod uma;
+pub mod unique_effect;
 
 use std::{default::Default, fmt::Display};
 
@@ -16,8 +18,13 @@ use serde::{Deserialize, Serialize};
 pub use support_card::*;
 pub use traits::*;
 pub use uma::*;
+pub use unique_effect::*;
 
-use crate::{gamedata::GAMEDATA, global};
+use crate::{
+    gamedata::{CardFriendEvents, EventData, GAMEDATA},
+    global,
+    utils::global_events
+};
 
 /// 回合阶段
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, Sequence)]
@@ -170,6 +177,75 @@ impl FriendState {
     }
 }
 
+/// 非剧本友人卡和团队卡的出行和团队Buff，剧本通用
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CardFriendState {
+    /// 在Persons中是哪一个
+    pub person_index: usize,
+    /// 是否为团队卡
+    pub is_team: bool,
+    /// 出行阶段
+    pub out_state: FriendOutState,
+    /// 已经出行几次
+    pub out_count: usize,
+    /// 团队Buff已经持续几回合，0为未开启
+    pub group_buff_turn: u32,
+    /// 事件体力回复量加成
+    pub vital_bonus: i32,
+    /// 事件效果加成
+    pub event_bonus: i32,
+    /// 这张卡的点击和出行事件
+    #[serde(skip)]
+    pub data: Option<&'static CardFriendEvents>
+}
+
+impl CardFriendState {
+    /// 没有这张卡的事件数据时返回None，不模拟出行和团队Buff
+    pub fn new(card: &SupportCard, index: usize) -> Option<Self> {
+        let data = global_events().card_friend.cards.get(&card.card_id.to_string())?;
+        let value = card.card_value();
+        Some(Self {
+            person_index: index,
+            is_team: card.card_type == 6,
+            vital_bonus: value.event_recovery_amount_up,
+            event_bonus: value.event_effect_up,
+            data: Some(data),
+            ..Default::default()
+        })
+    }
+
+    /// 这张卡的点击和出行事件
+    pub fn events(&self) -> Option<&'static CardFriendEvents> {
+        self.data
+    }
+
+    /// 下一次出行的事件
+    pub fn next_outing(&self) -> Option<&'static EventData> {
+        self.events()?.outings.get(self.out_count)
+    }
+
+    /// 第turn回合是否可以出行
+    pub fn can_outing(&self, turn: i32) -> bool {
+        self.out_state == FriendOutState::AfterUnlock && self.next_outing().is_some() && turn < 72
+    }
+
+    /// 设置羁绊目标并乘算事件效果和体力回复加成
+    pub fn bonus_event(&self, event: &EventData) -> EventData {
+        event.with_bonus(self.person_index as i32, self.event_bonus, self.vital_bonus)
+    }
+
+    pub fn explain(&self) -> String {
+        let mut ret = format!("#{} {}", self.person_index, self.out_state.explain());
+        if self.out_count > 0 {
+            ret += &self.out_count.to_string();
+        }
+        if self.group_buff_turn > 0 {
+            ret += &format!(" 团队Buff已持续 {} 回合", self.group_buff_turn);
+        }
+        ret
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -33,7 +33,11 @@ pub enum OnsenAction {
     /// 选择温泉
     Dig(i32),
     /// 升级工具
-    Upgrade(i32)
+    Upgrade(i32),
+    /// 选择温泉并升级工具，只用于联合搜索
+    DigUpgrade(i32, i32),
+    /// 非剧本友人卡/团队卡出行(人头编号)
+    CardOuting(i32)
 }
 
 impl Display for OnsenAction {
@@ -61,6 +65,12 @@ impl Display for OnsenAction {
                 let name = &global!(ONSENDATA).dig_tool_name[*x as usize];
                 write!(f, "升级 {}", name.bright_yellow())
             }
+            OnsenAction::DigUpgrade(x, y) => {
+                let onsen = &global!(ONSENDATA).onsen_info[*x as usize].name;
+                let tool = &global!(ONSENDATA).dig_tool_name[*y as usize];
+                write!(f, "挖掘 {} + 升级 {}", onsen.bright_yellow(), tool.bright_yellow())
+            }
+            OnsenAction::CardOuting(x) => write!(f, "友人卡#{x}出行")
         }
     }
 }
@@ -134,8 +144,8 @@ impl ActionEnum for OnsenAction {
                 // 注：比赛的体力效果通过事件处理，超回复已经在事件里判定
                 Ok(())
             }
-            // ========== 休息/普通外出/治病 ==========
-            OnsenAction::Sleep | OnsenAction::NormalOuting | OnsenAction::Clinic => {
+            // ========== 休息/普通外出/治病/友人卡外出 ==========
+            OnsenAction::Sleep | OnsenAction::NormalOuting | OnsenAction::Clinic | OnsenAction::CardOuting(_) => {
                 self.as_base_action().expect("as_base_action").apply(game, rng)?;
                 // 执行挖掘 (Clinic 挖掘值为0，但仍需调用)
                 if let Some(dig_value) = game.calc_dig_value(self) {
@@ -176,7 +186,11 @@ impl ActionEnum for OnsenAction {
             // ========== 挖掘动作（选择温泉） ==========
             OnsenAction::Dig(onsen_index) => game.do_select_dig(*onsen_index as usize),
             // ========== 升级工具动作 ==========
-            OnsenAction::Upgrade(tool) => game.do_upgrade_equipment(*tool as usize)
+            OnsenAction::Upgrade(tool) => game.do_upgrade_equipment(*tool as usize),
+            OnsenAction::DigUpgrade(onsen_index, tool) => {
+                game.do_select_dig(*onsen_index as usize)?;
+                game.do_upgrade_equipment(*tool as usize)
+            }
         }
     }
 
@@ -188,6 +202,7 @@ impl ActionEnum for OnsenAction {
             OnsenAction::FriendOuting => Some(BaseAction::FriendOuting),
             OnsenAction::NormalOuting => Some(BaseAction::NormalOuting),
             OnsenAction::Clinic => Some(BaseAction::Clinic),
+            OnsenAction::CardOuting(x) => Some(BaseAction::CardOuting(*x)),
             _ => None
         }
     }
@@ -1,4 +1,7 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc
+};
 
 use anyhow::{Result, anyhow};
 use colored::Colorize;
@@ -19,20 +22,27 @@ use crate::{
         CardTrainingEffect,
         FriendCardState,
         FriendOutState,
+        FriendState,
         Game,
         InheritInfo,
         Person,
         PersonType,
+        StateViolation,
         SupportCard,
         Trainer,
         Uma,
-        onsen::{action::OnsenAction, *}
+        UniqueTurnContext,
+        onsen::{action::OnsenAction, *},
+        race_planner::RacePlan
     },
     gamedata::{ActionValue, EventData, GAMECONSTANTS, onsen::ONSENDATA},
     global,
     utils::{Array5, Array6, AttributeArray, global_events, system_event, system_event_prob}
 };
 
+/// 育成结束事件(第78回合 ぴょいや！大宴会！)，作为剧本事件在turn=77开始时触发，育成结束时不需要另外结算
+pub const ENDING_EVENT_ID: u32 = 400012021;
+
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct OnsenGame {
     pub base: BaseGame,
@@ -64,7 +74,9 @@ pub struct OnsenGame {
     /// 挖掘完成后待处理选择（装备升级+源泉选择）
     pub pending_selection: bool,
     /// 是否能触发分身
-    pub deck_can_split: bool
+    pub deck_can_split: bool,
+    /// 本回合的自选比赛规划，在自选比赛区间内分配人头时更新，训练后清空
+    pub race_plan: Option<Arc<RacePlan>>
 }
 
 impl Deref for OnsenGame {
@@ -91,6 +103,9 @@ impl OnsenGame {
             self.friend.explain()
         ));
         lines.push(self.uma.explain()?);
+        if let Some(line) = self.explain_failure() {
+            lines.push(line);
+        }
         lines.push(self.bathing.explain());
         lines.push(format!(
             "已挖掘: {} {:?}, 当前挖掘: {}, 剩余: {:?}",
@@ -155,6 +170,13 @@ impl OnsenGame {
         Ok(ret)
     }
 
+    /// 本回合的自选比赛规划，没有缓存时(例如从通信局面构造)现场计算
+    pub fn current_race_plan(&self) -> Arc<RacePlan> {
+        self.race_plan
+            .clone()
+            .unwrap_or_else(|| Arc::new(RacePlan::new(&self.uma, self.turn)))
+    }
+
     pub fn add_friendship(&mut self, person_index: usize, value: i32) {
         if person_index < self.persons.len() {
             let old_value = self.persons[person_index].friendship;
@@ -319,9 +341,8 @@ impl OnsenGame {
         }
     }
 
-    /// 计算不同指令的挖掘量
-    pub fn calc_dig_value(&self, action: &OnsenAction) -> Option<[i32; 3]> {
-        let mut ret = [0, 0, 0];
+    /// Link角色提供的各层挖掘力加成(%)
+    pub fn link_bonus(&self) -> [i32; 3] {
         let mut link_bonus = [0, 0, 0];
         let link_effect = &global!(ONSENDATA).link_effect;
         // Link角色加成（马娘）
@@ -336,6 +357,13 @@ impl OnsenGame {
                 }
             }
         }
+        link_bonus
+    }
+
+    /// 计算不同指令的挖掘量
+    pub fn calc_dig_value(&self, action: &OnsenAction) -> Option<[i32; 3]> {
+        let mut ret = [0, 0, 0];
+        let link_bonus = self.link_bonus();
         // 基础挖掘量
         let base_dig_value = match action {
             OnsenAction::Train(t) => {
@@ -356,7 +384,7 @@ impl OnsenGame {
                     Some(15)
                 }
             }
-            OnsenAction::Sleep | OnsenAction::NormalOuting => Some(15),
+            OnsenAction::Sleep | OnsenAction::NormalOuting | OnsenAction::CardOuting(_) => Some(15),
             OnsenAction::FriendOuting => Some(25),
             OnsenAction::Clinic => Some(0),
             _ => None
@@ -533,16 +561,10 @@ impl OnsenGame {
         let failure_rate = self.calc_training_failure_rate(&buffs, train) / 100.0;
 
         if rng.random_bool(failure_rate as f64) {
-            // 再判断一次，如果还失败就是大失败
-            if rng.random_bool(failure_rate as f64) {
-                warn!("训练大失败!");
-                self.apply_event(system_event("training_fail_low")?, 0, rng)?;
-                self.uma.flags.ill = true;
-                self.uma.flags.bad_trainer = true;
-            } else {
-                warn!("训练失败!");
-                self.apply_event(system_event("training_fail")?, 0, rng)?;
-            }
+            let (event, outcome) = self.roll_training_failure(train, failure_rate, rng)?;
+            warn!("{}!", outcome.name);
+            self.apply_event(&event, 0, rng)?;
+            self.apply_failure_outcome(outcome, rng);
             let vital_cost = (vital_before - self.uma.vital).max(0);
             return Ok((false, vital_cost));
         }
@@ -554,24 +576,24 @@ impl OnsenGame {
         let vital_cost = -value.vital;
         // 增加训练次数
         self.train_level_count[train] += 1;
+        if self.shining_count(train) > 0 {
+            self.on_shining_train(train);
+        }
         // 增加羁绊
         let f = if self.uma.flags.aijiao { 9 } else { 7 };
         let mut hint_persons = vec![];
         let mut friend_clicked = false;
-        let mut yayoi_clicked = false;
-        let mut reporter_clicked = false;
+        let mut noncard_clicked = vec![];
+        let mut card_friend_clicked = vec![];
 
         for person_index in self.distribution[train].clone() {
             // 跳过空位（-1 表示空位）
             if person_index < 0 {
                 continue;
             }
-            // 点击记者/理事长只加2羁绊，其他7
-            if matches!(
-                self.persons[person_index as usize].person_type,
-                PersonType::Yayoi | PersonType::Reporter
-            ) {
-                self.add_friendship(person_index as usize, 2);
+            // 记者/理事长训练时的羁绊按数据表，点击效果的羁绊另外结算
+            if let Some(data) = self.persons[person_index as usize].noncard_data() {
+                self.add_friendship(person_index as usize, data.train_friendship);
             } else {
                 self.add_friendship(person_index as usize, f);
             }
@@ -580,8 +602,8 @@ impl OnsenGame {
             }
             match self.persons[person_index as usize].person_type {
                 PersonType::ScenarioCard => friend_clicked = true,
-                PersonType::Yayoi => yayoi_clicked = true,
-                PersonType::Reporter => reporter_clicked = true,
+                PersonType::Yayoi | PersonType::Reporter => noncard_clicked.push(person_index as usize),
+                PersonType::OtherFriend | PersonType::TeamCard => card_friend_clicked.push(person_index as usize),
                 _ => {}
             };
         }
@@ -611,14 +633,18 @@ impl OnsenGame {
                 }
             }
         }
-        if yayoi_clicked {
-            self.unresolved_events.push(system_event("yayoi_click")?.clone());
-        }
-        if reporter_clicked {
-            let mut event = system_event("reporter_click")?.clone();
-            event.choices[0].status_pt[train] = 2;
+        // 理事长/记者的点击效果
+        for person_index in noncard_clicked {
+            let event = self.persons[person_index].noncard_click_event(train)?;
             self.unresolved_events.push(event);
         }
+        // 非剧本友人卡/团队卡
+        for person_index in card_friend_clicked {
+            let friendship = self.persons[person_index].friendship;
+            if let Some(event) = self.click_card_friend(person_index, friendship, rng) {
+                self.unresolved_events.push(event);
+            }
+        }
         return Ok((true, vital_cost));
     }
 
@@ -639,6 +665,21 @@ impl OnsenGame {
             .collect::<Vec<_>>();
         actions
     }
+    /// 列出温泉选择和装备升级的组合，没有可升级装备时只有温泉选择
+    pub fn list_actions_dig_upgrade(&self) -> Vec<OnsenAction> {
+        let upgradeable = self.get_upgradeable_equipment();
+        let digs = self.list_actions_onsen_select();
+        if upgradeable.is_empty() {
+            return digs;
+        }
+        digs.iter()
+            .filter_map(|a| match a {
+                OnsenAction::Dig(i) => Some(*i),
+                _ => None
+            })
+            .flat_map(|i| upgradeable.iter().map(move |u| OnsenAction::DigUpgrade(i, *u as i32)))
+            .collect()
+    }
     /// 执行选择温泉
     pub fn do_select_onsen<T: Trainer<Self>>(&mut self, trainer: &T, rng: &mut StdRng) -> Result<()> {
         let actions = self.list_actions_onsen_select();
@@ -649,10 +690,15 @@ impl OnsenGame {
 
     /// 赠送票数
     pub fn grant_ticket_num(&self) -> i32 {
-        if self.turn == 2 {
+        self.grant_ticket_num_at(self.turn)
+    }
+
+    /// 第turn回合的赠送票数，用于预测之后的送券
+    pub fn grant_ticket_num_at(&self, turn: i32) -> i32 {
+        if turn == 2 {
             // 开局均为2张
             2
-        } else if self.turn == 72 {
+        } else if turn == 72 {
             // 72回合 SSR=2 其他为1
             if self.friend.card_state == FriendCardState::SSR {
                 2
@@ -782,6 +828,7 @@ impl OnsenGame {
 
         // 5. 消耗温泉券，设置buff持续2回合
         self.bathing.ticket_num -= 1;
+        self.bathing.used_count += 1;
         self.bathing.buff_remain_turn = 2;
         info!(
             "  温泉券剩余: {}, Buff持续: {}回合",
@@ -951,23 +998,25 @@ impl OnsenGame {
     ///   - 预留（6 维）
     /// - 支援卡信息（89 维 × 6 张 = 534 维）
     pub fn extract_nn_features(&self, pending_choices: Option<&[ActionValue]>) -> Vec<f32> {
-        use crate::training_sample::{CHOICE_DIM, NN_CARD_DIM, NN_INPUT_DIM, POLICY_DIM};
+        use crate::{
+            neural::feature_schema::{CardBlock, GlobalBlock},
+            training_sample::{CHOICE_DIM, NN_INPUT_DIM, POLICY_DIM}
+        };
 
         let mut features = vec![0.0_f32; NN_INPUT_DIM];
-        let mut idx = 0;
 
         // ========== 全局信息（587 维） ==========
 
         // 1. 搜索参数（6 维）- 预留，当前填充 0
-        idx += 6;
 
         // 2. 回合信息（78 维）- One-Hot 编码
+        let idx = GlobalBlock::Turn.offset();
         if (self.turn as usize) < 78 {
             features[idx + self.turn as usize] = 1.0;
         }
-        idx += 78;
 
         // 3. 马娘属性（15 维）
+        let idx = GlobalBlock::UmaStatus.offset();
         // 五维属性（归一化到 0-1）
         for i in 0..5 {
             features[idx + i] = self.uma.five_status[i] as f32 / 50.0;
@@ -981,16 +1030,16 @@ impl OnsenGame {
             let remain = self.uma.five_status_limit[i] - self.uma.five_status[i];
             features[idx + 10 + i] = remain as f32 / 50.0;
         }
-        idx += 15;
 
         // 4. 体力与干劲（5 维）
+        let idx = GlobalBlock::VitalMotivation.offset();
         features[idx] = self.uma.vital as f32 / 100.0;
         features[idx + 1] = self.uma.max_vital as f32 / 100.0;
         features[idx + 2] = self.uma.motivation as f32 / 4.0;
         // 3-4: 预留
-        idx += 5;
 
         // 5. 训练数值（30 维）- 5种训练 × 6维收益
+        let idx = GlobalBlock::TrainValue.offset();
         for train in 0..5 {
             if let Ok(buffs) = self.calc_training_buff(train) {
                 if let Ok(gain) = self.calc_training_value(&buffs, train) {
@@ -1003,18 +1052,18 @@ impl OnsenGame {
                 }
             }
         }
-        idx += 30;
 
         // 6. 失败率（5 维）
+        let idx = GlobalBlock::FailRate.offset();
         for train in 0..5 {
             if let Ok(buffs) = self.calc_training_buff(train) {
                 let fail_rate = self.calc_training_failure_rate(&buffs, train);
                 features[idx + train] = fail_rate / 100.0;
             }
         }
-        idx += 5;
 
         // 7. 温泉剧本特定（140 维）- 支持温泉选择学习
+        let idx = GlobalBlock::Onsen.offset();
         // 获取温泉静态数据
         let onsen_data = crate::gamedata::onsen::ONSENDATA.get();
 
@@ -1052,7 +1101,7 @@ impl OnsenGame {
         // 累计体力消耗（1 维）- 用于超回复判断
         features[idx + 19] = self.dig_vital_cost as f32 / 200.0;
 
-        idx += 20;
+        let idx = idx + 20;
 
         // ========== 7.2 每个温泉的详细状态（12 维 × 10 = 120 维） ==========
 
@@ -1141,9 +1190,9 @@ impl OnsenGame {
             features[base + 11] = (est_turns / 20.0).min(1.0);
         }
 
-        idx += 120;
 
         // 8. 其他全局信息（61 维）
+        let idx = GlobalBlock::Misc.offset();
         // 技能点
         features[idx] = self.uma.skill_pt as f32 / 100.0;
         // 干劲等级
@@ -1159,9 +1208,9 @@ impl OnsenGame {
         // Buff 剩余回合
         features[idx + 6] = self.bathing.buff_remain_turn as f32 / 10.0;
         // 预留 (7-60)
-        idx += 61;
 
         // 9. 事件选项特征（113 维 = 1 + 8*14）
+        let idx = GlobalBlock::Choices.offset();
         if let Some(choices) = pending_choices {
             features[idx] = choices.len() as f32 / CHOICE_DIM as f32;
 
@@ -1193,9 +1242,9 @@ impl OnsenGame {
                 features[base + 13] = 0.0;
             }
         }
-        idx += 1 + CHOICE_DIM * 14; // 113 维
 
         // 10. 动作合法掩码（50 维）
+        let idx = GlobalBlock::LegalMask.offset();
         // 标记当前可选的动作
         if let Ok(actions) = self.list_actions() {
             for action in &actions {
@@ -1206,19 +1255,17 @@ impl OnsenGame {
                 }
             }
         }
-        idx += POLICY_DIM; // 50 维
 
         // 11. 比赛回合标记（78 维）
+        let idx = GlobalBlock::RaceTurns.offset();
         // 标记所有比赛回合
         for turn in 0..78 {
             if self.uma.is_race_turn(turn as i32) {
                 features[idx + turn] = 1.0;
             }
         }
-        idx += 78;
 
         // 12. 预留（6 维）
-        idx += 6;
 
         // ========== 支援卡信息（89 维 × 6 张 = 534 维） ==========
         // 布局（对齐 C++ getCardParamNNInputV1）：
@@ -1226,7 +1273,7 @@ impl OnsenGame {
         // - Card 参数（77 维）
 
         for card_idx in 0..6 {
-            let base = idx + card_idx * NN_CARD_DIM;
+            let base = CardBlock::Person.offset(card_idx);
 
             if let Some(person) = self.persons.get(card_idx) {
                 // ===== Person 信息（12 维） =====
@@ -1253,7 +1300,7 @@ impl OnsenGame {
             }
 
             // ===== Card 参数（77 维，从 base+12 开始） =====
-            let card_base = base + 12;
+            let card_base = CardBlock::CardParams.offset(card_idx);
 
             if let Some(card) = self.deck.get(card_idx) {
                 let data = &card.data;
@@ -1359,9 +1406,15 @@ impl Game for OnsenGame {
             persons.push(person);
         }
         if let Some(i) = friend_index {
-            self.friend.person_index = i;
+            // 事件加成也要取剧本友人的
+            let card = &self.deck[i];
+            self.friend = FriendState::new(Some(card.card_id * 10 + card.rank), i)?;
             self.friend.card_state = friend_state;
+        } else {
+            // 没带剧本友人时不能沿用其他友人卡
+            self.friend = FriendState::new(None, 0)?;
         }
+        self.init_card_friends(&persons);
         for p in persons {
             self.add_person(p);
         }
@@ -1421,7 +1474,7 @@ impl Game for OnsenGame {
                         if self.turn > 13 && self.turn < 72 {
                             actions.push(OnsenAction::Race);
                         }
-                        if self.uma.flags.ill {
+                        if self.uma.flags.has_bad_condition() {
                             actions.push(OnsenAction::Clinic);
                         }
                         if self.friend.out_state == FriendOutState::AfterUnlock && self.turn < 72 {
@@ -1429,6 +1482,11 @@ impl Game for OnsenGame {
                                 actions.push(OnsenAction::FriendOuting);
                             }
                         }
+                        for friend in &self.card_friends {
+                            if friend.can_outing(self.turn) {
+                                actions.push(OnsenAction::CardOuting(friend.person_index as i32));
+                            }
+                        }
                     }
                     Ok(actions)
                 }
@@ -1448,6 +1506,12 @@ impl Game for OnsenGame {
             // 判断友人出门事件
             if let Some(event) = self.check_friend_out_event(rng) {
                 vec![event]
+            } else if let Some(event) = self.check_card_friend_unlock(&self.persons, rng) {
+                // 非剧本友人卡/团队卡出行解锁
+                vec![event]
+            } else if let Some(event) = self.check_noncard_event(&self.persons, rng) {
+                // 理事长/记者连续事件
+                vec![event]
             } else {
                 // 一般随机事件
                 let weights =
@@ -1457,7 +1521,9 @@ impl Game for OnsenGame {
                         // 支援卡连续事件
                         let available_indices = (0..6)
                             .filter(|x| {
-                                self.persons[*x].person_type == PersonType::Card && self.person_is_available(*x)
+                                self.persons[*x].person_type == PersonType::Card
+                                    && self.person_is_available(*x)
+                                    && self.has_card_event(*x)
                             })
                             .collect::<Vec<_>>();
                         if let Some(index) = available_indices.choose(rng) {
@@ -1500,29 +1566,27 @@ impl Game for OnsenGame {
                 self.add_friendship(*person_index as usize, value.friendship);
             }
         }
+        // 事件获得的状态
+        for name in self.uma.flags.grant_by_event(event.id, rng) {
+            warn!(">> 获得【{name}】");
+        }
         // 判断特殊事件
         match event.id {
             4012 | 4013 => {
                 // 继承
+                let result = self.inherit.inherit_event(rng);
                 let inherit_value = ActionValue {
-                    status_pt: self.inherit.inherit(rng),
+                    status_pt: result.status,
+                    hint_level: result.hint_level,
                     ..Default::default()
                 };
-                let inherit_limit = self.inherit.inherit_limit(rng);
                 self.uma.add_value(&inherit_value);
-                self.uma.five_status_limit.add_eq(&inherit_limit);
+                self.uma.five_status_limit.add_eq(&result.limit);
                 info!("当前 limit: {:?}", self.uma.five_status_limit);
                 for i in 0..5 {
                     self.uma.five_status_limit[i] = self.uma.five_status_limit[i].min(2800);
                 }
             }
-            5007 => {
-                // 大成功事件
-                if rng.random_bool(system_event_prob("qiezhe_normal")?) {
-                    warn!(">> 获得【切者】");
-                    self.uma.flags.qiezhe = true;
-                }
-            }
             809050004 => {
                 // 友人出门事件
                 info!(">> 友人出行已解锁");
@@ -1556,8 +1620,9 @@ impl Game for OnsenGame {
     fn deyilv(&mut self, person_index: i32) -> Result<f32> {
         if person_index < 6 {
             let (eff, lock) = self.deck[person_index as usize].calc_training_effect(self, 0)?;
-            self.deck[person_index as usize].effect = eff.clone();
+            // 只有锁定的固有可以写回面板，随局面变化的固有每次重新计算
             if lock {
+                self.deck[person_index as usize].effect = eff.clone();
                 self.deck[person_index as usize].is_locked = true;
             }
             Ok(eff.deyilv + self.scenario_buff.hotel.deyilv as f32)
@@ -1678,6 +1743,7 @@ impl Game for OnsenGame {
                     self.pending_selection = true;
                     self.handle_pending_selection(trainer, rng)?;
                 }
+                self.update_conditions(rng);
                 let mut events = self.generate_events(rng);
                 // 友人强制事件
                 if self.friend.out_state == FriendOutState::AfterUnlock {
@@ -1716,6 +1782,7 @@ impl Game for OnsenGame {
                 if self.uma.flags.refresh_mind > 0 {
                     self.update_refresh_mind(rng);
                 }
+                self.update_group_buff(rng);
                 // 执行回合前事件
                 for event in &events {
                     self.run_event(event, trainer, rng)?;
@@ -1723,6 +1790,9 @@ impl Game for OnsenGame {
             }
             OnsenTurnStage::Distribute => {
                 self.update_scenario_buff(false);
+                self.unique_ctx = Some(UniqueTurnContext::new(self));
+                self.race_plan = (!self.is_race_turn() && self.uma.find_free_race(self.turn).is_some())
+                    .then(|| Arc::new(RacePlan::new(&self.uma, self.turn)));
                 if self.is_race_turn() {
                     self.reset_distribution();
                 } else {
@@ -1738,6 +1808,8 @@ impl Game for OnsenGame {
                 self.list_and_apply_action(trainer, rng)?;
             }
             OnsenTurnStage::AfterTrain => {
+                self.unique_ctx = None;
+                self.race_plan = None;
                 let after_events = std::mem::take(&mut self.unresolved_events);
                 for event in &after_events {
                     self.run_event(event, trainer, rng)?;
@@ -1773,6 +1845,9 @@ impl Game for OnsenGame {
     fn turn(&self) -> i32 {
         self.turn
     }
+    fn stage_name(&self) -> String {
+        format!("{:?}", self.stage)
+    }
     fn max_turn(&self) -> i32 {
         77
     }
@@ -1785,6 +1860,9 @@ impl Game for OnsenGame {
     fn deck(&self) -> &Vec<SupportCard> {
         &self.deck
     }
+    fn unique_turn_context(&self) -> Option<&UniqueTurnContext> {
+        self.unique_ctx.as_ref()
+    }
     fn distribution(&self) -> &Vec<Vec<i32>> {
         &self.distribution
     }
@@ -1792,7 +1870,10 @@ impl Game for OnsenGame {
         &mut self.distribution
     }
     fn has_group_buff(&self) -> bool {
-        self.friend.group_buff_turn > 0
+        self.friend.group_buff_turn > 0 || self.has_card_group_buff()
+    }
+    fn has_group_buff_at(&self, person_index: usize) -> bool {
+        self.group_buff_at(person_index)
     }
     fn train_level(&self, train: usize) -> usize {
         if self.is_xiahesu() {
@@ -1802,17 +1883,210 @@ impl Game for OnsenGame {
         }
     }
 
-    fn on_simulation_end<T: Trainer<Self>>(&mut self, _trainer: &T, _rng: &mut StdRng) -> Result<()> {
-        info!(">> 育成结束，触发最终奖励事件");
-        /*
-        // 查找回合78事件 (ぴょいや！大宴会！)
-        let onsen_data = global!(ONSENDATA);
-        if let Some(event) = onsen_data.scenario_events.iter().find(|e| e.id == 400012021) {
-            self.run_event(event, trainer, rng)?;
-        } else {
-            warn!("未找到育成结束事件 (id=400012021)");
+    fn validate(&self) -> Vec<StateViolation> {
+        let mut ret = self.default_validate();
+        let mut messages = vec![];
+        for (i, remain) in self.dig_remain.iter().enumerate() {
+            if remain.iter().any(|x| *x < 0) {
+                messages.push(format!("温泉{i}剩余挖掘量为负: {remain:?}"));
+            }
+        }
+        if self.current_onsen >= self.onsen_state.len() {
+            messages.push(format!("当前温泉超出范围: {}", self.current_onsen));
+        }
+        let max_level = global!(ONSENDATA).dig_tool_level.len() as i32;
+        if self.dig_level.iter().any(|x| *x < 0 || *x >= max_level) {
+            messages.push(format!("工具等级超出范围: {:?}", self.dig_level));
+        }
+        if !(0..=3).contains(&self.bathing.ticket_num) {
+            messages.push(format!("温泉券数量超出范围: {}", self.bathing.ticket_num));
+        }
+        if !(0..=2).contains(&self.bathing.buff_remain_turn) {
+            messages.push(format!("温泉Buff剩余回合超出范围: {}", self.bathing.buff_remain_turn));
+        }
+        if self.bathing.buff_remain_turn > 0 && self.bathing.used_count == 0 {
+            messages.push(format!(
+                "温泉Buff生效中但没有使用过温泉券: {}",
+                self.bathing.buff_remain_turn
+            ));
+        }
+        if self.bathing.is_super && self.bathing.buff_remain_turn == 0 {
+            messages.push("超回复Buff在温泉Buff结束后仍然生效".to_string());
+        }
+        for friend in &self.card_friends {
+            if friend.events().is_some_and(|e| friend.out_count > e.outings.len()) {
+                messages.push(format!("友人卡#{}出行次数越界: {}", friend.person_index, friend.out_count));
+            }
+            if friend.group_buff_turn > 0 && !friend.is_team {
+                messages.push(format!("友人卡#{}不是团队卡但开启了团队Buff", friend.person_index));
+            }
+        }
+        ret.extend(messages.into_iter().map(|m| self.violation(m)));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        game::DEFAULT_FORECAST_SAMPLES,
+        gamedata::init_global,
+        game::CardFriendState,
+        gamedata::CardFriendEvents,
+        trainer::HandwrittenTrainer,
+        utils::init_logger
+    };
+
+    #[test]
+    fn test_ending_event() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let mut game = OnsenGame::newgame(101901, &[302424, 302464, 302484, 302564, 302574, 302644], Default::default())?;
+        let trainer = HandwrittenTrainer::new();
+        let mut rng = StdRng::seed_from_u64(1);
+        // 大宴会在最后一回合开始时触发且只触发一次
+        game.run_full_game(&trainer, &mut rng)?;
+        assert_eq!(game.events.get(&ENDING_EVENT_ID), Some(&1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let mut game = OnsenGame::newgame(101901, &[302424, 302464, 302484, 302564, 302574, 302644], Default::default())?;
+        let trainer = HandwrittenTrainer::new();
+        let mut rng = StdRng::seed_from_u64(2);
+        // 每个阶段后直接检查，不改动全局的检查模式，避免影响同时运行的其他测试
+        let mut finished = game.clone();
+        loop {
+            finished.run_stage(&trainer, &mut rng)?;
+            let violations = finished.validate();
+            assert!(violations.is_empty(), "{}: {violations:?}", finished.stage_name());
+            if !finished.next() {
+                break;
+            }
+        }
+        finished.on_simulation_end(&trainer, &mut rng)?;
+        assert!(finished.validate().is_empty());
+        // 构造不可能的状态
+        game.turn = 10;
+        game.uma.five_status[0] = game.uma.five_status_limit[0] + 1;
+        game.dig_remain[1][0] = -5;
+        game.bathing.ticket_num = -1;
+        game.bathing.buff_remain_turn = 2;
+        game.distribution = vec![vec![0, 1, 2, 3, 4, 5], vec![], vec![], vec![], vec![]];
+        let violations = game.validate();
+        for v in &violations {
+            println!("{v}");
+        }
+        assert_eq!(violations.len(), 5);
+        assert!(violations.iter().all(|v| v.turn == 10 && v.stage == "Begin"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_card_friend() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        // 剧本友人 + [友]骏川手纲 + [团]聚集于宝座的人们
+        let mut game = OnsenGame::newgame(101901, &[302764, 300214, 300674, 302424, 302464, 302484], Default::default())?;
+        let mut rng = StdRng::seed_from_u64(3);
+        assert_eq!(game.friend.person_index, 0);
+        assert_eq!(game.persons[1].person_type, PersonType::OtherFriend);
+        // 没有事件数据的卡不出行也没有团队Buff
+        assert!(game.card_friends.is_empty());
+        assert!(game.click_card_friend(1, 0, &mut rng).is_none());
+        let event = |id: u32| EventData {
+            id,
+            prob: 100,
+            max_trigger_time: 1,
+            choices: vec![ActionValue {
+                vital: 30,
+                status_pt: [0, 0, 0, 0, 0, 20],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let data: &'static CardFriendEvents = Box::leak(Box::new(CardFriendEvents {
+            first: event(1),
+            click: event(2),
+            unlock: event(3),
+            outings: vec![event(4), event(5)],
+            group_buff_friendship: 60,
+            group_buff_prob: 100
+        }));
+        for (person_index, card_id) in [(1, 30021), (2, 30067)] {
+            let card = &game.deck[person_index];
+            assert_eq!(card.card_id, card_id);
+            let friend = CardFriendState {
+                person_index,
+                is_team: card.card_type == 6,
+                event_bonus: card.card_value().event_effect_up,
+                data: Some(data),
+                ..Default::default()
+            };
+            game.card_friends.push(friend);
+        }
+        assert!(!game.card_friends[0].is_team && game.card_friends[1].is_team);
+        // 第一次点击后才能解锁出行
+        let first = game.click_card_friend(1, 0, &mut rng).expect("click");
+        assert_eq!(first.person_index, Some(1));
+        assert_eq!(first.id, 1);
+        assert_eq!(game.card_friends[0].out_state, FriendOutState::BeforeUnlock);
+        let unlock = game.card_friends[0].bonus_event(&data.unlock);
+        game.apply_event(&unlock, 0, &mut rng)?;
+        game.turn = 20;
+        game.stage = OnsenTurnStage::Train;
+        assert!(game.list_actions()?.contains(&OnsenAction::CardOuting(1)));
+        // 出行按顺序进行，效果乘算事件加成
+        game.apply_action(&OnsenAction::CardOuting(1), &mut rng)?;
+        let outing = game.unresolved_events.last().expect("outing");
+        assert_eq!(outing.id, 4);
+        assert_eq!(outing.choices[0].status_pt[5], 20 * (100 + game.card_friends[0].event_bonus) / 100);
+        assert_eq!(game.card_friends[0].out_count, 1);
+        // 团队卡羁绊足够时点击开启团队Buff，期间团队卡闪彩，最多持续6回合
+        game.click_card_friend(2, 60, &mut rng).expect("click");
+        assert_eq!(game.card_friends[1].group_buff_turn, 1);
+        assert!(game.is_shining_at(2, 0));
+        // 团队Buff只属于开启它的团队卡
+        game.persons[1].person_type = PersonType::TeamCard;
+        assert!(!game.is_shining_at(1, 0));
+        game.persons[1].person_type = PersonType::OtherFriend;
+        for _ in 0..6 {
+            game.update_group_buff(&mut rng);
+        }
+        assert!(!game.has_group_buff());
+        assert!(!game.is_shining_at(2, 0));
+        assert!(game.validate().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_forecast_distribution() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let mut game = OnsenGame::newgame(101901, &[302834, 302824, 302754, 302744, 302644, 302764], Default::default())?;
+        let mut rng = StdRng::seed_from_u64(5);
+        game.turn = 20;
+        let forecast = game.forecast_distribution(DEFAULT_FORECAST_SAMPLES, &mut rng)?;
+        let names: Vec<_> = game.persons.iter().map(|p| p.short_name()).collect();
+        println!("{}", forecast.explain(&names));
+        assert_eq!(forecast.probs.len(), game.persons.len());
+        for probs in &forecast.probs {
+            assert!((probs.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        }
+        // 支援卡更容易出现在得意训练
+        for (i, person) in game.persons.iter().enumerate().take(6) {
+            if person.person_type == PersonType::Card {
+                let train = person.train_type as usize;
+                let other = (train + 1) % 5;
+                assert!(forecast.probs[i][train] > forecast.probs[i][other]);
+            }
         }
-        */
         Ok(())
     }
 }
@@ -6,6 +6,8 @@ use crate::gamedata::onsen::{HotelEffect, OnsenEffect};
 
 pub mod action;
 pub mod game;
+pub mod planner;
+pub mod ticket;
 
 /// 温泉剧本的局中Buff
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
@@ -26,7 +28,10 @@ pub struct BathingInfo {
     /// buff是否超回复
     pub is_super: bool,
     /// 下一个buff是否超回复
-    pub is_super_ready: bool
+    pub is_super_ready: bool,
+    /// 本局已使用的温泉券数，用于状态检查，从小黑板读取时未知
+    #[serde(default)]
+    pub used_count: i32
 }
 
 impl BathingInfo {
@@ -0,0 +1,455 @@
+//! 温泉挖掘路线规划
+//!
+//! 按典型的行动分布估算每回合的期望挖掘量，预测各温泉还需要几个回合挖完，
+//! 并搜索剩余回合内的挖掘顺序和工具升级顺序。
+//! 属性带来的挖掘力加成按当前值计算，不考虑之后属性增长。
+
+use crate::{
+    game::{Game, onsen::game::OnsenGame},
+    gamedata::onsen::ONSENDATA,
+    global
+};
+
+/// 新温泉解锁回合，这些回合会重新选择温泉并升级一次工具
+pub const UNLOCK_TURNS: [i32; 3] = [24, 48, 65];
+/// 挖掘结束回合
+pub const DIG_END_TURN: i32 = 72;
+/// 搜索节点上限，防止温泉很多时耗时过长
+const MAX_SEARCH_NODES: usize = 50000;
+
+/// 典型的行动分布，用来估算每回合的期望基础挖掘量
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionMix {
+    pub name: &'static str,
+    /// 训练的比例
+    pub train: f64,
+    /// 训练平均人头数
+    pub train_persons: f64,
+    /// 比赛的比例
+    pub race: f64,
+    /// 休息/出行的比例
+    pub rest: f64
+}
+
+impl ActionMix {
+    /// 以训练为主
+    pub const TRAIN: ActionMix = ActionMix {
+        name: "训练为主",
+        train: 0.8,
+        train_persons: 2.0,
+        race: 0.05,
+        rest: 0.15
+    };
+    /// 训练和比赛、休息均衡
+    pub const BALANCED: ActionMix = ActionMix {
+        name: "均衡",
+        train: 0.65,
+        train_persons: 1.5,
+        race: 0.15,
+        rest: 0.2
+    };
+    /// 比赛、休息较多
+    pub const RELAXED: ActionMix = ActionMix {
+        name: "多比赛休息",
+        train: 0.5,
+        train_persons: 1.5,
+        race: 0.2,
+        rest: 0.3
+    };
+
+    pub fn all() -> [ActionMix; 3] {
+        [Self::TRAIN, Self::BALANCED, Self::RELAXED]
+    }
+
+    /// 每回合期望基础挖掘量，与calc_dig_value的基础值一致
+    pub fn base_dig_value(&self) -> f64 {
+        self.train * (25.0 + self.train_persons) + self.race * 15.0 + self.rest * 15.0
+    }
+}
+
+/// 单个温泉的完成预测
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnsenForecast {
+    /// 温泉编号
+    pub onsen: usize,
+    /// 以当前工具等级挖完需要的挖掘回合数
+    pub dig_turns: f64,
+    /// 从现在(或解锁时)开始挖，预计完成的回合，None为72回合前挖不完
+    pub finish_turn: Option<i32>
+}
+
+impl OnsenForecast {
+    /// 能在哪个节点(解锁回合或挖掘结束)之前完成
+    pub fn deadline(&self) -> Option<i32> {
+        let finish = self.finish_turn?;
+        UNLOCK_TURNS
+            .iter()
+            .chain(&[DIG_END_TURN])
+            .copied()
+            .find(|t| finish < *t)
+    }
+}
+
+/// 路线中的一次选择
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigPlanStep {
+    /// 选择发生的回合
+    pub turn: i32,
+    /// 选择的温泉
+    pub onsen: usize,
+    /// 同时升级的工具
+    pub upgrade: Option<usize>,
+    /// 预计完成回合，None为在下一次选择前没挖完
+    pub finish_turn: Option<i32>
+}
+
+/// 推荐的挖掘路线
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DigPlan {
+    pub steps: Vec<DigPlanStep>,
+    /// 预计完成的温泉和完成回合
+    pub completed: Vec<(usize, i32)>
+}
+
+impl DigPlan {
+    /// 完成数多的优先，其次完成得早的优先
+    fn score(&self) -> (usize, i32) {
+        (
+            self.completed.len(),
+            -self.completed.iter().map(|(_, t)| *t).sum::<i32>()
+        )
+    }
+
+    pub fn explain(&self) -> String {
+        let onsen_info = &global!(ONSENDATA).onsen_info;
+        let tool_name = &global!(ONSENDATA).dig_tool_name;
+        let steps: Vec<_> = self
+            .steps
+            .iter()
+            .map(|s| {
+                let mut text = format!("回合{} {}", s.turn + 1, onsen_info[s.onsen].name);
+                if let Some(u) = s.upgrade {
+                    text += &format!("+升级{}", tool_name[u]);
+                }
+                match s.finish_turn {
+                    Some(t) => text += &format!("(回合{}完成)", t + 1),
+                    None => text += "(未完成)"
+                }
+                text
+            })
+            .collect();
+        format!("推荐路线: 完成{}个 {}", self.completed.len(), steps.join(" -> "))
+    }
+}
+
+/// 搜索中的局面
+#[derive(Debug, Clone)]
+struct PlanState {
+    turn: i32,
+    dig_level: [i32; 3],
+    remain: Vec<[f64; 3]>,
+    done: Vec<bool>
+}
+
+/// 挖掘规划器，保存规划需要的局面快照
+#[derive(Debug, Clone)]
+pub struct DigPlanner {
+    pub mix: ActionMix,
+    turn: i32,
+    current_onsen: usize,
+    pending_selection: bool,
+    dig_level: [i32; 3],
+    /// 除工具以外的挖掘力加成(因子+属性)
+    base_power: [i32; 3],
+    link_bonus: [i32; 3],
+    remain: Vec<[f64; 3]>,
+    done: Vec<bool>
+}
+
+impl DigPlanner {
+    pub fn new(game: &OnsenGame, mix: ActionMix) -> Self {
+        let tool_level = &global!(ONSENDATA).dig_tool_level;
+        let mut base_power = [0; 3];
+        for i in 0..3 {
+            base_power[i] = game.dig_power[i] - tool_level[game.dig_level[i] as usize];
+        }
+        Self {
+            mix,
+            turn: game.turn(),
+            current_onsen: game.current_onsen,
+            pending_selection: game.pending_selection,
+            dig_level: game.dig_level,
+            base_power,
+            link_bonus: game.link_bonus(),
+            remain: game
+                .dig_remain
+                .iter()
+                .map(|r| [r[0] as f64, r[1] as f64, r[2] as f64])
+                .collect(),
+            done: game.onsen_state.clone()
+        }
+    }
+
+    /// 该回合是否会挖掘
+    pub fn is_dig_turn(turn: i32) -> bool {
+        (2..DIG_END_TURN).contains(&turn) && !(36..40).contains(&turn) && !(60..64).contains(&turn)
+    }
+
+    /// turn之后的下一个解锁回合
+    fn next_unlock_turn(turn: i32) -> Option<i32> {
+        UNLOCK_TURNS.iter().copied().find(|t| *t > turn)
+    }
+
+    /// 工具等级对应的各层挖掘倍率
+    fn dig_rate(&self, dig_level: &[i32; 3]) -> [f64; 3] {
+        let tool_level = &global!(ONSENDATA).dig_tool_level;
+        let mut ret = [0.0; 3];
+        for i in 0..3 {
+            let power = self.base_power[i] + tool_level[dig_level[i] as usize];
+            ret[i] = (100.0 + power as f64) * (100.0 + self.link_bonus[i] as f64) / 10000.0;
+        }
+        ret
+    }
+
+    /// 挖完remain需要的挖掘回合数
+    fn dig_turns(&self, remain: &[f64; 3], dig_level: &[i32; 3]) -> f64 {
+        let rate = self.dig_rate(dig_level);
+        let base = self.mix.base_dig_value();
+        (0..3).map(|i| remain[i] / (base * rate[i])).sum()
+    }
+
+    /// 从start回合挖到stop回合(不含)，挖完时返回完成回合
+    fn dig_until(&self, remain: &mut [f64; 3], dig_level: &[i32; 3], start: i32, stop: i32) -> Option<i32> {
+        let rate = self.dig_rate(dig_level);
+        for turn in start..stop.min(DIG_END_TURN) {
+            if !Self::is_dig_turn(turn) {
+                continue;
+            }
+            // 和calc_dig_value一样，挖穿一层后剩余的基础量按下一层的倍率计算
+            let mut base = self.mix.base_dig_value();
+            for ty in 0..3 {
+                if remain[ty] <= 0.0 {
+                    continue;
+                }
+                let value = base * rate[ty];
+                if value >= remain[ty] {
+                    base -= remain[ty] / rate[ty];
+                    remain[ty] = 0.0;
+                } else {
+                    remain[ty] -= value;
+                    break;
+                }
+            }
+            if remain.iter().all(|x| *x <= 0.0) {
+                return Some(turn);
+            }
+        }
+        None
+    }
+
+    /// 为onsen选择升级的工具: 挖掘回合数最少，相同时选后续温泉总量最多的层
+    fn choose_upgrade(&self, state: &PlanState, onsen: usize) -> Option<usize> {
+        let future: Vec<f64> = (0..3)
+            .map(|ty| {
+                state
+                    .remain
+                    .iter()
+                    .zip(&state.done)
+                    .filter(|(_, done)| !**done)
+                    .map(|(r, _)| r[ty])
+                    .sum()
+            })
+            .collect();
+        (0..3)
+            .filter(|ty| state.dig_level[*ty] < 6)
+            .map(|ty| {
+                let mut level = state.dig_level;
+                level[ty] += 1;
+                (ty, self.dig_turns(&state.remain[onsen], &level), future[ty])
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(b.2.total_cmp(&a.2)))
+            .map(|(ty, _, _)| ty)
+    }
+
+    /// 预测单个温泉
+    pub fn forecast(&self, onsen: usize) -> OnsenForecast {
+        let unlock_turn = global!(ONSENDATA).onsen_info[onsen].unlock_turn;
+        let mut remain = self.remain[onsen];
+        let finish_turn = if self.done[onsen] {
+            Some(self.turn)
+        } else {
+            self.dig_until(&mut remain, &self.dig_level, self.turn.max(unlock_turn), DIG_END_TURN)
+        };
+        OnsenForecast {
+            onsen,
+            dig_turns: self.dig_turns(&self.remain[onsen], &self.dig_level),
+            finish_turn
+        }
+    }
+
+    /// 预测所有未完成的温泉
+    pub fn forecast_all(&self) -> Vec<OnsenForecast> {
+        (0..self.done.len())
+            .filter(|i| !self.done[*i])
+            .map(|i| self.forecast(i))
+            .collect()
+    }
+
+    /// 搜索剩余的挖掘和升级顺序
+    pub fn plan(&self) -> DigPlan {
+        let state = PlanState {
+            turn: self.turn,
+            dig_level: self.dig_level,
+            remain: self.remain.clone(),
+            done: self.done.clone()
+        };
+        let mut best = DigPlan::default();
+        let mut nodes = 0;
+        let mut plan = DigPlan::default();
+        if self.turn < 2 {
+            // 第2回合初始温泉完成，第一次选择
+            self.search_select(state, 2, true, &mut plan, &mut best, &mut nodes);
+        } else if self.pending_selection || self.done.get(self.current_onsen).is_none_or(|d| *d) {
+            self.search_select(state, self.turn, self.pending_selection, &mut plan, &mut best, &mut nodes);
+        } else {
+            self.search_dig(state, self.current_onsen, &mut plan, &mut best, &mut nodes);
+        }
+        best
+    }
+
+    /// 在turn回合选择温泉(和升级)
+    fn search_select(
+        &self, state: PlanState, turn: i32, upgrade: bool, plan: &mut DigPlan, best: &mut DigPlan, nodes: &mut usize
+    ) {
+        *nodes += 1;
+        let onsen_info = &global!(ONSENDATA).onsen_info;
+        let candidates: Vec<_> = (0..state.done.len())
+            .filter(|i| !state.done[*i] && onsen_info[*i].unlock_turn <= turn)
+            .collect();
+        if turn >= DIG_END_TURN || *nodes > MAX_SEARCH_NODES {
+            Self::update_best(plan, best);
+        } else if candidates.is_empty() {
+            // 没有可挖的温泉，等到下一次解锁
+            match Self::next_unlock_turn(turn) {
+                Some(next) => self.search_select(state, next, true, plan, best, nodes),
+                None => Self::update_best(plan, best)
+            }
+        } else {
+            for onsen in candidates {
+                let mut next = state.clone();
+                next.turn = turn;
+                let upgrade = if upgrade { self.choose_upgrade(&next, onsen) } else { None };
+                if let Some(ty) = upgrade {
+                    next.dig_level[ty] += 1;
+                }
+                plan.steps.push(DigPlanStep {
+                    turn,
+                    onsen,
+                    upgrade,
+                    finish_turn: None
+                });
+                self.search_dig(next, onsen, plan, best, nodes);
+                plan.steps.pop();
+            }
+        }
+    }
+
+    /// 挖掘onsen直到完成或者下一次解锁
+    fn search_dig(&self, mut state: PlanState, onsen: usize, plan: &mut DigPlan, best: &mut DigPlan, nodes: &mut usize) {
+        let stop = Self::next_unlock_turn(state.turn).unwrap_or(DIG_END_TURN);
+        let mut remain = state.remain[onsen];
+        let finish = self.dig_until(&mut remain, &state.dig_level, state.turn, stop);
+        state.remain[onsen] = remain;
+        match finish {
+            Some(t) => {
+                state.done[onsen] = true;
+                if let Some(step) = plan.steps.last_mut() {
+                    step.finish_turn = Some(t);
+                }
+                plan.completed.push((onsen, t));
+                // 挖完后下回合选择新温泉并升级
+                self.search_select(state, t + 1, true, plan, best, nodes);
+                plan.completed.pop();
+                if let Some(step) = plan.steps.last_mut() {
+                    step.finish_turn = None;
+                }
+            }
+            None if stop < DIG_END_TURN => self.search_select(state, stop, true, plan, best, nodes),
+            None => Self::update_best(plan, best)
+        }
+    }
+
+    fn update_best(plan: &DigPlan, best: &mut DigPlan) {
+        if best.steps.is_empty() || plan.score() > best.score() {
+            *best = plan.clone();
+        }
+    }
+}
+
+impl OnsenGame {
+    /// 温泉挖掘预测和推荐路线
+    pub fn explain_dig_plan(&self) -> String {
+        let onsen_info = &global!(ONSENDATA).onsen_info;
+        let planners: Vec<_> = ActionMix::all()
+            .into_iter()
+            .map(|mix| DigPlanner::new(self, mix))
+            .collect();
+        let mut lines = vec![format!(
+            "挖掘预测(每回合基础挖掘量): {}",
+            planners
+                .iter()
+                .map(|p| format!("{} {:.1}", p.mix.name, p.mix.base_dig_value()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )];
+        let forecasts: Vec<_> = planners.iter().map(|p| p.forecast_all()).collect();
+        for (i, f) in forecasts[0].iter().enumerate() {
+            let text: Vec<_> = forecasts
+                .iter()
+                .map(|fs| {
+                    let f = &fs[i];
+                    match (f.finish_turn, f.deadline()) {
+                        (Some(t), Some(d)) => format!("{:.1}回合 回合{}完成(回合{}前)", f.dig_turns, t + 1, d + 1),
+                        _ => format!("{:.1}回合 无法完成", f.dig_turns)
+                    }
+                })
+                .collect();
+            lines.push(format!("{}: {}", onsen_info[f.onsen].name, text.join(" / ")));
+        }
+        lines.push(planners[0].plan().explain());
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::{gamedata::init_global, utils::init_logger};
+
+    #[test]
+    fn test_dig_plan() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let mut game = OnsenGame::newgame(101901, &[302424, 302464, 302484, 302564, 302574, 302644], Default::default())?;
+        game.update_scenario_buff(false);
+        println!("{}", game.explain_dig_plan());
+        let planner = DigPlanner::new(&game, ActionMix::TRAIN);
+        let plan = planner.plan();
+        // 开局三个温泉都能在第一次解锁前挖完其中一个
+        assert_eq!(plan.steps[0].turn, 2);
+        assert!(plan.steps[0].upgrade.is_some());
+        assert!(plan.completed.len() >= 3);
+        // 完成的温泉不重复
+        let mut onsens: Vec<_> = plan.completed.iter().map(|(o, _)| *o).collect();
+        onsens.sort();
+        onsens.dedup();
+        assert_eq!(onsens.len(), plan.completed.len());
+        // 挖得越少越晚完成
+        let fast = DigPlanner::new(&game, ActionMix::TRAIN).forecast(1);
+        let slow = DigPlanner::new(&game, ActionMix::RELAXED).forecast(1);
+        assert!(fast.dig_turns < slow.dig_turns);
+        Ok(())
+    }
+}
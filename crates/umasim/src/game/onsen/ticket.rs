@@ -0,0 +1,255 @@
+//! 温泉券使用时机分析
+//!
+//! 比较现在使用温泉券和等待若干回合后再使用的期望价值(折算为pt)。
+//! 考虑超回复触发概率、体力、送券导致的温泉券溢出，以及合宿和比赛回合的Buff收益。
+//! 体力和超回复累计消耗按每回合固定消耗估算，不考虑休息和事件。
+//! 折算权重取自constants.json的ticket_advice。
+
+use crate::{
+    game::{
+        Game,
+        onsen::{
+            game::OnsenGame,
+            planner::{ActionMix, DigPlanner}
+        }
+    },
+    gamedata::{
+        GAMECONSTANTS,
+        TicketAdviceData,
+        onsen::{ONSENDATA, OnsenEffect}
+    },
+    global
+};
+
+/// 默认向后看的回合数
+pub const DEFAULT_HORIZON: i32 = 6;
+/// 温泉券上限
+const MAX_TICKETS: i32 = 3;
+
+/// 在某回合使用温泉券的预测
+#[derive(Debug, Clone, PartialEq)]
+pub struct TicketTiming {
+    /// 使用的回合
+    pub turn: i32,
+    /// 使用时已有超回复的概率
+    pub super_prob: f64,
+    /// 使用时的预计体力
+    pub vital: i32,
+    /// 等到这回合比现在使用多浪费的温泉券(超过上限)
+    pub wasted: i32,
+    /// 期望价值(pt)
+    pub value: f64
+}
+
+/// 温泉券使用时机分析结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TicketAdvice {
+    /// 分析时的回合
+    pub turn: i32,
+    /// 各个可用回合的预测，按回合排序
+    pub timings: Vec<TicketTiming>,
+    /// 期望价值最高的时机(timings下标)
+    pub best: usize
+}
+
+impl TicketAdvice {
+    /// 是否建议现在使用
+    pub fn use_now(&self) -> bool {
+        self.timings.get(self.best).is_some_and(|t| t.turn == self.turn)
+    }
+
+    pub fn explain(&self) -> String {
+        let Some(best) = self.timings.get(self.best) else {
+            return "温泉券: 无可用时机".to_string();
+        };
+        let advice = if self.use_now() {
+            "建议现在使用".to_string()
+        } else {
+            format!("建议等到回合{}使用", best.turn + 1)
+        };
+        let mut lines = vec![format!("温泉券时机: {advice}")];
+        for t in &self.timings {
+            let mut text = format!(
+                "  回合{} 超回复{:.0}% 体力{} 价值{:.0}",
+                t.turn + 1,
+                t.super_prob * 100.0,
+                t.vital,
+                t.value
+            );
+            if t.wasted > 0 {
+                text += &format!(" 浪费{}张", t.wasted);
+            }
+            lines.push(text);
+        }
+        lines.join("\n")
+    }
+}
+
+impl OnsenGame {
+    /// 分析现在使用温泉券和等待horizon回合内再使用的期望价值，没有温泉券时返回None
+    pub fn analyze_ticket_timing(&self, horizon: i32) -> Option<TicketAdvice> {
+        if self.bathing.ticket_num == 0 || self.turn < 2 {
+            return None;
+        }
+        let weights = &global!(GAMECONSTANTS).ticket_advice;
+        let effect = self.bathing_effect();
+        let grants = self.upcoming_ticket_grants(self.turn + horizon);
+        let base_value = self.ticket_value(weights, &effect, self.turn, self.uma.vital, 0.0);
+        let mut not_super = if self.bathing.is_super_ready { 0.0 } else { 1.0 };
+        let mut penalty = 0.0;
+        let mut timings = vec![];
+        for delay in 0..=horizon {
+            let turn = self.turn + delay;
+            if turn > self.max_turn() {
+                break;
+            }
+            if delay > 0 {
+                // 等待的上一回合: 累计消耗体力可能触发超回复，低体力影响训练
+                let last_vital = self.uma.vital - (delay - 1) * weights.vital_cost_per_turn;
+                if not_super > 0.0 {
+                    not_super *= 1.0 - self.calc_super_prob(delay * weights.vital_cost_per_turn);
+                }
+                if last_vital < weights.low_vital {
+                    penalty += weights.low_vital_penalty;
+                }
+            }
+            // Buff未结束时不能使用
+            if delay < self.bathing.buff_remain_turn {
+                continue;
+            }
+            let vital = (self.uma.vital - delay * weights.vital_cost_per_turn).max(0);
+            let super_prob = 1.0 - not_super;
+            // 持有时收到的券超过上限的部分，和现在使用相比
+            let received: i32 = grants.iter().filter(|(t, _)| *t <= turn).map(|(_, n)| *n).sum();
+            let overflow_hold = (self.bathing.ticket_num + received - MAX_TICKETS).max(0);
+            let overflow_now = (self.bathing.ticket_num - 1 + received - MAX_TICKETS).max(0);
+            let wasted = overflow_hold - overflow_now;
+            let value =
+                self.ticket_value(weights, &effect, turn, vital, super_prob) - penalty - wasted as f64 * base_value;
+            timings.push(TicketTiming {
+                turn,
+                super_prob,
+                vital,
+                wasted,
+                value
+            });
+        }
+        // 价值相同时优先早用
+        let best = timings
+            .iter()
+            .enumerate()
+            .fold(None, |best: Option<(usize, f64)>, (i, t)| match best {
+                Some((_, v)) if v >= t.value => best,
+                _ => Some((i, t.value))
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        Some(TicketAdvice {
+            turn: self.turn,
+            timings,
+            best
+        })
+    }
+
+    /// 当前温泉使用温泉券时的效果
+    fn bathing_effect(&self) -> OnsenEffect {
+        let mut ret = OnsenEffect::default();
+        for (i, info) in global!(ONSENDATA).onsen_info.iter().enumerate() {
+            if self.onsen_state[i] {
+                ret.add_eq(&info.effect);
+            }
+        }
+        ret
+    }
+
+    /// 从下回合到end_turn之间预计获得的温泉券(回合, 数量)
+    fn upcoming_ticket_grants(&self, end_turn: i32) -> Vec<(i32, i32)> {
+        let mut ret: Vec<_> = [36, 60, 72]
+            .into_iter()
+            .filter(|t| *t > self.turn && *t <= end_turn)
+            .map(|t| (t, self.grant_ticket_num_at(t)))
+            .collect();
+        // 当前温泉挖完时送券
+        if !self.pending_selection && !self.onsen_state[self.current_onsen] {
+            let forecast = DigPlanner::new(self, ActionMix::BALANCED).forecast(self.current_onsen);
+            if let Some(t) = forecast.finish_turn.filter(|t| *t > self.turn && *t <= end_turn) {
+                ret.push((t, self.grant_ticket_num_at(t)));
+            }
+        }
+        ret
+    }
+
+    /// 第turn回合以vital体力、super_prob超回复概率使用温泉券的期望价值
+    fn ticket_value(
+        &self, weights: &TicketAdviceData, effect: &OnsenEffect, turn: i32, vital: i32, super_prob: f64
+    ) -> f64 {
+        let super_effect = &global!(ONSENDATA).super_effect;
+        let normal = (vital + effect.vital).min(self.uma.max_vital) - vital;
+        let with_super = (vital + effect.vital + super_effect.vital).min(super_effect.temp_max_vital) - vital;
+        let super_extra =
+            super_effect.pt as f64 + (super_effect.hint + self.scenario_buff.hotel.hint) as f64 * weights.pt_per_hint;
+        let recovery = (1.0 - super_prob) * normal as f64 * weights.pt_per_vital
+            + super_prob * (with_super as f64 * weights.pt_per_vital + super_extra);
+        let motivation = effect.motivation.min(5 - self.uma.motivation).max(0) as f64 * weights.pt_per_motivation;
+        // Buff持续2回合
+        let buff: f64 = (turn..turn + 2).map(|t| self.buff_turn_value(weights, effect, t)).sum();
+        recovery + motivation + buff
+    }
+
+    /// 温泉Buff在第turn回合的价值
+    fn buff_turn_value(&self, weights: &TicketAdviceData, effect: &OnsenEffect, turn: i32) -> f64 {
+        if turn > self.max_turn() {
+            0.0
+        } else if self.uma.is_race_turn(turn) {
+            effect.career_race_bonus as f64 * weights.pt_per_race_bonus
+        } else {
+            let bonus = effect.xunlian as f64 + effect.youqing.iter().sum::<i32>() as f64 / 5.0;
+            let factor = if (36..40).contains(&turn) || (60..64).contains(&turn) {
+                weights.xiahesu_factor
+            } else {
+                1.0
+            };
+            bonus * weights.pt_per_train_bonus * factor
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::{game::FriendCardState, gamedata::init_global, utils::init_logger};
+
+    #[test]
+    fn test_ticket_timing() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let mut game = OnsenGame::newgame(101901, &[302424, 302464, 302484, 302564, 302574, 302644], Default::default())?;
+        game.turn = 10;
+        assert!(game.analyze_ticket_timing(DEFAULT_HORIZON).is_none());
+        // 有超回复且体力低时应该立即使用
+        game.bathing.ticket_num = 1;
+        game.bathing.is_super_ready = true;
+        game.uma.vital = 30;
+        let advice = game.analyze_ticket_timing(DEFAULT_HORIZON).expect("advice");
+        println!("{}", advice.explain());
+        assert!(advice.use_now());
+        assert!(advice.timings.iter().all(|t| t.super_prob == 1.0));
+        // 满券时等到合宿会浪费送的券。卡组里是非剧本友人，送券按带了SSR剧本友人计算
+        game.friend.card_state = FriendCardState::SSR;
+        game.turn = 33;
+        game.bathing.ticket_num = 3;
+        game.bathing.is_super_ready = false;
+        game.uma.vital = 90;
+        let advice = game.analyze_ticket_timing(DEFAULT_HORIZON).expect("advice");
+        println!("{}", advice.explain());
+        assert!(advice.timings.iter().any(|t| t.turn >= 36 && t.wasted > 0));
+        // Buff未结束的回合不能使用
+        game.bathing.buff_remain_turn = 2;
+        let advice = game.analyze_ticket_timing(DEFAULT_HORIZON).expect("advice");
+        assert_eq!(advice.timings[0].turn, 35);
+        assert!(!advice.use_now());
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+//! 自选比赛规划
+//!
+//! 枚举剩余回合内满足所有自选比赛要求的比赛回合组合，估算每种组合的代价，推荐代价最低的组合。
+//! 代价按少训练的回合(合宿加权)、比赛消耗的体力和连续比赛计算，再减去比赛本身的收益。
+//! 比赛收益和体力消耗取自比赛事件(race_g1..race_g4)，折算权重取自constants.json的race_plan。
+//! 生涯比赛回合不能打自选比赛；已经赢下的比赛按掩码计入各个要求。
+
+use std::collections::HashSet;
+
+use crate::{
+    game::Uma,
+    gamedata::{FreeRaceData, GAMECONSTANTS, RacePlanData},
+    global,
+    utils::system_event
+};
+
+/// 可以打自选比赛的回合 [14, 72)
+pub const FREE_RACE_TURNS: std::ops::Range<i32> = 14..72;
+/// 枚举的比赛组合上限，候选回合按代价排序，先枚举到的是便宜的组合
+const MAX_SCHEDULES: usize = 256;
+/// 比赛等级数，race_grades中0为无比赛，1-4为G1/G2/G3/OP
+const RACE_GRADE_COUNT: usize = 5;
+
+/// 一个自选比赛要求的剩余情况
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaceRequirement {
+    pub start_turn: i32,
+    pub end_turn: i32,
+    /// 还要赢几场
+    pub need: u32,
+    /// 比赛掩码，与FreeRaceData相同
+    pub mask: u64,
+    /// 剩余可以打的回合
+    pub candidates: Vec<i32>
+}
+
+/// 一种比赛安排
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaceSchedule {
+    /// 要打的自选比赛回合，升序
+    pub turns: Vec<i32>,
+    /// 少训练的回合数(合宿加权)
+    pub lost_train: f64,
+    /// 消耗的体力
+    pub vital: i32,
+    /// 比赛收益(属性+pt)
+    pub reward: f64,
+    /// 总代价(pt)，越小越好
+    pub cost: f64
+}
+
+impl RaceSchedule {
+    pub fn explain(&self) -> String {
+        let turns: Vec<_> = self.turns.iter().map(|t| (t + 1).to_string()).collect();
+        format!(
+            "回合[{}] 少训练{:.1} 体力-{} 收益{:.0} 代价{:.0}",
+            turns.join(","),
+            self.lost_train,
+            self.vital,
+            self.reward,
+            self.cost
+        )
+    }
+}
+
+/// 自选比赛规划结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RacePlan {
+    /// 规划时的回合
+    pub turn: i32,
+    /// 还没完成的要求
+    pub requirements: Vec<RaceRequirement>,
+    /// 可行的安排，按代价升序
+    pub schedules: Vec<RaceSchedule>
+}
+
+impl RacePlan {
+    /// 从turn回合开始规划剩余的自选比赛
+    pub fn new(uma: &Uma, turn: i32) -> Self {
+        let mut ret = Self {
+            turn,
+            ..Default::default()
+        };
+        let Ok(data) = uma.get_data() else {
+            return ret;
+        };
+        let model = CostModel::new(uma);
+        ret.requirements = data
+            .free_races
+            .iter()
+            .filter_map(|free| model.requirement(free, turn))
+            .collect();
+        if ret.requirements.is_empty() {
+            return ret;
+        }
+        let mut found = vec![];
+        search(&ret.requirements, 0, &mut vec![], &mut found);
+        let mut seen = HashSet::new();
+        ret.schedules = found
+            .into_iter()
+            .filter(|turns| seen.insert(turns.clone()))
+            .map(|turns| model.schedule_cost(turns))
+            .collect();
+        ret.schedules.sort_by(|a, b| a.cost.total_cmp(&b.cost).then_with(|| a.turns.cmp(&b.turns)));
+        ret
+    }
+
+    /// 还有没完成的要求
+    pub fn has_pending(&self) -> bool {
+        !self.requirements.is_empty()
+    }
+
+    /// 有要求但无法全部满足
+    pub fn is_infeasible(&self) -> bool {
+        self.has_pending() && self.schedules.is_empty()
+    }
+
+    /// 代价最低的安排
+    pub fn best(&self) -> Option<&RaceSchedule> {
+        self.schedules.first()
+    }
+
+    /// 推荐的安排是否在本回合比赛
+    pub fn race_now(&self) -> bool {
+        self.best().is_some_and(|s| s.turns.contains(&self.turn))
+    }
+
+    pub fn explain(&self) -> String {
+        if !self.has_pending() {
+            return "自选比赛: 已完成".to_string();
+        }
+        let mut lines = vec![];
+        for req in &self.requirements {
+            lines.push(format!(
+                "自选比赛 回合{}-{} 还差{}场 可选{}回合",
+                req.start_turn + 1,
+                req.end_turn + 1,
+                req.need,
+                req.candidates.len()
+            ));
+        }
+        match self.best() {
+            Some(best) => {
+                let advice = if self.race_now() { "本回合比赛" } else { "本回合不用比赛" };
+                lines.push(format!("推荐: {} ({advice})", best.explain()));
+            }
+            None => lines.push("无法满足自选比赛要求".to_string())
+        }
+        lines.join("\n")
+    }
+}
+
+fn is_xiahesu(turn: i32) -> bool {
+    (36..40).contains(&turn) || (60..64).contains(&turn)
+}
+
+fn turn_bit(turn: i32) -> u64 {
+    1u64 << (turn - 11)
+}
+
+/// 规划时共用的代价数据，每次规划只查一次比赛事件
+struct CostModel<'a> {
+    uma: &'a Uma,
+    weights: &'a RacePlanData,
+    race_grades: &'a [i32],
+    /// 各等级比赛的收益(属性+pt，已乘算比赛加成)
+    rewards: [f64; RACE_GRADE_COUNT],
+    /// 各等级比赛消耗的体力
+    vitals: [i32; RACE_GRADE_COUNT]
+}
+
+impl<'a> CostModel<'a> {
+    fn new(uma: &'a Uma) -> Self {
+        let constants = global!(GAMECONSTANTS);
+        let mut rewards = [0.0; RACE_GRADE_COUNT];
+        let mut vitals = [0; RACE_GRADE_COUNT];
+        for grade in 1..RACE_GRADE_COUNT {
+            if let Ok(event) = system_event(&format!("race_g{grade}"))
+                && let Some(choice) = event.choices.first()
+            {
+                let total: i32 = choice.status_pt.iter().sum();
+                rewards[grade] = total as f64 * (100 + uma.race_bonus) as f64 / 100.0;
+                vitals[grade] = -choice.vital;
+            }
+        }
+        Self {
+            uma,
+            weights: &constants.race_plan,
+            race_grades: &constants.race_grades,
+            rewards,
+            vitals
+        }
+    }
+
+    fn grade(&self, turn: i32) -> usize {
+        self.race_grades[turn as usize] as usize
+    }
+
+    /// 少训练一回合的权重，合宿回合更高
+    fn train_factor(&self, turn: i32) -> f64 {
+        if is_xiahesu(turn) { self.weights.xiahesu_factor } else { 1.0 }
+    }
+
+    /// 单回合比赛的代价，用于候选排序
+    fn turn_cost(&self, turn: i32) -> f64 {
+        let grade = self.grade(turn);
+        self.train_factor(turn) * self.weights.pt_per_train_turn + self.vitals[grade] as f64 * self.weights.pt_per_vital
+            - self.rewards[grade]
+    }
+
+    /// 计算一个自选比赛要求的剩余情况，已完成或已过期时返回None
+    fn requirement(&self, free: &FreeRaceData, turn: i32) -> Option<RaceRequirement> {
+        let uma = self.uma;
+        let (start_turn, end_turn) = (free.start_turn as i32, free.end_turn as i32);
+        let need = free.count.saturating_sub(uma.count_free_race(free));
+        if need == 0 || end_turn < turn {
+            return None;
+        }
+        let mut candidates: Vec<_> = (start_turn.max(turn)..=end_turn)
+            .filter(|t| {
+                FREE_RACE_TURNS.contains(t)
+                    && self.race_grades[*t as usize] > 0
+                    && free.mask & turn_bit(*t) != 0
+                    && uma.win_races & turn_bit(*t) == 0
+                    && !uma.is_race_turn(*t)
+            })
+            .collect();
+        candidates.sort_by(|a, b| self.turn_cost(*a).total_cmp(&self.turn_cost(*b)));
+        Some(RaceRequirement {
+            start_turn,
+            end_turn,
+            need,
+            mask: free.mask,
+            candidates
+        })
+    }
+
+    /// 估算一种安排的代价
+    fn schedule_cost(&self, turns: Vec<i32>) -> RaceSchedule {
+        let uma = self.uma;
+        let lost_train: f64 = turns.iter().map(|t| self.train_factor(*t)).sum();
+        let vital: i32 = turns.iter().map(|t| self.vitals[self.grade(*t)]).sum();
+        let reward: f64 = turns.iter().map(|t| self.rewards[self.grade(*t)]).sum();
+        // 连续比赛: 与生涯比赛、已经打过的比赛和本安排的其他比赛连在一起
+        let is_race =
+            |t: i32| turns.contains(&t) || uma.is_race_turn(t) || (t >= 11 && uma.win_races & turn_bit(t) != 0);
+        let mut penalty = 0.0;
+        for t in &turns {
+            if is_race(t - 1) && is_race(t - 2) {
+                penalty += self.weights.consecutive_race_penalty;
+            }
+        }
+        let cost = lost_train * self.weights.pt_per_train_turn + vital as f64 * self.weights.pt_per_vital + penalty
+            - reward;
+        RaceSchedule {
+            turns,
+            lost_train,
+            vital,
+            reward,
+            cost
+        }
+    }
+}
+
+/// 逐个要求补足场数，已经选中的回合可以同时满足多个要求
+fn search(reqs: &[RaceRequirement], index: usize, chosen: &mut Vec<i32>, found: &mut Vec<Vec<i32>>) {
+    if found.len() >= MAX_SCHEDULES {
+        return;
+    }
+    let Some(req) = reqs.get(index) else {
+        let mut turns = chosen.clone();
+        turns.sort();
+        found.push(turns);
+        return;
+    };
+    let have = chosen.iter().filter(|t| req.mask & turn_bit(**t) != 0).count() as u32;
+    let need = req.need.saturating_sub(have) as usize;
+    let free: Vec<_> = req.candidates.iter().filter(|t| !chosen.contains(t)).copied().collect();
+    choose(&free, need, 0, chosen, &mut |chosen| search(reqs, index + 1, chosen, found));
+}
+
+/// 从items[start..]中选k个追加到chosen，对每种选法调用f
+fn choose(items: &[i32], k: usize, start: usize, chosen: &mut Vec<i32>, f: &mut dyn FnMut(&mut Vec<i32>)) {
+    if k == 0 {
+        f(chosen);
+        return;
+    }
+    for i in start..items.len() {
+        if items.len() - i < k {
+            break;
+        }
+        chosen.push(items[i]);
+        choose(items, k - 1, i + 1, chosen, f);
+        chosen.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::{gamedata::init_global, utils::init_logger};
+
+    #[test]
+    fn test_race_plan() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        // 回合46-59需要3场G1
+        let mut uma = Uma::new(101901)?;
+        let plan = RacePlan::new(&uma, 30);
+        println!("{}", plan.explain());
+        let best = plan.best().expect("schedule");
+        assert_eq!(best.turns.len(), 3);
+        // 体力消耗取自比赛事件
+        assert_eq!(best.vital, 45);
+        let race_grades = &global!(GAMECONSTANTS).race_grades;
+        assert!(best.turns.iter().all(|t| race_grades[*t as usize] == 1 && !uma.is_race_turn(*t)));
+        assert!(plan.schedules.windows(2).all(|w| w[0].cost <= w[1].cost));
+        // 已经赢下的比赛计入要求
+        uma.set_race(best.turns[0]);
+        let plan = RacePlan::new(&uma, best.turns[0] + 1);
+        assert_eq!(plan.requirements[0].need, 2);
+        assert!(plan.schedules.iter().all(|s| s.turns.len() == 2));
+        // 剩余回合不够时无解
+        let plan = RacePlan::new(&uma, 59);
+        assert!(plan.is_infeasible());
+        // 全部完成
+        for t in &best.turns[1..] {
+            uma.set_race(*t);
+        }
+        assert!(!RacePlan::new(&uma, 50).has_pending());
+        Ok(())
+    }
+}
@@ -6,8 +6,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     explain::Explain,
-    game::Game,
-    gamedata::{CardValue, GAMEDATA, SupportCardData},
+    game::{Game, STACK_KEY, UniqueContext, UniqueEffect, UniqueRule, UniqueValue},
+    gamedata::{CardValue, EventData, GAMEDATA, SupportCardData},
     global,
     utils::*
 };
@@ -203,7 +203,11 @@ pub struct SupportCard {
     /// 固有状态
     pub effect_state: HashMap<String, u32>,
     /// 已经获得的Hint等级
-    pub total_hints: i32
+    pub total_hints: i32,
+    /// 已经触发的连续事件段数
+    pub event_progress: usize,
+    /// 固有规则，None为不支持的固有
+    pub unique_rule: Option<Arc<UniqueRule>>
 }
 
 impl SupportCard {
@@ -228,6 +232,18 @@ impl SupportCard {
         &self.data.card_value[self.rank as usize]
     }
 
+    /// 这张卡的连续事件
+    pub fn card_events(&self) -> &'static [EventData] {
+        global_events().card_events.chain(self.card_id)
+    }
+
+    /// 第turn回合可以触发的下一段连续事件
+    pub fn next_card_event(&self, turn: i32) -> Option<&'static EventData> {
+        let event = self.card_events().get(self.event_progress)?;
+        let in_range = turn >= event.start_turn && (event.end_turn < 0 || turn < event.end_turn);
+        in_range.then_some(event)
+    }
+
     /// 初始属性加成
     pub fn initial_bonus(&self) -> &Array6 {
         &self.card_value().initial_bonus
@@ -252,7 +268,9 @@ impl SupportCard {
             is_link_card: false,
             is_locked: false,
             effect_state: HashMap::new(),
-            total_hints: 0
+            total_hints: 0,
+            event_progress: 0,
+            unique_rule: UniqueRule::from_data(data).map(Arc::new)
         })
     }
     /// 计算当前卡在指定位置时, 考虑固有的实际面板  
@@ -262,60 +280,17 @@ impl SupportCard {
         let mut locking = false;
         if !self.is_locked {
             // locked就不计算，节省时间
-            let param = &self.data.unique_effect_param;
-            match self.data.unique_effect_type {
-                0 => {
-                    locking = true;
-                }
-                1 | 2 => {
-                    // 羁绊>args[1]时触发词条args[2] = args[3]
-                    if self.friendship >= param[1] {
-                        debug!("{} 羁绊>{}, 触发固有: {:?}", self.data.short_name(), param[1], param);
-                        if param[2] > 0 {
-                            ret.add_effect_line(param[2], param[3]);
-                        }
-                        if param[4] > 0 {
-                            ret.add_effect_line(param[4], param[5]);
-                        }
-                        locking = true;
-                    }
-                }
-                13 => {
-                    // b95 参与友情训练时触发
-                    if game.shining_count(train as usize) > 0 {
-                        ret.add_effect_line(param[1], param[2]);
-                    }
-                }
-                20 => {
-                    // 巨匠: 羁绊>80时根据卡组决定加成
-                    if self.friendship >= param[2] {
-                        let mut card_type_count = vec![0, 0, 0, 0, 0, 0];
-                        for c in game.deck() {
-                            if c.card_type >= 5 {
-                                card_type_count[5] += 1;
-                            } else {
-                                card_type_count[c.card_type as usize] += 1;
-                            }
-                        }
-                        debug!(
-                            "{} 羁绊>{}, 副属性加成(最大+2): {:?}",
-                            self.data.short_name(),
-                            param[2],
-                            card_type_count
-                        );
-                        for i in 0..5 {
-                            if card_type_count[i] > 0 {
-                                // 0-4对应副属性词条#3-7
-                                ret.add_effect_line((i + 3) as i32, card_type_count[i].max(2));
-                            }
-                        }
-                        if card_type_count[5] > 0 {
-                            ret.add_effect_line(30, card_type_count[5].max(2));
-                        }
-                        locking = true;
+            match &self.unique_rule {
+                Some(rule) => {
+                    let ctx = UniqueContext::new(game, self, train as usize);
+                    let met = rule.apply(&ctx, &mut ret);
+                    if met && !rule.conditions.is_empty() {
+                        debug!("{} 触发固有: {:?}", self.data.short_name(), rule.effects);
                     }
+                    // 不随局面变化的固有满足条件后锁定，没有条件的直接锁定
+                    locking = rule.is_static() && (met || rule.conditions.is_empty());
                 }
-                _ => {
+                None => {
                     warn!(
                         "未实现固有逻辑: #{} - {}",
                         self.data.unique_effect_type,
@@ -323,10 +298,19 @@ impl SupportCard {
                     );
                     locking = true;
                 }
-            } // match unique_effect_type
+            }
         }
         Ok((ret, locking))
     }
+
+    /// 参加友情训练后，叠加型固有计数+1
+    pub fn on_shining_train(&mut self) {
+        if let Some(rule) = &self.unique_rule
+            && rule.effects.iter().any(|e| matches!(e, UniqueEffect::Line(_, UniqueValue::Stack { .. })))
+        {
+            *self.effect_state.entry(STACK_KEY.to_string()).or_insert(0) += 1;
+        }
+    }
 }
 
 #[cfg(test)]
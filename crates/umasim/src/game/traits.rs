@@ -1,13 +1,17 @@
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    sync::atomic::{AtomicBool, Ordering}
+};
 
 use anyhow::{Result, anyhow};
+use comfy_table::Table;
 use log::{info, warn};
 use rand::{Rng, rngs::StdRng};
 use rand_distr::{Distribution, weighted::WeightedIndex};
 
 use super::PersonType;
 use crate::{
-    game::{BaseAction, CardTrainingEffect, SupportCard, Uma},
+    game::{BaseAction, CardTrainingEffect, SupportCard, Uma, UniqueTurnContext},
     gamedata::{ActionValue, EventData, GAMECONSTANTS},
     global
 };
@@ -61,6 +65,63 @@ pub trait ActionEnum: Debug + Display + Clone + PartialEq {
     }
 }
 
+/// 是否在每个阶段后检查状态
+static CHECKED_MODE: AtomicBool = AtomicBool::new(false);
+
+/// 开启或关闭状态检查模式，开启后每个阶段运行完都会调用validate
+pub fn set_checked_mode(checked: bool) {
+    CHECKED_MODE.store(checked, Ordering::Relaxed);
+}
+
+/// 是否为状态检查模式
+pub fn checked_mode() -> bool {
+    CHECKED_MODE.load(Ordering::Relaxed)
+}
+
+/// 状态检查发现的不可能状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateViolation {
+    /// 回合
+    pub turn: i32,
+    /// 阶段
+    pub stage: String,
+    /// 说明
+    pub message: String
+}
+
+impl Display for StateViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "回合{} {}: {}", self.turn + 1, self.stage, self.message)
+    }
+}
+
+/// 默认的人头分布预测采样次数
+pub const DEFAULT_FORECAST_SAMPLES: usize = 2000;
+
+/// 人头分布预测，按采样频率估计每个人头出现在各训练的概率
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DistributionForecast {
+    /// 采样次数
+    pub samples: usize,
+    /// 按person_index排列，每项为 [速, 耐, 力, 根, 智, 不在] 的概率
+    pub probs: Vec<[f64; 6]>
+}
+
+impl DistributionForecast {
+    /// 显示为表格，names为各人头的名字
+    pub fn explain(&self, names: &[String]) -> String {
+        let mut table = Table::new();
+        table.set_header(vec!["人头", "速", "耐", "力", "根", "智", "不在"]);
+        for (i, probs) in self.probs.iter().enumerate() {
+            let name = names.get(i).cloned().unwrap_or_else(|| format!("#{i}"));
+            let mut row = vec![name];
+            row.extend(probs.iter().map(|p| format!("{:.0}%", p * 100.0)));
+            table.add_row(row);
+        }
+        format!("人头分布预测({}次采样):\n{table}", self.samples)
+    }
+}
+
 /// 游戏状态类型需要实现的Trait，不包括初始化
 pub trait Game: Clone {
     type Person: Person;
@@ -74,11 +135,25 @@ pub trait Game: Clone {
     fn next(&mut self) -> bool;
     /// 模拟当前Stage
     fn run_stage<T: Trainer<Self>>(&mut self, trainer: &T, rng: &mut StdRng) -> Result<()>;
+    /// 当前阶段名
+    fn stage_name(&self) -> String;
+    /// provided: 模拟当前Stage，状态检查模式下检查模拟后的状态
+    fn run_stage_checked<T: Trainer<Self>>(&mut self, trainer: &T, rng: &mut StdRng) -> Result<()> {
+        self.run_stage(trainer, rng)?;
+        if checked_mode() {
+            let violations = self.validate();
+            if !violations.is_empty() {
+                let text: Vec<_> = violations.iter().map(|v| v.to_string()).collect();
+                return Err(anyhow!("状态检查失败:\n{}", text.join("\n")));
+            }
+        }
+        Ok(())
+    }
     /// provided: 模拟到游戏结束
     fn run_full_game<T: Trainer<Self>>(&mut self, trainer: &T, rng: &mut StdRng) -> Result<()> {
-        self.run_stage(trainer, rng)?;
+        self.run_stage_checked(trainer, rng)?;
         while self.next() {
-            self.run_stage(trainer, rng)?;
+            self.run_stage_checked(trainer, rng)?;
         }
         // 触发育成结束奖励
         self.on_simulation_end(trainer, rng)?;
@@ -89,6 +164,60 @@ pub trait Game: Clone {
     fn on_simulation_end<T: Trainer<Self>>(&mut self, _trainer: &T, _rng: &mut StdRng) -> Result<()> {
         Ok(())
     }
+    /// provided: 检查状态是否合法，返回所有不可能状态
+    fn validate(&self) -> Vec<StateViolation> {
+        self.default_validate()
+    }
+    /// 如果validate被重写，仍然可以调用这里的通用检查
+    fn default_validate(&self) -> Vec<StateViolation> {
+        let mut messages = vec![];
+        if self.turn() < 0 || self.turn() > self.max_turn() {
+            messages.push(format!("回合超出范围: {}", self.turn()));
+        }
+        let uma = self.uma();
+        for i in 0..5 {
+            if uma.five_status[i] > uma.five_status_limit[i] {
+                messages.push(format!(
+                    "属性{i}超过上限: {} > {}",
+                    uma.five_status[i], uma.five_status_limit[i]
+                ));
+            }
+            if uma.five_status[i] < 0 {
+                messages.push(format!("属性{i}为负: {}", uma.five_status[i]));
+            }
+        }
+        if uma.vital < 0 {
+            messages.push(format!("体力为负: {}", uma.vital));
+        }
+        if !(1..=5).contains(&uma.motivation) {
+            messages.push(format!("干劲超出范围: {}", uma.motivation));
+        }
+        let person_count = self.persons().len() as i32;
+        for (train, persons) in self.distribution().iter().enumerate() {
+            // -1为空位
+            let mut indices: Vec<_> = persons.iter().copied().filter(|x| *x >= 0).collect();
+            if indices.len() > 5 {
+                messages.push(format!("训练{train}人数超过5: {persons:?}"));
+            }
+            if indices.iter().any(|x| *x >= person_count) {
+                messages.push(format!("训练{train}有不存在的人头: {persons:?}"));
+            }
+            indices.sort();
+            indices.dedup();
+            if indices.len() != persons.iter().filter(|x| **x >= 0).count() {
+                messages.push(format!("训练{train}有重复人头: {persons:?}"));
+            }
+        }
+        messages.into_iter().map(|m| self.violation(m)).collect()
+    }
+    /// provided: 生成当前回合和阶段的状态异常
+    fn violation(&self, message: String) -> StateViolation {
+        StateViolation {
+            turn: self.turn(),
+            stage: self.stage_name(),
+            message
+        }
+    }
     // 动作相关
     /// 获取当前可能的可控行动
     fn list_actions(&self) -> Result<Vec<Self::Action>>;
@@ -174,6 +303,10 @@ pub trait Game: Clone {
     fn deyilv(&mut self, person_index: i32) -> Result<f32>;
     /// 团队卡是否可以闪彩，不考虑多个团卡的情况
     fn has_group_buff(&self) -> bool;
+    /// provided: 指定的团队卡自己的团队Buff是否开启，默认按只有一张团卡处理
+    fn has_group_buff_at(&self, _person_index: usize) -> bool {
+        self.has_group_buff()
+    }
     /// 显示分布信息
     fn explain_distribution(&self) -> Result<String>;
     /// 重置分布和叹号
@@ -260,6 +393,27 @@ pub trait Game: Clone {
         }
         Ok(())
     }
+    /// provided: 按当前状态重复采样distribute_all，估计每个人头出现在各训练和不在的概率
+    /// 不包括分身等剧本额外分配，不可用的人头视为不在
+    fn forecast_distribution(&self, samples: usize, rng: &mut StdRng) -> Result<DistributionForecast> {
+        let mut game = self.clone();
+        let mut counts = vec![[0usize; 6]; self.persons().len()];
+        for _ in 0..samples {
+            game.distribute_all(rng)?;
+            for (i, count) in counts.iter_mut().enumerate() {
+                let trains = game.at_trains(i as i32);
+                match trains.iter().position(|t| *t) {
+                    Some(train) => count[train] += 1,
+                    None => count[5] += 1
+                }
+            }
+        }
+        let probs = counts
+            .iter()
+            .map(|count| count.map(|c| c as f64 / samples.max(1) as f64))
+            .collect();
+        Ok(DistributionForecast { samples, probs })
+    }
     /// 分配Hint. 注意同一个卡的不同分身会同时触发Hint
     fn distribute_hint(&mut self, rng: &mut StdRng) -> Result<()> {
         let base_hint_rate = global!(GAMECONSTANTS).base_hint_rate / 100.0;
@@ -289,7 +443,7 @@ pub trait Game: Clone {
         let person = &self.persons()[person_index];
         match person.person_type() {
             PersonType::Card => person.train_type() == train as i32 && person.friendship() >= 80,
-            PersonType::TeamCard => self.has_group_buff(),
+            PersonType::TeamCard => self.has_group_buff_at(person_index),
             // 默认实现中其他卡不能闪彩
             _ => false
         }
@@ -311,6 +465,10 @@ pub trait Game: Clone {
     fn uma_mut(&mut self) -> &mut Uma;
     /// deck getter
     fn deck(&self) -> &Vec<SupportCard>;
+    /// provided: 本回合固有计算共用的局面信息，没有缓存时返回None
+    fn unique_turn_context(&self) -> Option<&UniqueTurnContext> {
+        None
+    }
     /// provided: 计算来自支援卡的训练buff
     fn calc_training_buff(&self, train: usize) -> Result<CardTrainingEffect> {
         self.default_calc_training_buff(train)
@@ -395,13 +553,7 @@ pub trait Game: Clone {
         let x0 = global!(GAMECONSTANTS).training_vital_threshold[train][self.train_level(train) - 1];
         let vital = self.uma().vital as f32;
         // 失败率修正
-        let bias = if self.uma().flags.good_trainer {
-            -2.0
-        } else if self.uma().flags.bad_trainer {
-            2.0
-        } else {
-            0.0
-        };
+        let bias = self.uma().flags.failure_rate_bias();
         // 原始失败率最大99%
         let mut f = if vital < x0 {
             (100.0 - vital) * (x0 - vital) / 40.0
@@ -3,11 +3,12 @@ use std::default::Default;
 use anyhow::Result;
 use colored::Colorize;
 use log::info;
+use rand::{Rng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     explain::Explain,
-    gamedata::{ActionValue, FreeRaceData, GAMECONSTANTS, GAMEDATA, UmaData},
+    gamedata::{ActionValue, ConditionData, FreeRaceData, GAMECONSTANTS, GAMEDATA, UmaData},
     global,
     utils::*
 };
@@ -32,7 +33,25 @@ pub struct UmaFlags {
     /// 是否抓过娃娃
     pub doll: bool,
     /// 是否生病
-    pub ill: bool
+    pub ill: bool,
+    /// 注目株
+    #[serde(default)]
+    pub hot_topic: bool,
+    /// 熬夜
+    #[serde(default)]
+    pub night_owl: bool,
+    /// 摸鱼癖
+    #[serde(default)]
+    pub lazy: bool,
+    /// 皮肤粗糙
+    #[serde(default)]
+    pub skin_outbreak: bool,
+    /// 发胖
+    #[serde(default)]
+    pub slow_metabolism: bool,
+    /// 偏头痛，干劲不会上升
+    #[serde(default)]
+    pub migraine: bool
 }
 
 impl UmaFlags {
@@ -62,11 +81,166 @@ impl UmaFlags {
         if self.ill {
             s += "*生病 ";
         }
+        for key in ["night_owl", "lazy", "skin_outbreak", "slow_metabolism", "migraine"] {
+            if self.is_active(key) {
+                s += &format!("*{} ", condition_name(key));
+            }
+        }
+        if self.hot_topic {
+            s += "注目株 ";
+        }
         if self.refresh_mind > 0 {
             s += &format!("休息心得({}回合)", self.refresh_mind);
         }
         s
     }
+
+    /// 按状态表的key查询状态是否生效
+    pub fn is_active(&self, key: &str) -> bool {
+        match key {
+            "qiezhe" => self.qiezhe,
+            "aijiao" => self.aijiao,
+            "good_trainer" => self.good_trainer,
+            "bad_trainer" => self.bad_trainer,
+            "positive_thinking" => self.positive_thinking,
+            "refresh_mind" => self.refresh_mind > 0,
+            "lucky" => self.lucky,
+            "ill" => self.ill,
+            "hot_topic" => self.hot_topic,
+            "night_owl" => self.night_owl,
+            "lazy" => self.lazy,
+            "skin_outbreak" => self.skin_outbreak,
+            "slow_metabolism" => self.slow_metabolism,
+            "migraine" => self.migraine,
+            _ => false
+        }
+    }
+
+    /// 按状态表的key设置状态，未知的key忽略
+    pub fn set_active(&mut self, key: &str, active: bool) {
+        match key {
+            "qiezhe" => self.qiezhe = active,
+            "aijiao" => self.aijiao = active,
+            "good_trainer" => self.good_trainer = active,
+            "bad_trainer" => self.bad_trainer = active,
+            "positive_thinking" => self.positive_thinking = active,
+            "refresh_mind" => self.refresh_mind = active as i32,
+            "lucky" => self.lucky = active,
+            "ill" => self.ill = active,
+            "hot_topic" => self.hot_topic = active,
+            "night_owl" => self.night_owl = active,
+            "lazy" => self.lazy = active,
+            "skin_outbreak" => self.skin_outbreak = active,
+            "slow_metabolism" => self.slow_metabolism = active,
+            "migraine" => self.migraine = active,
+            _ => {}
+        }
+    }
+
+    /// 当前生效的状态
+    pub fn conditions(&self) -> Vec<&'static ConditionData> {
+        global!(GAMECONSTANTS)
+            .conditions
+            .iter()
+            .filter(|c| self.is_active(&c.key))
+            .collect()
+    }
+
+    /// 是否有坏状态（可以去保健室）
+    pub fn has_bad_condition(&self) -> bool {
+        self.conditions().iter().any(|c| !c.good)
+    }
+
+    /// 状态带来的训练失败率修正
+    pub fn failure_rate_bias(&self) -> f32 {
+        self.conditions().iter().map(|c| c.failure_rate_bias).sum()
+    }
+
+    /// 去保健室能治好的坏状态期望个数，用于评估是否值得治病
+    pub fn expected_clinic_cures(&self) -> f64 {
+        self.conditions()
+            .iter()
+            .filter(|c| !c.good)
+            .map(|c| c.clinic_cure_prob)
+            .sum()
+    }
+
+    /// 事件结算后按状态表获得状态，返回新获得的状态名
+    pub fn grant_by_event(&mut self, event_id: u32, rng: &mut StdRng) -> Vec<String> {
+        let mut ret = vec![];
+        for cond in &global!(GAMECONSTANTS).conditions {
+            if self.is_active(&cond.key) {
+                continue;
+            }
+            for (id, prob) in &cond.grant_events {
+                if *id == event_id && rng.random_bool(prob.clamp(0.0, 1.0)) {
+                    self.set_active(&cond.key, true);
+                    ret.push(cond.name.clone());
+                    break;
+                }
+            }
+        }
+        ret
+    }
+
+    /// 以一定概率获得指定状态，返回新获得的状态名
+    pub fn grant(&mut self, key: &str, prob: f64, rng: &mut StdRng) -> Option<String> {
+        if !self.is_active(key) && rng.random_bool(prob.clamp(0.0, 1.0)) {
+            self.set_active(key, true);
+            Some(condition_name(key))
+        } else {
+            None
+        }
+    }
+
+    /// 治疗坏状态，by_clinic为保健室，否则为休息自然恢复。返回治好的状态名
+    pub fn cure(&mut self, by_clinic: bool, rng: &mut StdRng) -> Vec<String> {
+        let mut ret = vec![];
+        for cond in self.conditions() {
+            if cond.good {
+                continue;
+            }
+            let prob = if by_clinic {
+                cond.clinic_cure_prob
+            } else {
+                cond.rest_cure_prob
+            };
+            if rng.random_bool(prob.clamp(0.0, 1.0)) {
+                self.set_active(&cond.key, false);
+                ret.push(cond.name.clone());
+            }
+        }
+        ret
+    }
+
+    /// 回合开始时状态发作的数值变化
+    pub fn roll_turn_effects(&self, rng: &mut StdRng) -> Vec<(&'static str, &'static ActionValue)> {
+        self.conditions()
+            .into_iter()
+            .filter(|c| c.turn_prob > 0.0 && rng.random_bool(c.turn_prob.min(1.0)))
+            .map(|c| (c.name.as_str(), &c.turn_effect))
+            .collect()
+    }
+
+    /// 按状态修正干劲变化：偏头痛时不会上升，正向思考抵消一次下降
+    pub fn filter_motivation(&mut self, delta: i32) -> i32 {
+        if delta > 0 && self.migraine {
+            0
+        } else if delta < 0 && self.positive_thinking {
+            self.positive_thinking = false;
+            0
+        } else {
+            delta
+        }
+    }
+}
+
+/// 状态名，数据里没有时用key代替
+fn condition_name(key: &str) -> String {
+    global!(GAMECONSTANTS)
+        .get_condition(key)
+        .map(|c| c.name.clone())
+        .unwrap_or(key.to_string())
 }
 
 /// 训练中的马娘信息，剧本通用（固定为5星）
@@ -202,7 +376,8 @@ impl Uma {
             self.five_status[i] = (self.five_status[i] + action.status_pt[i]).min(self.five_status_limit[i]);
         }
         self.skill_pt += action.status_pt[5];
-        self.motivation = (self.motivation + action.motivation).max(1).min(5);
+        let motivation = self.flags.filter_motivation(action.motivation);
+        self.motivation = (self.motivation + motivation).max(1).min(5);
         self.max_vital += action.max_vital;
         self.vital = (self.vital + action.vital).min(self.max_vital).max(0);
         self.total_hints += action.hint_level;
@@ -240,6 +415,8 @@ impl Uma {
 #[cfg(test)]
 mod tests {
 
+    use rand::SeedableRng;
+
     use super::*;
     use crate::gamedata::init_global;
 
@@ -263,4 +440,45 @@ mod tests {
         println!("{:?}", uma.list_races());
         Ok(())
     }
+
+    #[test]
+    fn test_filter_motivation() {
+        let mut flags = UmaFlags {
+            positive_thinking: true,
+            ..Default::default()
+        };
+        assert_eq!(flags.filter_motivation(1), 1);
+        assert_eq!(flags.filter_motivation(-1), 0);
+        assert!(!flags.positive_thinking);
+        assert_eq!(flags.filter_motivation(-1), -1);
+        flags.set_active("migraine", true);
+        assert!(flags.is_active("migraine"));
+        assert_eq!(flags.filter_motivation(2), 0);
+    }
+
+    #[test]
+    fn test_condition_sources() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let events = global_events();
+        let mut rng = StdRng::seed_from_u64(1);
+        for cond in &global!(GAMECONSTANTS).conditions {
+            for (id, _) in &cond.grant_events {
+                let exists = events.uma_events.iter().chain(events.system_events.values()).any(|e| e.id == *id);
+                assert!(exists, "{} 的获得事件 {id} 不存在", cond.key);
+            }
+        }
+        // 训练大失败必定获得生病和不擅长训练
+        let mut flags = UmaFlags::default();
+        flags.grant_by_event(4004, &mut rng);
+        assert!(flags.ill && flags.bad_trainer);
+        // 保健室治好所有坏状态，包括客户端设置的
+        for key in ["night_owl", "lazy", "skin_outbreak", "slow_metabolism", "migraine"] {
+            flags.set_active(key, true);
+        }
+        assert_eq!(flags.expected_clinic_cures(), 7.0);
+        flags.cure(true, &mut rng);
+        assert!(!flags.has_bad_condition());
+        Ok(())
+    }
 }
@@ -0,0 +1,407 @@
+//! 支援卡固有效果，由cardDB.json的uniqueEffectType/uniqueEffectParam解析成条件+效果
+
+use std::collections::BTreeMap;
+
+use crate::{
+    game::{CardTrainingEffect, Game, SupportCard},
+    gamedata::{GAMEDATA, SupportCardData},
+    global,
+    utils::*
+};
+
+/// 固有触发条件
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniqueCondition {
+    /// 羁绊 >= x
+    Friendship(i32),
+    /// 不是擅长训练
+    NotFavoredTrain,
+    /// 参加友情训练
+    Shining,
+    /// 卡组中支援卡种类数 >= x
+    DeckTypes(i32)
+}
+
+/// 固有数值，可以随局面变化
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniqueValue {
+    /// 固定值
+    Const(i32),
+    /// 同一训练的其他支援卡每张+per
+    PerCardInTrain { per: i32, max_cards: i32 },
+    /// 训练设施每级+per
+    PerTrainLevel { per: i32 },
+    /// 总设施等级达到full时为max
+    TotalTrainLevel { full: i32, max: i32 },
+    /// 卡组羁绊总和越高越大，全满时为max
+    TotalFriendship { max: i32 },
+    /// 体力越低越大，体力100时为base，体力<=threshold时为max
+    LowVital { base: i32, max: i32, threshold: i32 },
+    /// 体力越高越大，体力0时为base，体力100时为max
+    HighVital { base: i32, max: i32 },
+    /// 最大体力超过base_vital后每4点+1，从base到max
+    MaxVital { base_vital: i32, base: i32, max: i32 },
+    /// 每次友情训练后叠加per，最多max层
+    Stack { per: i32, max: i32 }
+}
+
+impl UniqueValue {
+    /// 是否不随局面变化（满足条件后可以锁定）
+    pub fn is_static(&self) -> bool {
+        matches!(self, UniqueValue::Const(_))
+    }
+}
+
+/// 固有效果
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniqueEffect {
+    /// 词条 effect_id 增加 value
+    Line(i32, UniqueValue),
+    /// 巨匠: 按卡组类型增加对应副属性，友人/团队卡增加pt，每项最多max
+    DeckTypeBonus { per: i32, max: i32 },
+    /// 开局: 按卡组类型增加初始属性，友人/团队卡增加全属性
+    InitialStatusByDeck { per: i32, all: i32 },
+    /// 开局: 卡组所有支援卡初始羁绊增加
+    InitialFriendship(i32)
+}
+
+/// 状态计数的key
+pub const STACK_KEY: &str = "stack";
+
+/// 同一回合所有支援卡共用的局面信息，分配人头时计算一次
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UniqueTurnContext {
+    /// 五个设施等级之和
+    pub total_train_level: i32,
+    /// 卡组羁绊总和
+    pub deck_friendship: i32,
+    /// 卡组张数
+    pub deck_len: i32,
+    /// 卡组各类型数量 0速1耐2力3根4智5团队6友人
+    pub deck_type_count: [i32; 7]
+}
+
+impl UniqueTurnContext {
+    pub fn new<G: Game>(game: &G) -> Self {
+        let mut deck_type_count = [0; 7];
+        for card in game.deck() {
+            if (0..7).contains(&card.card_type) {
+                deck_type_count[card.card_type as usize] += 1;
+            }
+        }
+        Self {
+            total_train_level: (0..5).map(|t| game.train_level(t) as i32).sum(),
+            deck_friendship: game.deck().iter().map(|c| c.friendship).sum(),
+            deck_len: game.deck().len() as i32,
+            deck_type_count
+        }
+    }
+}
+
+/// 计算固有时需要的局面信息
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UniqueContext {
+    /// 本卡羁绊
+    pub friendship: i32,
+    /// 本卡类型
+    pub card_type: i32,
+    /// 本卡的友情训练叠加次数
+    pub stack: i32,
+    /// 训练 0-4
+    pub train: usize,
+    pub vital: i32,
+    pub max_vital: i32,
+    /// 当前训练设施等级 1-5
+    pub train_level: i32,
+    /// 五个设施等级之和
+    pub total_train_level: i32,
+    /// 同一训练的其他支援卡数量
+    pub cards_in_train: i32,
+    /// 当前训练是否为友情训练
+    pub shining: bool,
+    /// 卡组羁绊总和
+    pub deck_friendship: i32,
+    /// 卡组张数
+    pub deck_len: i32,
+    /// 卡组各类型数量 0速1耐2力3根4智5团队6友人
+    pub deck_type_count: [i32; 7]
+}
+
+impl UniqueContext {
+    /// 优先使用本回合已经算好的共用信息，没有时(如从小黑板载入的局面)现场计算
+    pub fn new<G: Game>(game: &G, card: &SupportCard, train: usize) -> Self {
+        let turn_ctx = game.unique_turn_context().cloned().unwrap_or_else(|| UniqueTurnContext::new(game));
+        let (cards_in_train, shining) = match game.distribution().get(train) {
+            Some(persons) => (
+                persons.iter().filter(|p| (0..6).contains(*p)).count() as i32 - 1,
+                game.shining_count(train) > 0
+            ),
+            None => (0, false)
+        };
+        Self {
+            friendship: card.friendship,
+            card_type: card.card_type,
+            stack: *card.effect_state.get(STACK_KEY).unwrap_or(&0) as i32,
+            train,
+            vital: game.uma().vital,
+            max_vital: game.uma().max_vital,
+            train_level: game.train_level(train.min(4)) as i32,
+            total_train_level: turn_ctx.total_train_level,
+            cards_in_train: cards_in_train.max(0),
+            shining,
+            deck_friendship: turn_ctx.deck_friendship,
+            deck_len: turn_ctx.deck_len,
+            deck_type_count: turn_ctx.deck_type_count
+        }
+    }
+
+    /// 卡组中的支援卡种类数
+    pub fn deck_types(&self) -> i32 {
+        self.deck_type_count.iter().filter(|x| **x > 0).count() as i32
+    }
+}
+
+/// 一张卡的固有规则
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UniqueRule {
+    pub conditions: Vec<UniqueCondition>,
+    pub effects: Vec<UniqueEffect>
+}
+
+impl UniqueRule {
+    /// 按固有类型解析参数，不支持的类型返回None
+    pub fn from_data(data: &SupportCardData) -> Option<Self> {
+        use UniqueCondition::*;
+        use UniqueEffect::*;
+        use UniqueValue::*;
+        // 参数不足时补0
+        let p = |i: usize| data.unique_effect_param.get(i).copied().unwrap_or(0);
+        // 词条id为0表示没有
+        let lines = |pairs: &[(i32, i32)]| {
+            pairs
+                .iter()
+                .filter(|(id, _)| *id > 0)
+                .map(|(id, v)| Line(*id, Const(*v)))
+                .collect::<Vec<_>>()
+        };
+        let (conditions, effects) = match data.unique_effect_type {
+            0 => (vec![], vec![]),
+            // 羁绊>=p1时 词条p2=p3, p4=p5
+            1 | 2 => (vec![Friendship(p(1))], lines(&[(p(2), p(3)), (p(4), p(5))])),
+            // 羁绊>=p1且不是擅长训练时 训练=p2
+            3 => (vec![Friendship(p(1)), NotFavoredTrain], vec![Line(8, Const(p(2)))]),
+            // 卡组种类>=p1时 词条p2=p3
+            21 => (vec![DeckTypes(p(1))], lines(&[(p(2), p(3))])),
+            // 编成支援卡类型提供初始属性
+            5 => (vec![], vec![InitialStatusByDeck { per: p(1), all: p(2) }]),
+            // 友情训练后叠加 词条p2，每次p3，最多p1次
+            6 => (vec![], vec![Line(p(2), Stack { per: p(3), max: p(1) })]),
+            // 体力越低 词条p1越高，基础p5，体力<=p3时为p4
+            7 => (vec![], vec![Line(
+                p(1),
+                LowVital {
+                    base: p(5),
+                    max: p(4),
+                    threshold: p(3)
+                }
+            )]),
+            // 最大体力越高 词条p1越高，基础p4，最多p5
+            8 => (vec![], vec![Line(
+                p(1),
+                MaxVital {
+                    base_vital: p(2),
+                    base: p(4),
+                    max: p(5)
+                }
+            )]),
+            // 卡组羁绊总和越高 词条p1越高，最多p2
+            9 => (vec![], vec![Line(p(1), TotalFriendship { max: p(2) })]),
+            // 同时训练的支援卡每张 词条p1+p2
+            10 => (vec![], vec![Line(p(1), PerCardInTrain { per: p(2), max_cards: 5 })]),
+            // 训练设施每级 词条p1+p2
+            11 => (vec![], vec![Line(p(1), PerTrainLevel { per: p(2) })]),
+            // p1%概率失败率为0，按期望处理成失败率下降
+            12 => (vec![], vec![Line(27, Const(p(1)))]),
+            // 友情训练时 词条p1=p2
+            13 => (vec![Shining], lines(&[(p(1), p(2))])),
+            // 剩余体力越多 词条p1越高，从p2到p3
+            14 => (vec![], vec![Line(p(1), HighVital { base: p(2), max: p(3) })]),
+            // 卡组初始羁绊+p2
+            15 => (vec![], vec![InitialFriendship(p(2))]),
+            // 总设施等级越高 词条p1越高，总等级p2时为p3
+            17 => (vec![], vec![Line(p(1), TotalTrainLevel { full: p(2), max: p(3) })]),
+            // 巨匠: 羁绊>=p2时按卡组类型加副属性，每项p3，最多p4
+            20 => (vec![Friendship(p(2))], vec![DeckTypeBonus { per: p(3), max: p(4) }]),
+            _ => return None
+        };
+        Some(Self { conditions, effects })
+    }
+
+    /// 训练时效果是否不随局面变化，满足条件后可以锁定
+    pub fn is_static(&self) -> bool {
+        self.conditions
+            .iter()
+            .all(|c| matches!(c, UniqueCondition::Friendship(_) | UniqueCondition::DeckTypes(_)))
+            && self.effects.iter().all(|e| match e {
+                UniqueEffect::Line(_, v) => v.is_static(),
+                _ => true
+            })
+    }
+
+    pub fn conditions_met(&self, ctx: &UniqueContext) -> bool {
+        self.conditions.iter().all(|c| match c {
+            UniqueCondition::Friendship(x) => ctx.friendship >= *x,
+            UniqueCondition::NotFavoredTrain => ctx.card_type != ctx.train as i32,
+            UniqueCondition::Shining => ctx.shining,
+            UniqueCondition::DeckTypes(x) => ctx.deck_types() >= *x
+        })
+    }
+
+    /// 计算数值
+    pub fn calc_value(value: &UniqueValue, ctx: &UniqueContext) -> i32 {
+        match value {
+            UniqueValue::Const(x) => *x,
+            UniqueValue::PerCardInTrain { per, max_cards } => per * ctx.cards_in_train.min(*max_cards),
+            UniqueValue::PerTrainLevel { per } => per * ctx.train_level,
+            UniqueValue::TotalTrainLevel { full, max } => (max * ctx.total_train_level / (*full).max(1)).min(*max),
+            UniqueValue::TotalFriendship { max } => max * ctx.deck_friendship / (100 * ctx.deck_len).max(1),
+            UniqueValue::LowVital { base, max, threshold } => {
+                let range = (100 - threshold).max(1);
+                let t = (100 - ctx.vital).clamp(0, range);
+                base + (max - base) * t / range
+            }
+            UniqueValue::HighVital { base, max } => base + (max - base) * ctx.vital.clamp(0, 100) / 100,
+            UniqueValue::MaxVital { base_vital, base, max } => {
+                (base + (ctx.max_vital - base_vital).max(0) / 4).min(*max)
+            }
+            UniqueValue::Stack { per, max } => per * ctx.stack.min(*max)
+        }
+    }
+
+    /// 条件满足时把训练效果加到effect上，返回是否满足条件
+    pub fn apply(&self, ctx: &UniqueContext, effect: &mut CardTrainingEffect) -> bool {
+        if !self.conditions_met(ctx) {
+            return false;
+        }
+        for e in &self.effects {
+            match e {
+                UniqueEffect::Line(id, value) => {
+                    let v = Self::calc_value(value, ctx);
+                    if v != 0 {
+                        effect.add_effect_line(*id, v);
+                    }
+                }
+                UniqueEffect::DeckTypeBonus { per, max } => {
+                    let count = &ctx.deck_type_count;
+                    for (i, c) in count.iter().take(5).enumerate() {
+                        if *c > 0 {
+                            // 0-4对应副属性词条#3-7
+                            effect.add_effect_line((i + 3) as i32, (per * c).min(*max));
+                        }
+                    }
+                    let others = count[5] + count[6];
+                    if others > 0 {
+                        effect.add_effect_line(30, (per * others).min(*max));
+                    }
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    /// 开局时的初始属性加成
+    pub fn initial_status(&self, deck_type_count: &[i32; 7]) -> Array6 {
+        let mut ret = Array6::default();
+        for e in &self.effects {
+            if let UniqueEffect::InitialStatusByDeck { per, all } = e {
+                for i in 0..5 {
+                    ret[i] += per * deck_type_count[i] + all * (deck_type_count[5] + deck_type_count[6]);
+                }
+            }
+        }
+        ret
+    }
+
+    /// 开局时卡组初始羁绊加成
+    pub fn initial_friendship(&self) -> i32 {
+        self.effects
+            .iter()
+            .map(|e| match e {
+                UniqueEffect::InitialFriendship(x) => *x,
+                _ => 0
+            })
+            .sum()
+    }
+}
+
+/// 列出卡组数据中还不支持的固有类型: 类型 -> 卡名
+pub fn unsupported_unique_effects() -> BTreeMap<u32, Vec<String>> {
+    let mut ret: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for data in global!(GAMEDATA).card.values() {
+        if UniqueRule::from_data(data).is_none() {
+            ret.entry(data.unique_effect_type)
+                .or_default()
+                .push(data.card_name.clone());
+        }
+    }
+    for names in ret.values_mut() {
+        names.sort();
+    }
+    ret
+}
+
+/// 不支持的固有类型报告
+pub fn unique_effect_report() -> String {
+    let unsupported = unsupported_unique_effects();
+    let mut lines = vec![format!("未实现的固有类型: {} 种", unsupported.len())];
+    for (t, names) in &unsupported {
+        lines.push(format!("#{t} ({}张): {}", names.len(), names.join(" ")));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card_data(unique_effect_type: u32, param: Vec<i32>) -> SupportCardData {
+        SupportCardData {
+            unique_effect_type,
+            unique_effect_param: param,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unique_value() {
+        let ctx = UniqueContext {
+            vital: 30,
+            max_vital: 120,
+            ..Default::default()
+        };
+        let low = UniqueValue::LowVital {
+            base: 5,
+            max: 15,
+            threshold: 30
+        };
+        assert_eq!(UniqueRule::calc_value(&low, &ctx), 15);
+        let max_vital = UniqueValue::MaxVital {
+            base_vital: 100,
+            base: 5,
+            max: 20
+        };
+        assert_eq!(UniqueRule::calc_value(&max_vital, &ctx), 10);
+        assert!(UniqueRule::from_data(&card_data(18, vec![118, 1, 60])).is_none());
+        assert!(UniqueRule::from_data(&card_data(1, vec![101, 80, 3, 2, 0, 0])).unwrap().is_static());
+        assert!(!UniqueRule::from_data(&card_data(13, vec![113, 2, 60])).unwrap().is_static());
+    }
+
+    #[test]
+    fn test_unique_report() -> anyhow::Result<()> {
+        init_logger("test", "info")?;
+        crate::gamedata::init_global()?;
+        println!("{}", unique_effect_report());
+        Ok(())
+    }
+}
@@ -11,7 +11,10 @@ use log::info;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 use crate::{
-    explain::Explain, game::onsen::OnsenOrder, global, utils::{Array5, Array6}
+    explain::Explain,
+    game::{InheritInfo, InheritParent, onsen::OnsenOrder},
+    global,
+    utils::{Array5, Array6}
 };
 
 pub mod onsen;
@@ -138,7 +141,7 @@ pub struct CardValue {
 }
 
 /// 支援卡数据 CardDB.json
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SupportCardData {
     /// 支援卡ID
@@ -235,6 +238,143 @@ impl Display for ActionValue {
     }
 }
 
+/// 马娘状态(好/坏状态)数据，key对应UmaFlags的字段名
+///
+/// 获得、发作和自然恢复的概率只在有来源时填写，默认为0，不进入模拟；
+/// 没有来源的状态只由客户端设置，模拟中只影响失败率和是否可以去保健室。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConditionData {
+    /// UmaFlags字段名
+    pub key: String,
+    /// 名字
+    pub name: String,
+    /// 是否为好状态
+    pub good: bool,
+    /// 训练失败率修正，负数为降低
+    #[serde(default)]
+    pub failure_rate_bias: f32,
+    /// 每回合开始时发作的概率
+    #[serde(default)]
+    pub turn_prob: f64,
+    /// 发作时的数值变化
+    #[serde(default)]
+    pub turn_effect: ActionValue,
+    /// 获得该状态的事件 [事件ID, 概率]，目前只有训练大失败(4004)必定获得生病和不擅长训练
+    #[serde(default)]
+    pub grant_events: Vec<(u32, f64)>,
+    /// 保健室治疗成功率，原有逻辑为必定治好
+    #[serde(default)]
+    pub clinic_cure_prob: f64,
+    /// 休息时自然恢复的概率
+    #[serde(default)]
+    pub rest_cure_prob: f64
+}
+
+/// 一种训练失败结果
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FailureOutcome {
+    /// 名字
+    pub name: String,
+    /// 是否为大失败
+    #[serde(default)]
+    pub big: bool,
+    /// 在同类(普通/大失败)结果中的权重
+    pub weight: f64,
+    /// 所训练属性的下降量
+    #[serde(default)]
+    pub train_loss: i32,
+    /// 随机其他属性的下降量
+    #[serde(default)]
+    pub random_loss: i32,
+    /// 随机下降的其他属性个数
+    #[serde(default)]
+    pub random_loss_count: usize,
+    /// 干劲变化
+    #[serde(default)]
+    pub motivation: i32,
+    /// 体力变化
+    #[serde(default)]
+    pub vital: i32,
+    /// 获得的坏状态 [UmaFlags字段名, 概率]
+    #[serde(default)]
+    pub conditions: Vec<(String, f64)>
+}
+
+/// 训练失败结果分布
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrainingFailureData {
+    /// 失败后判定大失败的概率 = 失败率 x big_rate_scale
+    pub big_rate_scale: f64,
+    /// 失败率低于此值时不会大失败
+    #[serde(default)]
+    pub big_min_rate: f64,
+    /// 所有失败结果
+    pub outcomes: Vec<FailureOutcome>
+}
+
+/// 自选比赛规划的折算权重，都是估计值，不是游戏数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RacePlanData {
+    /// 少训练一回合折合pt
+    pub pt_per_train_turn: f64,
+    /// 合宿回合训练的权重
+    pub xiahesu_factor: f64,
+    /// 1体力折合pt
+    pub pt_per_vital: f64,
+    /// 连续第3场及以后的比赛每场的额外代价
+    pub consecutive_race_penalty: f64
+}
+
+impl Default for RacePlanData {
+    fn default() -> Self {
+        Self {
+            pt_per_train_turn: 60.0,
+            xiahesu_factor: 1.5,
+            pt_per_vital: 1.5,
+            consecutive_race_penalty: 40.0
+        }
+    }
+}
+
+/// 温泉券使用时机分析的折算权重，都是估计值，不是游戏数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TicketAdviceData {
+    /// 每回合期望消耗体力
+    pub vital_cost_per_turn: i32,
+    /// 等待期间体力低于此值时视为影响训练
+    pub low_vital: i32,
+    /// 低体力下等待一回合的损失
+    pub low_vital_penalty: f64,
+    /// 1体力折合pt
+    pub pt_per_vital: f64,
+    /// 1干劲折合pt
+    pub pt_per_motivation: f64,
+    /// 1个Hint折合pt
+    pub pt_per_hint: f64,
+    /// 1%训练/友情加成在一个训练回合折合pt
+    pub pt_per_train_bonus: f64,
+    /// 1%生涯比赛加成折合pt
+    pub pt_per_race_bonus: f64,
+    /// 合宿回合训练加成的权重
+    pub xiahesu_factor: f64
+}
+
+impl Default for TicketAdviceData {
+    fn default() -> Self {
+        Self {
+            vital_cost_per_turn: 15,
+            low_vital: 50,
+            low_vital_penalty: 15.0,
+            pt_per_vital: 1.5,
+            pt_per_motivation: 15.0,
+            pt_per_hint: 15.0,
+            pt_per_train_bonus: 1.0,
+            pt_per_race_bonus: 0.5,
+            xiahesu_factor: 1.5
+        }
+    }
+}
+
 /// 剧本事件信息，也用于临时生成一些固定事件如赛后
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct EventData {
@@ -308,6 +448,19 @@ impl EventData {
         ret.choices[0].status_pt[train] = 5;
         ret
     }
+
+    /// 设置羁绊目标，乘算支援卡的事件效果和体力回复加成(%)
+    pub fn with_bonus(&self, person_index: i32, event_bonus: i32, vital_bonus: i32) -> Self {
+        let mut ret = self.clone();
+        ret.person_index = Some(person_index);
+        for value in &mut ret.choices {
+            value.map_status(|x| x * (100 + event_bonus) / 100);
+            if value.vital > 0 {
+                value.vital = value.vital * (100 + vital_bonus) / 100;
+            }
+        }
+        ret
+    }
 }
 
 /// 事件数据表
@@ -318,12 +471,131 @@ pub struct EventCollection {
     /// 马娘正面事件
     pub uma_events: Vec<EventData>,
     /// 支援卡连续事件
-    pub card_events: Vec<EventData>,
+    pub card_events: CardEventData,
     /// 友人事件
     pub friend_events: HashMap<String, EventData>,
     /// 系统事件
-    pub system_events: HashMap<String, EventData>
+    pub system_events: HashMap<String, EventData>,
+    /// 理事长和记者
+    #[serde(default)]
+    pub noncard: NonCardData,
+    /// 非剧本友人卡和团队卡
+    #[serde(default)]
+    pub card_friend: CardFriendData
+}
+
+/// 理事长和记者的点击效果和连续事件
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NonCardData {
+    pub yayoi: NonCardPersonData,
+    pub reporter: NonCardPersonData
+}
+
+/// 点击效果的一档
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NonCardClickBonus {
+    /// 羁绊达到此值时生效
+    pub min_friendship: i32,
+    /// 点击获得的属性、体力和羁绊
+    pub value: ActionValue,
+    /// 所在训练的属性加值
+    #[serde(default)]
+    pub train_status: i32
+}
+
+/// 连续事件的一段
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NonCardEvent {
+    /// 羁绊达到此值时才能触发
+    pub min_friendship: i32,
+    #[serde(flatten)]
+    pub event: EventData
+}
+
+/// 单个理事长/记者的数据
+///
+/// 点击效果目前只有一档，数值与原来的点击事件相同；高羁绊时的加成和专属连续事件没有可靠数据，
+/// 暂不模拟(events为空)，有数据后按羁绊分档和按顺序填入即可。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NonCardPersonData {
+    /// 一起训练时直接增加的羁绊，点击效果里的羁绊另外结算
+    #[serde(default)]
+    pub train_friendship: i32,
+    /// 按羁绊分档的点击效果，按min_friendship升序
+    pub click: Vec<NonCardClickBonus>,
+    /// 连续事件，按顺序触发。暂无数据，为空
+    #[serde(default)]
+    pub events: Vec<NonCardEvent>
 }
+
+impl NonCardPersonData {
+    /// 羁绊为friendship时在训练train点击的效果
+    pub fn click_value(&self, friendship: i32, train: usize) -> Option<ActionValue> {
+        let bonus = self.click.iter().rev().find(|c| friendship >= c.min_friendship)?;
+        let mut ret = bonus.value.clone();
+        if train < 5 {
+            ret.status_pt[train] += bonus.train_status;
+        }
+        Some(ret)
+    }
+
+    /// 下一段可以触发的连续事件，前一段触发过才能触发下一段
+    pub fn next_event(&self, triggered: &HashMap<u32, u32>, turn: i32, friendship: i32) -> Option<&EventData> {
+        let next = self.events.iter().find(|e| !triggered.contains_key(&e.event.id))?;
+        if friendship >= next.min_friendship && turn >= next.event.start_turn && turn < next.event.end_turn {
+            Some(&next.event)
+        } else {
+            None
+        }
+    }
+}
+
+/// 支援卡连续事件，按顺序触发，后一段只能在前一段之后
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CardEventData {
+    /// 默认的连续事件，没有单独数据的卡都使用这一组
+    pub default: Vec<EventData>,
+    /// 指定支援卡(5位ID)的连续事件，优先于默认事件
+    #[serde(default)]
+    pub cards: HashMap<String, Vec<EventData>>
+}
+
+impl CardEventData {
+    /// 支援卡的连续事件
+    pub fn chain(&self, card_id: u32) -> &[EventData] {
+        self.cards.get(&card_id.to_string()).unwrap_or(&self.default)
+    }
+}
+
+/// 非剧本友人卡和团队卡的点击、出行和团队Buff事件
+///
+/// 每张卡的事件不同，只模拟有数据的卡，没有数据的卡不出行也没有团队Buff
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CardFriendData {
+    /// 支援卡(5位ID) -> 事件
+    #[serde(default)]
+    pub cards: HashMap<String, CardFriendEvents>
+}
+
+/// 一种友人卡/团队卡的点击和出行事件
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CardFriendEvents {
+    /// 第一次点击
+    pub first: EventData,
+    /// 之后的点击
+    pub click: EventData,
+    /// 出行解锁
+    pub unlock: EventData,
+    /// 出行，按顺序进行
+    pub outings: Vec<EventData>,
+    /// 点击开启团队Buff需要的羁绊，0为没有团队Buff
+    #[serde(default)]
+    pub group_buff_friendship: i32,
+    /// 点击时开启团队Buff的概率(%)
+    #[serde(default)]
+    pub group_buff_prob: i32
+}
+
 #[derive(Clone, Debug)]
 pub struct GameData {
     pub uma: BTreeMap<String, UmaData>,
@@ -417,7 +689,19 @@ pub struct GameConstants {
     /// PT特化时，超过1200的属性压缩系数
     pub five_status_favor_rate: Vec<f32>,
     /// 蒙特卡洛每回合比手写逻辑增加的分数, 用于修正估分
-    pub mcts_turn_bonus: i32
+    pub mcts_turn_bonus: i32,
+    /// 训练失败结果分布
+    #[serde(default)]
+    pub training_failure: TrainingFailureData,
+    /// 马娘状态表
+    #[serde(default)]
+    pub conditions: Vec<ConditionData>,
+    /// 自选比赛规划的折算权重
+    #[serde(default)]
+    pub race_plan: RacePlanData,
+    /// 温泉券使用时机分析的折算权重
+    #[serde(default)]
+    pub ticket_advice: TicketAdviceData
 }
 
 impl GameConstants {
@@ -440,6 +724,11 @@ impl GameConstants {
             .unwrap_or("US9".to_string())
     }
 
+    /// 按UmaFlags字段名查找状态
+    pub fn get_condition(&self, key: &str) -> Option<&ConditionData> {
+        self.conditions.iter().find(|c| c.key == key)
+    }
+
     /// 随机事件为支援卡，马娘，掉心情和不发生的分布
     pub fn get_event_distribution(&self) -> Vec<f64> {
         let probs = &self.event_probs;
@@ -635,6 +924,9 @@ pub struct CollectorConfig {
     /// 每个分片的样本数
     #[serde(default = "default_collector_shard_size")]
     pub shard_size: usize,
+    /// 分片的 zstd 压缩等级，0 表示不压缩（读取时自动识别）
+    #[serde(default)]
+    pub shard_zstd_level: i32,
     /// manifest 文件名（输出目录内）
     #[serde(default = "default_collector_manifest_name")]
     pub manifest_name: String,
@@ -707,6 +999,7 @@ impl Default for CollectorConfig {
             output_append_timestamp: default_collector_output_append_timestamp(),
             output_timestamp_format: default_collector_output_timestamp_format(),
             shard_size: default_collector_shard_size(),
+            shard_zstd_level: 0,
             manifest_name: default_collector_manifest_name(),
             score_mean_values_name: default_collector_score_mean_values_name(),
             resume: default_collector_resume(),
@@ -840,6 +1133,9 @@ pub struct GameConfig {
     /// neuralnet ONNX 模型路径（仅 trainer="neuralnet" / "nn" 生效）
     #[serde(default = "default_neuralnet_model_path")]
     pub neuralnet_model_path: String,
+    /// 神经网络推理后端: "tract" (ONNX) | "dense" (纯 Rust MLP，可读取 .dense 或简单 MLP 的 .onnx)
+    #[serde(default = "default_neuralnet_backend")]
+    pub neuralnet_backend: String,
     /// 模拟次数（默认1次，设置大于1可多次模拟并统计）
     #[serde(default = "default_simulation_count")]
     pub simulation_count: usize,
@@ -851,6 +1147,9 @@ pub struct GameConfig {
     pub blue_count: Array5,
     /// 种马额外属性
     pub extra_count: Array6,
+    /// 详细的种马和祖代因子（可选，设置后覆盖blue_count/extra_count；umaai中仅在小黑板未提供蓝因子时使用）
+    #[serde(default)]
+    pub inherit_parents: Vec<InheritParent>,
     /// 温泉顺序
     pub onsen_order: OnsenOrder,
     /// collector 配置（用于训练数据生成工具）
@@ -863,7 +1162,25 @@ pub struct GameConfig {
     #[serde(default)]
     pub mcts_selected_onsen: bool,
     /// 蒙特卡洛输出评分还是PT重视结果
-    pub mcts_selection: String
+    pub mcts_selection: String,
+    /// 每个阶段后检查状态合法性，调试用
+    #[serde(default)]
+    pub checked_mode: bool
+}
+
+impl GameConfig {
+    /// 继承信息，配置了详细种马时以其为准，否则按因子数量转换
+    pub fn inherit_info(&self) -> InheritInfo {
+        if self.inherit_parents.is_empty() {
+            InheritInfo {
+                blue_count: self.blue_count,
+                extra_count: self.extra_count,
+                ..Default::default()
+            }
+        } else {
+            InheritInfo::from_parents(self.inherit_parents.clone())
+        }
+    }
 }
 
 fn default_scenario() -> String {
@@ -883,6 +1200,10 @@ fn default_neuralnet_model_path() -> String {
     "saved_models/onsen_v4/model.onnx".to_string()
 }
 
+fn default_neuralnet_backend() -> String {
+    "tract".to_string()
+}
+
 fn default_simulation_count() -> usize {
     1
 }
@@ -938,6 +1259,66 @@ mod tests {
         println!("{:b}", free_race.mask); // 10111010000111110100000000000000000000
         Ok(())
     }
+
+    #[test]
+    fn test_noncard() -> Result<()> {
+        let events: EventCollection = load_json("gamedata/events.json")?;
+        let reporter = &events.noncard.reporter;
+        // 记者点击提升所在训练属性，按羁绊分档
+        let low = reporter.click_value(0, 2).expect("click");
+        assert_eq!(low.status_pt[2], 2);
+        let tiered = NonCardPersonData {
+            click: vec![
+                reporter.click[0].clone(),
+                NonCardClickBonus {
+                    min_friendship: 80,
+                    train_status: 4,
+                    ..Default::default()
+                }
+            ],
+            ..Default::default()
+        };
+        assert_eq!(tiered.click_value(60, 2).expect("click").status_pt[2], 2);
+        assert_eq!(tiered.click_value(100, 2).expect("click").status_pt[2], 4);
+        // 连续事件按顺序触发，需要满足羁绊和回合
+        let chain = |id: u32, min_friendship: i32| NonCardEvent {
+            min_friendship,
+            event: EventData {
+                id,
+                start_turn: 12,
+                end_turn: 78,
+                ..Default::default()
+            }
+        };
+        let yayoi = NonCardPersonData {
+            events: vec![chain(1, 20), chain(2, 60)],
+            ..Default::default()
+        };
+        let mut triggered = hashbrown::HashMap::new();
+        assert!(yayoi.next_event(&triggered, 30, 0).is_none());
+        let first = yayoi.next_event(&triggered, 30, 100).expect("event").id;
+        assert_eq!(first, yayoi.events[0].event.id);
+        triggered.insert(first, 1);
+        assert_eq!(yayoi.next_event(&triggered, 30, 100).map(|e| e.id), Some(yayoi.events[1].event.id));
+        Ok(())
+    }
+
+    #[test]
+    fn test_card_event_data() -> Result<()> {
+        let events: EventCollection = load_json("gamedata/events.json")?;
+        let mut data = events.card_events;
+        assert_eq!(data.chain(30242), data.default.as_slice());
+        // 有单独数据的卡使用自己的连续事件
+        let single = EventData {
+            id: 1,
+            prob: 100,
+            ..Default::default()
+        };
+        data.cards.insert("30242".to_string(), vec![single.clone()]);
+        assert_eq!(data.chain(30242), [single]);
+        assert_eq!(data.chain(30246), data.default.as_slice());
+        Ok(())
+    }
 }
 
 pub static GAMEDATA: OnceLock<GameData> = OnceLock::new();
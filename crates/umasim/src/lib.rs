@@ -1,10 +1,14 @@
 pub mod collector;
+pub mod dataset;
 pub mod explain;
 pub mod game;
 pub mod gamedata;
 pub mod neural;
 pub mod sample_collector;
+pub mod sample_export;
 pub mod search;
+#[cfg(test)]
+mod test_utils;
 pub mod trainer;
 pub mod training_sample;
 pub mod utils;
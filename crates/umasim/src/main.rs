@@ -6,12 +6,14 @@ use std::time::Instant;
 use anyhow::Result;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::info;
-use rand::{SeedableRng, rngs::StdRng};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use rayon::prelude::*;
 use umasim::{
-    game::{Game, InheritInfo, Trainer, basic::BasicGame, onsen::game::OnsenGame},
+    collector::make_game_id,
+    game::{Game, InheritInfo, Trainer, basic::BasicGame, onsen::game::OnsenGame, set_checked_mode},
     gamedata::{GAMECONSTANTS, GameConfig, init_global},
     global,
+    neural::InferenceBackendKind,
     sample_collector::GameSample,
     trainer::*,
     training_sample::TrainingSampleBatch,
@@ -86,21 +88,22 @@ fn run_collector_mode(config: &GameConfig, num_games: usize, rng: &mut StdRng) -
     );
 
     let start = Instant::now();
+    let run_key = chrono::Utc::now().to_rfc3339();
 
     // 主循环
     for i in 0..num_games {
         trainer.reset();
 
-        let inherit = InheritInfo {
-            blue_count: config.blue_count.clone(),
-            extra_count: config.extra_count.clone()
-        };
+        let inherit = config.inherit_info();
 
         let mut game = OnsenGame::newgame(config.uma, &config.cards, inherit)?;
-        game.run_full_game(&trainer, rng)?;
+        let recorder = RecordingTrainer::new(&trainer, rng.random(), i as u64);
+        game.run_full_game(&recorder, &mut recorder.game_rng())?;
 
         let score = game.uma.calc_score();
-        game_samples.push(trainer.finalize(score));
+        let mut sample = trainer.finalize(score);
+        sample.set_game_id(make_game_id(&run_key, i as u64));
+        game_samples.push(sample);
 
         if (i + 1) % 1000 == 0 {
             pb.set_position((i + 1) as u64);
@@ -235,6 +238,7 @@ async fn main() -> Result<()> {
     let game_config: GameConfig = toml::from_str(&config_file)?;
     // 2. 根据配置初始化日志
     init_logger("umasim", &game_config.log_level)?;
+    set_checked_mode(game_config.checked_mode);
 
     // 3. 再初始化全局数据
     init_global()?;
@@ -244,10 +248,7 @@ async fn main() -> Result<()> {
     // 开始计时
     let start = Instant::now();
 
-    let inherit = InheritInfo {
-        blue_count: game_config.blue_count.clone(),
-        extra_count: game_config.extra_count.clone()
-    };
+    let inherit = game_config.inherit_info();
 
     // 收集模拟结果
     let sim_results: Vec<Result<SimulationResult>> = (0..simulation_count)
@@ -286,7 +287,8 @@ async fn main() -> Result<()> {
                 "neuralnet" | "nn" => {
                     // 神经网络训练员
                     let model_path = game_config.neuralnet_model_path.as_str();
-                    match NeuralNetTrainer::load(model_path) {
+                    let backend = InferenceBackendKind::from_name(&game_config.neuralnet_backend)?;
+                    match NeuralNetTrainer::load_with_backend(model_path, backend) {
                         Ok(trainer) => {
                             let trainer = trainer.verbose(simulation_count == 1);
                             match game_config.scenario.as_str() {
@@ -359,8 +361,9 @@ async fn main() -> Result<()> {
                                 ));
                             }
                             // 先验证模型可加载（避免“以为开了 NN 实际没开”的伪对照）
-                            let _ = umasim::neural::NeuralNetEvaluator::load(model_path)?;
-                            trainer.search = trainer.search.with_leaf_evaluator_nn(model_path.to_string());
+                            let backend = InferenceBackendKind::from_name(&game_config.neuralnet_backend)?;
+                            let _ = umasim::neural::NeuralNetEvaluator::load_with_backend(model_path, backend)?;
+                            trainer.search = trainer.search.with_leaf_evaluator_nn(model_path.to_string(), backend);
                         }
                         other => {
                             return Err(anyhow::anyhow!(
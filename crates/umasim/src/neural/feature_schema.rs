@@ -0,0 +1,378 @@
+//! 神经网络特征布局
+//!
+//! 输入特征和输出按命名的块声明，偏移由块的顺序和维度计算，不再手工数偏移。
+//! 布局变化时必须提升 [`FEATURE_SCHEMA_VERSION`]，Python 端通过导出的 JSON 读取同一份布局。
+//!
+//! ONNX 模型在 metadata_props 中记录训练时的布局版本和反归一化参数，
+//! 加载时由 [`ModelMeta`] 检查，布局不一致的模型拒绝加载。
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Result, bail};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// 特征布局版本，块的顺序或维度变化、动作索引含义变化时必须加1
+///
+/// - 2: 动作索引26-31分配给非剧本友人卡/团队卡出行
+pub const FEATURE_SCHEMA_VERSION: u32 = 2;
+/// 数据目录中导出的特征布局文件名
+pub const FEATURE_SCHEMA_FILE: &str = "feature_schema.json";
+
+/// 一个特征块
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeatureBlock {
+    /// 块名，导出给Python使用
+    pub name: &'static str,
+    /// 维度
+    pub dim: usize,
+    /// 说明
+    pub desc: &'static str
+}
+
+const fn block(name: &'static str, dim: usize, desc: &'static str) -> FeatureBlock {
+    FeatureBlock { name, dim, desc }
+}
+
+/// 全局特征块，顺序与 [`GlobalBlock`] 一致
+pub const GLOBAL_BLOCKS: [FeatureBlock; 12] = [
+    block("search_params", 6, "搜索参数(预留)"),
+    block("turn", 78, "回合One-Hot"),
+    block("uma_status", 15, "五维属性、上限和剩余空间"),
+    block("vital_motivation", 5, "体力、最大体力和干劲"),
+    block("train_value", 30, "5种训练 x 6维收益"),
+    block("fail_rate", 5, "训练失败率"),
+    block("onsen", 140, "温泉全局状态20 + 每个温泉12 x 10"),
+    block("misc", 61, "技能点、挖掘数量、友人和温泉Buff等"),
+    block("choices", 113, "事件选项 1 + 8 x 14"),
+    block("legal_mask", 50, "动作合法掩码"),
+    block("race_turns", 78, "比赛回合标记"),
+    block("reserved", 6, "预留")
+];
+
+/// 每张支援卡的特征块，顺序与 [`CardBlock`] 一致
+pub const CARD_BLOCKS: [FeatureBlock; 2] = [
+    block("person", 12, "羁绊、Hint、彩圈和所在训练"),
+    block("card_params", 77, "卡片类型、加成和固有效果")
+];
+
+/// 输出块，顺序与 [`OutputBlock`] 一致
+pub const OUTPUT_BLOCKS: [FeatureBlock; 3] = [
+    block("policy", 50, "动作logits"),
+    block("choice", 8, "事件选项logits"),
+    block("value", 3, "scoreMean, scoreStdev, value (归一化)")
+];
+
+/// 支援卡数量
+pub const CARD_NUM: usize = 6;
+/// 全局特征维度
+pub const GLOBAL_DIM: usize = blocks_dim(&GLOBAL_BLOCKS);
+/// 每张支援卡的特征维度
+pub const CARD_DIM: usize = blocks_dim(&CARD_BLOCKS);
+/// 输入维度
+pub const INPUT_DIM: usize = GLOBAL_DIM + CARD_DIM * CARD_NUM;
+/// 输出维度
+pub const OUTPUT_DIM: usize = blocks_dim(&OUTPUT_BLOCKS);
+
+/// 块的总维度
+pub const fn blocks_dim(blocks: &[FeatureBlock]) -> usize {
+    block_offset(blocks, blocks.len())
+}
+
+/// 第index个块的偏移
+pub const fn block_offset(blocks: &[FeatureBlock], index: usize) -> usize {
+    let mut ret = 0;
+    let mut i = 0;
+    while i < index {
+        ret += blocks[i].dim;
+        i += 1;
+    }
+    ret
+}
+
+/// 全局特征块
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalBlock {
+    SearchParams,
+    Turn,
+    UmaStatus,
+    VitalMotivation,
+    TrainValue,
+    FailRate,
+    Onsen,
+    Misc,
+    Choices,
+    LegalMask,
+    RaceTurns,
+    Reserved
+}
+
+impl GlobalBlock {
+    /// 在输入中的偏移
+    pub const fn offset(self) -> usize {
+        block_offset(&GLOBAL_BLOCKS, self as usize)
+    }
+
+    pub const fn dim(self) -> usize {
+        GLOBAL_BLOCKS[self as usize].dim
+    }
+}
+
+/// 支援卡特征块
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardBlock {
+    Person,
+    CardParams
+}
+
+impl CardBlock {
+    /// 第card_index张卡的这个块在输入中的偏移
+    pub const fn offset(self, card_index: usize) -> usize {
+        GLOBAL_DIM + card_index * CARD_DIM + block_offset(&CARD_BLOCKS, self as usize)
+    }
+
+    pub const fn dim(self) -> usize {
+        CARD_BLOCKS[self as usize].dim
+    }
+}
+
+/// 输出块
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputBlock {
+    Policy,
+    Choice,
+    Value
+}
+
+impl OutputBlock {
+    /// 在输出中的偏移
+    pub const fn offset(self) -> usize {
+        block_offset(&OUTPUT_BLOCKS, self as usize)
+    }
+
+    pub const fn dim(self) -> usize {
+        OUTPUT_BLOCKS[self as usize].dim
+    }
+
+    /// 在输出中的范围
+    pub const fn range(self) -> std::ops::Range<usize> {
+        self.offset()..self.offset() + self.dim()
+    }
+}
+
+/// Value输出的反归一化参数
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ValueNormalization {
+    /// scoreMean = value_mean + value_scale * output
+    pub value_mean: f64,
+    pub value_scale: f64,
+    /// scoreStdev = stdev_scale * abs(output)
+    pub stdev_scale: f64
+}
+
+impl Default for ValueNormalization {
+    fn default() -> Self {
+        Self {
+            value_mean: 58000.0,
+            value_scale: 300.0,
+            stdev_scale: 150.0
+        }
+    }
+}
+
+/// 导出的特征块，带偏移
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaBlock {
+    pub name: String,
+    pub offset: usize,
+    pub dim: usize,
+    pub desc: String
+}
+
+/// 导出给Python的特征布局
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureSchema {
+    pub version: u32,
+    pub input_dim: usize,
+    pub output_dim: usize,
+    pub global_dim: usize,
+    pub card_dim: usize,
+    pub card_num: usize,
+    /// 全局特征块
+    pub global: Vec<SchemaBlock>,
+    /// 每张卡的特征块，偏移相对于卡的起点
+    pub card: Vec<SchemaBlock>,
+    /// 输出块
+    pub output: Vec<SchemaBlock>,
+    pub normalization: ValueNormalization
+}
+
+fn export_blocks(blocks: &[FeatureBlock]) -> Vec<SchemaBlock> {
+    blocks
+        .iter()
+        .enumerate()
+        .map(|(i, b)| SchemaBlock {
+            name: b.name.to_string(),
+            offset: block_offset(blocks, i),
+            dim: b.dim,
+            desc: b.desc.to_string()
+        })
+        .collect()
+}
+
+impl FeatureSchema {
+    /// 当前版本的布局
+    pub fn current() -> Self {
+        Self {
+            version: FEATURE_SCHEMA_VERSION,
+            input_dim: INPUT_DIM,
+            output_dim: OUTPUT_DIM,
+            global_dim: GLOBAL_DIM,
+            card_dim: CARD_DIM,
+            card_num: CARD_NUM,
+            global: export_blocks(&GLOBAL_BLOCKS),
+            card: export_blocks(&CARD_BLOCKS),
+            output: export_blocks(&OUTPUT_BLOCKS),
+            normalization: ValueNormalization::default()
+        }
+    }
+
+    /// 保存为JSON
+    pub fn save_json(&self, path: &Path) -> Result<()> {
+        fs_err::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// 读取数据目录中保存的布局
+    pub fn load_json(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs_err::read_to_string(path)?)?)
+    }
+}
+
+/// ONNX metadata_props 中的键
+pub const META_SCHEMA_VERSION: &str = "feature_schema_version";
+pub const META_INPUT_DIM: &str = "input_dim";
+pub const META_OUTPUT_DIM: &str = "output_dim";
+pub const META_VALUE_MEAN: &str = "value_mean";
+pub const META_VALUE_SCALE: &str = "value_scale";
+pub const META_STDEV_SCALE: &str = "stdev_scale";
+
+/// 从ONNX模型读出的布局信息
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelMeta {
+    /// 训练时的布局版本，旧模型没有记录时为None
+    pub schema_version: Option<u32>,
+    pub input_dim: Option<usize>,
+    pub output_dim: Option<usize>,
+    /// 反归一化参数，没有记录的项使用默认值
+    pub normalization: ValueNormalization
+}
+
+impl ModelMeta {
+    /// 从metadata_props解析
+    pub fn from_props(props: &HashMap<String, String>) -> Result<Self> {
+        fn parse<T: std::str::FromStr>(props: &HashMap<String, String>, key: &str) -> Result<Option<T>> {
+            match props.get(key) {
+                Some(v) => match v.trim().parse() {
+                    Ok(x) => Ok(Some(x)),
+                    Err(_) => bail!("模型元数据 {key} 格式错误: {v}")
+                },
+                None => Ok(None)
+            }
+        }
+        let default = ValueNormalization::default();
+        Ok(Self {
+            schema_version: parse(props, META_SCHEMA_VERSION)?,
+            input_dim: parse(props, META_INPUT_DIM)?,
+            output_dim: parse(props, META_OUTPUT_DIM)?,
+            normalization: ValueNormalization {
+                value_mean: parse(props, META_VALUE_MEAN)?.unwrap_or(default.value_mean),
+                value_scale: parse(props, META_VALUE_SCALE)?.unwrap_or(default.value_scale),
+                stdev_scale: parse(props, META_STDEV_SCALE)?.unwrap_or(default.stdev_scale)
+            }
+        })
+    }
+
+    /// 写入ONNX的metadata_props
+    pub fn to_props(&self) -> Vec<(String, String)> {
+        let mut ret = vec![];
+        if let Some(v) = self.schema_version {
+            ret.push((META_SCHEMA_VERSION.to_string(), v.to_string()));
+        }
+        if let Some(v) = self.input_dim {
+            ret.push((META_INPUT_DIM.to_string(), v.to_string()));
+        }
+        if let Some(v) = self.output_dim {
+            ret.push((META_OUTPUT_DIM.to_string(), v.to_string()));
+        }
+        ret.push((META_VALUE_MEAN.to_string(), self.normalization.value_mean.to_string()));
+        ret.push((META_VALUE_SCALE.to_string(), self.normalization.value_scale.to_string()));
+        ret.push((META_STDEV_SCALE.to_string(), self.normalization.stdev_scale.to_string()));
+        ret
+    }
+
+    /// 当前布局的元数据
+    pub fn current() -> Self {
+        Self {
+            schema_version: Some(FEATURE_SCHEMA_VERSION),
+            input_dim: Some(INPUT_DIM),
+            output_dim: Some(OUTPUT_DIM),
+            normalization: ValueNormalization::default()
+        }
+    }
+
+    /// 检查模型是否与当前布局兼容。没有版本记录的旧模型按维度检查并给出警告
+    pub fn check_compatible(&self) -> Result<()> {
+        match self.schema_version {
+            Some(v) if v != FEATURE_SCHEMA_VERSION => {
+                bail!("模型特征布局版本为 {v}，当前为 {FEATURE_SCHEMA_VERSION}，请重新训练或导出模型")
+            }
+            Some(_) => {}
+            None => warn!("模型没有记录特征布局版本，按版本 {FEATURE_SCHEMA_VERSION} 处理"),
+        }
+        if let Some(dim) = self.input_dim
+            && dim != INPUT_DIM
+        {
+            bail!("模型输入维度为 {dim}，当前特征为 {INPUT_DIM} 维");
+        }
+        if let Some(dim) = self.output_dim
+            && dim != OUTPUT_DIM
+        {
+            bail!("模型输出维度为 {dim}，当前为 {OUTPUT_DIM} 维");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_schema() -> Result<()> {
+        assert_eq!(INPUT_DIM, 1121);
+        assert_eq!(GLOBAL_DIM, 587);
+        assert_eq!(CARD_DIM, 89);
+        assert_eq!(OUTPUT_DIM, 61);
+        assert_eq!(GlobalBlock::Reserved.offset() + GlobalBlock::Reserved.dim(), GLOBAL_DIM);
+        assert_eq!(CardBlock::CardParams.offset(5) + CardBlock::CardParams.dim(), INPUT_DIM);
+        assert_eq!(OutputBlock::Value.range(), 58..61);
+        // 导出的JSON可以读回
+        let schema = FeatureSchema::current();
+        let json = serde_json::to_string(&schema)?;
+        assert_eq!(serde_json::from_str::<FeatureSchema>(&json)?, schema);
+        assert_eq!(schema.global[1].offset, GlobalBlock::Turn.offset());
+        // 元数据往返和兼容检查
+        let meta = ModelMeta::current();
+        let props: HashMap<_, _> = meta.to_props().into_iter().collect();
+        assert_eq!(ModelMeta::from_props(&props)?, meta);
+        assert!(meta.check_compatible().is_ok());
+        assert!(ModelMeta::default().check_compatible().is_ok());
+        let mut props = props;
+        props.insert(META_SCHEMA_VERSION.to_string(), "0".to_string());
+        assert!(ModelMeta::from_props(&props)?.check_compatible().is_err());
+        props.insert(META_VALUE_MEAN.to_string(), "abc".to_string());
+        assert!(ModelMeta::from_props(&props).is_err());
+        Ok(())
+    }
+}
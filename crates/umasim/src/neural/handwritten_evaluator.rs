@@ -692,6 +692,28 @@ impl Evaluator<OnsenGame> for HandwrittenEvaluator {
                     value
                 }
 
+                OnsenAction::CardOuting(index) => {
+                    // 非剧本友人卡出行：按这张卡下一次出行事件的体力、干劲和属性估值
+                    let gain = game
+                        .card_friends
+                        .iter()
+                        .find(|f| f.person_index as i32 == *index)
+                        .and_then(|f| f.next_outing().map(|e| f.bonus_event(e)))
+                        .and_then(|e| e.choices.first().cloned())
+                        .unwrap_or_default();
+                    let mut value = (0..5).map(|i| gain.status_pt[i] as f64 * STATUS_WEIGHTS[i]).sum::<f64>()
+                        + gain.status_pt[5] as f64 * self.skill_weight;
+                    if !skip_vital_penalty {
+                        let vital_after = (game.uma.vital + gain.vital).min(game.uma.max_vital);
+                        value += vital_factor * (vital_evaluation(vital_after, game.uma.max_vital) - vital_before);
+                    }
+                    if gain.motivation > 0 && game.uma.motivation < 5 {
+                        value += OUTGOING_BONUS_IF_NOT_FULL_MOTIVATION;
+                    }
+                    value += self.evaluate_dig_value(game, action);
+                    value
+                }
+
                 OnsenAction::Race => {
                     // 比赛价值评估（参考 action.rs 的实际执行逻辑）
                     let mut value = if game.is_race_turn() {
@@ -711,7 +733,11 @@ impl Evaluator<OnsenGame> for HandwrittenEvaluator {
                             let race_grade = global!(GAMECONSTANTS).race_grades[game.turn as usize];
                             let count = game.uma.count_free_race(free);
                             let remain_turn = free.end_turn as i32 - game.turn + 1;
-                            if count < free.count {
+                            // 规划出的最优安排不在本回合比赛时，留到更合适的回合
+                            let plan = game.current_race_plan();
+                            if count < free.count && plan.best().is_some() && !plan.race_now() {
+                                NON_TARGET_RACE_BONUS
+                            } else if count < free.count {
                                 match &free.grade {
                                     Some(g) => {
                                         if race_grade <= *g as i32 {
@@ -0,0 +1,623 @@
+//! 推理后端
+//!
+//! 评估器只通过 [`InferenceBackend`] 做推理，具体实现有两种：
+//! - [`TractBackend`]: 用 tract 运行 ONNX 模型，支持任意网络结构
+//! - [`DenseBackend`]: 纯 Rust 的全连接网络推理，只支持 MLP，没有 tract 的调度开销，
+//!   模型本身就是线程安全的，搜索时所有线程共享一份
+//!
+//! 后端由 game_config.toml 的 `neuralnet_backend` 选择。Dense 后端可以读取 train_model
+//! 导出的 `.dense` 文件，也可以直接从只包含 MatMul/Gemm/Add/Relu 的 ONNX 模型转换。
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::Arc
+};
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use serde::{Deserialize, Serialize};
+use tract_onnx::{
+    pb::{GraphProto, TensorProto, tensor_proto::DataType},
+    prelude::*
+};
+
+use super::{
+    Dense,
+    feature_schema::{INPUT_DIM, ModelMeta, OUTPUT_DIM}
+};
+
+/// Dense 模型文件的扩展名
+pub const DENSE_MODEL_EXT: &str = "dense";
+/// Dense 模型文件的格式标记
+const DENSE_MODEL_FORMAT: &str = "umasim-dense-v1";
+
+/// 推理后端类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InferenceBackendKind {
+    /// tract 运行 ONNX
+    #[default]
+    Tract,
+    /// 纯 Rust 全连接网络
+    Dense
+}
+
+impl InferenceBackendKind {
+    /// 从配置中的名字解析: "tract" | "dense"
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "tract" | "onnx" => Ok(Self::Tract),
+            "dense" => Ok(Self::Dense),
+            other => bail!("未知 neuralnet_backend=\"{other}\"（仅支持 \"tract\" | \"dense\"）")
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Tract => "tract",
+            Self::Dense => "dense"
+        }
+    }
+
+    /// 加载好的模型能否在线程间共享。tract 的模型在搜索中按线程各加载一份
+    pub fn is_shareable(&self) -> bool {
+        matches!(self, Self::Dense)
+    }
+
+    /// 用这种后端加载模型
+    pub fn load(&self, model_path: &str) -> Result<Arc<dyn InferenceBackend>> {
+        Ok(match self {
+            Self::Tract => Arc::new(TractBackend::load(model_path)?),
+            Self::Dense => Arc::new(DenseBackend::load(model_path)?)
+        })
+    }
+}
+
+/// 推理后端
+pub trait InferenceBackend: Send + Sync {
+    /// 后端类型
+    fn kind(&self) -> InferenceBackendKind;
+
+    /// 模型记录的布局信息
+    fn meta(&self) -> &ModelMeta;
+
+    /// 批量推理：输入 [batch, INPUT_DIM] 的扁平数组，输出 [batch, OUTPUT_DIM] 的扁平数组
+    fn infer_batch(&self, features_flat: &[f32], batch: usize) -> Result<Vec<f32>>;
+
+    /// 单样本推理
+    fn infer(&self, features: &[f32]) -> Result<Vec<f32>> {
+        self.infer_batch(features, 1)
+    }
+}
+
+fn check_input(features_flat: &[f32], batch: usize) -> Result<()> {
+    ensure!(batch > 0, "batch 不能为 0");
+    ensure!(
+        features_flat.len() == batch * INPUT_DIM,
+        "输入维度错误: 期望 {} (=batch*INPUT_DIM), 实际 {}",
+        batch * INPUT_DIM,
+        features_flat.len()
+    );
+    Ok(())
+}
+
+// ============================================================================
+// TractBackend
+// ============================================================================
+
+type OnnxModel = SimplePlan<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
+
+/// 读取 ONNX 的 metadata_props 并检查布局
+fn onnx_meta(props: &[tract_onnx::pb::StringStringEntryProto], model_path: &str) -> Result<ModelMeta> {
+    let props: HashMap<_, _> = props.iter().map(|p| (p.key.clone(), p.value.clone())).collect();
+    let meta = ModelMeta::from_props(&props)?;
+    meta.check_compatible()
+        .with_context(|| format!("模型与当前特征布局不兼容: {model_path}"))?;
+    Ok(meta)
+}
+
+/// tract ONNX 后端
+pub struct TractBackend {
+    model: OnnxModel,
+    meta: ModelMeta
+}
+
+impl TractBackend {
+    /// 读取模型和元数据，布局版本或输入输出维度与当前特征不一致时拒绝加载
+    pub fn load(model_path: &str) -> Result<Self> {
+        let onnx = tract_onnx::onnx();
+        let proto = onnx.proto_model_for_path(model_path).context("无法读取 ONNX 模型文件")?;
+        let meta = onnx_meta(&proto.metadata_props, model_path)?;
+        let dir = Path::new(model_path).parent().and_then(|d| d.to_str());
+        let parsed = onnx.parse(&proto, dir).context("无法解析 ONNX 模型")?;
+        if !parsed.unresolved_inputs.is_empty() {
+            bail!("ONNX 模型有未解析的输入: {:?}", parsed.unresolved_inputs);
+        }
+        let model = parsed.model.into_optimized().context("模型优化失败")?;
+        // 旧模型没有元数据时，至少检查图上的输入输出维度
+        let input_dim =
+            model.input_fact(0).ok().and_then(|f| f.shape.last().and_then(|d| d.as_i64()).map(|d| d as usize));
+        if let Some(dim) = input_dim
+            && dim != INPUT_DIM
+        {
+            bail!("模型输入维度为 {dim}，当前特征为 {INPUT_DIM} 维");
+        }
+        let output_dim =
+            model.output_fact(0).ok().and_then(|f| f.shape.last().and_then(|d| d.as_i64()).map(|d| d as usize));
+        if let Some(dim) = output_dim
+            && dim != OUTPUT_DIM
+        {
+            bail!("模型输出维度为 {dim}，当前为 {OUTPUT_DIM} 维");
+        }
+        Ok(Self {
+            model: model.into_runnable().context("模型转换失败")?,
+            meta
+        })
+    }
+
+    fn run(&self, features_flat: &[f32], batch: usize) -> Result<Vec<f32>> {
+        let input = tract_ndarray::Array2::from_shape_vec((batch, INPUT_DIM), features_flat.to_vec())
+            .context("创建输入张量失败")?;
+        let output = self.model.run(tvec!(input.into_tvalue())).context("推理失败")?;
+        let output_tensor = output[0].to_array_view::<f32>().context("提取输出张量失败")?;
+        let out: Vec<f32> = output_tensor.iter().copied().collect();
+        ensure!(
+            out.len() == batch * OUTPUT_DIM,
+            "输出维度错误: 期望 {} (=batch*OUTPUT_DIM), 实际 {}",
+            batch * OUTPUT_DIM,
+            out.len()
+        );
+        Ok(out)
+    }
+}
+
+impl InferenceBackend for TractBackend {
+    fn kind(&self) -> InferenceBackendKind {
+        InferenceBackendKind::Tract
+    }
+
+    fn meta(&self) -> &ModelMeta {
+        &self.meta
+    }
+
+    /// 模型不支持动态 batch 时，回退为逐样本推理（并打印 warning）
+    fn infer_batch(&self, features_flat: &[f32], batch: usize) -> Result<Vec<f32>> {
+        check_input(features_flat, batch)?;
+        match self.run(features_flat, batch) {
+            Ok(out) => Ok(out),
+            Err(e) if batch > 1 => {
+                log::warn!("[NN] batch 推理失败，回退为逐样本 infer(1)（性能受限）。原因: {e}");
+                let mut out_all = Vec::with_capacity(batch * OUTPUT_DIM);
+                for features in features_flat.chunks_exact(INPUT_DIM) {
+                    out_all.extend(self.run(features, 1)?);
+                }
+                Ok(out_all)
+            }
+            Err(e) => Err(e)
+        }
+    }
+}
+
+// ============================================================================
+// DenseBackend
+// ============================================================================
+
+/// Dense 模型文件内容
+#[derive(Debug, Serialize, Deserialize)]
+struct DenseModelFile {
+    format: String,
+    /// 与 ONNX metadata_props 相同的键值
+    props: Vec<(String, String)>,
+    layers: Vec<Dense>
+}
+
+/// 纯 Rust 全连接网络后端，隐藏层使用 ReLU
+#[derive(Debug, Clone)]
+pub struct DenseBackend {
+    layers: Vec<Dense>,
+    meta: ModelMeta
+}
+
+impl DenseBackend {
+    /// 检查各层维度首尾相接且与当前布局一致
+    pub fn new(layers: Vec<Dense>, meta: ModelMeta) -> Result<Self> {
+        meta.check_compatible()?;
+        ensure!(!layers.is_empty(), "模型没有任何层");
+        for (i, layer) in layers.iter().enumerate() {
+            ensure!(
+                layer.w.len() == layer.in_dim * layer.out_dim && layer.b.len() == layer.out_dim,
+                "第 {i} 层权重大小与维度 {}x{} 不一致",
+                layer.out_dim,
+                layer.in_dim
+            );
+        }
+        for (i, w) in layers.windows(2).enumerate() {
+            ensure!(
+                w[0].out_dim == w[1].in_dim,
+                "第 {i} 层输出 {} 维，第 {} 层输入 {} 维",
+                w[0].out_dim,
+                i + 1,
+                w[1].in_dim
+            );
+        }
+        let (input_dim, output_dim) = (layers[0].in_dim, layers[layers.len() - 1].out_dim);
+        ensure!(input_dim == INPUT_DIM, "模型输入维度为 {input_dim}，当前特征为 {INPUT_DIM} 维");
+        ensure!(output_dim == OUTPUT_DIM, "模型输出维度为 {output_dim}，当前为 {OUTPUT_DIM} 维");
+        Ok(Self { layers, meta })
+    }
+
+    /// 读取模型，`.onnx` 文件会按 MLP 结构转换，其他文件按 Dense 模型文件读取
+    pub fn load(model_path: &str) -> Result<Self> {
+        let path = Path::new(model_path);
+        if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("onnx")) {
+            return Self::from_onnx(model_path);
+        }
+        let file: DenseModelFile = bincode::deserialize_from(BufReader::new(File::open(path)?))
+            .with_context(|| format!("无法读取 Dense 模型文件: {model_path}"))?;
+        ensure!(
+            file.format == DENSE_MODEL_FORMAT,
+            "Dense 模型文件格式为 {}，当前为 {DENSE_MODEL_FORMAT}",
+            file.format
+        );
+        let props: HashMap<_, _> = file.props.into_iter().collect();
+        let meta = ModelMeta::from_props(&props)?;
+        Self::new(file.layers, meta).with_context(|| format!("模型与当前特征布局不兼容: {model_path}"))
+    }
+
+    /// 保存为 Dense 模型文件
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = DenseModelFile {
+            format: DENSE_MODEL_FORMAT.to_string(),
+            props: self.meta.to_props(),
+            layers: self.layers.clone()
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(&mut writer, &file)?;
+        Ok(())
+    }
+
+    /// 从 ONNX 模型读取权重。只支持 [MatMul/Gemm → Add → Relu] 串联的 MLP
+    pub fn from_onnx(model_path: &str) -> Result<Self> {
+        let proto = tract_onnx::onnx()
+            .proto_model_for_path(model_path)
+            .context("无法读取 ONNX 模型文件")?;
+        let meta = onnx_meta(&proto.metadata_props, model_path)?;
+        let graph = proto.graph.as_ref().ok_or_else(|| anyhow!("ONNX 模型没有计算图"))?;
+        let layers = onnx_layers(graph).with_context(|| format!("无法转换为 Dense 模型: {model_path}"))?;
+        Self::new(layers, meta).with_context(|| format!("模型与当前特征布局不兼容: {model_path}"))
+    }
+}
+
+impl InferenceBackend for DenseBackend {
+    fn kind(&self) -> InferenceBackendKind {
+        InferenceBackendKind::Dense
+    }
+
+    fn meta(&self) -> &ModelMeta {
+        &self.meta
+    }
+
+    fn infer_batch(&self, features_flat: &[f32], batch: usize) -> Result<Vec<f32>> {
+        check_input(features_flat, batch)?;
+        let mut x = dense_batch(&self.layers[0], features_flat, batch, self.layers.len() > 1);
+        for (i, layer) in self.layers.iter().enumerate().skip(1) {
+            x = dense_batch(layer, &x, batch, i + 1 < self.layers.len());
+        }
+        Ok(x)
+    }
+}
+
+/// 8 路累加的点积
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let (ca, cb) = (a.as_chunks::<8>(), b.as_chunks::<8>());
+    let mut acc = [0.0_f32; 8];
+    for (x, y) in ca.0.iter().zip(cb.0) {
+        for i in 0..8 {
+            acc[i] += x[i] * y[i];
+        }
+    }
+    let tail: f32 = ca.1.iter().zip(cb.1).map(|(x, y)| x * y).sum();
+    acc.iter().sum::<f32>() + tail
+}
+
+/// y[s][o] = w[o]·x[s] 的通用实现
+fn matmul_generic(w: &[f32], x: &[f32], y: &mut [f32], n_in: usize, n_out: usize) {
+    for (o, row) in w.chunks_exact(n_in).enumerate() {
+        for (s, xs) in x.chunks_exact(n_in).enumerate() {
+            y[s * n_out + o] = dot(row, xs);
+        }
+    }
+}
+
+/// AVX2 + FMA 实现：每次取 4 行权重在所有样本上复用，样本两个一组，读一次权重算两个样本。
+/// 发布版按体积优化，不会自动向量化，这里直接写 SIMD
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+fn matmul_avx2(w: &[f32], x: &[f32], y: &mut [f32], n_in: usize, n_out: usize) {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2,fma")]
+    fn hsum(v: __m256) -> f32 {
+        let v = _mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps(v, 1));
+        let v = _mm_add_ps(v, _mm_movehl_ps(v, v));
+        let v = _mm_add_ss(v, _mm_shuffle_ps(v, v, 1));
+        _mm_cvtss_f32(v)
+    }
+
+    let body = n_in / 8 * 8;
+    let batch = x.len() / n_in;
+    let groups = n_out / 4 * 4;
+    for o in (0..groups).step_by(4) {
+        let rows = [&w[o * n_in..], &w[(o + 1) * n_in..], &w[(o + 2) * n_in..], &w[(o + 3) * n_in..]];
+        let ptrs = [rows[0].as_ptr(), rows[1].as_ptr(), rows[2].as_ptr(), rows[3].as_ptr()];
+        let mut s = 0;
+        // 两个样本一组，8 个累加器，每次读取的权重用两次
+        while s + 2 <= batch {
+            let (x0, x1) = (&x[s * n_in..(s + 1) * n_in], &x[(s + 1) * n_in..(s + 2) * n_in]);
+            let mut acc = [_mm256_setzero_ps(); 8];
+            // SAFETY: k + 8 <= body <= n_in，每行和每个样本都至少有 n_in 个元素
+            unsafe {
+                let (xp0, xp1) = (x0.as_ptr(), x1.as_ptr());
+                let [mut a0, mut a1, mut a2, mut a3, mut b0, mut b1, mut b2, mut b3] = acc;
+                for k in (0..body).step_by(8) {
+                    let (u, v) = (_mm256_loadu_ps(xp0.add(k)), _mm256_loadu_ps(xp1.add(k)));
+                    let w0 = _mm256_loadu_ps(ptrs[0].add(k));
+                    a0 = _mm256_fmadd_ps(w0, u, a0);
+                    b0 = _mm256_fmadd_ps(w0, v, b0);
+                    let w1 = _mm256_loadu_ps(ptrs[1].add(k));
+                    a1 = _mm256_fmadd_ps(w1, u, a1);
+                    b1 = _mm256_fmadd_ps(w1, v, b1);
+                    let w2 = _mm256_loadu_ps(ptrs[2].add(k));
+                    a2 = _mm256_fmadd_ps(w2, u, a2);
+                    b2 = _mm256_fmadd_ps(w2, v, b2);
+                    let w3 = _mm256_loadu_ps(ptrs[3].add(k));
+                    a3 = _mm256_fmadd_ps(w3, u, a3);
+                    b3 = _mm256_fmadd_ps(w3, v, b3);
+                }
+                acc = [a0, a1, a2, a3, b0, b1, b2, b3];
+            }
+            for (i, xs) in [x0, x1].into_iter().enumerate() {
+                for j in 0..4 {
+                    let tail: f32 = rows[j][body..n_in].iter().zip(&xs[body..]).map(|(a, b)| a * b).sum();
+                    y[(s + i) * n_out + o + j] = hsum(acc[i * 4 + j]) + tail;
+                }
+            }
+            s += 2;
+        }
+        if s < batch {
+            let xs = &x[s * n_in..(s + 1) * n_in];
+            let (mut a0, mut a1, mut a2, mut a3) =
+                (_mm256_setzero_ps(), _mm256_setzero_ps(), _mm256_setzero_ps(), _mm256_setzero_ps());
+            // SAFETY: 同上
+            unsafe {
+                let xp = xs.as_ptr();
+                for k in (0..body).step_by(8) {
+                    let xv = _mm256_loadu_ps(xp.add(k));
+                    a0 = _mm256_fmadd_ps(_mm256_loadu_ps(ptrs[0].add(k)), xv, a0);
+                    a1 = _mm256_fmadd_ps(_mm256_loadu_ps(ptrs[1].add(k)), xv, a1);
+                    a2 = _mm256_fmadd_ps(_mm256_loadu_ps(ptrs[2].add(k)), xv, a2);
+                    a3 = _mm256_fmadd_ps(_mm256_loadu_ps(ptrs[3].add(k)), xv, a3);
+                }
+            }
+            for (j, acc) in [a0, a1, a2, a3].into_iter().enumerate() {
+                let tail: f32 = rows[j][body..n_in].iter().zip(&xs[body..]).map(|(a, b)| a * b).sum();
+                y[s * n_out + o + j] = hsum(acc) + tail;
+            }
+        }
+    }
+    // 剩下不足 4 行
+    for o in groups..n_out {
+        let row = &w[o * n_in..(o + 1) * n_in];
+        for (s, xs) in x.chunks_exact(n_in).enumerate() {
+            y[s * n_out + o] = dot(row, xs);
+        }
+    }
+}
+
+/// 一层的批量计算，隐藏层接 ReLU
+fn dense_batch(layer: &Dense, x: &[f32], batch: usize, relu: bool) -> Vec<f32> {
+    let (n_in, n_out) = (layer.in_dim, layer.out_dim);
+    let mut y = vec![0.0_f32; batch * n_out];
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // SAFETY: 已经检测过 CPU 支持 avx2 和 fma
+        unsafe { matmul_avx2(&layer.w, x, &mut y, n_in, n_out) };
+    } else {
+        matmul_generic(&layer.w, x, &mut y, n_in, n_out);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    matmul_generic(&layer.w, x, &mut y, n_in, n_out);
+    for ys in y.chunks_exact_mut(n_out) {
+        for (v, b) in ys.iter_mut().zip(&layer.b) {
+            *v += b;
+            if relu {
+                *v = v.max(0.0);
+            }
+        }
+    }
+    y
+}
+
+/// 读取 float 初始化张量
+fn tensor_f32(tensor: &TensorProto) -> Result<Vec<f32>> {
+    ensure!(
+        tensor.data_type == DataType::Float as i32,
+        "张量 {} 不是 float32",
+        tensor.name
+    );
+    let count: i64 = tensor.dims.iter().product();
+    let data: Vec<f32> = if !tensor.raw_data.is_empty() {
+        tensor
+            .raw_data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    } else {
+        tensor.float_data.clone()
+    };
+    ensure!(data.len() as i64 == count, "张量 {} 的数据长度与形状不一致", tensor.name);
+    Ok(data)
+}
+
+/// 按节点顺序把 MLP 的计算图还原为全连接层
+fn onnx_layers(graph: &GraphProto) -> Result<Vec<Dense>> {
+    let inits: HashMap<_, _> = graph.initializer.iter().map(|t| (t.name.as_str(), t)).collect();
+    let mut current = graph
+        .input
+        .iter()
+        .find(|i| !inits.contains_key(i.name.as_str()))
+        .map(|i| i.name.clone())
+        .ok_or_else(|| anyhow!("找不到模型输入"))?;
+    let mut layers: Vec<Dense> = vec![];
+    // 最后一层是否已经加上 bias / Relu
+    let (mut has_bias, mut has_relu) = (false, false);
+    for node in &graph.node {
+        let other: Vec<&str> = node.input.iter().map(|s| s.as_str()).filter(|s| *s != current).collect();
+        ensure!(
+            node.input.len() == other.len() + 1,
+            "节点 {}({}) 不在主链上，Dense 后端只支持串联的 MLP",
+            node.name,
+            node.op_type
+        );
+        let init = |name: &str| {
+            inits
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow!("节点 {} 的输入 {name} 不是常量", node.name))
+        };
+        match node.op_type.as_str() {
+            "MatMul" | "Gemm" => {
+                // 隐藏层必须接 Relu
+                ensure!(layers.is_empty() || has_relu, "节点 {} 之前的隐藏层缺少 Relu", node.name);
+                let mut trans_b = false;
+                for attr in &node.attribute {
+                    match attr.name.as_str() {
+                        "transB" => trans_b = attr.i != 0,
+                        "transA" => ensure!(attr.i == 0, "不支持 transA"),
+                        "alpha" | "beta" => ensure!(attr.f == 1.0, "不支持 {}={}", attr.name, attr.f),
+                        _ => {}
+                    }
+                }
+                ensure!(node.input[0] == current, "节点 {} 的第一个输入必须是上一层输出", node.name);
+                let w = init(&node.input[1])?;
+                ensure!(w.dims.len() == 2, "权重 {} 不是二维张量", w.name);
+                let data = tensor_f32(w)?;
+                // ONNX 的权重为 [in, out]（transB 时为 [out, in]），Dense 按 [out][in] 存放
+                let (in_dim, out_dim) = if trans_b {
+                    (w.dims[1] as usize, w.dims[0] as usize)
+                } else {
+                    (w.dims[0] as usize, w.dims[1] as usize)
+                };
+                let mut layer = Dense {
+                    in_dim,
+                    out_dim,
+                    w: vec![0.0; in_dim * out_dim],
+                    b: vec![0.0; out_dim]
+                };
+                if trans_b {
+                    layer.w = data;
+                } else {
+                    for j in 0..in_dim {
+                        for o in 0..out_dim {
+                            layer.w[o * in_dim + j] = data[j * out_dim + o];
+                        }
+                    }
+                }
+                has_bias = false;
+                if let Some(b) = node.input.get(2) {
+                    layer.b = broadcast_bias(init(b)?, out_dim)?;
+                    has_bias = true;
+                }
+                layers.push(layer);
+                has_relu = false;
+            }
+            "Add" => {
+                let layer = layers.last_mut().ok_or_else(|| anyhow!("Add 之前没有线性层"))?;
+                ensure!(!has_bias && other.len() == 1, "节点 {} 不是线性层的 bias", node.name);
+                layer.b = broadcast_bias(init(other[0])?, layer.out_dim)?;
+                has_bias = true;
+            }
+            "Relu" => {
+                ensure!(!layers.is_empty() && !has_relu, "节点 {} 的位置不支持 Relu", node.name);
+                has_relu = true;
+            }
+            "Identity" => {}
+            op => bail!("Dense 后端不支持算子 {op}")
+        }
+        ensure!(node.output.len() == 1, "节点 {} 有多个输出", node.name);
+        current = node.output[0].clone();
+    }
+    ensure!(
+        graph.output.first().is_some_and(|o| o.name == current),
+        "模型输出不是最后一层"
+    );
+    ensure!(!has_relu, "最后一层不能有 Relu");
+    Ok(layers)
+}
+
+fn broadcast_bias(tensor: &TensorProto, out_dim: usize) -> Result<Vec<f32>> {
+    let data = tensor_f32(tensor)?;
+    match data.len() {
+        1 => Ok(vec![data[0]; out_dim]),
+        n if n == out_dim => Ok(data),
+        n => bail!("bias {} 长度为 {n}，层输出为 {out_dim}", tensor.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{neural::Mlp, test_utils::TempDir};
+
+    #[test]
+    fn test_backends_match() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let model = Mlp::new(&[24, 16], &mut rng);
+        let batch = 7;
+        let features: Vec<f32> = (0..batch * INPUT_DIM).map(|_| rng.random_range(-1.0..1.0)).collect();
+        let expected: Vec<f32> = features.chunks_exact(INPUT_DIM).flat_map(|x| model.forward(x)).collect();
+
+        let dir = TempDir::new("backend");
+        let onnx_path = dir.join("model.onnx");
+        let dense_path = dir.join(format!("model.{DENSE_MODEL_EXT}"));
+        model.export_onnx(&onnx_path, &ModelMeta::current())?;
+        DenseBackend::new(model.layers.clone(), ModelMeta::current())?.save(&dense_path)?;
+        let backends: Vec<Arc<dyn InferenceBackend>> = vec![
+            InferenceBackendKind::Tract.load(onnx_path.to_string_lossy().as_ref())?,
+            // 从 ONNX 转换
+            InferenceBackendKind::Dense.load(onnx_path.to_string_lossy().as_ref())?,
+            InferenceBackendKind::Dense.load(dense_path.to_string_lossy().as_ref())?,
+        ];
+        drop(dir);
+        for backend in &backends {
+            assert_eq!(backend.meta(), &ModelMeta::current());
+            let output = backend.infer_batch(&features, batch)?;
+            assert_eq!(output.len(), batch * OUTPUT_DIM);
+            for (a, b) in expected.iter().zip(&output) {
+                assert!((a - b).abs() < 1e-3, "{}: {a} != {b}", backend.kind().name());
+            }
+            let single = backend.infer(&features[..INPUT_DIM])?;
+            assert!(single.iter().zip(&output).all(|(a, b)| (a - b).abs() < 1e-4));
+            assert!(backend.infer_batch(&features[1..], 1).is_err());
+        }
+
+        // 通用实现与 SIMD 实现一致
+        let layer = &model.layers[0];
+        let mut generic = vec![0.0; batch * layer.out_dim];
+        matmul_generic(&layer.w, &features, &mut generic, INPUT_DIM, layer.out_dim);
+        let simd = dense_batch(layer, &features, batch, false);
+        for (i, (a, b)) in generic.iter().zip(&simd).enumerate() {
+            assert!((a + layer.b[i % layer.out_dim] - b).abs() < 1e-3);
+        }
+
+        // 维度不一致的模型拒绝加载
+        let mut layers = model.layers.clone();
+        layers.pop();
+        assert!(DenseBackend::new(layers, ModelMeta::current()).is_err());
+        assert!(InferenceBackendKind::from_name("cuda").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,535 @@
+//! 纯 Rust 的 MLP 训练
+//!
+//! 在 CPU 上直接用 [`TrainingSample`] 训练小型全连接网络，不依赖 Python。
+//! 输出布局与 [`super::feature_schema`] 的输出块一致(policy 50 + choice 8 + value 3)，
+//! 导出为 ONNX 后可以直接由 `NeuralNetEvaluator` 加载。
+//!
+//! 损失与样本目标对应：
+//! - policy/choice: 目标分布与 softmax 的交叉熵，目标全为0的样本不计入
+//! - value: 按 [`ValueNormalization`] 归一化后的均方误差
+
+use std::path::Path;
+
+use anyhow::{Result, ensure};
+use prost::Message;
+use rand::{Rng, rngs::StdRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tract_onnx::pb::{
+    GraphProto,
+    ModelProto,
+    NodeProto,
+    OperatorSetIdProto,
+    StringStringEntryProto,
+    TensorProto,
+    TensorShapeProto,
+    TypeProto,
+    ValueInfoProto,
+    tensor_proto::DataType,
+    tensor_shape_proto::{Dimension, dimension},
+    type_proto
+};
+
+use super::feature_schema::{INPUT_DIM, ModelMeta, OUTPUT_DIM, OutputBlock, ValueNormalization};
+use crate::training_sample::TrainingSample;
+
+/// 导出 ONNX 使用的 opset 版本
+const ONNX_OPSET: i64 = 13;
+/// 导出 ONNX 使用的 IR 版本
+const ONNX_IR_VERSION: i64 = 7;
+
+/// 全连接层，权重按 [out][in] 存放
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dense {
+    pub in_dim: usize,
+    pub out_dim: usize,
+    pub w: Vec<f32>,
+    pub b: Vec<f32>
+}
+
+impl Dense {
+    fn zeros(in_dim: usize, out_dim: usize) -> Self {
+        Self {
+            in_dim,
+            out_dim,
+            w: vec![0.0; in_dim * out_dim],
+            b: vec![0.0; out_dim]
+        }
+    }
+
+    fn forward(&self, x: &[f32]) -> Vec<f32> {
+        let mut y = self.b.clone();
+        for (o, y) in y.iter_mut().enumerate() {
+            let row = &self.w[o * self.in_dim..(o + 1) * self.in_dim];
+            *y += row.iter().zip(x).map(|(w, x)| w * x).sum::<f32>();
+        }
+        y
+    }
+}
+
+/// 各输出头的损失权重
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LossWeights {
+    pub policy: f32,
+    pub choice: f32,
+    pub value: f32
+}
+
+impl Default for LossWeights {
+    fn default() -> Self {
+        Self {
+            policy: 1.0,
+            choice: 1.0,
+            value: 1.0
+        }
+    }
+}
+
+/// 累计的各输出头损失
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MlpLoss {
+    pub policy: f64,
+    pub policy_count: usize,
+    pub choice: f64,
+    pub choice_count: usize,
+    pub value: f64,
+    pub value_count: usize
+}
+
+impl MlpLoss {
+    pub fn merge(mut self, other: Self) -> Self {
+        self.policy += other.policy;
+        self.policy_count += other.policy_count;
+        self.choice += other.choice;
+        self.choice_count += other.choice_count;
+        self.value += other.value;
+        self.value_count += other.value_count;
+        self
+    }
+
+    fn mean(sum: f64, count: usize) -> f64 {
+        if count == 0 { 0.0 } else { sum / count as f64 }
+    }
+
+    pub fn policy_mean(&self) -> f64 {
+        Self::mean(self.policy, self.policy_count)
+    }
+
+    pub fn choice_mean(&self) -> f64 {
+        Self::mean(self.choice, self.choice_count)
+    }
+
+    pub fn value_mean(&self) -> f64 {
+        Self::mean(self.value, self.value_count)
+    }
+
+    /// 按权重加总的平均损失，用于早停
+    pub fn total(&self, weights: &LossWeights) -> f64 {
+        weights.policy as f64 * self.policy_mean()
+            + weights.choice as f64 * self.choice_mean()
+            + weights.value as f64 * self.value_mean()
+    }
+
+    pub fn explain(&self) -> String {
+        format!(
+            "policy {:.4} choice {:.4} value {:.4}",
+            self.policy_mean(),
+            self.choice_mean(),
+            self.value_mean()
+        )
+    }
+}
+
+/// 目标分布与 softmax(logits) 的交叉熵，梯度累加到grad。目标全为0时返回None
+fn softmax_cross_entropy(logits: &[f32], target: &[f32], weight: f32, grad: &mut [f32]) -> Option<f64> {
+    let sum: f32 = target.iter().sum();
+    if sum <= 0.0 {
+        return None;
+    }
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = logits.iter().map(|x| (x - max).exp()).collect();
+    let exp_sum: f32 = exp.iter().sum();
+    let log_sum = exp_sum.ln();
+    let mut loss = 0.0;
+    for i in 0..logits.len() {
+        let t = target[i] / sum;
+        loss -= (t * (logits[i] - max - log_sum)) as f64;
+        grad[i] += weight * (exp[i] / exp_sum - t);
+    }
+    Some(loss)
+}
+
+/// 多层感知机，隐藏层使用 ReLU
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mlp {
+    pub layers: Vec<Dense>
+}
+
+impl Mlp {
+    /// 按隐藏层大小随机初始化(He uniform)
+    pub fn new(hidden: &[usize], rng: &mut StdRng) -> Self {
+        let mut dims = vec![INPUT_DIM];
+        dims.extend_from_slice(hidden);
+        dims.push(OUTPUT_DIM);
+        let layers = dims
+            .windows(2)
+            .map(|d| {
+                let mut layer = Dense::zeros(d[0], d[1]);
+                let bound = (6.0 / d[0] as f32).sqrt();
+                for w in &mut layer.w {
+                    *w = rng.random_range(-bound..bound);
+                }
+                layer
+            })
+            .collect();
+        Self { layers }
+    }
+
+    /// 同样结构的全0网络，用于累计梯度
+    pub fn zeros_like(&self) -> Self {
+        Self {
+            layers: self.layers.iter().map(|l| Dense::zeros(l.in_dim, l.out_dim)).collect()
+        }
+    }
+
+    fn add_assign(&mut self, other: &Self) {
+        for (a, b) in self.layers.iter_mut().zip(&other.layers) {
+            a.w.iter_mut().zip(&b.w).for_each(|(x, y)| *x += y);
+            a.b.iter_mut().zip(&b.b).for_each(|(x, y)| *x += y);
+        }
+    }
+
+    /// 前向计算，返回 OUTPUT_DIM 维输出
+    pub fn forward(&self, x: &[f32]) -> Vec<f32> {
+        self.forward_trace(x).pop().unwrap_or_default()
+    }
+
+    /// 前向计算并保留每层的输出，第0项为输入
+    fn forward_trace(&self, x: &[f32]) -> Vec<Vec<f32>> {
+        let mut acts = vec![x.to_vec()];
+        for (i, layer) in self.layers.iter().enumerate() {
+            let mut y = layer.forward(&acts[i]);
+            if i + 1 < self.layers.len() {
+                y.iter_mut().for_each(|v| *v = v.max(0.0));
+            }
+            acts.push(y);
+        }
+        acts
+    }
+
+    /// 计算单个样本的损失，并把梯度累加到grad
+    fn sample_grad(
+        &self, sample: &TrainingSample, weights: &LossWeights, norm: &ValueNormalization, grad: Option<&mut Mlp>
+    ) -> MlpLoss {
+        let acts = self.forward_trace(&sample.nn_input);
+        let output = &acts[acts.len() - 1];
+        let mut grad_out = vec![0.0_f32; OUTPUT_DIM];
+        let mut loss = MlpLoss::default();
+        let policy = OutputBlock::Policy.range();
+        if let Some(l) = softmax_cross_entropy(
+            &output[policy.clone()],
+            &sample.policy_target,
+            weights.policy,
+            &mut grad_out[policy]
+        ) {
+            loss.policy = l;
+            loss.policy_count = 1;
+        }
+        let choice = OutputBlock::Choice.range();
+        if let Some(l) = softmax_cross_entropy(
+            &output[choice.clone()],
+            &sample.choice_target,
+            weights.choice,
+            &mut grad_out[choice]
+        ) {
+            loss.choice = l;
+            loss.choice_count = 1;
+        }
+        let value = OutputBlock::Value.range();
+        let target = [
+            (sample.value_target[0] as f64 - norm.value_mean) / norm.value_scale,
+            sample.value_target[1] as f64 / norm.stdev_scale,
+            (sample.value_target[2] as f64 - norm.value_mean) / norm.value_scale
+        ];
+        for (i, t) in target.iter().enumerate() {
+            let diff = output[value.start + i] - *t as f32;
+            loss.value += (diff * diff) as f64 / target.len() as f64;
+            grad_out[value.start + i] = weights.value * 2.0 * diff / target.len() as f32;
+        }
+        loss.value_count = 1;
+
+        if let Some(grad) = grad {
+            // 反向传播
+            let mut delta = grad_out;
+            for i in (0..self.layers.len()).rev() {
+                let layer = &self.layers[i];
+                let input = &acts[i];
+                let g = &mut grad.layers[i];
+                for (o, &d) in delta.iter().enumerate() {
+                    if d == 0.0 {
+                        continue;
+                    }
+                    g.b[o] += d;
+                    let row = &mut g.w[o * layer.in_dim..(o + 1) * layer.in_dim];
+                    row.iter_mut().zip(input).for_each(|(w, x)| *w += d * x);
+                }
+                if i > 0 {
+                    let mut prev = vec![0.0_f32; layer.in_dim];
+                    for (o, &d) in delta.iter().enumerate() {
+                        if d == 0.0 {
+                            continue;
+                        }
+                        let row = &layer.w[o * layer.in_dim..(o + 1) * layer.in_dim];
+                        prev.iter_mut().zip(row).for_each(|(p, w)| *p += d * w);
+                    }
+                    // ReLU
+                    for (p, a) in prev.iter_mut().zip(input) {
+                        if *a <= 0.0 {
+                            *p = 0.0;
+                        }
+                    }
+                    delta = prev;
+                }
+            }
+        }
+        loss
+    }
+
+    /// 计算一批样本的梯度之和与损失
+    pub fn batch_grad(
+        &self, batch: &[&TrainingSample], weights: &LossWeights, norm: &ValueNormalization
+    ) -> (Mlp, MlpLoss) {
+        batch
+            .par_iter()
+            .fold(
+                || (self.zeros_like(), MlpLoss::default()),
+                |(mut grad, loss), sample| {
+                    let l = self.sample_grad(sample, weights, norm, Some(&mut grad));
+                    (grad, loss.merge(l))
+                }
+            )
+            .reduce(
+                || (self.zeros_like(), MlpLoss::default()),
+                |(mut a, la), (b, lb)| {
+                    a.add_assign(&b);
+                    (a, la.merge(lb))
+                }
+            )
+    }
+
+    /// 计算样本上的损失，不计算梯度
+    pub fn evaluate(&self, samples: &[&TrainingSample], weights: &LossWeights, norm: &ValueNormalization) -> MlpLoss {
+        samples
+            .par_iter()
+            .map(|s| self.sample_grad(s, weights, norm, None))
+            .reduce(MlpLoss::default, MlpLoss::merge)
+    }
+
+    /// 导出为 ONNX，输入 "input" [batch, INPUT_DIM]，输出 "output" [batch, OUTPUT_DIM]，
+    /// meta 写入 metadata_props
+    pub fn export_onnx(&self, path: &Path, meta: &ModelMeta) -> Result<()> {
+        ensure!(
+            self.layers.first().map(|l| l.in_dim) == Some(INPUT_DIM)
+                && self.layers.last().map(|l| l.out_dim) == Some(OUTPUT_DIM),
+            "网络输入输出维度与特征布局不一致"
+        );
+        let mut graph = GraphProto {
+            name: "umasim_mlp".to_string(),
+            input: vec![value_info("input", INPUT_DIM)],
+            output: vec![value_info("output", OUTPUT_DIM)],
+            ..Default::default()
+        };
+        let mut prev = "input".to_string();
+        for (i, layer) in self.layers.iter().enumerate() {
+            // MatMul 需要 [in, out] 的权重
+            let mut wt = vec![0.0_f32; layer.w.len()];
+            for o in 0..layer.out_dim {
+                for j in 0..layer.in_dim {
+                    wt[j * layer.out_dim + o] = layer.w[o * layer.in_dim + j];
+                }
+            }
+            graph.initializer.push(float_tensor(&format!("w{i}"), &[layer.in_dim, layer.out_dim], &wt));
+            graph.initializer.push(float_tensor(&format!("b{i}"), &[layer.out_dim], &layer.b));
+            let is_last = i + 1 == self.layers.len();
+            let sum = if is_last { "output".to_string() } else { format!("z{i}") };
+            graph.node.push(node("MatMul", &[&prev, &format!("w{i}")], &format!("mm{i}")));
+            graph.node.push(node("Add", &[&format!("mm{i}"), &format!("b{i}")], &sum));
+            if !is_last {
+                graph.node.push(node("Relu", &[&sum], &format!("h{i}")));
+                prev = format!("h{i}");
+            }
+        }
+        let model = ModelProto {
+            ir_version: ONNX_IR_VERSION,
+            opset_import: vec![OperatorSetIdProto {
+                domain: String::new(),
+                version: ONNX_OPSET
+            }],
+            producer_name: "umasim".to_string(),
+            producer_version: env!("CARGO_PKG_VERSION").to_string(),
+            graph: Some(graph),
+            metadata_props: meta
+                .to_props()
+                .into_iter()
+                .map(|(key, value)| StringStringEntryProto { key, value })
+                .collect(),
+            ..Default::default()
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs_err::create_dir_all(dir)?;
+        }
+        fs_err::write(path, model.encode_to_vec())?;
+        Ok(())
+    }
+}
+
+fn value_info(name: &str, dim: usize) -> ValueInfoProto {
+    let shape = TensorShapeProto {
+        dim: vec![
+            Dimension {
+                value: Some(dimension::Value::DimParam("batch".to_string())),
+                ..Default::default()
+            },
+            Dimension {
+                value: Some(dimension::Value::DimValue(dim as i64)),
+                ..Default::default()
+            },
+        ]
+    };
+    ValueInfoProto {
+        name: name.to_string(),
+        r#type: Some(TypeProto {
+            value: Some(type_proto::Value::TensorType(type_proto::Tensor {
+                elem_type: DataType::Float as i32,
+                shape: Some(shape)
+            })),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn float_tensor(name: &str, dims: &[usize], data: &[f32]) -> TensorProto {
+    TensorProto {
+        name: name.to_string(),
+        dims: dims.iter().map(|d| *d as i64).collect(),
+        data_type: DataType::Float as i32,
+        raw_data: data.iter().flat_map(|x| x.to_le_bytes()).collect(),
+        ..Default::default()
+    }
+}
+
+fn node(op_type: &str, inputs: &[&str], output: &str) -> NodeProto {
+    NodeProto {
+        op_type: op_type.to_string(),
+        name: output.to_string(),
+        input: inputs.iter().map(|s| s.to_string()).collect(),
+        output: vec![output.to_string()],
+        ..Default::default()
+    }
+}
+
+/// Adam 优化器
+#[derive(Debug, Clone)]
+pub struct Adam {
+    pub lr: f32,
+    pub beta1: f32,
+    pub beta2: f32,
+    pub eps: f32,
+    step: i32,
+    m: Mlp,
+    v: Mlp
+}
+
+impl Adam {
+    pub fn new(model: &Mlp, lr: f32) -> Self {
+        Self {
+            lr,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            step: 0,
+            m: model.zeros_like(),
+            v: model.zeros_like()
+        }
+    }
+
+    /// 用梯度之和grad更新模型，batch_size用于取平均
+    pub fn update(&mut self, model: &mut Mlp, grad: &Mlp, batch_size: usize) {
+        self.step += 1;
+        let scale = 1.0 / batch_size.max(1) as f32;
+        let lr = self.lr * (1.0 - self.beta2.powi(self.step)).sqrt() / (1.0 - self.beta1.powi(self.step));
+        let (beta1, beta2, eps) = (self.beta1, self.beta2, self.eps);
+        let update = |p: &mut [f32], g: &[f32], m: &mut [f32], v: &mut [f32]| {
+            for i in 0..p.len() {
+                let g = g[i] * scale;
+                m[i] = beta1 * m[i] + (1.0 - beta1) * g;
+                v[i] = beta2 * v[i] + (1.0 - beta2) * g * g;
+                p[i] -= lr * m[i] / (v[i].sqrt() + eps);
+            }
+        };
+        for (i, layer) in model.layers.iter_mut().enumerate() {
+            update(&mut layer.w, &grad.layers[i].w, &mut self.m.layers[i].w, &mut self.v.layers[i].w);
+            update(&mut layer.b, &grad.layers[i].b, &mut self.m.layers[i].b, &mut self.v.layers[i].b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        neural::NeuralNetEvaluator,
+        test_utils::TempDir,
+        training_sample::{CHOICE_DIM, POLICY_DIM}
+    };
+
+    /// 策略目标由第0维特征决定的合成样本
+    fn synthetic_samples(n: usize, rng: &mut StdRng) -> Vec<TrainingSample> {
+        (0..n)
+            .map(|_| {
+                let mut input: Vec<f32> = (0..INPUT_DIM).map(|_| rng.random_range(0.0..1.0)).collect();
+                let action = rng.random_range(0..2);
+                input[0] = action as f32;
+                let mut policy = vec![0.0; POLICY_DIM];
+                policy[action] = 1.0;
+                TrainingSample::new(input, policy, vec![0.0; CHOICE_DIM], vec![58300.0, 150.0, 58600.0])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_mlp_train_and_export() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let samples = synthetic_samples(64, &mut rng);
+        let refs: Vec<_> = samples.iter().collect();
+        let (weights, norm) = (LossWeights::default(), ValueNormalization::default());
+        let mut model = Mlp::new(&[16], &mut rng);
+        let mut adam = Adam::new(&model, 0.01);
+        let before = model.evaluate(&refs, &weights, &norm);
+        for _ in 0..30 {
+            let (grad, _) = model.batch_grad(&refs, &weights, &norm);
+            adam.update(&mut model, &grad, refs.len());
+        }
+        let after = model.evaluate(&refs, &weights, &norm);
+        println!("before: {} after: {}", before.explain(), after.explain());
+        assert_eq!(after.choice_count, 0);
+        assert!(after.total(&weights) < before.total(&weights));
+        assert!(after.policy_mean() < before.policy_mean());
+
+        // 导出的模型可以被评估器加载，输出与 Rust 前向一致
+        let dir = TempDir::new("mlp");
+        let path = dir.join("model.onnx");
+        model.export_onnx(&path, &ModelMeta::current())?;
+        let evaluator = NeuralNetEvaluator::load(path.to_string_lossy().as_ref())?;
+        assert_eq!(evaluator.meta(), &ModelMeta::current());
+        let expected = model.forward(&samples[0].nn_input);
+        let output = evaluator.infer(&samples[0].nn_input)?;
+        for (a, b) in expected.iter().zip(&output) {
+            assert!((a - b).abs() < 1e-3, "{a} != {b}");
+        }
+        Ok(())
+    }
+}
@@ -7,8 +7,11 @@
 //! - [`Evaluator`]: 评估器 trait
 //! - [`HandwrittenEvaluator`]: 手写启发式评估器（用于数据收集）
 //! - [`NeuralNetEvaluator`]: 神经网络评估器（ONNX 推理）
+//! - [`InferenceBackend`]: 推理后端（tract / 纯 Rust dense）
 //! - [`RandomEvaluator`]: 随机评估器（基准测试）
 //! - [`ValueOutput`]: 评估器输出值
+//! - `feature_schema`: 特征布局和模型元数据
+//! - [`Mlp`]: 纯 Rust 的 MLP 训练和 ONNX 导出
 //!
 //! # 使用示例
 //!
@@ -25,13 +28,24 @@
 //! ```
 
 mod evaluator;
+pub mod feature_schema;
 mod handwritten_evaluator;
+mod inference_backend;
+mod mlp;
 mod neural_net_evaluator;
 mod value_output;
 
 // 公开导出
 pub use evaluator::{Evaluator, RandomEvaluator};
 pub use handwritten_evaluator::HandwrittenEvaluator;
+pub use inference_backend::{
+    DENSE_MODEL_EXT,
+    DenseBackend,
+    InferenceBackend,
+    InferenceBackendKind,
+    TractBackend
+};
+pub use mlp::{Adam, Dense, LossWeights, Mlp, MlpLoss};
 pub use neural_net_evaluator::{
     NeuralNetEvaluator,
     ThreadLocalNeuralNetLeafEvaluator,
@@ -7,79 +7,49 @@
 //! - 输出：61 维 (Policy 50 + Choice 8 + Value 3)
 //!
 //! # Value 反归一化
-//! - scoreMean = VALUE_MEAN + VALUE_SCALE * output[58]
-//! - scoreStdev = STDEV_SCALE * abs(output[59])
-//! - value = VALUE_MEAN + VALUE_SCALE * output[60]
+//! - scoreMean = value_mean + value_scale * output[58]
+//! - scoreStdev = stdev_scale * abs(output[59])
+//! - value = value_mean + value_scale * output[60]
+//!
+//! 反归一化参数和特征布局版本从模型的 metadata_props 读取，见 [`super::feature_schema`]。
 
 use std::{
     cell::RefCell,
     sync::{
         Arc,
+        OnceLock,
         atomic::{AtomicU64, Ordering}
     },
     time::Instant
 };
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use rand::{Rng, rngs::StdRng};
-use tract_onnx::prelude::*;
 
-use super::{Evaluator, ValueOutput};
-use crate::game::{
-    ActionScore,
-    Game,
-    onsen::{action::OnsenAction, game::OnsenGame}
+use super::{
+    Evaluator,
+    ValueOutput,
+    feature_schema::{INPUT_DIM, ModelMeta, OUTPUT_DIM, OutputBlock, ValueNormalization},
+    inference_backend::{InferenceBackend, InferenceBackendKind}
+};
+use crate::{
+    game::{
+        ActionScore,
+        Game,
+        onsen::{action::OnsenAction, game::OnsenGame}
+    },
+    training_sample::POLICY_DIM
 };
 
-// ============================================================================
-// 常量定义（与 Python config.py 一致）
-// ============================================================================
-
-/// 输入维度
-const INPUT_DIM: usize = 1121;
-
-/// 输出维度
-const OUTPUT_DIM: usize = 61;
-
-/// Policy 输出维度
-const POLICY_DIM: usize = 50;
-
-/// Choice 输出维度
-const CHOICE_DIM: usize = 8;
-
-/// Value 反归一化参数 - 均值
-const VALUE_MEAN: f64 = 58000.0;
-
-/// Value 反归一化参数 - 缩放
-const VALUE_SCALE: f64 = 300.0;
-
-/// Stdev 反归一化参数 - 缩放
-const STDEV_SCALE: f64 = 150.0;
-
-// ============================================================================
-// 类型别名
-// ============================================================================
-
-type OnnxModel = SimplePlan<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
-
-fn load_onnx_model(model_path: &str) -> Result<OnnxModel> {
-    tract_onnx::onnx()
-        .model_for_path(model_path)
-        .context("无法读取 ONNX 模型文件")?
-        .into_optimized()
-        .context("模型优化失败")?
-        .into_runnable()
-        .context("模型转换失败")
-}
-
-fn extract_value_from_output(output: &[f32]) -> ValueOutput {
-    let score_mean = VALUE_MEAN + VALUE_SCALE * output[POLICY_DIM + CHOICE_DIM] as f64;
-    let score_stdev = STDEV_SCALE * output[POLICY_DIM + CHOICE_DIM + 1] as f64;
+fn extract_value_from_output(output: &[f32], norm: &ValueNormalization) -> ValueOutput {
+    let value = &output[OutputBlock::Value.range()];
+    let score_mean = norm.value_mean + norm.value_scale * value[0] as f64;
+    let score_stdev = norm.stdev_scale * value[1] as f64;
     ValueOutput::new(score_mean, score_stdev.abs())
 }
 
 thread_local! {
-    static THREAD_LOCAL_MODEL: RefCell<Option<(String, OnnxModel)>> = RefCell::new(None);
+    static THREAD_LOCAL_MODEL: RefCell<Option<(String, Arc<dyn InferenceBackend>)>> = RefCell::new(None);
 }
 
 fn action_to_global_index_v1(action: &OnsenAction) -> Option<usize> {
@@ -93,39 +63,106 @@ fn action_to_global_index_v1(action: &OnsenAction) -> Option<usize> {
         OnsenAction::PR => Some(10),
         OnsenAction::Dig(idx) => Some(11 + *idx as usize),
         OnsenAction::Upgrade(idx) => Some(21 + *idx as usize),
-        OnsenAction::UseTicket(is_super) => Some(if *is_super { 25 } else { 24 })
+        OnsenAction::UseTicket(is_super) => Some(if *is_super { 25 } else { 24 }),
+        // 复合动作只在搜索内部使用，没有对应的策略输出
+        OnsenAction::DigUpgrade(_, _) => None,
+        OnsenAction::CardOuting(idx) if (0..6).contains(idx) => Some(26 + *idx as usize),
+        OnsenAction::CardOuting(_) => None
     }
 }
 
+/// 根据 Policy logits 采样选择动作索引
+///
+/// 注意：神经网络输出的是 logits（可为负数），不能直接当作概率使用。
+/// 这里对合法动作做 softmax，再按概率采样。
+fn sample_action_index(logits: &[f32], legal_mask: &[bool], rng: &mut StdRng) -> usize {
+    // 找到合法动作中最大的 logit（softmax 数值稳定）
+    let mut max_logit = f32::NEG_INFINITY;
+    for (i, &v) in logits.iter().enumerate() {
+        if i < legal_mask.len() && legal_mask[i] && v > max_logit {
+            max_logit = v;
+        }
+    }
+
+    // 没有任何合法动作，回退到第一个合法动作
+    if !max_logit.is_finite() {
+        return legal_mask.iter().position(|&x| x).unwrap_or(0);
+    }
+
+    // 计算 softmax 权重（只对合法动作赋值）
+    let mut weights: Vec<f64> = vec![0.0; logits.len()];
+    let mut sum: f64 = 0.0;
+    for (i, &v) in logits.iter().enumerate() {
+        if i < legal_mask.len() && legal_mask[i] {
+            let w = ((v - max_logit) as f64).exp();
+            weights[i] = w;
+            sum += w;
+        }
+    }
+
+    if sum <= 0.0 || !sum.is_finite() {
+        // 数值异常时回退到最后一个合法动作（保持确定性）
+        return legal_mask.iter().rposition(|&x| x).unwrap_or(0);
+    }
+
+    // 采样：在 [0, sum) 上采样，再落到累计权重区间
+    let r: f64 = rng.random::<f64>() * sum;
+    let mut acc = 0.0;
+    for (i, &w) in weights.iter().enumerate() {
+        acc += w;
+        if r <= acc {
+            return i;
+        }
+    }
+
+    // 理论上不会走到这里，兜底返回最后一个合法动作
+    legal_mask.iter().rposition(|&x| x).unwrap_or(0)
+}
+
 // ============================================================================
 // NeuralNetEvaluator
 // ============================================================================
 
 /// 神经网络评估器
 ///
-/// 使用 ONNX 模型进行策略和价值评估。
+/// 使用 ONNX 模型进行策略和价值评估，推理由 [`InferenceBackend`] 完成。
 #[derive(Clone)]
 pub struct NeuralNetEvaluator {
-    /// ONNX 模型（使用 Arc 共享，因为 SimplePlan 不可克隆）
-    model: Arc<OnnxModel>
+    /// 推理后端（使用 Arc 共享）
+    model: Arc<dyn InferenceBackend>
 }
 
 impl NeuralNetEvaluator {
-    /// 从 ONNX 文件加载模型
+    /// 用 tract 后端从 ONNX 文件加载模型
     ///
     /// # 参数
     /// - `model_path`: ONNX 模型文件路径
     ///
     /// # 返回
-    /// 加载成功返回 NeuralNetEvaluator，失败返回错误
+    /// 加载成功返回 NeuralNetEvaluator，失败返回错误。模型的特征布局与当前不一致时返回错误
     pub fn load(model_path: &str) -> Result<Self> {
-        log::info!("加载 ONNX 模型: {}", model_path);
+        Self::load_with_backend(model_path, InferenceBackendKind::Tract)
+    }
 
-        let model = load_onnx_model(model_path)?;
+    /// 用指定后端加载模型
+    pub fn load_with_backend(model_path: &str, backend: InferenceBackendKind) -> Result<Self> {
+        log::info!("加载模型: {model_path} (后端 {})", backend.name());
 
-        log::info!("ONNX 模型加载成功");
+        let model = backend.load(model_path)?;
 
-        Ok(Self { model: Arc::new(model) })
+        log::info!("模型加载成功: {:?}", model.meta());
+
+        Ok(Self { model })
+    }
+
+    /// 模型记录的布局信息
+    pub fn meta(&self) -> &ModelMeta {
+        self.model.meta()
+    }
+
+    /// 推理后端类型
+    pub fn backend(&self) -> InferenceBackendKind {
+        self.model.kind()
     }
 
     /// 执行神经网络推理
@@ -140,88 +177,25 @@ impl NeuralNetEvaluator {
             anyhow::bail!("输入维度错误: 期望 {}, 实际 {}", INPUT_DIM, features.len());
         }
 
-        // 创建输入张量 [1, 1121]
-        let input =
-            tract_ndarray::Array2::from_shape_vec((1, INPUT_DIM), features.to_vec()).context("创建输入张量失败")?;
-
-        // 运行推理
-        let output = self.model.run(tvec!(input.into_tvalue())).context("推理失败")?;
-
-        // 提取输出
-        let output_tensor = output[0].to_array_view::<f32>().context("提取输出张量失败")?;
-
-        let result: Vec<f32> = output_tensor.iter().copied().collect();
-
-        if result.len() != OUTPUT_DIM {
-            anyhow::bail!("输出维度错误: 期望 {}, 实际 {}", OUTPUT_DIM, result.len());
-        }
-
-        Ok(result)
+        self.model.infer(features)
     }
 
     /// 从输出中提取 Policy 概率分布
     fn extract_policy(&self, output: &[f32]) -> Vec<f32> {
-        output[0..POLICY_DIM].to_vec()
+        output[OutputBlock::Policy.range()].to_vec()
     }
 
     /// 从输出中提取 Choice 概率分布
     fn extract_choice(&self, output: &[f32]) -> Vec<f32> {
-        output[POLICY_DIM..POLICY_DIM + CHOICE_DIM].to_vec()
+        output[OutputBlock::Choice.range()].to_vec()
     }
 
     /// 从输出中提取 Value（反归一化）
     fn extract_value(&self, output: &[f32]) -> ValueOutput {
-        // output[POLICY_DIM + CHOICE_DIM + 2] 是 value，但我们使用 score_mean 作为主要评估值
-        extract_value_from_output(output)
+        // value 块的第3项是 value，但我们使用 score_mean 作为主要评估值
+        extract_value_from_output(output, &self.meta().normalization)
     }
 
-    /// 根据 Policy logits 采样选择动作索引
-    ///
-    /// 注意：神经网络输出的是 logits（可为负数），不能直接当作概率使用。
-    /// 这里对合法动作做 softmax，再按概率采样。
-    fn sample_action_index(&self, logits: &[f32], legal_mask: &[bool], rng: &mut StdRng) -> usize {
-        // 找到合法动作中最大的 logit（softmax 数值稳定）
-        let mut max_logit = f32::NEG_INFINITY;
-        for (i, &v) in logits.iter().enumerate() {
-            if i < legal_mask.len() && legal_mask[i] && v > max_logit {
-                max_logit = v;
-            }
-        }
-
-        // 没有任何合法动作，回退到第一个合法动作
-        if !max_logit.is_finite() {
-            return legal_mask.iter().position(|&x| x).unwrap_or(0);
-        }
-
-        // 计算 softmax 权重（只对合法动作赋值）
-        let mut weights: Vec<f64> = vec![0.0; logits.len()];
-        let mut sum: f64 = 0.0;
-        for (i, &v) in logits.iter().enumerate() {
-            if i < legal_mask.len() && legal_mask[i] {
-                let w = ((v - max_logit) as f64).exp();
-                weights[i] = w;
-                sum += w;
-            }
-        }
-
-        if sum <= 0.0 || !sum.is_finite() {
-            // 数值异常时回退到最后一个合法动作（保持确定性）
-            return legal_mask.iter().rposition(|&x| x).unwrap_or(0);
-        }
-
-        // 采样：在 [0, sum) 上采样，再落到累计权重区间
-        let r: f64 = rng.random::<f64>() * sum;
-        let mut acc = 0.0;
-        for (i, &w) in weights.iter().enumerate() {
-            acc += w;
-            if r <= acc {
-                return i;
-            }
-        }
-
-        // 理论上不会走到这里，兜底返回最后一个合法动作
-        legal_mask.iter().rposition(|&x| x).unwrap_or(0)
-    }
     /*
     /// 将动作转换为全局索引
     ///
@@ -236,10 +210,13 @@ impl NeuralNetEvaluator {
 // ThreadLocalNeuralNetLeafEvaluator
 // ============================================================================
 
-/// 搜索 leaf eval 专用：每线程懒加载一份模型，避免跨线程共享 `SimplePlan` 的线程安全风险。
+/// 搜索 leaf eval 专用：tract 后端每线程懒加载一份模型，避免跨线程共享 `SimplePlan` 的线程安全风险；
+/// 可共享的后端（dense）只加载一份，所有线程共用。
 #[derive(Clone)]
 pub struct ThreadLocalNeuralNetLeafEvaluator {
     model_path: Arc<String>,
+    backend: InferenceBackendKind,
+    shared: Arc<OnceLock<Arc<dyn InferenceBackend>>>,
     stats: Arc<ThreadLocalNeuralNetLeafStats>
 }
 
@@ -277,10 +254,29 @@ impl ThreadLocalNeuralNetLeafEvaluator {
     pub fn new(model_path: impl Into<String>) -> Self {
         Self {
             model_path: Arc::new(model_path.into()),
+            backend: InferenceBackendKind::default(),
+            shared: Arc::new(OnceLock::new()),
             stats: Arc::new(ThreadLocalNeuralNetLeafStats::new())
         }
     }
 
+    /// 设置推理后端
+    pub fn with_backend(mut self, backend: InferenceBackendKind) -> Self {
+        self.backend = backend;
+        self.shared = Arc::new(OnceLock::new());
+        self
+    }
+
+    /// 模型路径
+    pub fn model_path(&self) -> &str {
+        self.model_path.as_str()
+    }
+
+    /// 推理后端类型
+    pub fn backend(&self) -> InferenceBackendKind {
+        self.backend
+    }
+
     pub fn stats(&self) -> ThreadLocalNeuralNetLeafStatsSnapshot {
         ThreadLocalNeuralNetLeafStatsSnapshot {
             model_loads: self.stats.model_loads.load(Ordering::Relaxed),
@@ -291,83 +287,58 @@ impl ThreadLocalNeuralNetLeafEvaluator {
         }
     }
 
+    /// 在本线程可用的模型上执行f，模型未加载或路径变化时重新加载
+    fn with_model<R>(&self, f: impl FnOnce(&dyn InferenceBackend) -> Result<R>) -> Result<R> {
+        if self.backend.is_shareable() {
+            if let Some(model) = self.shared.get() {
+                return f(model.as_ref());
+            }
+            log::info!("[NN][leaf] 加载共享模型: {}", self.model_path.as_str());
+            let loaded = self.backend.load(self.model_path.as_str())?;
+            let model = self.shared.get_or_init(|| {
+                self.stats.model_loads.fetch_add(1, Ordering::Relaxed);
+                loaded
+            });
+            return f(model.as_ref());
+        }
+        // 取出 Arc 后释放借用，推理过程中不持有 RefCell
+        let model = THREAD_LOCAL_MODEL.with(|slot| -> Result<Arc<dyn InferenceBackend>> {
+            let mut slot = slot.borrow_mut();
+            match slot.as_ref() {
+                Some((p, model)) if p == self.model_path.as_str() && model.kind() == self.backend => {
+                    Ok(model.clone())
+                }
+                _ => {
+                    log::info!("[NN][leaf] 线程内加载模型: {}", self.model_path.as_str());
+                    let model = self.backend.load(self.model_path.as_str())?;
+                    *slot = Some((self.model_path.as_str().to_string(), model.clone()));
+                    self.stats.model_loads.fetch_add(1, Ordering::Relaxed);
+                    Ok(model)
+                }
+            }
+        })?;
+        f(model.as_ref())
+    }
+
+    /// 模型的反归一化参数
+    fn normalization(&self) -> Result<ValueNormalization> {
+        self.with_model(|model| Ok(model.meta().normalization))
+    }
+
     /// 微批推理：输入 [batch,1121] 的扁平数组，输出 [batch,61] 的扁平数组。
     ///
-    /// - 当模型不支持动态 batch 时，会回退为循环 `infer(1)`（并打印 warning）。
+    /// - 当模型不支持动态 batch 时，tract 后端会回退为循环 `infer(1)`（并打印 warning）。
     pub fn infer_batch(&self, features_flat: &[f32], batch: usize) -> Result<Vec<f32>> {
         if batch == 0 {
             anyhow::bail!("batch 不能为 0");
         }
-        if features_flat.len() != batch * INPUT_DIM {
-            anyhow::bail!(
-                "输入维度错误: 期望 {} (=batch*INPUT_DIM), 实际 {}",
-                batch * INPUT_DIM,
-                features_flat.len()
-            );
-        }
 
         // 计数：一次 batch 调用，覆盖 batch 个样本
         self.stats.infer_batches.fetch_add(1, Ordering::Relaxed);
         self.stats.infer_calls.fetch_add(batch as u64, Ordering::Relaxed);
         let t0 = Instant::now();
 
-        let run_once = |model: &OnnxModel| -> Result<Vec<f32>> {
-            let input = tract_ndarray::Array2::from_shape_vec((batch, INPUT_DIM), features_flat.to_vec())
-                .context("创建 batch 输入张量失败")?;
-            let output = model.run(tvec!(input.into_tvalue())).context("batch 推理失败")?;
-            let output_tensor = output[0].to_array_view::<f32>().context("提取 batch 输出张量失败")?;
-            let out: Vec<f32> = output_tensor.iter().copied().collect();
-            if out.len() != batch * OUTPUT_DIM {
-                anyhow::bail!(
-                    "batch 输出维度错误: 期望 {} (=batch*OUTPUT_DIM), 实际 {}",
-                    batch * OUTPUT_DIM,
-                    out.len()
-                );
-            }
-            Ok(out)
-        };
-
-        let result = THREAD_LOCAL_MODEL.with(|slot| -> Result<Vec<f32>> {
-            let mut slot = slot.borrow_mut();
-            let need_reload = match slot.as_ref() {
-                Some((p, _)) => p != self.model_path.as_str(),
-                None => true
-            };
-            if need_reload {
-                log::info!("[NN][leaf] 线程内加载模型: {}", self.model_path.as_str());
-                let model = load_onnx_model(self.model_path.as_str())?;
-                *slot = Some((self.model_path.as_str().to_string(), model));
-                self.stats.model_loads.fetch_add(1, Ordering::Relaxed);
-            }
-
-            let (_, model) = slot.as_ref().expect("thread_local model");
-
-            // 先尝试动态 batch（ONNX 导出脚本已开启 dynamic_axes）
-            match run_once(model) {
-                Ok(v) => Ok(v),
-                Err(e) => {
-                    // 降级：逐样本 infer(1)，保证功能不挂（但会很慢）
-                    log::warn!("[NN][leaf] batch 推理失败，回退为逐样本 infer(1)（性能受限）。原因: {e}");
-                    let mut out_all = Vec::with_capacity(batch * OUTPUT_DIM);
-                    for i in 0..batch {
-                        let start = i * INPUT_DIM;
-                        let end = start + INPUT_DIM;
-
-                        let input =
-                            tract_ndarray::Array2::from_shape_vec((1, INPUT_DIM), features_flat[start..end].to_vec())
-                                .context("创建单样本输入张量失败")?;
-                        let output = model.run(tvec!(input.into_tvalue())).context("单样本推理失败")?;
-                        let output_tensor = output[0].to_array_view::<f32>().context("提取单样本输出张量失败")?;
-                        let out: Vec<f32> = output_tensor.iter().copied().collect();
-                        if out.len() != OUTPUT_DIM {
-                            anyhow::bail!("单样本输出维度错误: 期望 {}, 实际 {}", OUTPUT_DIM, out.len());
-                        }
-                        out_all.extend_from_slice(&out);
-                    }
-                    Ok(out_all)
-                }
-            }
-        });
+        let result = self.with_model(|model| model.infer_batch(features_flat, batch));
 
         let elapsed_ns: u64 = t0.elapsed().as_nanos().min(u128::from(u64::MAX)) as u64;
         self.stats.infer_time_ns_total.fetch_add(elapsed_ns, Ordering::Relaxed);
@@ -380,11 +351,12 @@ impl ThreadLocalNeuralNetLeafEvaluator {
     /// 给定一批 features，直接返回每个样本的 value 输出（mean/stdev）。
     pub fn evaluate_features_batch(&self, features_flat: &[f32], batch: usize) -> Result<Vec<ValueOutput>> {
         let out = self.infer_batch(features_flat, batch)?;
+        let norm = self.normalization()?;
         let mut values = Vec::with_capacity(batch);
         for i in 0..batch {
             let start = i * OUTPUT_DIM;
             let end = start + OUTPUT_DIM;
-            values.push(extract_value_from_output(&out[start..end]));
+            values.push(extract_value_from_output(&out[start..end], &norm));
         }
         Ok(values)
     }
@@ -398,38 +370,7 @@ impl ThreadLocalNeuralNetLeafEvaluator {
         self.stats.infer_calls.fetch_add(1, Ordering::Relaxed);
         let t0 = Instant::now();
 
-        let result = THREAD_LOCAL_MODEL.with(|slot| -> Result<Vec<f32>> {
-            let mut slot = slot.borrow_mut();
-            let need_reload = match slot.as_ref() {
-                Some((p, _)) => p != self.model_path.as_str(),
-                None => true
-            };
-            if need_reload {
-                log::info!("[NN][leaf] 线程内加载模型: {}", self.model_path.as_str());
-                let model = load_onnx_model(self.model_path.as_str())?;
-                *slot = Some((self.model_path.as_str().to_string(), model));
-                self.stats.model_loads.fetch_add(1, Ordering::Relaxed);
-            }
-
-            let (_, model) = slot.as_ref().expect("thread_local model");
-
-            // 创建输入张量 [1, 1121]
-            let input =
-                tract_ndarray::Array2::from_shape_vec((1, INPUT_DIM), features.to_vec()).context("创建输入张量失败")?;
-
-            // 运行推理
-            let output = model.run(tvec!(input.into_tvalue())).context("推理失败")?;
-
-            // 提取输出
-            let output_tensor = output[0].to_array_view::<f32>().context("提取输出张量失败")?;
-            let out: Vec<f32> = output_tensor.iter().copied().collect();
-
-            if out.len() != OUTPUT_DIM {
-                anyhow::bail!("输出维度错误: 期望 {}, 实际 {}", OUTPUT_DIM, out.len());
-            }
-
-            Ok(out)
-        });
+        let result = self.with_model(|model| model.infer(features));
 
         let elapsed_ns: u64 = t0.elapsed().as_nanos().min(u128::from(u64::MAX)) as u64;
         self.stats.infer_time_ns_total.fetch_add(elapsed_ns, Ordering::Relaxed);
@@ -440,6 +381,31 @@ impl ThreadLocalNeuralNetLeafEvaluator {
 
         result
     }
+
+    /// rollout 用：按 Policy 输出在给定动作中采样
+    ///
+    /// 动作都没有对应的策略输出时返回 None，由调用方回退
+    pub fn select_action_index(
+        &self, game: &OnsenGame, actions: &[OnsenAction], rng: &mut StdRng
+    ) -> Result<Option<usize>> {
+        let mut legal_mask = vec![false; POLICY_DIM];
+        for action in actions {
+            if let Some(idx) = action_to_global_index_v1(action)
+                && idx < POLICY_DIM
+            {
+                legal_mask[idx] = true;
+            }
+        }
+        if !legal_mask.contains(&true) {
+            return Ok(None);
+        }
+
+        let output = self.infer(&game.extract_nn_features(None))?;
+        let global_idx = sample_action_index(&output[OutputBlock::Policy.range()], &legal_mask, rng);
+        Ok(actions
+            .iter()
+            .position(|a| action_to_global_index_v1(a) == Some(global_idx)))
+    }
 }
 
 // ============================================================================
@@ -481,7 +447,7 @@ impl Evaluator<OnsenGame> for NeuralNetEvaluator {
         }
 
         // 采样选择全局动作索引
-        let global_idx = self.sample_action_index(&policy, &legal_mask, rng);
+        let global_idx = sample_action_index(&policy, &legal_mask, rng);
 
         // 找到对应的动作
         for action in &actions {
@@ -592,8 +558,8 @@ impl Evaluator<OnsenGame> for ThreadLocalNeuralNetLeafEvaluator {
         }
 
         let features = game.extract_nn_features(None);
-        match self.infer(&features) {
-            Ok(output) => extract_value_from_output(&output),
+        match self.infer(&features).and_then(|output| Ok(extract_value_from_output(&output, &self.normalization()?))) {
+            Ok(value) => value,
             Err(e) => {
                 log::warn!("[NN][leaf] 推理失败: {}", e);
                 // 回退到简单评估（不允许 silent fallback：必须有日志）
@@ -605,11 +571,3 @@ impl Evaluator<OnsenGame> for ThreadLocalNeuralNetLeafEvaluator {
         }
     }
 }
-
-// ============================================================================
-// Send + Sync 实现
-// ============================================================================
-
-// NeuralNetEvaluator 通过 Arc 共享模型，是线程安全的
-unsafe impl Send for NeuralNetEvaluator {}
-unsafe impl Sync for NeuralNetEvaluator {}
@@ -4,7 +4,7 @@
 /// 每回合记录游戏状态、选择的动作、事件选项等信息
 /// 游戏结束后根据最终分数生成训练样本
 use crate::game::onsen::action::OnsenAction;
-use crate::training_sample::{CHOICE_DIM, NN_INPUT_DIM, TrainingSample};
+use crate::training_sample::{CHOICE_DIM, NN_INPUT_DIM, SampleProvenance, TrainingSample};
 
 /// Policy 输出维度
 pub const POLICY_DIM: usize = 50;
@@ -25,7 +25,8 @@ pub const POLICY_DIM: usize = 50;
 /// - 11-20: Dig(0-9) 挖掘温泉
 /// - 21-23: Upgrade(0-2) 装备升级
 /// - 24-25: UseTicket(false/true) 使用温泉券
-/// - 26-49: 保留
+/// - 26-31: CardOuting(0-5) 非剧本友人卡/团队卡出行
+/// - 32-49: 保留
 pub fn action_to_global_index(action: &OnsenAction) -> Option<usize> {
     match action {
         OnsenAction::Train(t) => Some(*t as usize),
@@ -37,7 +38,11 @@ pub fn action_to_global_index(action: &OnsenAction) -> Option<usize> {
         OnsenAction::PR => Some(10),
         OnsenAction::Dig(idx) => Some(11 + *idx as usize),
         OnsenAction::Upgrade(idx) => Some(21 + *idx as usize),
-        OnsenAction::UseTicket(is_super) => Some(if *is_super { 25 } else { 24 })
+        OnsenAction::UseTicket(is_super) => Some(if *is_super { 25 } else { 24 }),
+        // 复合动作只在搜索内部使用，没有对应的策略输出
+        OnsenAction::DigUpgrade(_, _) => None,
+        OnsenAction::CardOuting(idx) if (0..6).contains(idx) => Some(26 + *idx as usize),
+        OnsenAction::CardOuting(_) => None
     }
 }
 
@@ -51,7 +56,9 @@ pub struct TurnData {
     /// 事件选项索引（如果有）
     pub choice_idx: Option<usize>,
     /// 事件选项数量
-    pub num_choices: usize
+    pub num_choices: usize,
+    /// 样本来源
+    pub provenance: SampleProvenance
 }
 
 /// 样本收集器
@@ -83,7 +90,8 @@ impl SampleCollector {
     /// # 参数
     /// - `features`: 当前游戏状态的特征向量（1121 维）
     /// - `action`: 选择的动作
-    pub fn record_turn(&mut self, features: Vec<f32>, action: &OnsenAction) {
+    /// - `provenance`: 决策点信息（回合、阶段、可选动作数）
+    pub fn record_turn(&mut self, features: Vec<f32>, action: &OnsenAction, provenance: SampleProvenance) {
         debug_assert_eq!(features.len(), NN_INPUT_DIM, "特征维度必须是 {}", NN_INPUT_DIM);
 
         // 使用全局动作索引
@@ -93,7 +101,8 @@ impl SampleCollector {
             features,
             global_action_idx,
             choice_idx: None,
-            num_choices: 0
+            num_choices: 0,
+            provenance
         });
     }
 
@@ -128,6 +137,11 @@ impl SampleCollector {
         self.turn_data.len()
     }
 
+    /// 已记录的回合数据（可修改）
+    pub fn turns_mut(&mut self) -> &mut [TurnData] {
+        &mut self.turn_data
+    }
+
     /// 是否已完成
     pub fn is_finished(&self) -> bool {
         self.is_finished
@@ -172,7 +186,9 @@ impl SampleCollector {
                     final_score, // value: 绝对分数
                 ];
 
-                TrainingSample::new(turn.features, policy_target, choice_target, value_target)
+                let mut sample = TrainingSample::new(turn.features, policy_target, choice_target, value_target);
+                sample.provenance = turn.provenance;
+                sample
             })
             .collect()
     }
@@ -202,4 +218,11 @@ impl GameSample {
         let samples = collector.finalize();
         Self { final_score, samples }
     }
+
+    /// 给本局所有样本设置对局编号
+    pub fn set_game_id(&mut self, game_id: u64) {
+        for sample in &mut self.samples {
+            sample.game_id = game_id;
+        }
+    }
 }
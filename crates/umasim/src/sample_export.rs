@@ -0,0 +1,331 @@
+//! 训练样本导出为 NumPy（.npy）或 safetensors
+//!
+//! Python 端可以直接 `np.load(path, mmap_mode="r")` 或 `safetensors.numpy.load_file(path)`，
+//! 不用再按 bincode 布局解析 part_*.bin。数组与 [`TrainingSample`] 字段一一对应：
+//! - nn_input: f32 [N, NN_INPUT_DIM]
+//! - policy_target: f32 [N, POLICY_DIM]
+//! - choice_target: f32 [N, CHOICE_DIM]
+//! - value_target: f32 [N, VALUE_DIM]
+//! - game_id: u64 [N]
+//!
+//! npy 格式把所有分片拼成一组文件；safetensors 格式每个输入分片导出一个文件。
+//! 两种格式都会在输出目录写 export.json（文件列表和样本数）和 feature_schema.json，
+//! 输入目录有 manifest.json 时也复制过去。
+
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf}
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use safetensors::{Dtype, tensor::TensorView};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    neural::feature_schema::{FEATURE_SCHEMA_FILE, FEATURE_SCHEMA_VERSION, FeatureSchema},
+    training_sample::{CHOICE_DIM, NN_INPUT_DIM, POLICY_DIM, TrainingSample, VALUE_DIM}
+};
+
+/// 导出目录中的索引文件名
+pub const EXPORT_INDEX_FILE: &str = "export.json";
+
+/// 导出的数组名和每个样本的列数，列数为 None 表示一维
+pub const EXPORT_ARRAYS: [(&str, Option<usize>); 5] = [
+    ("nn_input", Some(NN_INPUT_DIM)),
+    ("policy_target", Some(POLICY_DIM)),
+    ("choice_target", Some(CHOICE_DIM)),
+    ("value_target", Some(VALUE_DIM)),
+    ("game_id", None)
+];
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// 每个数组一个 .npy 文件，所有分片拼接
+    #[default]
+    Npy,
+    /// 每个分片一个 .safetensors 文件
+    Safetensors
+}
+
+impl ExportFormat {
+    /// 从命令行中的名字解析: "npy" | "safetensors"
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "npy" | "numpy" => Ok(Self::Npy),
+            "safetensors" => Ok(Self::Safetensors),
+            other => bail!("未知导出格式 \"{other}\"（仅支持 \"npy\" | \"safetensors\"）")
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Npy => "npy",
+            Self::Safetensors => "safetensors"
+        }
+    }
+}
+
+/// 导出的一个文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportFile {
+    pub name: String,
+    /// 来源分片（npy 格式为 None）
+    pub source: Option<String>,
+    pub samples: u64
+}
+
+/// 导出的一个数组
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportArray {
+    pub name: String,
+    pub dtype: String,
+    /// 每个样本的形状（一维数组为空）
+    pub shape: Vec<usize>
+}
+
+/// export.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportIndex {
+    pub format: ExportFormat,
+    pub feature_schema_version: u32,
+    pub samples: u64,
+    pub arrays: Vec<ExportArray>,
+    pub files: Vec<ExportFile>
+}
+
+// ============================================================================
+// npy
+// ============================================================================
+
+/// .npy 文件头的总长度（含 magic），预留足够长度以便结束时回填样本数
+const NPY_HEADER_LEN: usize = 128;
+
+/// 可写入 .npy 的元素类型
+pub trait NpyElement: Copy {
+    /// numpy 的 dtype 描述
+    const DESCR: &'static str;
+
+    fn write_le(&self, w: &mut impl Write) -> std::io::Result<()>;
+}
+
+impl NpyElement for f32 {
+    const DESCR: &'static str = "<f4";
+
+    fn write_le(&self, w: &mut impl Write) -> std::io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+impl NpyElement for u64 {
+    const DESCR: &'static str = "<u8";
+
+    fn write_le(&self, w: &mut impl Write) -> std::io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+fn npy_header(descr: &str, rows: u64, cols: Option<usize>) -> Vec<u8> {
+    let shape = match cols {
+        Some(c) => format!("({rows}, {c})"),
+        None => format!("({rows},)")
+    };
+    let dict = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+    // magic(6) + 版本(2) + 头长度(2) + dict，用空格补齐并以换行结尾
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&((NPY_HEADER_LEN - 10) as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header.resize(NPY_HEADER_LEN - 1, b' ');
+    header.push(b'\n');
+    header
+}
+
+/// 逐行写入的 .npy 文件，[`NpyWriter::finish`] 时回填行数
+pub struct NpyWriter<T: NpyElement> {
+    path: PathBuf,
+    writer: BufWriter<fs_err::File>,
+    cols: Option<usize>,
+    rows: u64,
+    _marker: std::marker::PhantomData<T>
+}
+
+impl<T: NpyElement> NpyWriter<T> {
+    pub fn create(path: &Path, cols: Option<usize>) -> Result<Self> {
+        let file = fs_err::File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&npy_header(T::DESCR, 0, cols))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            cols,
+            rows: 0,
+            _marker: std::marker::PhantomData
+        })
+    }
+
+    pub fn write_row(&mut self, row: &[T]) -> Result<()> {
+        ensure!(
+            row.len() == self.cols.unwrap_or(1),
+            "{} 的列数应为 {}，实际为 {}",
+            self.path.display(),
+            self.cols.unwrap_or(1),
+            row.len()
+        );
+        for v in row {
+            v.write_le(&mut self.writer)?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<u64> {
+        let header = npy_header(T::DESCR, self.rows, self.cols);
+        ensure!(header.len() == NPY_HEADER_LEN, "npy 文件头过长: {}", self.path.display());
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer
+            .flush()
+            .with_context(|| format!("写入失败: {}", self.path.display()))?;
+        Ok(self.rows)
+    }
+}
+
+/// npy 格式导出：每个数组一个文件
+pub struct NpyExporter {
+    nn_input: NpyWriter<f32>,
+    policy_target: NpyWriter<f32>,
+    choice_target: NpyWriter<f32>,
+    value_target: NpyWriter<f32>,
+    game_id: NpyWriter<u64>
+}
+
+impl NpyExporter {
+    pub fn create(output_dir: &Path) -> Result<Self> {
+        let path = |name: &str| output_dir.join(format!("{name}.npy"));
+        Ok(Self {
+            nn_input: NpyWriter::create(&path("nn_input"), Some(NN_INPUT_DIM))?,
+            policy_target: NpyWriter::create(&path("policy_target"), Some(POLICY_DIM))?,
+            choice_target: NpyWriter::create(&path("choice_target"), Some(CHOICE_DIM))?,
+            value_target: NpyWriter::create(&path("value_target"), Some(VALUE_DIM))?,
+            game_id: NpyWriter::create(&path("game_id"), None)?
+        })
+    }
+
+    pub fn add(&mut self, sample: &TrainingSample) -> Result<()> {
+        self.nn_input.write_row(&sample.nn_input)?;
+        self.policy_target.write_row(&sample.policy_target)?;
+        self.choice_target.write_row(&sample.choice_target)?;
+        self.value_target.write_row(&sample.value_target)?;
+        self.game_id.write_row(&[sample.game_id])
+    }
+
+    /// 回填行数，返回每个文件的记录
+    pub fn finish(self) -> Result<Vec<ExportFile>> {
+        let file = |name: &str, samples: u64| ExportFile {
+            name: format!("{name}.npy"),
+            source: None,
+            samples
+        };
+        Ok(vec![
+            file("nn_input", self.nn_input.finish()?),
+            file("policy_target", self.policy_target.finish()?),
+            file("choice_target", self.choice_target.finish()?),
+            file("value_target", self.value_target.finish()?),
+            file("game_id", self.game_id.finish()?)
+        ])
+    }
+}
+
+// ============================================================================
+// safetensors
+// ============================================================================
+
+/// 把一个分片的样本写成 safetensors 文件，metadata 中记录特征布局版本和来源
+pub fn write_safetensors(path: &Path, samples: &[TrainingSample], source: &str) -> Result<()> {
+    let n = samples.len();
+    let f32_bytes = |field: fn(&TrainingSample) -> &[f32]| -> Vec<u8> {
+        samples.iter().flat_map(field).flat_map(|v| v.to_le_bytes()).collect()
+    };
+    let buffers = [
+        f32_bytes(|s| &s.nn_input),
+        f32_bytes(|s| &s.policy_target),
+        f32_bytes(|s| &s.choice_target),
+        f32_bytes(|s| &s.value_target),
+        samples.iter().flat_map(|s| s.game_id.to_le_bytes()).collect()
+    ];
+
+    let mut tensors = Vec::with_capacity(EXPORT_ARRAYS.len());
+    for ((name, cols), data) in EXPORT_ARRAYS.iter().zip(&buffers) {
+        let (dtype, shape) = match cols {
+            Some(c) => (Dtype::F32, vec![n, *c]),
+            None => (Dtype::U64, vec![n])
+        };
+        let view = TensorView::new(dtype, shape, data).with_context(|| format!("构造张量 {name} 失败"))?;
+        tensors.push((*name, view));
+    }
+    let metadata = HashMap::from([
+        ("feature_schema_version".to_string(), FEATURE_SCHEMA_VERSION.to_string()),
+        ("source".to_string(), source.to_string())
+    ]);
+    safetensors::serialize_to_file(tensors, Some(metadata), path)
+        .with_context(|| format!("写入 safetensors 失败: {}", path.display()))?;
+    Ok(())
+}
+
+/// 在输出目录写 export.json 和 feature_schema.json
+pub fn write_export_index(output_dir: &Path, format: ExportFormat, files: Vec<ExportFile>) -> Result<ExportIndex> {
+    let samples = match format {
+        // 每个数组一个文件，行数相同
+        ExportFormat::Npy => files.first().map(|f| f.samples).unwrap_or(0),
+        ExportFormat::Safetensors => files.iter().map(|f| f.samples).sum()
+    };
+    let index = ExportIndex {
+        format,
+        feature_schema_version: FEATURE_SCHEMA_VERSION,
+        samples,
+        arrays: EXPORT_ARRAYS
+            .iter()
+            .map(|(name, cols)| ExportArray {
+                name: name.to_string(),
+                dtype: if cols.is_some() { "f32" } else { "u64" }.to_string(),
+                shape: cols.iter().copied().collect()
+            })
+            .collect(),
+        files
+    };
+    let text = serde_json::to_string_pretty(&index)?;
+    fs_err::write(output_dir.join(EXPORT_INDEX_FILE), text)?;
+    FeatureSchema::current().save_json(&output_dir.join(FEATURE_SCHEMA_FILE))?;
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_npy_header() {
+        let header = npy_header("<f4", 123456789, Some(NN_INPUT_DIM));
+        assert_eq!(header.len(), NPY_HEADER_LEN);
+        assert_eq!(header.len() % 64, 0);
+        assert_eq!(u16::from_le_bytes([header[8], header[9]]) as usize, NPY_HEADER_LEN - 10);
+        let text = String::from_utf8_lossy(&header[10..]);
+        assert!(text.contains("'shape': (123456789, 1121)"), "{text}");
+        assert!(text.ends_with('\n'));
+
+        let dir = TempDir::new("export");
+        let path = dir.join("game_id.npy");
+        let mut writer = NpyWriter::<u64>::create(&path, None).unwrap();
+        writer.write_row(&[7]).unwrap();
+        writer.write_row(&[9]).unwrap();
+        assert!(writer.write_row(&[1, 2]).is_err());
+        assert_eq!(writer.finish().unwrap(), 2);
+        let bytes = fs_err::read(&path).unwrap();
+        assert_eq!(bytes.len(), NPY_HEADER_LEN + 16);
+        assert!(String::from_utf8_lossy(&bytes[..NPY_HEADER_LEN]).contains("'shape': (2,)"));
+        assert_eq!(bytes[NPY_HEADER_LEN + 8], 9);
+    }
+}
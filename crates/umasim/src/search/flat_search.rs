@@ -7,7 +7,7 @@
 
 use anyhow::Result;
 use log::{debug, info};
-use rand::{SeedableRng, rngs::StdRng};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use rayon::prelude::*;
 
 use super::{
@@ -22,6 +22,7 @@ use crate::{
     neural::{
         Evaluator,
         HandwrittenEvaluator,
+        InferenceBackendKind,
         ThreadLocalNeuralNetLeafEvaluator,
         ThreadLocalNeuralNetLeafStatsSnapshot,
         ValueOutput
@@ -52,12 +53,15 @@ impl LeafEvaluator {
 
 /// 扁平蒙特卡洛搜索
 ///
-/// 使用手写逻辑进行模拟，统计各动作的分数分布。
+/// 默认使用手写逻辑进行模拟，统计各动作的分数分布。
 #[derive(Clone)]
 pub struct FlatSearch {
     /// 手写评估器（用于模拟）
     rollout_evaluator: HandwrittenEvaluator,
 
+    /// rollout 动作策略：None 时使用手写逻辑，否则按模型 policy 采样（温泉/装备选择和事件选项仍用手写逻辑）
+    rollout_policy: Option<ThreadLocalNeuralNetLeafEvaluator>,
+
     /// leaf eval 评估器（用于 max_depth>0 截断估值）
     leaf_evaluator: LeafEvaluator,
 
@@ -73,6 +77,7 @@ impl FlatSearch {
     pub fn new(config: SearchConfig) -> Self {
         Self {
             rollout_evaluator: HandwrittenEvaluator::new(),
+            rollout_policy: None,
             leaf_evaluator: LeafEvaluator::Handwritten,
             config,
             rollout_batch_size: 1
@@ -85,8 +90,9 @@ impl FlatSearch {
     }
 
     /// 设置 leaf eval 为神经网络（用于 max_depth>0 截断估值）
-    pub fn with_leaf_evaluator_nn(mut self, model_path: impl Into<String>) -> Self {
-        self.leaf_evaluator = LeafEvaluator::NeuralNet(ThreadLocalNeuralNetLeafEvaluator::new(model_path));
+    pub fn with_leaf_evaluator_nn(mut self, model_path: impl Into<String>, backend: InferenceBackendKind) -> Self {
+        self.leaf_evaluator =
+            LeafEvaluator::NeuralNet(ThreadLocalNeuralNetLeafEvaluator::new(model_path).with_backend(backend));
         self
     }
 
@@ -96,6 +102,19 @@ impl FlatSearch {
         self
     }
 
+    /// 设置 rollout 动作使用神经网络 policy 采样
+    ///
+    /// 与 leaf eval 使用同一个模型时共用已加载的模型
+    pub fn with_rollout_policy_nn(mut self, model_path: impl Into<String>, backend: InferenceBackendKind) -> Self {
+        let model_path = model_path.into();
+        let policy = match &self.leaf_evaluator {
+            LeafEvaluator::NeuralNet(nn) if nn.model_path() == model_path && nn.backend() == backend => nn.clone(),
+            _ => ThreadLocalNeuralNetLeafEvaluator::new(model_path).with_backend(backend)
+        };
+        self.rollout_policy = Some(policy);
+        self
+    }
+
     /// 设置 leaf eval 微批大小（仅 nn leaf 生效）
     pub fn with_rollout_batch_size(mut self, batch_size: usize) -> Self {
         self.rollout_batch_size = batch_size.max(1).min(1024);
@@ -127,6 +146,13 @@ impl FlatSearch {
         }
     }
 
+    fn rollout_trainer(&self) -> SimulationTrainer<'_> {
+        SimulationTrainer {
+            evaluator: &self.rollout_evaluator,
+            policy: self.rollout_policy.as_ref()
+        }
+    }
+
     /// 执行搜索
     ///
     /// 根据配置选择搜索策略：
@@ -148,11 +174,12 @@ impl FlatSearch {
         let radical_factor = self.compute_radical_factor(game.turn as usize);
 
         debug!(
-            "[回合 {}] 开始搜索: {} 个动作, search_n={}, max_depth={}, leaf_eval={}, radical_factor={:.1}, ucb={}",
+            "[回合 {}] 开始搜索: {} 个动作, search_n={}, max_depth={}, rollout={}, leaf_eval={}, radical_factor={:.1}, ucb={}",
             game.turn,
             actions.len(),
             self.config.search_n,
             self.config.max_depth,
+            if self.rollout_policy.is_some() { "nn" } else { "handwritten" },
             self.leaf_evaluator.name(),
             radical_factor,
             self.config.use_ucb
@@ -430,16 +457,12 @@ impl FlatSearch {
     /// # 返回
     /// 最终分数
     fn simulate(&self, game: &OnsenGame, action: &OnsenAction, rng: &mut StdRng) -> Result<(f64, f64)> {
-        if matches!(action, OnsenAction::Dig(_)) {
-            self.simulate_onsen_select(game, action, rng)
-        } else if matches!(action, OnsenAction::Upgrade(_)) {
-            self.simulate_dig_upgrade(game, action, rng)
+        if Self::is_selection_action(action) {
+            self.simulate_selection(game, action, rng)
         } else {
             // 克隆游戏状态
             let mut sim_game = game.clone();
-            let trainer_hw = SimulationTrainer {
-                evaluator: &self.rollout_evaluator
-            };
+            let trainer_hw = self.rollout_trainer();
 
             // 执行初始动作
             sim_game.apply_action(action, rng)?;
@@ -447,7 +470,7 @@ impl FlatSearch {
             // max_depth==0：保持旧行为，rollout 跑到终局
             if self.config.max_depth == 0 {
                 while sim_game.next() {
-                    sim_game.run_stage(&trainer_hw, rng)?;
+                    sim_game.run_stage_checked(&trainer_hw, rng)?;
                 }
                 sim_game.on_simulation_end(&trainer_hw, rng)?;
                 return Ok((
@@ -466,7 +489,7 @@ impl FlatSearch {
                     finished = true;
                     break;
                 }
-                sim_game.run_stage(&trainer_hw, rng)?;
+                sim_game.run_stage_checked(&trainer_hw, rng)?;
                 if (sim_game.turn - start_turn) >= max_depth {
                     break;
                 }
@@ -560,20 +583,14 @@ impl FlatSearch {
         &self, game: &OnsenGame, action: &OnsenAction, rng: &mut StdRng
     ) -> Result<SimOutcome> {
         // Dig/Upgrade 目前仍走完整模拟（未对齐 max_depth）；这里直接复用现有路径，视为 Terminal
-        if matches!(action, OnsenAction::Dig(_)) {
-            let (s, pt) = self.simulate_onsen_select(game, action, rng)?;
-            return Ok(SimOutcome::Terminal { score: s, score_pt: pt });
-        }
-        if matches!(action, OnsenAction::Upgrade(_)) {
-            let (s, pt) = self.simulate_dig_upgrade(game, action, rng)?;
+        if Self::is_selection_action(action) {
+            let (s, pt) = self.simulate_selection(game, action, rng)?;
             return Ok(SimOutcome::Terminal { score: s, score_pt: pt });
         }
 
         // 克隆游戏状态
         let mut sim_game = game.clone();
-        let trainer_hw = SimulationTrainer {
-            evaluator: &self.rollout_evaluator
-        };
+        let trainer_hw = self.rollout_trainer();
 
         // 执行初始动作
         sim_game.apply_action(action, rng)?;
@@ -581,7 +598,7 @@ impl FlatSearch {
         // max_depth==0：保持旧行为，rollout 跑到终局
         if self.config.max_depth == 0 {
             while sim_game.next() {
-                sim_game.run_stage(&trainer_hw, rng)?;
+                sim_game.run_stage_checked(&trainer_hw, rng)?;
             }
             sim_game.on_simulation_end(&trainer_hw, rng)?;
             return Ok(SimOutcome::Terminal {
@@ -600,7 +617,7 @@ impl FlatSearch {
                 finished = true;
                 break;
             }
-            sim_game.run_stage(&trainer_hw, rng)?;
+            sim_game.run_stage_checked(&trainer_hw, rng)?;
             if (sim_game.turn - start_turn) >= max_depth {
                 break;
             }
@@ -622,34 +639,71 @@ impl FlatSearch {
         Ok(SimOutcome::Leaf { features, pt_bias })
     }
 
-    /// 模拟选择温泉. 因为没有做成单独的阶段，所以单独处理
-    pub fn simulate_onsen_select(
-        &self, game: &OnsenGame, action: &OnsenAction, rng: &mut StdRng
-    ) -> Result<(f64, f64)> {
-        let mut sim_game = game.clone();
-        let mut best_score = (0.0, 0.0);
-        
-        sim_game.apply_action(action, rng)?;
-        for i in sim_game.get_upgradeable_equipment() {
-            let score = self.simulate_dig_upgrade(&sim_game, &OnsenAction::Upgrade(i as i32), rng)?;
-            if score.0 > best_score.0 {
-                best_score = score;
-            }
+    /// 是否为温泉选择/装备升级动作
+    fn is_selection_action(action: &OnsenAction) -> bool {
+        matches!(
+            action,
+            OnsenAction::Dig(_) | OnsenAction::Upgrade(_) | OnsenAction::DigUpgrade(_, _)
+        )
+    }
+
+    /// 联合搜索温泉选择和装备升级
+    ///
+    /// 两者通过挖掘力强相关，所以把(Dig, Upgrade)组合成复合动作一起评估。
+    /// 所有组合共用同一组随机种子模拟（公共随机数），减少组合之间比较的方差。
+    pub fn search_selection(&self, game: &OnsenGame, rng: &mut StdRng) -> Result<SearchOutput> {
+        let actions = game.list_actions_dig_upgrade();
+        if actions.is_empty() {
+            anyhow::bail!("没有可选温泉");
         }
-        Ok(best_score)
+        let radical_factor = self.compute_radical_factor(game.turn as usize);
+        let seeds: Vec<u64> = (0..self.config.search_n).map(|_| rng.random()).collect();
+        debug!(
+            "[回合 {}] 联合搜索温泉和装备: {} 个组合, search_n={}",
+            game.turn,
+            actions.len(),
+            self.config.search_n
+        );
+        let action_results = actions
+            .par_iter()
+            .map(|action| {
+                let mut result = ActionResult::new();
+                let mut result_pt = ActionResult::new();
+                for seed in &seeds {
+                    let mut sim_rng = StdRng::seed_from_u64(*seed);
+                    if let Ok(score) = self.simulate_selection(game, action, &mut sim_rng) {
+                        result.add(score.0);
+                        result_pt.add(score.1);
+                    }
+                }
+                (result, result_pt)
+            })
+            .collect();
+        Ok(SearchOutput::new(actions, action_results, radical_factor))
     }
 
-    /// 模拟升级挖掘装备
-    pub fn simulate_dig_upgrade(&self, game: &OnsenGame, action: &OnsenAction, rng: &mut StdRng) -> Result<(f64, f64)> {
+    /// 模拟温泉选择和装备升级. 因为没有做成单独的阶段，所以单独处理
+    ///
+    /// 只选择温泉时，装备升级使用手写逻辑；只升级装备时，温泉已经选好
+    pub fn simulate_selection(&self, game: &OnsenGame, action: &OnsenAction, rng: &mut StdRng) -> Result<(f64, f64)> {
         let mut sim_game = game.clone();
         sim_game.apply_action(action, rng)?;
-        sim_game.pending_selection = false;
+        if matches!(action, OnsenAction::Dig(_)) {
+            let upgrade_actions: Vec<_> = sim_game
+                .get_upgradeable_equipment()
+                .iter()
+                .map(|x| OnsenAction::Upgrade(*x as i32))
+                .collect();
+            if !upgrade_actions.is_empty() {
+                let idx = self.rollout_evaluator.select_upgrade_action(&sim_game, &upgrade_actions);
+                sim_game.apply_action(&upgrade_actions[idx], rng)?;
+            }
+        }
         // 去除pending_selection状态后就可以正常模拟了。
-        let trainer_hw = SimulationTrainer {
-            evaluator: &self.rollout_evaluator
-        };
+        sim_game.pending_selection = false;
+        let trainer_hw = self.rollout_trainer();
         while sim_game.next() {
-            sim_game.run_stage(&trainer_hw, rng)?;
+            sim_game.run_stage_checked(&trainer_hw, rng)?;
         }
         sim_game.on_simulation_end(&trainer_hw, rng)?;
         Ok((
@@ -666,9 +720,10 @@ enum SimOutcome {
 
 /// 模拟用训练员
 ///
-/// 包装 HandwrittenEvaluator，实现 Trainer trait。
+/// 包装 HandwrittenEvaluator，实现 Trainer trait。设置了 policy 时普通动作按模型 policy 采样。
 struct SimulationTrainer<'a> {
-    evaluator: &'a HandwrittenEvaluator
+    evaluator: &'a HandwrittenEvaluator,
+    policy: Option<&'a ThreadLocalNeuralNetLeafEvaluator>
 }
 
 impl<'a> crate::game::Trainer<OnsenGame> for SimulationTrainer<'a> {
@@ -690,6 +745,14 @@ impl<'a> crate::game::Trainer<OnsenGame> for SimulationTrainer<'a> {
             return Ok(self.evaluator.select_upgrade_action(game, actions));
         }
 
+        if let Some(policy) = self.policy {
+            match policy.select_action_index(game, actions, rng) {
+                Ok(Some(idx)) => return Ok(idx),
+                Ok(None) => {}
+                Err(e) => log::warn!("[NN][rollout] 推理失败，回退手写逻辑: {e}")
+            }
+        }
+
         // 使用 HandwrittenEvaluator 的 select_action 逻辑
         let selected_action = self.evaluator.select_action(game, rng);
         let idx = match &selected_action {
@@ -719,4 +782,4 @@ impl<'a> crate::game::Trainer<OnsenGame> for SimulationTrainer<'a> {
     }
 }
 
-// 说明：E6 的“rollout 动作走 NN”已回退；rollout 全程固定使用 SimulationTrainer(HandwrittenEvaluator)。
+// 说明：rollout 默认使用 SimulationTrainer(HandwrittenEvaluator)；self-play 通过 with_rollout_policy_nn 改用模型 policy。
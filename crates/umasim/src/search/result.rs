@@ -9,7 +9,7 @@ use super::SearchConfig;
 use crate::{
     game::{ActionEnum, onsen::{action::OnsenAction, game::OnsenGame}},
     sample_collector::action_to_global_index,
-    training_sample::{CHOICE_DIM, TrainingSample}
+    training_sample::{CHOICE_DIM, SampleProvenance, TrainingSample}
 };
 
 /// 最大分数（用于分布直方图）
@@ -281,7 +281,18 @@ impl SearchOutput {
         // 4. Choice Target: 暂时为空（8 维）
         let choice_target = vec![0.0_f32; CHOICE_DIM];
 
-        TrainingSample::new(features, policy_target, choice_target, value_target)
+        let mut sample = TrainingSample::new(features, policy_target, choice_target, value_target);
+        sample.provenance = self.provenance(game);
+        sample
+    }
+
+    /// 样本来源：决策点、总模拟次数和最优动作的标准差
+    pub fn provenance(&self, game: &OnsenGame) -> SampleProvenance {
+        SampleProvenance {
+            search_count: self.action_results.iter().map(|r| r.0.count()).sum(),
+            best_stdev: self.best_result().stdev() as f32,
+            ..SampleProvenance::at(game, self.actions.len())
+        }
     }
 
     /// 计算 Policy Target
@@ -317,6 +328,30 @@ impl SearchOutput {
         policy
     }
 
+    /// 按访问次数计算 Policy Target（self-play 用）
+    ///
+    /// 各动作的模拟次数除以有策略输出的动作的总次数；UCB 分配下次数越多说明搜索越看好该动作。
+    /// 没有任何动作有策略输出时返回 None
+    pub fn calc_visit_policy_target(&self) -> Option<Vec<f32>> {
+        let mut policy = vec![0.0_f32; 50];
+        let mut total = 0u64;
+        for (action, result) in self.actions.iter().zip(&self.action_results) {
+            if let Some(global_idx) = action_to_global_index(action)
+                && global_idx < 50
+            {
+                policy[global_idx] += result.0.count() as f32;
+                total += result.0.count() as u64;
+            }
+        }
+        if total == 0 {
+            return None;
+        }
+        for p in &mut policy {
+            *p /= total as f32;
+        }
+        Some(policy)
+    }
+
     /// 统计两种评分的动作均分和标准差，用于结果输出
     pub fn to_scores(&self) -> Vec<Vec<ScoreEntry>> {
         let mut ret = vec![];
@@ -0,0 +1,84 @@
+//! 测试共用的临时目录和样本分片
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering}
+};
+
+use crate::{
+    collector::{CollectorManifest, ShardWriter},
+    dataset::{MANIFEST_NAME, SCORE_MEAN_VALUES_NAME},
+    gamedata::CollectorConfig,
+    search::SearchConfig,
+    training_sample::{CHOICE_DIM, NN_INPUT_DIM, POLICY_DIM, TrainingSample, VALUE_DIM}
+};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// 系统临时目录下的测试目录，名字带进程 id，drop 时删除
+pub struct TempDir {
+    path: PathBuf
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("umasim_{name}_test_{}_{id}", std::process::id()));
+        let _ = fs_err::remove_dir_all(&path);
+        fs_err::create_dir_all(&path).expect("create temp dir");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// 写入 n 个样本，每片 shard_size 个，第 i 个样本的 nn_input 和 value_target 都是 i，返回写完后的 manifest
+    pub fn write_shards(&self, n: usize, shard_size: usize, zstd_level: i32) -> CollectorManifest {
+        let manifest = CollectorManifest::new(
+            &self.path,
+            None,
+            "",
+            String::new(),
+            Vec::new(),
+            None,
+            CollectorConfig::default(),
+            &SearchConfig::default()
+        );
+        let (mut writer, _) = ShardWriter::open_or_create(
+            &self.path,
+            MANIFEST_NAME,
+            SCORE_MEAN_VALUES_NAME,
+            shard_size,
+            false,
+            true,
+            || Ok(manifest)
+        )
+        .expect("open shard writer");
+        writer.set_zstd_level(zstd_level);
+        let samples: Vec<TrainingSample> = (0..n)
+            .map(|i| {
+                TrainingSample::new(
+                    vec![i as f32; NN_INPUT_DIM],
+                    vec![0.0; POLICY_DIM],
+                    vec![0.0; CHOICE_DIM],
+                    vec![i as f32; VALUE_DIM]
+                )
+            })
+            .collect();
+        writer.push_samples(samples).expect("push samples");
+        writer.finish().expect("finish shard");
+        writer.save_manifest().expect("save manifest");
+        writer.manifest.clone()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs_err::remove_dir_all(&self.path);
+    }
+}
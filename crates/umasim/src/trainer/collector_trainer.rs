@@ -29,7 +29,9 @@ use crate::{
     game::{Trainer, onsen::game::OnsenGame},
     gamedata::ActionValue,
     neural::{Evaluator, HandwrittenEvaluator},
-    sample_collector::{GameSample, SampleCollector}
+    sample_collector::{GameSample, SampleCollector},
+    trainer::SampleSource,
+    training_sample::SampleProvenance
 };
 
 /// 默认探索率（40%）
@@ -154,10 +156,11 @@ impl Trainer<OnsenGame> for CollectorTrainer {
 
         // 提取特征（在选择动作之前）
         let features = game.extract_nn_features(None);
+        let provenance = SampleProvenance::at(game, actions.len());
 
         // 只有一个动作时直接返回
         if actions.len() <= 1 {
-            self.collector.borrow_mut().record_turn(features, &actions[0]);
+            self.collector.borrow_mut().record_turn(features, &actions[0], provenance);
             return Ok(0);
         }
 
@@ -187,7 +190,7 @@ impl Trainer<OnsenGame> for CollectorTrainer {
                 strategy_idx
             };
 
-            self.collector.borrow_mut().record_turn(features, &actions[idx]);
+            self.collector.borrow_mut().record_turn(features, &actions[idx], provenance);
             return Ok(idx);
         }
 
@@ -195,7 +198,7 @@ impl Trainer<OnsenGame> for CollectorTrainer {
         let all_upgrade = actions.iter().all(|a| matches!(a, OnsenAction::Upgrade(_)));
         if all_upgrade {
             let idx = self.evaluator.select_upgrade_action(game, actions);
-            self.collector.borrow_mut().record_turn(features, &actions[idx]);
+            self.collector.borrow_mut().record_turn(features, &actions[idx], provenance);
             if self.verbose {
                 debug!("[回合 {}] 收集：装备升级 {}", game.turn, actions[idx]);
             }
@@ -210,7 +213,7 @@ impl Trainer<OnsenGame> for CollectorTrainer {
         };
 
         // 记录到收集器（使用实际选择的动作）
-        self.collector.borrow_mut().record_turn(features, &actions[idx]);
+        self.collector.borrow_mut().record_turn(features, &actions[idx], provenance);
 
         if self.verbose {
             debug!("[回合 {}] 收集：动作 {}", game.turn, actions[idx]);
@@ -247,3 +250,15 @@ impl Trainer<OnsenGame> for CollectorTrainer {
         Ok(best_idx)
     }
 }
+
+impl SampleSource for CollectorTrainer {
+    fn pending_samples(&self) -> usize {
+        self.collector.borrow().num_turns()
+    }
+
+    fn stamp_samples(&self, start: usize, stamp: &mut dyn FnMut(&mut SampleProvenance)) {
+        for turn in self.collector.borrow_mut().turns_mut().iter_mut().skip(start) {
+            stamp(&mut turn.provenance);
+        }
+    }
+}
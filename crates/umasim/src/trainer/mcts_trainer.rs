@@ -50,7 +50,9 @@ pub struct MctsTrainer {
     /// 第一回合分数
     pub initial_score: (AtomicU64, AtomicU64),
     /// 保存当前的搜索结果用于输出
-    pub search_output: Arc<Mutex<SearchOutput>>
+    pub search_output: Arc<Mutex<SearchOutput>>,
+    /// 联合搜索温泉时选出的装备，在随后的装备升级中使用
+    pub planned_upgrade: Mutex<Option<i32>>
 }
 
 impl MctsTrainer {
@@ -65,7 +67,8 @@ impl MctsTrainer {
             last_game: None,
             last_score: (AtomicU64::new(0), AtomicU64::new(0)),
             initial_score: (AtomicU64::new(0), AtomicU64::new(0)),
-            search_output: Arc::new(Mutex::new(SearchOutput::default()))
+            search_output: Arc::new(Mutex::new(SearchOutput::default())),
+            planned_upgrade: Mutex::new(None)
         }
     }
 
@@ -116,6 +119,51 @@ impl MctsTrainer {
             format!("{}", text.bright_black())
         }
     }
+    /// 联合搜索温泉和装备升级，返回温泉的索引，装备留给之后的升级选择
+    fn select_dig_upgrade(&self, game: &OnsenGame, actions: &[OnsenAction], rng: &mut StdRng) -> Result<usize> {
+        global!(LOGGER)
+            .lock()
+            .expect("logger lock")
+            .push_temp_spec(LogSpecification::off());
+        let search_output = self.search.search_selection(game, rng);
+        global!(LOGGER).lock().expect("logger lock").pop_temp_spec();
+        let search_output = search_output?;
+
+        let selection = match self.mcts_selection.as_str() {
+            "pt" => search_output.best_action_2(),
+            _ => search_output.best_action()
+        }
+        .clone();
+        let (onsen, upgrade) = match selection {
+            OnsenAction::DigUpgrade(onsen, tool) => (onsen, Some(tool)),
+            OnsenAction::Dig(onsen) => (onsen, None),
+            _ => return Err(anyhow!("联合搜索结果不是温泉选择: {selection}"))
+        };
+        *self.planned_upgrade.lock().map_err(|_| anyhow!("lock failed"))? = upgrade;
+        if self.verbose {
+            let best_score = search_output.best_result().weighted_mean(search_output.radical_factor);
+            let line: Vec<_> = search_output
+                .actions
+                .iter()
+                .zip(&search_output.action_results)
+                .map(|(action, result)| {
+                    let score = result.0.weighted_mean(search_output.radical_factor);
+                    self.format_action_result(action, &result.0, score, best_score)
+                })
+                .collect();
+            info!("[回合 {}] 联合搜索温泉和装备: {}", game.turn + 1, line.join(" "));
+            info!("[回合 {}] 选择: {}", game.turn + 1, selection);
+        }
+        {
+            let mut s = self.search_output.lock().map_err(|_| anyhow!("lock failed"))?;
+            *s = search_output;
+        }
+        actions
+            .iter()
+            .position(|a| *a == OnsenAction::Dig(onsen))
+            .ok_or(anyhow!("温泉不在可选列表中: {onsen}"))
+    }
+
     // 计算本回合均分
     fn update_score(&self, game: &OnsenGame, actions: &[OnsenAction], search_output: &SearchOutput) {
         let mut sum = 0.0;
@@ -262,6 +310,21 @@ impl Trainer<OnsenGame> for MctsTrainer {
             }
             return Ok(idx);
         }
+        if is_dig {
+            return self.select_dig_upgrade(game, actions, rng);
+        }
+        // 联合搜索时已经选好的装备
+        let all_upgrade = actions.iter().all(|a| matches!(a, OnsenAction::Upgrade(_)));
+        let planned = self.planned_upgrade.lock().map_err(|_| anyhow!("lock failed"))?.take();
+        if all_upgrade
+            && let Some(tool) = planned
+            && let Some(idx) = actions.iter().position(|a| *a == OnsenAction::Upgrade(tool))
+        {
+            if self.verbose {
+                info!("[回合 {}] 选择装备升级（联合搜索）: {}", game.turn + 1, actions[idx]);
+            }
+            return Ok(idx);
+        }
         /*
             // 检查是否是装备升级场景（所有动作都是 Upgrade）
                 let all_upgrade = actions.iter().all(|a| matches!(a, OnsenAction::Upgrade(_)));
@@ -22,8 +22,8 @@ use crate::{
     gamedata::{ActionValue, EventData},
     neural::{Evaluator, HandwrittenEvaluator},
     search::FlatSearch,
-    trainer::HandwrittenTrainer,
-    training_sample::{CHOICE_DIM, POLICY_DIM, TrainingSample}
+    trainer::{HandwrittenTrainer, SampleSource},
+    training_sample::{CHOICE_DIM, POLICY_DIM, SampleProvenance, TrainingSample}
 };
 
 #[derive(Debug, Clone)]
@@ -430,7 +430,7 @@ impl MeanFilterCollectorTrainer {
 
             // 从下一阶段开始推进到终局（与 FlatSearch::simulate 的处理一致）
             while sim_game.next() {
-                if sim_game.run_stage(&sim_trainer, &mut rollout_rng).is_err() {
+                if sim_game.run_stage_checked(&sim_trainer, &mut rollout_rng).is_err() {
                     break;
                 }
             }
@@ -684,7 +684,12 @@ impl Trainer<OnsenGame> for MeanFilterCollectorTrainer {
 
                 drop(stats);
 
-                let sample = TrainingSample::new(nn_input, policy_target, choice_target, value_target);
+                let mut sample = TrainingSample::new(nn_input, policy_target, choice_target, value_target);
+                sample.provenance = SampleProvenance {
+                    search_count: (self.choice_rollouts_per_option.max(1) * choices.len()) as u32,
+                    best_stdev: best_result.stdev() as f32,
+                    ..SampleProvenance::at(game, choices.len())
+                };
                 self.samples.borrow_mut().push(sample);
             } else {
                 stats.choice_dropped += 1;
@@ -712,3 +717,15 @@ impl Trainer<OnsenGame> for MeanFilterCollectorTrainer {
         Ok(best_idx)
     }
 }
+
+impl SampleSource for MeanFilterCollectorTrainer {
+    fn pending_samples(&self) -> usize {
+        self.samples.borrow().len()
+    }
+
+    fn stamp_samples(&self, start: usize, stamp: &mut dyn FnMut(&mut SampleProvenance)) {
+        for sample in self.samples.borrow_mut().iter_mut().skip(start) {
+            stamp(&mut sample.provenance);
+        }
+    }
+}
@@ -8,18 +8,22 @@ use crate::{
     gamedata::ActionValue
 };
 
-// 导出手写逻辑训练员、数据收集训练员、神经网络训练员和 MCTS 训练员
+// 导出手写逻辑训练员、数据收集训练员、神经网络训练员、MCTS 训练员、self-play 训练员和对局记录/重放
 pub mod collector_trainer;
 pub mod handwritten_trainer;
 pub mod mcts_trainer;
 pub mod mean_filter_collector_trainer;
 pub mod neural_net_trainer;
+pub mod replay_trainer;
+pub mod self_play_trainer;
 
 pub use collector_trainer::CollectorTrainer;
 pub use handwritten_trainer::HandwrittenTrainer;
 pub use mcts_trainer::MctsTrainer;
 pub use mean_filter_collector_trainer::MeanFilterCollectorTrainer;
 pub use neural_net_trainer::NeuralNetTrainer;
+pub use replay_trainer::{RecordingTrainer, ReplayPoint, SampleSource, replay_sample};
+pub use self_play_trainer::{SelfPlayStats, SelfPlayTrainer};
 
 /// 猴子训练师
 pub struct RandomTrainer;
@@ -39,7 +43,7 @@ impl<G: Game> Trainer<G> for RandomTrainer {
             } else if game.uma().motivation < 5 {
                 if matches!(
                     actions[i].as_base_action(),
-                    Some(BaseAction::NormalOuting) | Some(BaseAction::FriendOuting)
+                    Some(BaseAction::NormalOuting) | Some(BaseAction::FriendOuting) | Some(BaseAction::CardOuting(_))
                 ) {
                     ret = i;
                     break;
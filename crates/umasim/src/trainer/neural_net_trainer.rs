@@ -14,7 +14,7 @@ use crate::{
         onsen::{action::OnsenAction, game::OnsenGame}
     },
     gamedata::{ActionValue, EventData},
-    neural::{Evaluator, HandwrittenEvaluator, NeuralNetEvaluator},
+    neural::{Evaluator, HandwrittenEvaluator, InferenceBackendKind, NeuralNetEvaluator},
     training_sample::{CHOICE_DIM, POLICY_DIM}
 };
 
@@ -42,7 +42,12 @@ impl NeuralNetTrainer {
     /// # 返回
     /// 加载成功返回 NeuralNetTrainer，失败返回错误
     pub fn load(model_path: &str) -> Result<Self> {
-        let evaluator = NeuralNetEvaluator::load(model_path)?;
+        Self::load_with_backend(model_path, InferenceBackendKind::Tract)
+    }
+
+    /// 用指定推理后端加载模型
+    pub fn load_with_backend(model_path: &str, backend: InferenceBackendKind) -> Result<Self> {
+        let evaluator = NeuralNetEvaluator::load_with_backend(model_path, backend)?;
         Ok(Self { evaluator, verbose: false })
     }
 
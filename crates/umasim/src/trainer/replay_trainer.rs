@@ -0,0 +1,268 @@
+//! 对局记录与重放
+//!
+//! [`RecordingTrainer`] 包装采样用的 trainer，记录每次选择，并给新产生的样本补上种子、对局序号和决策序号；
+//! 整局的决策记录只保存在最新的样本中，对局结束时就是该局最后一个样本。
+//! 被包装的 trainer 使用由种子派生的单独 RNG，游戏 RNG 只被游戏本身消耗，
+//! 因此 [`replay_sample`] 用同一个种子按记录依次做出选择，就能重建产生样本时的 `OnsenGame`。
+
+use std::cell::{Cell, RefCell};
+
+use anyhow::{Context, Result, bail, ensure};
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::{
+    game::{
+        Game,
+        Trainer,
+        onsen::{action::OnsenAction, game::OnsenGame}
+    },
+    gamedata::{ActionValue, EventData},
+    training_sample::{SampleProvenance, TrainingSample}
+};
+
+/// 在对局中产生训练样本的 trainer
+pub trait SampleSource {
+    /// 本局已产生、还没取走的样本数
+    fn pending_samples(&self) -> usize;
+    /// 修改第 start 个及之后样本的来源信息
+    fn stamp_samples(&self, start: usize, stamp: &mut dyn FnMut(&mut SampleProvenance));
+}
+
+/// 由对局种子派生 trainer RNG 的种子，和游戏 RNG 区分开
+fn trainer_seed(seed: u64) -> u64 {
+    seed ^ 0x9E37_79B9_7F4A_7C15
+}
+
+/// 记录对局选择的 trainer 包装
+pub struct RecordingTrainer<'a, T> {
+    inner: &'a T,
+    seed: u64,
+    game_index: u64,
+    rng: RefCell<StdRng>,
+    decisions: RefCell<Vec<u16>>,
+    /// 当前保存决策记录的样本下标
+    log_sample: Cell<Option<usize>>
+}
+
+impl<'a, T: Trainer<OnsenGame> + SampleSource> RecordingTrainer<'a, T> {
+    pub fn new(inner: &'a T, seed: u64, game_index: u64) -> Self {
+        Self {
+            inner,
+            seed,
+            game_index,
+            rng: RefCell::new(StdRng::seed_from_u64(trainer_seed(seed))),
+            decisions: RefCell::new(Vec::new()),
+            log_sample: Cell::new(None)
+        }
+    }
+
+    /// 本局使用的游戏 RNG
+    pub fn game_rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+
+    /// 已做出的选择
+    pub fn decisions(&self) -> Vec<u16> {
+        self.decisions.borrow().clone()
+    }
+
+    fn record(&self, start: usize, selection: Result<usize>) -> Result<usize> {
+        let selection = selection?;
+        let mut decisions = self.decisions.borrow_mut();
+        let end = self.inner.pending_samples();
+        if end > start {
+            // 决策记录从之前的样本移到最新的样本
+            let from = self.log_sample.get().unwrap_or(start).min(start);
+            let mut index = from;
+            self.inner.stamp_samples(from, &mut |p| {
+                if index >= start {
+                    p.seed = self.seed;
+                    p.game_index = self.game_index;
+                    p.decision_index = decisions.len() as u32;
+                }
+                p.decisions = if index + 1 == end { decisions.clone() } else { Vec::new() };
+                index += 1;
+            });
+            self.log_sample.set(Some(end - 1));
+        }
+        decisions.push(u16::try_from(selection).context("选择索引超出 u16 范围")?);
+        Ok(selection)
+    }
+}
+
+impl<T: Trainer<OnsenGame> + SampleSource> Trainer<OnsenGame> for RecordingTrainer<'_, T> {
+    fn select_action(&self, game: &OnsenGame, actions: &[OnsenAction], _rng: &mut StdRng) -> Result<usize> {
+        let start = self.inner.pending_samples();
+        let selection = self.inner.select_action(game, actions, &mut self.rng.borrow_mut());
+        self.record(start, selection)
+    }
+
+    fn select_choice(&self, game: &OnsenGame, choices: &[ActionValue], _rng: &mut StdRng) -> Result<usize> {
+        let start = self.inner.pending_samples();
+        let selection = self.inner.select_choice(game, choices, &mut self.rng.borrow_mut());
+        self.record(start, selection)
+    }
+
+    fn select_event_choice(
+        &self, game: &OnsenGame, event: &EventData, choices: &[ActionValue], _rng: &mut StdRng
+    ) -> Result<usize> {
+        let start = self.inner.pending_samples();
+        let selection = self.inner.select_event_choice(game, event, choices, &mut self.rng.borrow_mut());
+        self.record(start, selection)
+    }
+}
+
+/// 重放到达的决策点
+#[derive(Debug, Clone)]
+pub struct ReplayPoint {
+    /// 做出选择之前的游戏状态
+    pub game: OnsenGame,
+    /// 动作决策时的可选动作，事件决策时为空
+    pub actions: Vec<OnsenAction>,
+    /// 事件决策时的选项
+    pub choices: Option<Vec<ActionValue>>
+}
+
+impl ReplayPoint {
+    /// 重新提取的特征，应与样本的 nn_input 一致
+    pub fn nn_input(&self) -> Vec<f32> {
+        self.game.extract_nn_features(self.choices.as_deref())
+    }
+
+    /// 可选动作数或选项数
+    pub fn legal_actions(&self) -> usize {
+        match &self.choices {
+            Some(choices) => choices.len(),
+            None => self.actions.len()
+        }
+    }
+
+    /// 与样本记录不一致的地方，为空表示重建成功
+    pub fn mismatches(&self, sample: &TrainingSample) -> Vec<String> {
+        let p = &sample.provenance;
+        let mut ret = Vec::new();
+        if self.game.turn != p.turn || self.game.stage != p.stage {
+            ret.push(format!(
+                "到达回合 {} {:?}，样本记录为回合 {} {:?}",
+                self.game.turn, self.game.stage, p.turn, p.stage
+            ));
+        }
+        if self.legal_actions() != p.legal_actions as usize {
+            ret.push(format!("可选数 {}，样本记录为 {}", self.legal_actions(), p.legal_actions));
+        }
+        let nn_input = self.nn_input();
+        let diff = nn_input
+            .iter()
+            .zip(&sample.nn_input)
+            .enumerate()
+            .find(|(_, (a, b))| (*a - *b).abs() > 1e-5);
+        if let Some((i, (a, b))) = diff {
+            ret.push(format!("nn_input[{i}] 重新提取为 {a}，样本为 {b}"));
+        }
+        ret
+    }
+}
+
+/// 按记录依次返回选择，到达最后一个决策点时保存状态并中止模拟
+struct ReplayTrainer<'a> {
+    decisions: &'a [u16],
+    next: Cell<usize>,
+    point: RefCell<Option<ReplayPoint>>
+}
+
+impl ReplayTrainer<'_> {
+    fn replay(&self, game: &OnsenGame, actions: &[OnsenAction], choices: Option<&[ActionValue]>) -> Result<usize> {
+        let i = self.next.get();
+        let Some(&selection) = self.decisions.get(i) else {
+            *self.point.borrow_mut() = Some(ReplayPoint {
+                game: game.clone(),
+                actions: actions.to_vec(),
+                choices: choices.map(<[ActionValue]>::to_vec)
+            });
+            bail!("已到达样本的决策点");
+        };
+        let len = choices.map_or(actions.len(), <[ActionValue]>::len);
+        ensure!(
+            (selection as usize) < len.max(1),
+            "第 {i} 个选择 {selection} 超出可选范围 {len}，对局与记录不一致（游戏数据或配置可能已改变）"
+        );
+        self.next.set(i + 1);
+        Ok(selection as usize)
+    }
+}
+
+impl Trainer<OnsenGame> for ReplayTrainer<'_> {
+    fn select_action(&self, game: &OnsenGame, actions: &[OnsenAction], _rng: &mut StdRng) -> Result<usize> {
+        self.replay(game, actions, None)
+    }
+
+    fn select_choice(&self, game: &OnsenGame, choices: &[ActionValue], _rng: &mut StdRng) -> Result<usize> {
+        self.replay(game, &[], Some(choices))
+    }
+
+    fn select_event_choice(
+        &self, game: &OnsenGame, _event: &EventData, choices: &[ActionValue], _rng: &mut StdRng
+    ) -> Result<usize> {
+        self.replay(game, &[], Some(choices))
+    }
+}
+
+/// 从新开局重放到样本的决策点
+///
+/// `game` 需要用产生样本时的配置（马娘、支援卡、继承）创建，`log` 为同一局的决策记录
+pub fn replay_sample(mut game: OnsenGame, provenance: &SampleProvenance, log: &[u16]) -> Result<ReplayPoint> {
+    let Some(decisions) = log.get(..provenance.decision_index as usize) else {
+        bail!("决策记录只有 {} 个选择，样本在第 {} 个", log.len(), provenance.decision_index);
+    };
+    let trainer = ReplayTrainer {
+        decisions,
+        next: Cell::new(0),
+        point: RefCell::new(None)
+    };
+    let mut rng = StdRng::seed_from_u64(provenance.seed);
+    let result = game.run_full_game(&trainer, &mut rng);
+    if let Some(point) = trainer.point.into_inner() {
+        return Ok(point);
+    }
+    result.context("重放失败")?;
+    bail!("对局在 {} 个选择后结束，没有到达样本的决策点", decisions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gamedata::init_global, trainer::CollectorTrainer, utils::init_logger};
+
+    #[test]
+    fn test_replay_sample() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let deck = [302424, 302464, 302484, 302564, 302574, 302644];
+        let trainer = CollectorTrainer::new();
+        let recorder = RecordingTrainer::new(&trainer, 12345, 3);
+        let mut game = OnsenGame::newgame(101901, &deck, Default::default())?;
+        game.run_full_game(&recorder, &mut recorder.game_rng())?;
+        let samples = trainer.finalize(game.uma.calc_score()).samples;
+        assert!(samples.len() > 20);
+        assert!(samples.iter().all(|s| s.provenance.seed == 12345 && s.provenance.game_index == 3));
+        // 决策记录只保存在最后一个样本中
+        let last = &samples[samples.len() - 1].provenance;
+        assert_eq!(last.decisions.len(), last.decision_index as usize);
+        assert!(samples[..samples.len() - 1].iter().all(|s| s.provenance.decisions.is_empty()));
+        let log = &last.decisions;
+
+        for sample in [&samples[0], &samples[samples.len() / 2], &samples[samples.len() - 1]] {
+            let game = OnsenGame::newgame(101901, &deck, Default::default())?;
+            let point = replay_sample(game, &sample.provenance, log)?;
+            assert_eq!(point.mismatches(sample), Vec::<String>::new());
+        }
+
+        // 决策记录被改动时应能发现不一致
+        let mut provenance = samples[samples.len() / 2].provenance.clone();
+        provenance.seed += 1;
+        let changed = replay_sample(OnsenGame::newgame(101901, &deck, Default::default())?, &provenance, log)
+            .map(|p| p.mismatches(&samples[samples.len() / 2]));
+        assert!(changed.map_or(true, |m| !m.is_empty()));
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+//! Self-play 训练员
+//!
+//! 每次动作决策都用 FlatSearch 选择 best action，搜索的 rollout 和 leaf eval 可以来自当前模型
+//! （见 [`FlatSearch::with_rollout_policy_nn`] 和 [`FlatSearch::with_leaf_evaluator_nn`]）。
+//! 每个决策导出一条样本：
+//! - policy_target：各动作的访问次数分布（[`SearchOutput::calc_visit_policy_target`]）
+//! - value_target：整局结束后用最终分数覆盖 scoreMean 和 value，stdev 保留搜索估计
+//!
+//! 温泉选择和装备升级与 MctsTrainer 一样走手写逻辑，不搜索也不导出样本。
+//!
+//! [`SearchOutput::calc_visit_policy_target`]: crate::search::SearchOutput::calc_visit_policy_target
+
+use std::cell::RefCell;
+
+use anyhow::Result;
+use log::debug;
+use rand::rngs::StdRng;
+
+use crate::{
+    game::{
+        Trainer,
+        onsen::{action::OnsenAction, game::OnsenGame}
+    },
+    gamedata::ActionValue,
+    neural::{Evaluator, HandwrittenEvaluator},
+    search::FlatSearch,
+    trainer::SampleSource,
+    training_sample::{CHOICE_DIM, SampleProvenance, TrainingSample}
+};
+
+/// Self-play 统计
+#[derive(Debug, Clone, Default)]
+pub struct SelfPlayStats {
+    /// 经过搜索的动作决策数
+    pub decisions: u64,
+    /// 导出的样本数
+    pub samples: u64,
+    /// 搜索失败次数（回退选择第一个动作）
+    pub search_errors: u64,
+    /// 没有任何动作有策略输出而跳过的决策数
+    pub skipped_no_policy: u64
+}
+
+/// Self-play 训练员
+pub struct SelfPlayTrainer {
+    search: FlatSearch,
+    evaluator: HandwrittenEvaluator,
+
+    /// 本局已导出但还没有最终分数的样本
+    samples: RefCell<Vec<TrainingSample>>,
+    stats: RefCell<SelfPlayStats>,
+
+    verbose: bool
+}
+
+impl SelfPlayTrainer {
+    pub fn new(search: FlatSearch) -> Self {
+        Self {
+            search,
+            evaluator: HandwrittenEvaluator::new(),
+            samples: RefCell::new(Vec::new()),
+            stats: RefCell::new(SelfPlayStats::default()),
+            verbose: false
+        }
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn search(&self) -> &FlatSearch {
+        &self.search
+    }
+
+    /// 取走上次调用以来的统计
+    pub fn take_stats(&self) -> SelfPlayStats {
+        std::mem::take(&mut *self.stats.borrow_mut())
+    }
+
+    /// 整局结束后取走本局样本，并用最终分数覆盖 value_target 的 scoreMean 和 value
+    pub fn finish_game(&self, final_score: f64) -> Vec<TrainingSample> {
+        let mut samples = std::mem::take(&mut *self.samples.borrow_mut());
+        for sample in &mut samples {
+            sample.value_target[0] = final_score as f32;
+            sample.value_target[2] = final_score as f32;
+        }
+        samples
+    }
+
+    /// 丢弃本局样本（模拟失败时调用）
+    pub fn discard_game(&self) {
+        self.samples.borrow_mut().clear();
+    }
+}
+
+impl Trainer<OnsenGame> for SelfPlayTrainer {
+    fn select_action(&self, game: &OnsenGame, actions: &[OnsenAction], rng: &mut StdRng) -> Result<usize> {
+        if actions.len() <= 1 {
+            return Ok(0);
+        }
+        // 温泉选择和装备升级：使用手写逻辑
+        if actions.iter().any(|a| matches!(a, OnsenAction::Dig(_))) {
+            let idx = self.evaluator.select_onsen_index(game, actions);
+            if self.verbose {
+                debug!("[回合 {}] 选择温泉（手写逻辑）: {}", game.turn + 1, actions[idx]);
+            }
+            return Ok(idx);
+        }
+        if actions.iter().all(|a| matches!(a, OnsenAction::Upgrade(_))) {
+            let idx = self.evaluator.select_upgrade_action(game, actions);
+            if self.verbose {
+                debug!("[回合 {}] 选择装备升级（手写逻辑）: {}", game.turn + 1, actions[idx]);
+            }
+            return Ok(idx);
+        }
+
+        let output = match self.search.search(game, actions, rng) {
+            Ok(v) => v,
+            Err(e) => {
+                self.stats.borrow_mut().search_errors += 1;
+                if self.verbose {
+                    debug!("[回合 {}] FlatSearch 失败，回退选择第一个动作：{e}", game.turn + 1);
+                }
+                return Ok(0);
+            }
+        };
+
+        let mut stats = self.stats.borrow_mut();
+        stats.decisions += 1;
+        match output.calc_visit_policy_target() {
+            Some(policy_target) => {
+                let best = output.best_result();
+                let value_target = vec![
+                    best.mean() as f32,
+                    best.stdev() as f32,
+                    best.weighted_mean(output.radical_factor) as f32,
+                ];
+                let mut sample = TrainingSample::new(
+                    game.extract_nn_features(None),
+                    policy_target,
+                    vec![0.0_f32; CHOICE_DIM],
+                    value_target
+                );
+                sample.provenance = output.provenance(game);
+                self.samples.borrow_mut().push(sample);
+                stats.samples += 1;
+            }
+            None => stats.skipped_no_policy += 1
+        }
+
+        Ok(output.best_action_idx)
+    }
+
+    fn select_choice(&self, game: &OnsenGame, choices: &[ActionValue], _rng: &mut StdRng) -> Result<usize> {
+        // choice head 还不可靠，事件选项沿用手写逻辑
+        let mut best_idx = 0;
+        let mut best_value = f64::NEG_INFINITY;
+
+        for (i, _choice) in choices.iter().enumerate() {
+            let value = self.evaluator.evaluate_choice(game, i);
+            if value > best_value {
+                best_value = value;
+                best_idx = i;
+            }
+        }
+
+        Ok(best_idx)
+    }
+}
+
+impl SampleSource for SelfPlayTrainer {
+    fn pending_samples(&self) -> usize {
+        self.samples.borrow().len()
+    }
+
+    fn stamp_samples(&self, start: usize, stamp: &mut dyn FnMut(&mut SampleProvenance)) {
+        for sample in self.samples.borrow_mut().iter_mut().skip(start) {
+            stamp(&mut sample.provenance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        collector::{CollectorManifest, ManifestGeneration, ShardWriter, compute_file_signature},
+        dataset::DatasetReader,
+        game::Game,
+        gamedata::{CollectorConfig, init_global},
+        neural::{InferenceBackendKind, Mlp, feature_schema::ModelMeta},
+        search::SearchConfig,
+        test_utils::TempDir,
+        trainer::RecordingTrainer,
+        utils::init_logger
+    };
+
+    #[test]
+    fn test_self_play_generation() -> Result<()> {
+        init_logger("test", "info")?;
+        init_global()?;
+        let temp = TempDir::new("self_play");
+        let dir = temp.path();
+        let (manifest_name, values_name) = ("manifest.json", "score_mean_values.bin");
+        let (mut writer, _) = ShardWriter::open_or_create(dir, manifest_name, values_name, 64, false, true, || {
+            Ok(CollectorManifest::new(
+                dir,
+                None,
+                "",
+                String::new(),
+                Vec::new(),
+                None,
+                CollectorConfig::default(),
+                &SearchConfig::default()
+            ))
+        })?;
+
+        // 第 0 代模型
+        let model_path = dir.join("model.onnx");
+        Mlp::new(&[16], &mut StdRng::seed_from_u64(3)).export_onnx(&model_path, &ModelMeta::current())?;
+        let snapshot = model_path.to_string_lossy().to_string();
+        writer.manifest.generations.push(ManifestGeneration {
+            generation: 0,
+            model_path: snapshot.clone(),
+            model_sig: compute_file_signature(&model_path, true)?,
+            started_at: String::new(),
+            games_run: 0,
+            samples: 0
+        });
+        let search_config = SearchConfig {
+            search_n: 2,
+            max_depth: 2,
+            use_ucb: true,
+            search_group_size: 1,
+            ..Default::default()
+        };
+        let search = FlatSearch::new(search_config)
+            .with_leaf_evaluator_nn(&snapshot, InferenceBackendKind::Dense)
+            .with_rollout_policy_nn(&snapshot, InferenceBackendKind::Dense);
+        let trainer = SelfPlayTrainer::new(search);
+
+        let mut game = OnsenGame::newgame(101901, &[302424, 302464, 302484, 302564, 302574, 302644], Default::default())?;
+        let recorder = RecordingTrainer::new(&trainer, 1, 0);
+        game.run_full_game(&recorder, &mut recorder.game_rng())?;
+        let stats = trainer.take_stats();
+        assert_eq!(stats.search_errors, 0);
+        assert!(stats.samples > 0);
+        let score = game.uma().calc_score() as f64;
+        let samples = trainer.finish_game(score);
+        assert_eq!(samples.len() as u64, stats.samples);
+        assert!(samples.iter().all(|s| s.value_target[0] == score as f32));
+        let n = samples.len() as u64;
+        writer.push_game(samples)?;
+        writer.manifest.progress.games_run += 1;
+        writer.manifest.progress.accepted += n;
+        if let Some(g) = writer.manifest.generations.last_mut() {
+            g.games_run += 1;
+            g.samples += n;
+        }
+        writer.finish()?;
+        writer.save_manifest()?;
+        drop(writer);
+
+        let manifest = CollectorManifest::load(&dir.join(manifest_name))?;
+        let generations: Vec<_> = manifest.generations.iter().map(|g| (g.generation, g.games_run, g.samples)).collect();
+        assert_eq!(generations, vec![(0, 1, n)]);
+        assert_eq!(DatasetReader::open(dir)?.len(), n);
+        Ok(())
+    }
+}
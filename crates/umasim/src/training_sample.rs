@@ -1,41 +1,65 @@
 use std::{
     fs::File,
-    io::{BufWriter, Write}
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail, ensure};
+use bincode::Options;
 /// 神经网络训练样本模块
 ///
 /// 用于收集和导出训练数据
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    game::onsen::{OnsenTurnStage, game::OnsenGame},
+    neural::feature_schema::{self, OutputBlock}
+};
+
 // ============================================================================
-// 神经网络输入输出维度常量
+// 神经网络输入输出维度常量，由特征布局计算
 // ============================================================================
 
 /// 神经网络输入维度
-pub const NN_INPUT_DIM: usize = 1121;
+pub const NN_INPUT_DIM: usize = feature_schema::INPUT_DIM;
 
 /// 全局特征维度
-pub const NN_GLOBAL_DIM: usize = 587;
+pub const NN_GLOBAL_DIM: usize = feature_schema::GLOBAL_DIM;
 
 /// 支援卡特征维度（每张卡）
-pub const NN_CARD_DIM: usize = 89;
+pub const NN_CARD_DIM: usize = feature_schema::CARD_DIM;
 
 /// 支援卡数量
-pub const NN_CARD_NUM: usize = 6;
+pub const NN_CARD_NUM: usize = feature_schema::CARD_NUM;
 
 /// 神经网络输出维度
-pub const NN_OUTPUT_DIM: usize = 61;
+pub const NN_OUTPUT_DIM: usize = feature_schema::OUTPUT_DIM;
+
+/// 二进制样本文件头，后接 4 字节小端版本号
+///
+/// 旧文件（版本 1）没有文件头，开头直接是 bincode 的样本数（u64），不会和文件头冲突
+pub const SAMPLE_FILE_MAGIC: [u8; 4] = *b"UMTS";
+
+/// zstd 帧的 magic：压缩的样本文件整体是一个 zstd 帧，读取时按开头自动识别
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// 当前样本格式版本
+/// - 1：无文件头，样本只有 nn_input 和三个目标
+/// - 2：增加文件头和 game_id
+/// - 3：增加样本来源 provenance，整局的决策记录只保存在该局最后一个样本中
+pub const SAMPLE_FORMAT_VERSION: u32 = 3;
+
+/// 单个样本解码的字节上限，防止损坏的长度字段导致一次分配过大的内存
+const MAX_SAMPLE_BYTES: u64 = 16 << 20;
 
 /// Policy 输出维度
-pub const POLICY_DIM: usize = 50;
+pub const POLICY_DIM: usize = OutputBlock::Policy.dim();
 
 /// Choice 输出维度
-pub const CHOICE_DIM: usize = 8;
+pub const CHOICE_DIM: usize = OutputBlock::Choice.dim();
 
 /// Value 输出维度
-pub const VALUE_DIM: usize = 3;
+pub const VALUE_DIM: usize = OutputBlock::Value.dim();
 
 /// 训练样本结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,7 +90,75 @@ pub struct TrainingSample {
     pub choice_target: Vec<f32>,
 
     /// Value 目标（3 维：scoreMean, scoreStdev, value）
-    pub value_target: Vec<f32>
+    pub value_target: Vec<f32>,
+
+    /// 样本所属对局的编号，0 表示未知（旧数据）
+    ///
+    /// 同一局的样本编号相同，用于按对局划分训练集和验证集
+    #[serde(default)]
+    pub game_id: u64,
+
+    /// 样本来源，用于追溯和重放对局，旧数据为默认值
+    #[serde(default)]
+    pub provenance: SampleProvenance
+}
+
+/// 样本来源
+///
+/// 对局用 `seed` 初始化游戏 RNG，trainer 使用单独的 RNG，因此按决策记录的前 `decision_index` 个
+/// 依次做出相同选择，即可重建产生样本时的 `OnsenGame`（见 [`crate::trainer::replay_trainer`]）。
+/// 决策记录每局只保存一份，在该局最后一个样本的 `decisions` 中。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SampleProvenance {
+    /// 对局 RNG 种子，没有决策记录时无法重放
+    pub seed: u64,
+    /// 对局在本次运行中的序号
+    pub game_index: u64,
+    /// 回合（从 0 开始）
+    pub turn: i32,
+    /// 回合阶段
+    pub stage: OnsenTurnStage,
+    /// 可选动作数，事件样本为选项数
+    pub legal_actions: u32,
+    /// 搜索的总模拟次数，未搜索为 0
+    pub search_count: u32,
+    /// 最优动作的分数标准差
+    pub best_stdev: f32,
+    /// 产生样本之前 trainer 做出的选择数，即样本在决策记录中的位置
+    pub decision_index: u32,
+    /// 本局的决策记录（动作和事件选项，按顺序排列），只有该局最后一个样本非空
+    pub decisions: Vec<u16>
+}
+
+impl SampleProvenance {
+    /// 当前决策点的回合、阶段和可选数；种子和决策记录由 RecordingTrainer 补上
+    pub fn at(game: &OnsenGame, legal_actions: usize) -> Self {
+        Self {
+            turn: game.turn,
+            stage: game.stage.clone(),
+            legal_actions: legal_actions as u32,
+            ..Default::default()
+        }
+    }
+}
+
+/// 版本 1 的样本，仅用于读取旧文件
+#[derive(Deserialize)]
+struct TrainingSampleV1 {
+    nn_input: Vec<f32>,
+    policy_target: Vec<f32>,
+    choice_target: Vec<f32>,
+    value_target: Vec<f32>
+}
+
+/// 版本 2 的样本，仅用于读取旧文件
+#[derive(Deserialize)]
+struct TrainingSampleV2 {
+    nn_input: Vec<f32>,
+    policy_target: Vec<f32>,
+    choice_target: Vec<f32>,
+    value_target: Vec<f32>,
+    game_id: u64
 }
 
 impl TrainingSample {
@@ -91,10 +183,26 @@ impl TrainingSample {
             nn_input,
             policy_target,
             choice_target,
-            value_target
+            value_target,
+            game_id: 0,
+            provenance: SampleProvenance::default()
         }
     }
 
+    /// 检查各部分维度，读取文件时用于发现损坏或特征布局不一致的数据
+    pub fn check_dims(&self) -> Result<()> {
+        let dims = [
+            ("nn_input", self.nn_input.len(), NN_INPUT_DIM),
+            ("policy_target", self.policy_target.len(), POLICY_DIM),
+            ("choice_target", self.choice_target.len(), CHOICE_DIM),
+            ("value_target", self.value_target.len(), VALUE_DIM)
+        ];
+        for (name, len, dim) in dims {
+            ensure!(len == dim, "{name} 是 {len} 维，应为 {dim} 维");
+        }
+        Ok(())
+    }
+
     /// 创建空的 choice_target（无事件选项时使用）
     pub fn empty_choice_target() -> Vec<f32> {
         vec![0.0; CHOICE_DIM]
@@ -146,10 +254,25 @@ impl TrainingSampleBatch {
     pub fn save_binary(&self, path: &str) -> Result<()> {
         let file = File::create(path)?;
         let mut writer = BufWriter::new(file);
-        bincode::serialize_into(&mut writer, self)?;
+        self.write_binary(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// 以当前格式写出二进制数据（文件头 + bincode）
+    pub fn write_binary(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(&SAMPLE_FILE_MAGIC)?;
+        writer.write_all(&SAMPLE_FORMAT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(writer, self)?;
         Ok(())
     }
 
+    /// 读取未压缩的二进制数据，兼容没有文件头的旧格式
+    pub fn read_binary(reader: &mut impl Read) -> Result<Self> {
+        let samples = TrainingSampleReader::new(reader)?.collect::<Result<Vec<_>>>()?;
+        Ok(Self { samples })
+    }
+
     /// 从 JSON 文件加载
     pub fn load_json(path: &str) -> Result<Self> {
         let file = File::open(path)?;
@@ -157,11 +280,10 @@ impl TrainingSampleBatch {
         Ok(batch)
     }
 
-    /// 从二进制文件加载
+    /// 从二进制文件加载（支持 zstd 压缩的文件）
     pub fn load_binary(path: &str) -> Result<Self> {
-        let file = File::open(path)?;
-        let batch = bincode::deserialize_from(file)?;
-        Ok(batch)
+        let samples = TrainingSampleReader::open(Path::new(path))?.collect::<Result<Vec<_>>>()?;
+        Ok(Self { samples })
     }
 
     /// 追加保存到文件（JSONL 格式，每行一个样本）
@@ -185,3 +307,211 @@ impl Default for TrainingSampleBatch {
         Self::new()
     }
 }
+
+/// 逐个读取二进制样本文件，内存中只保留当前样本
+///
+/// 文件布局为 `[文件头] + 样本数(u64) + 样本...`，打开时只读取开头，
+/// 因此也可以用来快速获取文件中的样本数。
+pub struct TrainingSampleReader<R: Read> {
+    reader: R,
+    version: u32,
+    len: u64,
+    position: u64
+}
+
+impl TrainingSampleReader<Box<dyn Read + Send>> {
+    /// 打开样本文件，zstd 压缩的文件自动解压
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = BufReader::new(fs_err::File::open(path)?);
+        let reader: Box<dyn Read + Send> = if file.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+            Box::new(zstd::Decoder::with_buffer(file)?)
+        } else {
+            Box::new(file)
+        };
+        Self::new(reader).with_context(|| format!("读取样本文件失败: {}", path.display()))
+    }
+}
+
+impl<R: Read> TrainingSampleReader<R> {
+    /// 读取文件头和样本数
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut head = [0u8; 8];
+        reader.read_exact(&mut head)?;
+        if head[..4] != SAMPLE_FILE_MAGIC {
+            // 版本 1：开头 8 字节就是样本数
+            return Ok(Self {
+                reader,
+                version: 1,
+                len: u64::from_le_bytes(head),
+                position: 0
+            });
+        }
+        let version = u32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+        if !(2..=SAMPLE_FORMAT_VERSION).contains(&version) {
+            bail!("不支持的样本格式版本: {version}，当前为 {SAMPLE_FORMAT_VERSION}");
+        }
+        let mut len = [0u8; 8];
+        reader.read_exact(&mut len)?;
+        Ok(Self {
+            reader,
+            version,
+            len: u64::from_le_bytes(len),
+            position: 0
+        })
+    }
+
+    /// 文件的样本格式版本
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// 文件中的样本数
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 下一个要读取的样本下标
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// 读取下一个样本，读完时返回 None
+    pub fn read_sample(&mut self) -> Result<Option<TrainingSample>> {
+        if self.position >= self.len {
+            return Ok(None);
+        }
+        // 与 bincode::serialize_into 相同的定长编码，另外限制单个样本的大小
+        let options = bincode::options().with_fixint_encoding().with_limit(MAX_SAMPLE_BYTES);
+        let sample = match self.version {
+            1 => {
+                let s: TrainingSampleV1 = options.deserialize_from(&mut self.reader)?;
+                TrainingSample {
+                    nn_input: s.nn_input,
+                    policy_target: s.policy_target,
+                    choice_target: s.choice_target,
+                    value_target: s.value_target,
+                    game_id: 0,
+                    provenance: SampleProvenance::default()
+                }
+            }
+            2 => {
+                let s: TrainingSampleV2 = options.deserialize_from(&mut self.reader)?;
+                TrainingSample {
+                    nn_input: s.nn_input,
+                    policy_target: s.policy_target,
+                    choice_target: s.choice_target,
+                    value_target: s.value_target,
+                    game_id: s.game_id,
+                    provenance: SampleProvenance::default()
+                }
+            }
+            _ => options.deserialize_from(&mut self.reader)?
+        };
+        sample
+            .check_dims()
+            .with_context(|| format!("第 {} 个样本维度不对", self.position))?;
+        self.position += 1;
+        Ok(Some(sample))
+    }
+
+    /// 跳过 n 个样本（样本不定长，仍需逐个解码）
+    pub fn skip_samples(&mut self, n: u64) -> Result<()> {
+        for _ in 0..n {
+            if self.read_sample()?.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 取回底层 reader（例如检查样本之后是否还有多余数据）
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for TrainingSampleReader<R> {
+    type Item = Result<TrainingSample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_sample().transpose()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let rest = (self.len - self.position) as usize;
+        (0, Some(rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_binary_versions() {
+        let sample = |v: f32| {
+            (
+                vec![v; NN_INPUT_DIM],
+                vec![0.0_f32; POLICY_DIM],
+                vec![0.0_f32; CHOICE_DIM],
+                vec![v; VALUE_DIM]
+            )
+        };
+        // 版本 1 没有文件头和 game_id，字段顺序与元组相同
+        let v1 = bincode::serialize(&vec![sample(1.0), sample(2.0)]).unwrap();
+        let batch = TrainingSampleBatch::read_binary(&mut v1.as_slice()).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.samples[1].value_target[0], 2.0);
+        assert_eq!(batch.samples[1].game_id, 0);
+
+        // 版本 2 有文件头和 game_id，没有 provenance
+        let (nn_input, policy, choice, value) = sample(2.0);
+        let mut v2 = SAMPLE_FILE_MAGIC.to_vec();
+        v2.extend(2u32.to_le_bytes());
+        v2.extend(bincode::serialize(&vec![(nn_input, policy, choice, value, 7u64)]).unwrap());
+        let batch = TrainingSampleBatch::read_binary(&mut v2.as_slice()).unwrap();
+        assert_eq!(batch.samples[0].game_id, 7);
+        assert_eq!(batch.samples[0].provenance, SampleProvenance::default());
+
+        let (nn_input, policy, choice, value) = sample(3.0);
+        let mut s = TrainingSample::new(nn_input, policy, choice, value);
+        s.game_id = 42;
+        s.provenance = SampleProvenance {
+            seed: 9,
+            game_index: 3,
+            turn: 20,
+            stage: OnsenTurnStage::Train,
+            legal_actions: 6,
+            search_count: 1200,
+            best_stdev: 1500.0,
+            decision_index: 3,
+            decisions: vec![0, 2, 1]
+        };
+        let mut bytes = Vec::new();
+        TrainingSampleBatch { samples: vec![s.clone()] }.write_binary(&mut bytes).unwrap();
+        assert_eq!(bytes[..4], SAMPLE_FILE_MAGIC);
+        let batch = TrainingSampleBatch::read_binary(&mut bytes.as_slice()).unwrap();
+        assert_eq!(batch.samples[0].game_id, 42);
+        assert_eq!(batch.samples[0].nn_input[0], 3.0);
+        assert_eq!(batch.samples[0].provenance, s.provenance);
+
+        // 维度不对或长度字段损坏时返回错误，不会 panic
+        let (mut nn_input, policy, choice, value) = sample(4.0);
+        nn_input.truncate(10);
+        let bad_v1 = bincode::serialize(&vec![(nn_input.clone(), policy, choice, value)]).unwrap();
+        assert!(TrainingSampleBatch::read_binary(&mut bad_v1.as_slice()).is_err());
+        s.nn_input = nn_input;
+        let mut bad_v3 = Vec::new();
+        TrainingSampleBatch { samples: vec![s] }.write_binary(&mut bad_v3).unwrap();
+        assert!(TrainingSampleBatch::read_binary(&mut bad_v3.as_slice()).is_err());
+        let mut huge = SAMPLE_FILE_MAGIC.to_vec();
+        huge.extend(3u32.to_le_bytes());
+        huge.extend(1u64.to_le_bytes());
+        huge.extend(u64::MAX.to_le_bytes());
+        assert!(TrainingSampleBatch::read_binary(&mut huge.as_slice()).is_err());
+    }
+}